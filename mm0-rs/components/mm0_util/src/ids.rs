@@ -2,9 +2,10 @@
 
 #[cfg(feature = "memory")]
 use mm0_deepsize_derive::DeepSizeOf;
-use std::fmt;
-use std::iter::FromIterator;
-use std::ops::{Deref, DerefMut, Index, IndexMut};
+use alloc::vec::Vec;
+use core::fmt;
+use core::iter::FromIterator;
+use core::ops::{Deref, DerefMut, Index, IndexMut};
 
 macro_rules! id_wrapper {
   ($id:ident: $ty:ty, $vec:ident) => {
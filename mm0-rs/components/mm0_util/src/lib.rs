@@ -2,6 +2,9 @@
 //!
 //! The types `Position` and `Range` will be different depending on whether
 //! the `server` feature is enabled.
+//!
+//! Without the (default) `std` feature, this crate is `no_std` and only provides the
+//! id types and [`Modifiers`], plus the utilities that only depend on `alloc`.
 
 // rust lints we want
 #![warn(
@@ -54,32 +57,42 @@
   clippy::use_self
 )]
 
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
 #[macro_use]
 extern crate bitflags;
 
 #[cfg(feature = "memory")]
 use mm0_deepsize_derive::DeepSizeOf;
-use std::borrow::Borrow;
+use alloc::{borrow::Borrow, boxed::Box, rc::Rc, string::String, sync::Arc, vec::Vec};
+use core::fmt;
+use core::hash::Hash;
+use core::mem::{self, MaybeUninit};
+use core::ops::{Deref, DerefMut};
+#[cfg(feature = "std")]
 use std::collections::{
   hash_map::{Entry, OccupiedEntry},
   HashMap,
 };
+#[cfg(feature = "std")]
 use std::error::Error;
-use std::fmt;
-use std::hash::{BuildHasher, Hash, Hasher};
-use std::mem::{self, MaybeUninit};
-use std::ops::{Deref, DerefMut};
+#[cfg(feature = "std")]
+use std::hash::{BuildHasher, Hasher};
+#[cfg(feature = "std")]
 use std::path::PathBuf;
-use std::rc::Rc;
-use std::sync::Arc;
 
 mod atoms;
 mod ids;
+#[cfg(feature = "std")]
 mod lined_string;
 
-pub use {ids::*, lined_string::*};
+pub use ids::*;
+#[cfg(feature = "std")]
+pub use lined_string::*;
 
 /// Newtype for `Box<dyn Error + Send + Sync>`
+#[cfg(feature = "std")]
 pub type BoxError = Box<dyn Error + Send + Sync>;
 
 /// Extension trait for `cloned_box`.
@@ -97,6 +110,7 @@ impl<T> SliceExt<T> for [T] {
 }
 
 /// Extension trait for [`HashMap`]`<K, V>`.
+#[cfg(feature = "std")]
 pub trait HashMapExt<K, V> {
   /// Like `insert`, but if the insertion fails then it returns the value
   /// that it attempted to insert, as well as an [`OccupiedEntry`] containing
@@ -105,6 +119,7 @@ pub trait HashMapExt<K, V> {
   fn try_insert_ext(&mut self, k: K, v: V) -> Option<(V, OccupiedEntry<'_, K, V>)>;
 }
 
+#[cfg(feature = "std")]
 impl<K: Hash + Eq, V, S: BuildHasher> HashMapExt<K, V> for HashMap<K, V, S> {
   fn try_insert_ext(&mut self, k: K, v: V) -> Option<(V, OccupiedEntry<'_, K, V>)> {
     match self.entry(k) {
@@ -158,7 +173,7 @@ macro_rules! let_unchecked {
     if let $p = $e { $bl } else if cfg!(debug_assertions) {
       unreachable!()
     } else {
-      unsafe { std::hint::unreachable_unchecked() }
+      unsafe { core::hint::unreachable_unchecked() }
     }
   };
 }
@@ -205,22 +220,26 @@ pub fn alphanumber(n: usize) -> String {
 }
 
 /// Extension trait for [`Mutex`](std::sync::Mutex)`<T>`.
+#[cfg(feature = "std")]
 pub trait MutexExt<T> {
   /// Like `lock`, but propagates instead of catches panics.
   fn ulock(&self) -> std::sync::MutexGuard<'_, T>;
 }
 
+#[cfg(feature = "std")]
 impl<T> MutexExt<T> for std::sync::Mutex<T> {
   fn ulock(&self) -> std::sync::MutexGuard<'_, T> {
     self.lock().expect("propagating poisoned mutex")
   }
 }
 /// Extension trait for [`Condvar`](std::sync::Condvar).
+#[cfg(feature = "std")]
 pub trait CondvarExt {
   /// Like `wait`, but propagates instead of catches panics.
   fn uwait<'a, T>(&self, g: std::sync::MutexGuard<'a, T>) -> std::sync::MutexGuard<'a, T>;
 }
 
+#[cfg(feature = "std")]
 impl CondvarExt for std::sync::Condvar {
   fn uwait<'a, T>(&self, g: std::sync::MutexGuard<'a, T>) -> std::sync::MutexGuard<'a, T> {
     self.wait(g).expect("propagating poisoned mutex")
@@ -275,7 +294,7 @@ impl ArcString {
   #[must_use]
   pub fn as_str(&self) -> &str {
     // Safety: ensured by caller
-    unsafe { std::str::from_utf8_unchecked(self) }
+    unsafe { core::str::from_utf8_unchecked(self) }
   }
 }
 
@@ -417,14 +436,14 @@ pub struct Span {
 #[cfg(feature = "memory")]
 mm0_deepsize::deep_size_0!(Span);
 
-impl From<std::ops::Range<usize>> for Span {
+impl From<core::ops::Range<usize>> for Span {
   #[inline]
-  fn from(r: std::ops::Range<usize>) -> Self { Span { start: r.start, end: r.end } }
+  fn from(r: core::ops::Range<usize>) -> Self { Span { start: r.start, end: r.end } }
 }
 
-impl From<std::ops::RangeInclusive<usize>> for Span {
+impl From<core::ops::RangeInclusive<usize>> for Span {
   #[inline]
-  fn from(r: std::ops::RangeInclusive<usize>) -> Self {
+  fn from(r: core::ops::RangeInclusive<usize>) -> Self {
     Span { start: *r.start(), end: *r.end() + 1 }
   }
 }
@@ -434,21 +453,21 @@ impl From<usize> for Span {
   fn from(n: usize) -> Self { Span { start: n, end: n } }
 }
 
-impl From<Span> for std::ops::Range<usize> {
+impl From<Span> for core::ops::Range<usize> {
   #[inline]
   fn from(s: Span) -> Self { s.start..s.end }
 }
 
 impl Deref for Span {
-  type Target = std::ops::Range<usize>;
-  fn deref(&self) -> &std::ops::Range<usize> {
+  type Target = core::ops::Range<usize>;
+  fn deref(&self) -> &core::ops::Range<usize> {
     // Safety: Range<usize> and Span are layout compatible
     unsafe { &*<*const _>::cast(self) }
   }
 }
 
 impl DerefMut for Span {
-  fn deref_mut(&mut self) -> &mut std::ops::Range<usize> {
+  fn deref_mut(&mut self) -> &mut core::ops::Range<usize> {
     // Safety: Range<usize> and Span are layout compatible
     unsafe { &mut *<*mut _>::cast(self) }
   }
@@ -456,8 +475,8 @@ impl DerefMut for Span {
 
 impl IntoIterator for Span {
   type Item = usize;
-  type IntoIter = std::ops::Range<usize>;
-  fn into_iter(self) -> std::ops::Range<usize> { (*self).clone() }
+  type IntoIter = core::ops::Range<usize>;
+  fn into_iter(self) -> core::ops::Range<usize> { (*self).clone() }
 }
 
 impl fmt::Debug for Span {
@@ -515,6 +534,7 @@ fn make_relative(buf: &std::path::Path) -> String {
     .to_owned()
 }
 
+#[cfg(feature = "std")]
 #[cfg_attr(feature = "memory", derive(DeepSizeOf))]
 #[derive(Default)]
 struct FileRefInner {
@@ -530,11 +550,12 @@ struct FileRefInner {
 /// and provides (precomputed) access to these views using
 /// [`path()`](FileRef::path) and [`url()`](FileRef::url), as well as
 /// [`rel()`](FileRef::rel) to get the relative path from [`struct@CURRENT_DIR`].
+#[cfg(feature = "std")]
 #[cfg_attr(feature = "memory", derive(DeepSizeOf))]
 #[derive(Clone, Default)]
 pub struct FileRef(Arc<FileRefInner>);
 
-#[cfg(any(target_arch = "wasm32", feature = "lined_string"))]
+#[cfg(all(feature = "std", any(target_arch = "wasm32", feature = "lined_string")))]
 impl From<PathBuf> for FileRef {
   #[cfg(target_arch = "wasm32")]
   fn from(_: PathBuf) -> FileRef { todo!() }
@@ -563,6 +584,7 @@ impl From<lsp_types::Url> for FileRef {
   }
 }
 
+#[cfg(feature = "std")]
 impl FileRef {
   /// Convert this [`FileRef`] to a [`PathBuf`], for use with OS file actions.
   #[must_use]
@@ -591,15 +613,19 @@ impl FileRef {
     self.path().extension().map_or(false, |s| s == ext)
   }
 }
+#[cfg(feature = "std")]
 impl PartialEq for FileRef {
   fn eq(&self, other: &Self) -> bool { self.0.rel == other.0.rel }
 }
+#[cfg(feature = "std")]
 impl Eq for FileRef {}
 
+#[cfg(feature = "std")]
 impl Hash for FileRef {
   fn hash<H: Hasher>(&self, state: &mut H) { self.0.rel.hash(state) }
 }
 
+#[cfg(feature = "std")]
 impl fmt::Display for FileRef {
   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
     let s = self.0.path.file_name().unwrap_or(self.0.path.as_os_str());
@@ -607,11 +633,13 @@ impl fmt::Display for FileRef {
   }
 }
 
+#[cfg(feature = "std")]
 impl fmt::Debug for FileRef {
   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result { fmt::Display::fmt(self, f) }
 }
 
 /// A span paired with a [`FileRef`].
+#[cfg(feature = "std")]
 #[cfg_attr(feature = "memory", derive(DeepSizeOf))]
 #[derive(Clone, Default, PartialEq, Eq)]
 pub struct FileSpan {
@@ -621,11 +649,13 @@ pub struct FileSpan {
   pub span: Span,
 }
 
+#[cfg(feature = "std")]
 impl fmt::Debug for FileSpan {
   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
     write!(f, "{}:{:?}", self.file, self.span)
   }
 }
+#[cfg(feature = "std")]
 impl<'a> From<&'a FileSpan> for Span {
   fn from(fsp: &'a FileSpan) -> Self { fsp.span }
 }
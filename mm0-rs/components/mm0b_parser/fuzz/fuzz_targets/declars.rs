@@ -1,12 +1,13 @@
 #![no_main]
 #[macro_use] extern crate libfuzzer_sys;
-extern crate mmbp;
+extern crate mm0b_parser;
+extern crate mm0_util;
 
 fuzz_target!(|data: &[u8]| {
-    if let Ok(mmb) = mmbp::parser::MmbFile::parse(data) {
+    if let Ok(mmb) = mm0b_parser::BasicMmbFile::parse(data) {
         for i in 0..data.len() {
-            let term_id = mmbp::TermId(i as u32);
-            let thm_id = mmbp::ThmId(i as u32);
+            let term_id = mm0_util::TermId(i as u32);
+            let thm_id = mm0_util::ThmId(i as u32);
             let _ = mmb.term(term_id);
             let _ = mmb.thm(thm_id);
         }
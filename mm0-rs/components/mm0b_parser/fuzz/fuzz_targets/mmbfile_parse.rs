@@ -1,21 +1,9 @@
 #![no_main]
 #[macro_use] extern crate libfuzzer_sys;
-extern crate mmbp;
+extern crate mm0b_parser;
 
 fuzz_target!(|data: &[u8]| {
-    if let Ok(mmb) = mmbp::parser::MmbFile::parse(data) {
-        for declar in mmb.proof() {
-            match declar {
-                Ok((_, proof)) => {
-                    for elem in proof {
-                        match elem {
-                            Ok(..) => continue,
-                            Err(_) => break
-                        }
-                    }
-                },
-                _ => break
-            }
-        }
+    if let Ok(mmb) = mm0b_parser::BareMmbFile::parse(data) {
+        let _ = mmb.validate();
     }
-});
\ No newline at end of file
+});
@@ -0,0 +1,26 @@
+#![no_main]
+#[macro_use] extern crate libfuzzer_sys;
+extern crate mm0b_parser;
+
+use mm0b_parser::{ArbitraryMmb, BasicMmbFile, Mm0Writer};
+
+fuzz_target!(|mmb: ArbitraryMmb| {
+    let file = BasicMmbFile::parse(mmb.bytes()).expect("generated file should parse");
+    let errors = file.validate();
+    assert!(errors.is_empty(), "generated file should validate: {:?}", errors);
+
+    // Copying the file through the writer should preserve the declarations
+    let mut w = Mm0Writer::new(vec![]);
+    w.init(&file).unwrap();
+    let mut out = vec![];
+    w.finish(&mut out).unwrap();
+    let mut buf = vec![0u64; (out.len() + 7) / 8];
+    zerocopy::AsBytes::as_bytes_mut(&mut *buf)[..out.len()].copy_from_slice(&out);
+    let copy = BasicMmbFile::parse(&zerocopy::AsBytes::as_bytes(&*buf)[..out.len()]).unwrap();
+    assert!(copy.validate().is_empty());
+    assert_eq!(proof(&file), proof(&copy));
+});
+
+fn proof<'a>(f: &BasicMmbFile<'a>) -> &'a [u8] {
+    &f.buf[f.header.p_proof.get() as usize..f.proof().after_end().unwrap()]
+}
@@ -3,6 +3,12 @@
 //! See [`mm0-c/verifier.c`] for information on the MMB format.
 //!
 //! [`mm0-c/verifier.c`]: https://github.com/digama0/mm0/blob/master/mm0-c/verifier.c
+//!
+//! Without the (default) `std` feature, this crate is `no_std` (but requires `alloc`),
+//! and only provides the parser; the `Mm0Writer` requires `std`.
+//!
+//! The `arbitrary` feature provides `ArbitraryMmb`, a generator of random well-formed MMB files
+//! for property testing and fuzzing.
 
 // rust lints we want
 #![warn(
@@ -54,20 +60,31 @@
   clippy::too_many_lines,
   clippy::use_self
 )]
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
 
 mod parser;
+#[cfg(feature = "arbitrary")]
+mod random;
 mod ty;
+mod validate;
+#[cfg(feature = "std")]
 mod write;
 
-use std::ffi::CStr;
-use std::mem::size_of;
+use core::ffi::CStr;
+use core::mem::size_of;
 
 use byteorder::LE;
 use mm0_util::{Modifiers, SortId, TermId, ThmId};
 use zerocopy::{AsBytes, FromBytes, Unaligned, U16, U32, U64};
 
 pub use mm0_util::u32_as_usize;
-pub use {parser::*, ty::*, write::*};
+pub use {parser::*, ty::*, validate::*};
+#[cfg(feature = "arbitrary")]
+pub use random::ArbitraryMmb;
+#[cfg(feature = "std")]
+pub use write::*;
 
 /// The maximum number of bound variables supported by the MMB format.
 pub const MAX_BOUND_VARS: usize = 55;
@@ -158,6 +175,8 @@ pub mod cmd {
   pub const INDEX_VAR_NAME: [u8; 4] = *b"VarN";
   /// `"HypN"` is the magic number for the hypothesis name table.
   pub const INDEX_HYP_NAME: [u8; 4] = *b"HypN";
+  /// `"SrcL"` is the magic number for the source location table.
+  pub const INDEX_SOURCE: [u8; 4] = *b"SrcL";
 }
 
 #[inline]
@@ -656,3 +675,16 @@ pub struct NameEntry {
   /// A pointer to the entity's name as a UTF-8 C string.
   pub p_name: U64<LE>,
 }
+
+/// An individual source location entry in the index.
+#[repr(C, align(8))]
+#[derive(Debug, Clone, Copy, FromBytes, AsBytes)]
+pub struct SourceEntry {
+  /// A pointer to the path of the source file as a UTF-8 C string, or 0 if the
+  /// location is not known.
+  pub p_file: U64<LE>,
+  /// The first line of the statement (1-based).
+  pub start_line: U32<LE>,
+  /// The last line of the statement (1-based, inclusive).
+  pub end_line: U32<LE>,
+}
@@ -1,13 +1,16 @@
 //! Parser for MMB binary proof files.
 use crate::{
   cmd, cstr_from_bytes_prefix, exhausted, u32_as_usize, u64_as_usize, Arg, Header, NameEntry,
-  NumdStmtCmd, ProofCmd, SortData, StmtCmd, TableEntry, TermEntry, ThmEntry, UnifyCmd,
+  NumdStmtCmd, ProofCmd, SortData, SourceEntry, StmtCmd, TableEntry, TermEntry, ThmEntry,
+  UnifyCmd,
 };
+use alloc::{borrow::Cow, boxed::Box, format};
 use byteorder::LE;
+use core::ops::Range;
+use core::{mem, mem::size_of};
 use mm0_util::{SortId, TermId, ThmId};
-use std::borrow::Cow;
-use std::ops::Range;
-use std::{io, mem, mem::size_of};
+#[cfg(feature = "std")]
+use std::io;
 use zerocopy::{FromBytes, LayoutVerified, U16, U32, U64};
 
 /// A parsed `MMB` file, as a borrowed type. This does only shallow parsing;
@@ -151,6 +154,7 @@ make_index_trait! {
 }
 impl<'a> NoSymbolNames for Option<VarNames<'a>> {}
 impl<'a> NoSymbolNames for Option<HypNames<'a>> {}
+impl<'a> NoSymbolNames for Option<SourceLocs<'a>> {}
 
 /// This index subcomponent supplies variable names for terms and theorems.
 #[derive(Debug)]
@@ -184,6 +188,7 @@ make_index_trait! {
 }
 impl<'a> NoVarNames for Option<SymbolNames<'a>> {}
 impl<'a> NoVarNames for Option<HypNames<'a>> {}
+impl<'a> NoVarNames for Option<SourceLocs<'a>> {}
 
 /// This index subcomponent supplies hypothesis names for theorems.
 #[derive(Debug)]
@@ -213,9 +218,52 @@ make_index_trait! {
 }
 impl<'a> NoHypNames for Option<SymbolNames<'a>> {}
 impl<'a> NoHypNames for Option<VarNames<'a>> {}
+impl<'a> NoHypNames for Option<SourceLocs<'a>> {}
 
-/// A basic index, usable for getting names of declarations and variables.
-pub type BasicIndex<'a> = (Option<SymbolNames<'a>>, (Option<VarNames<'a>>, Option<HypNames<'a>>));
+/// This index subcomponent supplies the source file and line range of sorts, terms, and theorems.
+#[derive(Debug)]
+pub struct SourceLocs<'a> {
+  /// The source locations of the sorts
+  sorts: &'a [SourceEntry],
+  /// The source locations of the terms
+  terms: &'a [SourceEntry],
+  /// The source locations of the theorems
+  thms: &'a [SourceEntry],
+}
+
+impl<'a> MmbIndexBuilder<'a> for Option<SourceLocs<'a>> {
+  fn build<X>(&mut self, f: &mut MmbFile<'a, X>, e: &'a TableEntry) -> Result<(), ParseError> {
+    if e.id == cmd::INDEX_SOURCE {
+      let rest = f.buf.get(u64_as_usize(e.ptr)..).ok_or_else(|| f.bad_index_parse())?;
+      let (sorts, rest) =
+        new_slice_prefix(rest, f.sorts.len()).ok_or_else(|| f.bad_index_parse())?;
+      let (terms, rest) =
+        new_slice_prefix(rest, f.terms.len()).ok_or_else(|| f.bad_index_parse())?;
+      let (thms, _) = new_slice_prefix(rest, f.thms.len()).ok_or_else(|| f.bad_index_parse())?;
+      if self.replace(SourceLocs { sorts, terms, thms }).is_some() {
+        return Err(ParseError::DuplicateIndexTable {
+          p_index: u64_as_usize(f.header.p_index),
+          id: e.id,
+        })
+      }
+    }
+    Ok(())
+  }
+}
+
+make_index_trait! {
+  [<'a>, SourceLocs, HasSourceLocs, NoSourceLocs, get_source_locs, get_source_locs_mut]
+}
+impl<'a> NoSourceLocs for Option<SymbolNames<'a>> {}
+impl<'a> NoSourceLocs for Option<VarNames<'a>> {}
+impl<'a> NoSourceLocs for Option<HypNames<'a>> {}
+
+/// A basic index, usable for getting names of declarations and variables,
+/// and the source locations of declarations.
+pub type BasicIndex<'a> = (
+  Option<SymbolNames<'a>>,
+  (Option<VarNames<'a>>, (Option<HypNames<'a>>, Option<SourceLocs<'a>>)),
+);
 
 /// Return the raw command data (a pair `[(u8, u32)]`)
 /// while ensuring that an iterator which is literally empty
@@ -471,6 +519,7 @@ pub enum ParseError {
   /// An error with the provided message and location.
   StrError(&'static str, usize),
   /// An error in IO.
+  #[cfg(feature = "std")]
   IoError(io::Error),
 }
 
@@ -495,8 +544,8 @@ const HEADER_CAVEAT: &str = "\
     untrustworthy header, and should therefore be considered \
     suggestions for where to begin troubleshooting.";
 
-impl std::fmt::Display for ParseError {
-  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl core::fmt::Display for ParseError {
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
     match self {
       ParseError::BadProofLen(start) =>
         write!(f, "proof starting at byte {} has an incorrect length", start),
@@ -567,7 +616,7 @@ impl std::fmt::Display for ParseError {
       ),
       ParseError::DuplicateIndexTable { p_index, id } => {
         write!(f, "MMB index at {} contains a duplicate index entry for key = ", p_index)?;
-        match std::str::from_utf8(id) {
+        match core::str::from_utf8(id) {
           Ok(s) => write!(f, "'{}'", s)?,
           Err(_) => write!(f, "{:?}", id)?,
         }
@@ -585,17 +634,19 @@ impl std::fmt::Display for ParseError {
       ),
       ParseError::SorryError => write!(f, "Proof uses 'sorry'."),
       ParseError::StrError(s, _) => write!(f, "{}", s),
+      #[cfg(feature = "std")]
       ParseError::IoError(e) => write!(f, "{}", e),
     }
   }
 }
 
+#[cfg(feature = "std")]
 impl From<io::Error> for ParseError {
   fn from(e: io::Error) -> Self { Self::IoError(e) }
 }
 
 #[inline]
-fn new_slice_prefix<T: FromBytes>(bytes: &[u8], n: usize) -> Option<(&[T], &[u8])> {
+pub(crate) fn new_slice_prefix<T: FromBytes>(bytes: &[u8], n: usize) -> Option<(&[T], &[u8])> {
   let mid = mem::size_of::<T>().checked_mul(n)?;
   if mid <= bytes.len() {
     let (left, right) = bytes.split_at(mid);
@@ -927,7 +978,7 @@ macro_rules! str_list_wrapper {
     #[derive(Debug, Clone, Copy)]
     pub struct $ty<'a>(StrListRef<'a>);
 
-    impl<'a> std::ops::Deref for $ty<'a> {
+    impl<'a> core::ops::Deref for $ty<'a> {
       type Target = &'a [U64<LE>];
       fn deref(&self) -> &&'a [U64<LE>] { &self.0.strs }
     }
@@ -1038,6 +1089,75 @@ impl<'a, X: HasHypNames<'a>> MmbFile<'a, X> {
   }
 }
 
+/// The source location of a sort, term, or theorem, from the index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SourceLoc<'a> {
+  /// The path of the source file, relative to the directory it was compiled from.
+  pub file: &'a str,
+  /// The first line of the statement (1-based).
+  pub start_line: u32,
+  /// The last line of the statement (1-based, inclusive).
+  pub end_line: u32,
+}
+
+impl core::fmt::Display for SourceLoc<'_> {
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    write!(f, "{}:{}", self.file, self.start_line)?;
+    if self.end_line != self.start_line {
+      write!(f, "-{}", self.end_line)?
+    }
+    Ok(())
+  }
+}
+
+impl<'a, X: HasSourceLocs<'a>> MmbFile<'a, X> {
+  fn source_loc(&self, e: &SourceEntry) -> Option<SourceLoc<'a>> {
+    let p_file = u64_as_usize(e.p_file);
+    if p_file == 0 {
+      return None
+    }
+    let (file, _) = cstr_from_bytes_prefix(self.buf.get(p_file..)?)?;
+    Some(SourceLoc {
+      file: file.to_str().ok()?,
+      start_line: e.start_line.get(),
+      end_line: e.end_line.get(),
+    })
+  }
+
+  /// Get the source location of a sort, or `None` if the index does not exist
+  /// or does not know the location.
+  #[must_use]
+  pub fn sort_source(&self, n: SortId) -> Option<SourceLoc<'a>> {
+    self.source_loc(self.index.get_source_locs()?.sorts.get(usize::from(n.0))?)
+  }
+
+  /// Get the source location of a term, or `None` if the index does not exist
+  /// or does not know the location.
+  #[must_use]
+  pub fn term_source(&self, n: TermId) -> Option<SourceLoc<'a>> {
+    self.source_loc(self.index.get_source_locs()?.terms.get(u32_as_usize(n.0))?)
+  }
+
+  /// Get the source location of a theorem, or `None` if the index does not exist
+  /// or does not know the location.
+  #[must_use]
+  pub fn thm_source(&self, n: ThmId) -> Option<SourceLoc<'a>> {
+    self.source_loc(self.index.get_source_locs()?.thms.get(u32_as_usize(n.0))?)
+  }
+
+  /// Convenience function for getting a source location without having to destructure
+  /// the [`StmtCmd`] every time.
+  #[must_use]
+  pub fn stmt_source(&self, stmt: NumdStmtCmd) -> Option<SourceLoc<'a>> {
+    use crate::NumdStmtCmd::{Axiom, Sort, TermDef, Thm};
+    match stmt {
+      Sort { sort_id } => self.sort_source(sort_id),
+      Axiom { thm_id } | Thm { thm_id, .. } => self.thm_source(thm_id),
+      TermDef { term_id, .. } => self.term_source(term_id),
+    }
+  }
+}
+
 impl<'a> TermRef<'a> {
   /// Returns true if this is a `def`, false for a `term`.
   #[inline]
@@ -0,0 +1,530 @@
+//! Generation of random well-formed MMB files, for property testing.
+//!
+//! An [`ArbitraryMmb`] is built through the [`Mm0Writer`] from a stream of random bytes (using
+//! the [`arbitrary`] crate), and is intended to pass both [`MmbFile::validate`] and a proof
+//! checker. It contains some sorts with random modifiers, followed by a random sequence of:
+//!
+//! * terms, with random binders and return types;
+//! * definitions, whose values can contain dummy variables;
+//! * axioms, with random hypotheses and conclusion;
+//! * theorems, which either restate one of their hypotheses (possibly with a trivial conversion
+//!   proof) or instantiate an earlier axiom or theorem.
+//!
+//! [`MmbFile::validate`]: crate::MmbFile::validate
+
+use crate::{u32_as_usize, Arg, Mm0Writer, ProofCmd, SortData, UnifyCmd};
+use arbitrary::{Arbitrary, Result, Unstructured};
+use mm0_util::{Modifiers, SortId, TermId, ThmId};
+use std::collections::HashMap;
+use std::io::{self, Write};
+use zerocopy::AsBytes;
+
+/// A randomly generated well-formed MMB file.
+#[derive(Clone, Debug)]
+pub struct ArbitraryMmb {
+  /// The file contents, stored as words so that the buffer is suitably aligned for parsing
+  words: Vec<u64>,
+  /// The length of the file in bytes
+  len: usize,
+}
+
+impl ArbitraryMmb {
+  /// The contents of the generated file.
+  #[must_use]
+  pub fn bytes(&self) -> &[u8] { &self.words.as_bytes()[..self.len] }
+}
+
+impl<'a> Arbitrary<'a> for ArbitraryMmb {
+  fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+    let mut buf = vec![];
+    Gen { u, sorts: vec![], terms: vec![], thms: vec![], var_names: false }
+      .run(&mut buf)
+      .map_err(GenError::into_arbitrary)?;
+    let mut words = vec![0_u64; buf.len().div_ceil(8)];
+    words.as_bytes_mut()[..buf.len()].copy_from_slice(&buf);
+    Ok(ArbitraryMmb { words, len: buf.len() })
+  }
+}
+
+/// An error during generation: either the random source failed, or the writer did.
+/// (The latter cannot happen when writing to memory.)
+#[allow(variant_size_differences)]
+enum GenError {
+  Arbitrary(arbitrary::Error),
+  Io(io::Error),
+}
+
+type GenResult = std::result::Result<(), GenError>;
+
+impl From<arbitrary::Error> for GenError {
+  fn from(e: arbitrary::Error) -> Self { GenError::Arbitrary(e) }
+}
+
+impl From<io::Error> for GenError {
+  fn from(e: io::Error) -> Self { GenError::Io(e) }
+}
+
+impl GenError {
+  fn into_arbitrary(self) -> arbitrary::Error {
+    match self {
+      GenError::Arbitrary(e) => e,
+      GenError::Io(e) => panic!("writing to memory failed: {e}"),
+    }
+  }
+}
+
+/// A statement in a generated theorem. Variables are numbered in the order of the heap,
+/// that is, the arguments followed by the dummy variables (in definitions).
+#[derive(Clone, PartialEq, Eq, Hash)]
+#[allow(variant_size_differences)]
+enum Expr {
+  Var(u32),
+  App(TermId, Box<[Expr]>),
+}
+
+impl Expr {
+  /// Replace the variable `i` by `subst[i]`.
+  fn subst(&self, subst: &[Expr]) -> Expr {
+    match self {
+      &Expr::Var(i) => subst[u32_as_usize(i)].clone(),
+      Expr::App(t, es) => Expr::App(*t, es.iter().map(|e| e.subst(subst)).collect()),
+    }
+  }
+
+  /// Does this expression contain a local definition?
+  fn uses_local(&self, terms: &[Term]) -> bool {
+    match self {
+      Expr::Var(_) => false,
+      Expr::App(t, es) => terms[u32_as_usize(t.0)].local || es.iter().any(|e| e.uses_local(terms)),
+    }
+  }
+}
+
+/// A term or definition of the generated file.
+struct Term {
+  args: Vec<Arg>,
+  ret: Arg,
+  /// True for local definitions, which cannot appear in public statements
+  local: bool,
+}
+
+/// An axiom or theorem of the generated file.
+struct Thm {
+  args: Vec<Arg>,
+  hyps: Vec<Expr>,
+  concl: Expr,
+}
+
+/// The variables which are available when generating an expression.
+struct Ctx {
+  /// The variables which can appear in regular positions, with their sorts
+  leaves: Vec<(u32, u8)>,
+  /// The bound variables, which can appear in bound positions
+  bound: Vec<(u32, u8)>,
+  /// The number of arguments
+  nargs: u32,
+  /// True if local definitions can be used
+  local: bool,
+  /// In a definition, the sorts of the dummy variables introduced so far (which are numbered
+  /// after the arguments). This is `None` in theorem statements, which cannot have dummies.
+  dummies: Option<Vec<u8>>,
+}
+
+impl Ctx {
+  /// A context in which all the arguments can be used.
+  fn new(args: &[Arg], dummies: bool, local: bool) -> Self {
+    let mut cx = Ctx {
+      leaves: vec![],
+      bound: vec![],
+      nargs: args.len().try_into().expect("overflow"),
+      local,
+      dummies: if dummies { Some(vec![]) } else { None },
+    };
+    for (i, a) in (0..).zip(args) {
+      cx.leaves.push((i, a.sort().0));
+      if a.bound() {
+        cx.bound.push((i, a.sort().0))
+      }
+    }
+    cx
+  }
+}
+
+/// Writes expressions to a unify stream, without saving anything to the unify heap.
+struct UnifyWriter<'a> {
+  nargs: u32,
+  dummies: &'a [u8],
+  /// The unify heap index of each dummy variable seen so far
+  seen: HashMap<u32, u32>,
+}
+
+impl UnifyWriter<'_> {
+  fn expr(&mut self, w: &mut impl Write, e: &Expr) -> io::Result<()> {
+    match *e {
+      Expr::Var(i) if i < self.nargs => UnifyCmd::Ref(i).write_to(w),
+      Expr::Var(i) =>
+        if let Some(&n) = self.seen.get(&i) {
+          UnifyCmd::Ref(n).write_to(w)
+        } else {
+          let n = self.nargs + u32::try_from(self.seen.len()).expect("overflow");
+          self.seen.insert(i, n);
+          UnifyCmd::Dummy(SortId(self.dummies[u32_as_usize(i - self.nargs)])).write_to(w)
+        },
+      Expr::App(tid, ref es) => {
+        UnifyCmd::Term { tid, save: false }.write_to(w)?;
+        es.iter().try_for_each(|e| self.expr(w, e))
+      }
+    }
+  }
+}
+
+/// Writes expressions to a proof stream. Every compound expression is saved to the heap
+/// the first time it is built and referenced afterwards, so that equal expressions are
+/// always represented by the same pointer, as the verifier requires.
+struct ProofWriter<'a> {
+  dummies: &'a [u8],
+  nargs: u32,
+  /// The heap index of each expression built so far
+  heap: HashMap<Expr, u32>,
+  /// The size of the heap
+  next: u32,
+}
+
+impl<'a> ProofWriter<'a> {
+  fn new(nargs: u32, dummies: &'a [u8]) -> Self {
+    let heap = (0..nargs).map(|i| (Expr::Var(i), i)).collect();
+    ProofWriter { dummies, nargs, heap, next: nargs }
+  }
+
+  fn push(&mut self) -> u32 {
+    self.next += 1;
+    self.next - 1
+  }
+
+  fn expr(&mut self, w: &mut impl Write, e: &Expr) -> io::Result<()> {
+    if let Some(&i) = self.heap.get(e) {
+      return ProofCmd::Ref(i).write_to(w)
+    }
+    match *e {
+      Expr::Var(i) => ProofCmd::Dummy(SortId(self.dummies[u32_as_usize(i - self.nargs)])).write_to(w)?,
+      Expr::App(tid, ref es) => {
+        for e in &**es {
+          self.expr(w, e)?
+        }
+        ProofCmd::Term { tid, save: true }.write_to(w)?
+      }
+    }
+    let n = self.push();
+    self.heap.insert(e.clone(), n);
+    Ok(())
+  }
+
+  /// Build the expression `e` and add it as a hypothesis, returning its heap index.
+  fn hyp(&mut self, w: &mut impl Write, e: &Expr) -> io::Result<u32> {
+    self.expr(w, e)?;
+    ProofCmd::Hyp.write_to(w)?;
+    Ok(self.push())
+  }
+}
+
+/// Write the statement of a theorem to its unify stream.
+fn write_stmt(w: &mut impl Write, nargs: u32, hyps: &[Expr], concl: &Expr) -> io::Result<()> {
+  let mut uw = UnifyWriter { nargs, dummies: &[], seen: HashMap::new() };
+  uw.expr(w, concl)?;
+  for h in hyps.iter().rev() {
+    UnifyCmd::Hyp.write_to(w)?;
+    uw.expr(w, h)?
+  }
+  Ok(())
+}
+
+fn names(prefix: &str, n: usize) -> Vec<String> { (0..n).map(|i| format!("{prefix}{i}")).collect() }
+
+fn name_refs(names: &[String]) -> Vec<&str> { names.iter().map(|s| &**s).collect() }
+
+/// The maximum number of arguments of a generated term or theorem
+const MAX_ARGS: usize = 4;
+
+/// The maximum depth of a generated expression
+const MAX_DEPTH: u32 = 3;
+
+/// The generator state.
+struct Gen<'a, 'b> {
+  u: &'b mut Unstructured<'a>,
+  sorts: Vec<Modifiers>,
+  terms: Vec<Term>,
+  thms: Vec<Thm>,
+  /// True if variable and hypothesis names should be added to the index
+  var_names: bool,
+}
+
+impl Gen<'_, '_> {
+  fn run(mut self, out: &mut Vec<u8>) -> GenResult {
+    let mut w = Mm0Writer::new(vec![]);
+    self.var_names = self.u.arbitrary()?;
+    for i in 0..self.u.int_in_range(1..=4)? {
+      let mods = Modifiers::new(self.u.int_in_range(0..=15)?);
+      w.add_sort(Some(&format!("s{i}")), SortData(mods.bits()))?;
+      self.sorts.push(mods);
+    }
+    for _ in 0..self.u.int_in_range(0..=30)? {
+      match self.u.int_in_range(0..=3)? {
+        0 => self.term(&mut w)?,
+        1 => self.def(&mut w)?,
+        2 => self.axiom(&mut w)?,
+        _ => self.thm(&mut w)?,
+      }
+    }
+    w.finish(out)?;
+    Ok(())
+  }
+
+  /// Generate random binders. Bound variables are never in strict sorts, and regular variables
+  /// only depend on earlier bound variables.
+  fn binders(&mut self) -> Result<Vec<Arg>> {
+    let mut args = vec![];
+    let mut bv = 0;
+    for _ in 0..self.u.int_in_range(0..=MAX_ARGS)? {
+      let s = self.u.choose_index(self.sorts.len())?;
+      let mut arg = Arg::new_of_sort(s.try_into().expect("overflow"));
+      if !self.sorts[s].contains(Modifiers::STRICT) && self.u.arbitrary()? {
+        arg |= Arg::new(true);
+        arg.add_dep(bv);
+        bv += 1;
+      } else {
+        arg |= Arg::from(self.u.arbitrary::<u64>()? & ((1 << bv) - 1));
+      }
+      args.push(arg)
+    }
+    Ok(args)
+  }
+
+  /// Choose a random sort satisfying `f`.
+  fn sort(&mut self, f: impl Fn(Modifiers) -> bool) -> Result<Option<u8>> {
+    let sorts = (0..).zip(&self.sorts).filter(|p| f(*p.1)).map(|p| p.0).collect::<Vec<u8>>();
+    Ok(if sorts.is_empty() { None } else { Some(*self.u.choose(&sorts)?) })
+  }
+
+  /// Choose a variable for a bound position of sort `s`. In a definition, this can be a dummy
+  /// variable if the position does not appear in the type of the enclosing term.
+  fn bound_var(&mut self, cx: &mut Ctx, s: u8, in_ret: bool) -> Result<Option<Expr>> {
+    let mut vars = cx.bound.iter().filter(|v| v.1 == s).map(|v| v.0).collect::<Vec<_>>();
+    if let Some(dummies) = &mut cx.dummies {
+      if !in_ret && !self.sorts[usize::from(s)].intersects(Modifiers::STRICT | Modifiers::FREE) {
+        vars.extend((cx.nargs..).zip(&*dummies).filter(|v| *v.1 == s).map(|v| v.0));
+        if vars.is_empty() || self.u.ratio(1, 3)? {
+          dummies.push(s);
+          return Ok(Some(Expr::Var(cx.nargs + u32::try_from(dummies.len() - 1).expect("overflow"))))
+        }
+      }
+    }
+    Ok(if vars.is_empty() { None } else { Some(Expr::Var(*self.u.choose(&vars)?)) })
+  }
+
+  /// Generate a random expression of sort `s`, or `None` if we did not find one.
+  fn expr(&mut self, cx: &mut Ctx, s: u8, depth: u32) -> Result<Option<Expr>> {
+    let leaves = cx.leaves.iter().filter(|v| v.1 == s).map(|v| v.0).collect::<Vec<_>>();
+    let terms = (0..self.terms.len())
+      .filter(|&t| {
+        let t = &self.terms[t];
+        t.ret.sort().0 == s
+          && (cx.local || !t.local)
+          && (depth > 0 || t.args.iter().all(|a| a.bound()))
+      })
+      .collect::<Vec<_>>();
+    if terms.is_empty() || !leaves.is_empty() && self.u.arbitrary()? {
+      return Ok(if leaves.is_empty() { None } else { Some(Expr::Var(*self.u.choose(&leaves)?)) })
+    }
+    let t = *self.u.choose(&terms)?;
+    let (targs, ret) = (self.terms[t].args.clone(), self.terms[t].ret);
+    let mut args = vec![];
+    let mut bv = 0;
+    for a in targs {
+      let e = if a.bound() {
+        bv += 1;
+        self.bound_var(cx, a.sort().0, ret.depends_on(bv - 1))?
+      } else {
+        self.expr(cx, a.sort().0, depth.saturating_sub(1))?
+      };
+      match e {
+        Some(e) => args.push(e),
+        None => return Ok(None),
+      }
+    }
+    Ok(Some(Expr::App(TermId(t.try_into().expect("overflow")), args.into())))
+  }
+
+  /// Generate some random hypotheses for a theorem.
+  fn hyps(&mut self, cx: &mut Ctx) -> Result<Vec<Expr>> {
+    let mut hyps = vec![];
+    for _ in 0..self.u.int_in_range(0..=3)? {
+      if let Some(s) = self.sort(|m| m.contains(Modifiers::PROVABLE))? {
+        hyps.extend(self.expr(cx, s, MAX_DEPTH)?)
+      }
+    }
+    Ok(hyps)
+  }
+
+  fn term(&mut self, w: &mut Mm0Writer<Vec<u8>>) -> GenResult {
+    let args = self.binders()?;
+    if let Some(s) = self.sort(|m| !m.contains(Modifiers::PURE))? {
+      let bvs = args.iter().filter(|a| a.bound()).count();
+      let ret = Arg::new_of_sort(s) | Arg::from(self.u.arbitrary::<u64>()? & ((1 << bvs) - 1));
+      let tid = w.add_term(Some(&format!("t{}", self.terms.len())), &args, ret)?;
+      if self.var_names {
+        w.set_term_vars(tid, &name_refs(&names("v", args.len())))
+      }
+      self.terms.push(Term { args, ret, local: false })
+    }
+    Ok(())
+  }
+
+  fn def(&mut self, w: &mut Mm0Writer<Vec<u8>>) -> GenResult {
+    let args = self.binders()?;
+    let local = self.u.arbitrary()?;
+    let mut cx = Ctx::new(&args, true, local);
+    let val = match self.sort(|m| !m.contains(Modifiers::PURE))? {
+      Some(s) => self.expr(&mut cx, s, MAX_DEPTH)?.map(|val| (s, val)),
+      None => None,
+    };
+    if let Some((s, val)) = val {
+      // The value of a definition may depend on all of its bound variables
+      let mut ret = Arg::new_of_sort(s);
+      for a in &args {
+        if a.bound() {
+          ret |= Arg::from(a.deps_unchecked())
+        }
+      }
+      let dummies = cx.dummies.unwrap_or_default();
+      let mut b = w.add_def(local, Some(&format!("t{}", self.terms.len())), &args, ret);
+      let mut uw = UnifyWriter { nargs: cx.nargs, dummies: &dummies, seen: HashMap::new() };
+      uw.expr(b.unify(), &val)?;
+      ProofWriter::new(cx.nargs, &dummies).expr(b.proof(), &val)?;
+      let tid = b.finish()?;
+      if self.var_names {
+        let mut vars = names("v", args.len());
+        vars.extend(names("d", dummies.len()));
+        w.set_term_vars(tid, &name_refs(&vars))
+      }
+      self.terms.push(Term { args, ret, local });
+    }
+    Ok(())
+  }
+
+  /// Add a theorem or axiom with the given statement. The `proof` function is called with the
+  /// heap indices of the hypotheses, and writes the rest of the proof.
+  fn add_thm(
+    &mut self, w: &mut Mm0Writer<Vec<u8>>, axiom: bool, local: bool, thm: Thm,
+    proof: impl FnOnce(&mut ProofWriter<'_>, &mut Vec<u8>, &[u32]) -> io::Result<()>,
+  ) -> GenResult {
+    let name = format!("T{}", self.thms.len());
+    let mut b = if axiom {
+      w.add_axiom(Some(&name), &thm.args)
+    } else {
+      w.add_thm(local, Some(&name), &thm.args)
+    };
+    let nargs = thm.args.len().try_into().expect("overflow");
+    write_stmt(b.unify(), nargs, &thm.hyps, &thm.concl)?;
+    let mut pw = ProofWriter::new(nargs, &[]);
+    let mut buf = vec![];
+    let hyps = thm.hyps.iter().map(|h| pw.hyp(&mut buf, h)).collect::<io::Result<Vec<_>>>()?;
+    proof(&mut pw, &mut buf, &hyps)?;
+    b.proof().write_all(&buf)?;
+    let tid = b.finish()?;
+    if self.var_names {
+      let (vars, hyps) = (names("v", thm.args.len()), names("h", thm.hyps.len()));
+      w.set_thm_vars(tid, &name_refs(&vars), &name_refs(&hyps))
+    }
+    self.thms.push(thm);
+    Ok(())
+  }
+
+  fn axiom(&mut self, w: &mut Mm0Writer<Vec<u8>>) -> GenResult {
+    let args = self.binders()?;
+    let mut cx = Ctx::new(&args, false, false);
+    let hyps = self.hyps(&mut cx)?;
+    let concl = match self.sort(|m| m.contains(Modifiers::PROVABLE))? {
+      Some(s) => self.expr(&mut cx, s, MAX_DEPTH)?,
+      None => None,
+    };
+    match concl {
+      Some(concl) => {
+        let thm = Thm { args, hyps, concl: concl.clone() };
+        self.add_thm(w, true, false, thm, |pw, buf, _| pw.expr(buf, &concl))
+      }
+      None => Ok(()),
+    }
+  }
+
+  fn thm(&mut self, w: &mut Mm0Writer<Vec<u8>>) -> GenResult {
+    if self.thms.is_empty() || self.u.arbitrary()? {
+      self.thm_by_hyp(w)
+    } else {
+      self.thm_by_inst(w)
+    }
+  }
+
+  /// A theorem which restates one of its hypotheses.
+  fn thm_by_hyp(&mut self, w: &mut Mm0Writer<Vec<u8>>) -> GenResult {
+    let args = self.binders()?;
+    let local = self.u.arbitrary()?;
+    let mut cx = Ctx::new(&args, false, local);
+    let hyps = self.hyps(&mut cx)?;
+    if hyps.is_empty() {
+      return Ok(())
+    }
+    let i = self.u.choose_index(hyps.len())?;
+    let concl = hyps[i].clone();
+    let conv = self.u.arbitrary()?;
+    self.add_thm(w, false, local, Thm { args, hyps, concl: concl.clone() }, |pw, buf, hyps| {
+      if conv {
+        pw.expr(buf, &concl)?;
+        ProofCmd::Ref(hyps[i]).write_to(buf)?;
+        ProofCmd::Conv.write_to(buf)?;
+        ProofCmd::Sym.write_to(buf)?;
+        ProofCmd::Refl.write_to(buf)?;
+      } else {
+        ProofCmd::Ref(hyps[i]).write_to(buf)?;
+      }
+      Ok(())
+    })
+  }
+
+  /// A theorem which instantiates the regular variables of an earlier theorem.
+  fn thm_by_inst(&mut self, w: &mut Mm0Writer<Vec<u8>>) -> GenResult {
+    let t = self.u.choose_index(self.thms.len())?;
+    let args = self.thms[t].args.clone();
+    let mut local = self.u.arbitrary()?;
+    let mut subst = vec![];
+    for (i, a) in (0..).zip(&args) {
+      // A regular variable can be replaced by anything depending on a subset of its dependencies
+      let e = if !a.bound() && self.u.arbitrary()? {
+        let deps = a.deps_unchecked();
+        let mut cx = Ctx::new(&args, false, local);
+        cx.leaves.retain(|v| args[u32_as_usize(v.0)].deps_unchecked() & !deps == 0);
+        self.expr(&mut cx, a.sort().0, MAX_DEPTH - 1)?
+      } else {
+        None
+      };
+      subst.push(e.unwrap_or(Expr::Var(i)))
+    }
+    let mut cx = Ctx::new(&args, false, local);
+    let mut hyps = self.hyps(&mut cx)?;
+    let extra = hyps.len();
+    hyps.extend(self.thms[t].hyps.iter().map(|h| h.subst(&subst)));
+    let concl = self.thms[t].concl.subst(&subst);
+    // The statement of the instantiated theorem may mention local definitions
+    local |= hyps.iter().chain([&concl]).any(|e| e.uses_local(&self.terms));
+    self.add_thm(w, false, local, Thm { args, hyps, concl: concl.clone() }, |pw, buf, hyps| {
+      for &h in &hyps[extra..] {
+        ProofCmd::Ref(h).write_to(buf)?
+      }
+      for e in &subst {
+        pw.expr(buf, e)?
+      }
+      pw.expr(buf, &concl)?;
+      ProofCmd::Thm { tid: ThmId(t.try_into().expect("overflow")), save: false }.write_to(buf)?;
+      Ok(())
+    })
+  }
+}
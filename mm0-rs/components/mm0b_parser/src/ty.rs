@@ -28,7 +28,7 @@ pub struct Type(U64<LE>);
 /// Newtype for `Type` that makes some situations easier to read.
 pub type Arg = Type;
 
-impl std::default::Default for Type {
+impl core::default::Default for Type {
   fn default() -> Self { Type(U64::new(0)) }
 }
 
@@ -155,25 +155,25 @@ impl Type {
   pub fn disjoint(self, other: Self) -> bool { (self.0.get() & other.0.get()) == 0 }
 }
 
-impl std::ops::BitAnd<Type> for Type {
+impl core::ops::BitAnd<Type> for Type {
   type Output = Self;
   fn bitand(self, rhs: Self) -> Self::Output { Type::from(self.0.get() & rhs.0.get()) }
 }
 
-impl std::ops::BitAndAssign<Type> for Type {
+impl core::ops::BitAndAssign<Type> for Type {
   fn bitand_assign(&mut self, other: Self) { self.0.set(self.0.get() & other.0.get()) }
 }
 
-impl std::ops::BitOr<Type> for Type {
+impl core::ops::BitOr<Type> for Type {
   type Output = Self;
   fn bitor(self, rhs: Self) -> Self::Output { Type::from(self.0.get() | rhs.0.get()) }
 }
 
-impl std::ops::BitOrAssign<Type> for Type {
+impl core::ops::BitOrAssign<Type> for Type {
   fn bitor_assign(&mut self, other: Self) { self.0.set(self.0.get() | other.0.get()) }
 }
 
-impl std::ops::Not for Type {
+impl core::ops::Not for Type {
   type Output = Type;
   fn not(self) -> Self::Output { Type::from(!self.0.get()) }
 }
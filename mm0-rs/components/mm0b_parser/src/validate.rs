@@ -0,0 +1,469 @@
+//! Structural validation of MMB files.
+use crate::{
+  cmd, cstr_from_bytes_prefix, new_slice_prefix, parse_cmd, u32_as_usize, u64_as_usize, Arg,
+  Header, MmbFile, NameEntry, ParseError, ProofCmd, SourceEntry, StmtCmd, TableEntry, TermEntry,
+  ThmEntry, UnifyCmd, UnifyIter, MAX_BOUND_VARS,
+};
+use alloc::{format, string::String, vec, vec::Vec};
+use byteorder::LE;
+use core::mem::size_of;
+use mm0_util::{Modifiers, TermId, ThmId};
+use zerocopy::{FromBytes, LayoutVerified, U64};
+
+/// A structural problem in an MMB file, found by [`MmbFile::validate`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationError {
+  /// The byte offset of the problem in the file.
+  pub pos: usize,
+  /// A description of the problem.
+  pub msg: String,
+}
+
+impl core::fmt::Display for ValidationError {
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    write!(f, "at {:#x}: {}", self.pos, self.msg)
+  }
+}
+
+/// The offset of a field of the header in the file.
+fn header_pos<T>(h: &Header, field: &T) -> usize {
+  <*const T>::cast::<u8>(field) as usize - <*const Header>::cast::<u8>(h) as usize
+}
+
+/// The state of [`MmbFile::validate`].
+struct Validator<'a, 'b, X> {
+  file: &'b MmbFile<'a, X>,
+  errors: Vec<ValidationError>,
+  /// The position of the statement declaring each sort, once found.
+  sort_stmts: Vec<Option<usize>>,
+  /// The position of the statement declaring each term, once found.
+  term_stmts: Vec<Option<usize>>,
+  /// The position of the statement declaring each theorem, once found.
+  thm_stmts: Vec<Option<usize>>,
+  /// The number of terms that have to be declared before each theorem,
+  /// because the theorem statement uses them.
+  thm_terms: Vec<u32>,
+}
+
+impl<'a, X> Validator<'a, '_, X> {
+  fn err(&mut self, pos: usize, msg: impl Into<String>) {
+    self.errors.push(ValidationError { pos, msg: msg.into() })
+  }
+
+  /// A description of the `n`-th entry of the name and source location tables,
+  /// which list the sorts, then the terms, then the theorems.
+  fn item(&self, n: usize) -> (String, Option<usize>) {
+    let (num_sorts, num_terms) = (self.sort_stmts.len(), self.term_stmts.len());
+    if n < num_sorts {
+      (format!("sort {}", n), self.sort_stmts[n])
+    } else if n < num_sorts + num_terms {
+      (format!("term {}", n - num_sorts), self.term_stmts[n - num_sorts])
+    } else {
+      let n = n - num_sorts - num_terms;
+      (format!("theorem {}", n), self.thm_stmts.get(n).copied().flatten())
+    }
+  }
+
+  fn header(&mut self) {
+    let h = &self.file.header;
+    if h.reserved != [0; 2] {
+      self.err(header_pos(h, &h.reserved), "reserved header bytes are nonzero")
+    }
+    if h.reserved2 != [0; 4] {
+      self.err(header_pos(h, &h.reserved2), "reserved header bytes are nonzero")
+    }
+    if h.num_sorts > 128 {
+      self.err(header_pos(h, &h.num_sorts), format!("too many sorts ({})", h.num_sorts))
+    }
+    for (i, &sd) in self.file.sorts.iter().enumerate() {
+      if Modifiers::try_from(sd).is_err() {
+        self.err(size_of::<Header>() + i, format!("sort {}: invalid modifiers {:#04x}", i, sd.0))
+      }
+    }
+  }
+
+  /// Check an argument list, with `ret` being the return type of a term.
+  fn args(&mut self, what: &str, pos: usize, args: &[Arg], ret: Option<Arg>) {
+    let mut bv = 0;
+    for (i, &arg) in args.iter().chain(&ret).enumerate() {
+      let pos = pos + i * size_of::<Arg>();
+      let name = if i == args.len() { "return type".into() } else { format!("argument {}", i) };
+      if usize::from(arg.sort().0) >= self.file.sorts.len() {
+        self.err(pos, format!("{}: {} has unknown sort {}", what, name, arg.sort().0))
+      }
+      match (i == args.len(), arg.bound()) {
+        (true, true) => self.err(pos, format!("{}: return type is marked as bound", what)),
+        (false, true) if bv >= MAX_BOUND_VARS =>
+          self.err(pos, format!("{}: too many bound variables", what)),
+        (false, true) if arg.deps_unchecked() != 1 << bv =>
+          self.err(pos, format!("{}: {} should be bound variable {}", what, name, bv)),
+        (_, false) if arg.deps_unchecked() >> bv != 0 =>
+          self.err(pos, format!("{}: {} depends on unknown bound variables", what, name)),
+        _ => {}
+      }
+      bv += usize::from(arg.bound())
+    }
+  }
+
+  /// Check that a unify stream decodes completely. `heap` is the initial size of the
+  /// unify heap, and `num_terms` is the number of terms declared before this one, if this is
+  /// a definition. Returns the number of terms that the stream uses.
+  fn unify(
+    &mut self, what: &str, mut it: UnifyIter<'a>, mut heap: usize, num_terms: Option<u32>,
+  ) -> u32 {
+    let mut used = 0;
+    loop {
+      let pos = it.pos;
+      match it.next() {
+        None => return used,
+        Some(Err(ParseError::UnifyCmdConv(cmd, data))) => {
+          self.err(pos, format!("{}: invalid unify command ({:#04x}, {})", what, cmd, data));
+          return used
+        }
+        Some(Err(_)) => {
+          self.err(pos, format!("{}: unify stream is truncated", what));
+          return used
+        }
+        Some(Ok(UnifyCmd::Term { tid, save })) => {
+          match num_terms {
+            _ if u32_as_usize(tid.0) >= self.file.terms.len() =>
+              self.err(pos, format!("{}: unify stream uses unknown term {}", what, tid.0)),
+            Some(n) if tid.0 >= n =>
+              self.err(pos, format!("{}: unify stream uses term {}, declared later", what, tid.0)),
+            _ => {}
+          }
+          used = used.max(tid.0 + 1);
+          if save {
+            heap += 1
+          }
+        }
+        Some(Ok(UnifyCmd::Ref(i))) =>
+          if u32_as_usize(i) >= heap {
+            self.err(pos, format!("{}: unify stream uses unknown heap element {}", what, i))
+          },
+        Some(Ok(UnifyCmd::Dummy(s))) => {
+          if usize::from(s.0) >= self.file.sorts.len() {
+            self.err(pos, format!("{}: unify stream uses unknown sort {}", what, s.0))
+          }
+          heap += 1
+        }
+        Some(Ok(UnifyCmd::Hyp)) =>
+          if num_terms.is_some() {
+            self.err(pos, format!("{}: definition has a hypothesis", what))
+          },
+      }
+    }
+  }
+
+  fn terms(&mut self) {
+    let file = self.file;
+    let p_terms = u32_as_usize(file.header.p_terms.get());
+    for (i, e) in (0..).zip(file.terms) {
+      let what = format!("term {}", i);
+      let pos = p_terms + u32_as_usize(i) * size_of::<TermEntry>();
+      if e.reserved != 0 {
+        self.err(pos + 3, format!("{}: reserved byte is nonzero", what))
+      }
+      if let Some(td) = file.term(TermId(i)) {
+        self.args(&what, u32_as_usize(e.p_args.get()), td.args(), Some(td.ret()));
+        if td.def() {
+          self.unify(&what, td.unify(), td.args().len(), Some(i));
+        }
+      } else {
+        self.err(pos + 4, format!("{}: argument list is out of range or misaligned", what))
+      }
+    }
+  }
+
+  fn thms(&mut self) {
+    let file = self.file;
+    let p_thms = u32_as_usize(file.header.p_thms.get());
+    for (i, e) in (0..).zip(file.thms) {
+      let what = format!("theorem {}", i);
+      let pos = p_thms + u32_as_usize(i) * size_of::<ThmEntry>();
+      if e.reserved != [0; 2] {
+        self.err(pos + 2, format!("{}: reserved bytes are nonzero", what))
+      }
+      let used = if let Some(td) = file.thm(ThmId(i)) {
+        self.args(&what, u32_as_usize(e.p_args.get()), td.args(), None);
+        self.unify(&what, td.unify(), td.args().len(), None)
+      } else {
+        self.err(pos + 4, format!("{}: argument list is out of range or misaligned", what));
+        0
+      };
+      self.thm_terms.push(used)
+    }
+  }
+
+  /// Check that the proof stream `pos..end` decodes completely, and only refers to
+  /// the first `num_terms` terms and `num_thms` theorems.
+  fn proof(&mut self, what: &str, mut pos: usize, end: usize, num_terms: u32, num_thms: u32) {
+    loop {
+      let (cmd, data, next) = match parse_cmd(self.file.buf, pos) {
+        Ok(res) if res.2 <= end => res,
+        _ => return self.err(pos, format!("{}: proof stream is truncated", what)),
+      };
+      if cmd == 0 {
+        if next != end {
+          self.err(next, format!("{}: proof stream ends before the statement", what))
+        }
+        return
+      }
+      match ProofCmd::try_from((cmd, data)) {
+        Err(_) => {
+          self.err(pos, format!("{}: invalid proof command ({:#04x}, {})", what, cmd, data));
+          return
+        }
+        Ok(ProofCmd::Term { tid, .. }) if tid.0 >= num_terms =>
+          self.err(pos, format!("{}: proof uses term {}, which is not declared yet", what, tid.0)),
+        Ok(ProofCmd::Thm { tid, .. }) if tid.0 >= num_thms => self
+          .err(pos, format!("{}: proof uses theorem {}, which is not declared yet", what, tid.0)),
+        Ok(ProofCmd::Dummy(s)) if usize::from(s.0) >= self.file.sorts.len() =>
+          self.err(pos, format!("{}: proof uses unknown sort {}", what, s.0)),
+        Ok(_) => {}
+      }
+      pos = next
+    }
+  }
+
+  fn decls(&mut self) {
+    let file = self.file;
+    let mut pos = u32_as_usize(file.header.p_proof.get());
+    let (mut sorts, mut terms, mut thms) = (0, 0, 0);
+    let stream_end = loop {
+      let (cmd, len, start) = match parse_cmd(file.buf, pos) {
+        Ok(res) => res,
+        Err(_) => return self.err(pos, "declaration stream is truncated"),
+      };
+      if cmd == 0 {
+        break start
+      }
+      let end = pos + u32_as_usize(len);
+      if end < start || end > file.buf.len() {
+        return self.err(pos, format!("statement has invalid length {}", len))
+      }
+      match StmtCmd::try_from(cmd) {
+        Err(_) => self.err(pos, format!("invalid statement command {:#04x}", cmd)),
+        Ok(StmtCmd::Sort) => {
+          match self.sort_stmts.get_mut(sorts) {
+            Some(stmt) => *stmt = Some(pos),
+            None => self.err(pos, format!("sort {} is not in the header", sorts)),
+          }
+          if start != end {
+            self.err(start, format!("sort {}: sort statement has a proof", sorts))
+          }
+          sorts += 1
+        }
+        Ok(StmtCmd::TermDef { .. }) => {
+          let what = format!("term {}", terms);
+          match self.term_stmts.get_mut(u32_as_usize(terms)) {
+            Some(stmt) => *stmt = Some(pos),
+            None => self.err(pos, format!("{} is not in the term table", what)),
+          }
+          match file.term(TermId(terms)) {
+            Some(td) if td.def() =>
+              if start == end {
+                self.err(pos, format!("{}: def statement has no value", what))
+              } else {
+                self.proof(&what, start, end, terms, thms)
+              },
+            Some(_) if start != end =>
+              self.err(pos, format!("{}: term statement has a value, but is not a def", what)),
+            _ => {}
+          }
+          terms += 1
+        }
+        Ok(StmtCmd::Axiom | StmtCmd::Thm { .. }) => {
+          let what = format!("theorem {}", thms);
+          match self.thm_stmts.get_mut(u32_as_usize(thms)) {
+            Some(stmt) => *stmt = Some(pos),
+            None => self.err(pos, format!("{} is not in the theorem table", what)),
+          }
+          if let Some(&used) = self.thm_terms.get(u32_as_usize(thms)) {
+            if used > terms {
+              self.err(pos, format!("{}: statement uses term {}, declared later", what, used - 1))
+            }
+          }
+          self.proof(&what, start, end, terms, thms);
+          thms += 1
+        }
+      }
+      pos = end
+    };
+    let p_index = u64_as_usize(file.header.p_index);
+    if p_index != 0 && stream_end > p_index {
+      self.err(pos, "declaration stream overlaps the index")
+    }
+    let p_terms = u32_as_usize(file.header.p_terms.get());
+    let p_thms = u32_as_usize(file.header.p_thms.get());
+    let missing = (self.sort_stmts.iter().enumerate())
+      .map(|(i, stmt)| (stmt, size_of::<Header>() + i, format!("sort {}", i)))
+      .chain(self.term_stmts.iter().enumerate().map(|(i, stmt)| {
+        (stmt, p_terms + i * size_of::<TermEntry>(), format!("term {}", i))
+      }))
+      .chain(self.thm_stmts.iter().enumerate().map(|(i, stmt)| {
+        (stmt, p_thms + i * size_of::<ThmEntry>(), format!("theorem {}", i))
+      }))
+      .filter(|(stmt, _, _)| stmt.is_none())
+      .map(|(_, pos, what)| ValidationError { pos, msg: format!("{} is never declared", what) })
+      .collect::<Vec<_>>();
+    self.errors.extend(missing)
+  }
+
+  /// Get a table of `n` entries at `ptr`, from the index entry at `pos`.
+  fn table<T: FromBytes>(&mut self, pos: usize, what: &str, ptr: usize, n: usize) -> &'a [T] {
+    if let Some((table, _)) = self.file.buf.get(ptr..).and_then(|s| new_slice_prefix(s, n)) {
+      table
+    } else {
+      self.err(pos, format!("{} table at {:#x} is out of range or misaligned", what, ptr));
+      &[]
+    }
+  }
+
+  /// Check that there is a UTF-8 C string at `ptr`, referenced at `pos`.
+  fn cstr(&mut self, pos: usize, what: &str, ptr: usize) {
+    match self.file.buf.get(ptr..).and_then(cstr_from_bytes_prefix) {
+      None => self.err(pos, format!("{} at {:#x} is not a nul-terminated string", what, ptr)),
+      Some((s, _)) =>
+        if s.to_str().is_err() {
+          self.err(ptr, format!("{} is not valid UTF-8", what))
+        },
+    }
+  }
+
+  fn names(&mut self, pos: usize, ptr: usize) {
+    let n = self.sort_stmts.len() + self.term_stmts.len() + self.thm_stmts.len();
+    for (i, e) in self.table::<NameEntry>(pos, "name", ptr, n).iter().enumerate() {
+      let pos = ptr + i * size_of::<NameEntry>();
+      let (what, stmt) = self.item(i);
+      match u64_as_usize(e.p_name) {
+        0 => self.err(pos, format!("{} has no name", what)),
+        p_name => self.cstr(pos, &format!("name of {}", what), p_name),
+      }
+      let p_proof = u64_as_usize(e.p_proof);
+      match stmt {
+        Some(stmt) if p_proof != 0 && p_proof != stmt => self.err(pos, format!(
+          "name entry for {} points to {:#x}, but it is declared at {:#x}",
+          what, p_proof, stmt
+        )),
+        _ => {}
+      }
+    }
+  }
+
+  /// Check a table of `n` string lists, describing the items starting at `first`
+  /// in the numbering of [`Validator::item`].
+  fn str_lists(&mut self, pos: usize, what: &str, ptr: usize, first: usize, n: usize) {
+    for (i, p_list) in self.table::<U64<LE>>(pos, what, ptr, n).iter().enumerate() {
+      let pos = ptr + i * size_of::<U64<LE>>();
+      let what = format!("{} list of {}", what, self.item(first + i).0);
+      let p_list = u64_as_usize(*p_list);
+      if p_list == 0 {
+        continue
+      }
+      let list = self.file.buf.get(p_list..).and_then(|s| {
+        let (len, rest) = LayoutVerified::<_, U64<LE>>::new_unaligned_from_prefix(s)?;
+        new_slice_prefix::<U64<LE>>(rest, len.get().try_into().ok()?)
+      });
+      match list {
+        None => self.err(pos, format!("{} at {:#x} is out of range", what, p_list)),
+        Some((strs, _)) =>
+          for (j, p_str) in strs.iter().enumerate() {
+            match u64_as_usize(*p_str) {
+              0 => {}
+              p_str => self.cstr(p_list + (j + 1) * size_of::<U64<LE>>(), &what, p_str),
+            }
+          },
+      }
+    }
+  }
+
+  fn source_locs(&mut self, pos: usize, ptr: usize) {
+    let n = self.sort_stmts.len() + self.term_stmts.len() + self.thm_stmts.len();
+    for (i, e) in self.table::<SourceEntry>(pos, "source location", ptr, n).iter().enumerate() {
+      let pos = ptr + i * size_of::<SourceEntry>();
+      let what = self.item(i).0;
+      let p_file = u64_as_usize(e.p_file);
+      if p_file == 0 {
+        continue
+      }
+      self.cstr(pos, &format!("source file of {}", what), p_file);
+      let (start, end) = (e.start_line.get(), e.end_line.get());
+      if start == 0 || end < start {
+        self.err(pos, format!("{}: invalid line range {}-{}", what, start, end))
+      }
+    }
+  }
+
+  fn index(&mut self) {
+    let file = self.file;
+    let p_index = u64_as_usize(file.header.p_index);
+    if p_index == 0 {
+      return
+    }
+    let entries = file.buf.get(p_index..).and_then(|s| {
+      let (len, rest) = LayoutVerified::<_, U64<LE>>::new_unaligned_from_prefix(s)?;
+      new_slice_prefix::<TableEntry>(rest, len.get().try_into().ok()?)
+    });
+    let entries = if let Some((entries, _)) = entries { entries } else {
+      return self.err(p_index, "index is out of range or misaligned")
+    };
+    let (num_sorts, num_terms) = (file.sorts.len(), file.terms.len());
+    let mut seen = vec![];
+    for (i, e) in entries.iter().enumerate() {
+      let pos = p_index + size_of::<U64<LE>>() + i * size_of::<TableEntry>();
+      let known =
+        [cmd::INDEX_NAME, cmd::INDEX_VAR_NAME, cmd::INDEX_HYP_NAME, cmd::INDEX_SOURCE];
+      if known.contains(&e.id) {
+        if seen.contains(&e.id) {
+          self.err(pos, format!("duplicate index table {}", String::from_utf8_lossy(&e.id)));
+          continue
+        }
+        seen.push(e.id)
+      }
+      let ptr = u64_as_usize(e.ptr);
+      match e.id {
+        cmd::INDEX_NAME => self.names(pos, ptr),
+        cmd::INDEX_VAR_NAME =>
+          self.str_lists(pos, "variable name", ptr, num_sorts, num_terms + file.thms.len()),
+        cmd::INDEX_HYP_NAME =>
+          self.str_lists(pos, "hypothesis name", ptr, num_sorts + num_terms, file.thms.len()),
+        cmd::INDEX_SOURCE => self.source_locs(pos, ptr),
+        _ => {}
+      }
+    }
+  }
+}
+
+impl<'a, X> MmbFile<'a, X> {
+  /// Check every structural property of the file, without verifying the proofs:
+  ///
+  /// * the reserved bytes of the header and the tables are zero, and the sorts are valid;
+  /// * every term and theorem entry parses, with arguments of known sorts and consistent
+  ///   bound variables, and a unify stream that decodes completely;
+  /// * every proof in the declaration stream decodes completely, and the statements agree
+  ///   with the term and theorem tables and only refer to earlier declarations;
+  /// * the index tables are in bounds, and the names in them are valid C strings.
+  ///
+  /// Returns every problem found along with its byte offset, or an empty vector if the file
+  /// is well formed. Because [`MmbFile::parse`] fails on a malformed index unless it is
+  /// ignored, a file should be parsed as a [`BareMmbFile`](crate::BareMmbFile)
+  /// to get a full report; this function reads the index directly.
+  #[must_use]
+  pub fn validate(&self) -> Vec<ValidationError> {
+    let mut v = Validator {
+      file: self,
+      errors: vec![],
+      sort_stmts: vec![None; self.sorts.len()],
+      term_stmts: vec![None; self.terms.len()],
+      thm_stmts: vec![None; self.thms.len()],
+      thm_terms: Vec::with_capacity(self.thms.len()),
+    };
+    v.header();
+    v.terms();
+    v.thms();
+    v.decls();
+    v.index();
+    v.errors
+  }
+}
@@ -1,12 +1,14 @@
 #[allow(clippy::wildcard_imports)]
 use crate::cmd::*;
 use crate::{
-  Arg, HasSymbolNames, Header, MmbFile, NameEntryRef, ProofCmd, SortData, TableEntry, TermEntry,
-  ThmEntry, UnifyCmd,
+  Arg, HasSymbolNames, Header, MmbFile, NameEntry, NameEntryRef, ProofCmd, SortData, TableEntry,
+  TermEntry, ThmEntry, UnifyCmd,
 };
 use byteorder::{WriteBytesExt, LE};
 use mm0_util::{u32_as_usize, SortId, SortVec, TermId, TermVec, ThmId, ThmVec};
-use std::io::{self, Cursor, Read, Write};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
 use zerocopy::{AsBytes, U32};
 
 /// Encode the command `cmd` (one of the `STMT_*`, `PROOF_*` or `UNIFY_*` commands
@@ -110,6 +112,17 @@ impl Reopen for Vec<u8> {
   fn reopen(self) -> io::Result<Self::Reopened> { Ok(Cursor::new(self)) }
 }
 
+/// A file can be used as a `Reopen` writer, by seeking back to the start. The file should be
+/// empty when it is passed to [`Mm0Writer::new`], and it must be opened for both reading and
+/// writing.
+impl Reopen for File {
+  type Reopened = Self;
+  fn reopen(mut self) -> io::Result<Self::Reopened> {
+    self.seek(SeekFrom::Start(0))?;
+    Ok(self)
+  }
+}
+
 #[derive(Debug)]
 struct TrackSize<W>(W, usize);
 
@@ -148,6 +161,16 @@ pub struct Mm0Writer<W> {
   sort_names: SortVec<(usize, usize)>,
   term_names: TermVec<(usize, usize)>,
   thm_names: ThmVec<(usize, usize)>,
+  /// The variable names of each term, as offsets into `names_buf`.
+  term_vars: TermVec<Vec<usize>>,
+  /// The variable names of each theorem, as offsets into `names_buf`.
+  thm_vars: ThmVec<Vec<usize>>,
+  /// The hypothesis names of each theorem, as offsets into `names_buf`.
+  thm_hyps: ThmVec<Vec<usize>>,
+  /// The offsets of the variable and hypothesis names in `names_buf`, which are shared between
+  /// declarations. This is empty if no variable names have been set, in which case the variable
+  /// and hypothesis name tables are omitted from the index.
+  var_names: HashMap<String, usize>,
 }
 
 fn push_name(buf: &mut Vec<u8>, name: Option<&str>) -> usize {
@@ -184,6 +207,10 @@ impl<W: Reopen> Mm0Writer<W> {
       sort_names: Default::default(),
       term_names: Default::default(),
       thm_names: Default::default(),
+      term_vars: Default::default(),
+      thm_vars: Default::default(),
+      thm_hyps: Default::default(),
+      var_names: Default::default(),
     }
   }
 
@@ -216,6 +243,7 @@ impl<W: Reopen> Mm0Writer<W> {
       t.p_args.set(self.term_thm_buf.len().try_into().expect("overflow"));
       self.term_thm_buf.extend_from_slice(&mmb.buf[start..end]);
       self.term_names.push(push_entry(&mut self.names_buf, mmb.term_index(id)));
+      self.term_vars.push(vec![]);
     }
     for (id, t) in self.thms.enum_iter_mut() {
       let start = u32_as_usize(t.p_args.get());
@@ -223,6 +251,8 @@ impl<W: Reopen> Mm0Writer<W> {
       t.p_args.set(self.term_thm_buf.len().try_into().expect("overflow"));
       self.term_thm_buf.extend_from_slice(&mmb.buf[start..end]);
       self.thm_names.push(push_entry(&mut self.names_buf, mmb.thm_index(id)));
+      self.thm_vars.push(vec![]);
+      self.thm_hyps.push(vec![]);
     }
     let start = u32_as_usize(mmb.header.p_proof.get());
     let end = mmb.proof().after_end().expect("parse error");
@@ -237,16 +267,18 @@ impl<W: Reopen> Mm0Writer<W> {
       p_args: U32::new(self.term_thm_buf.len().try_into().expect("overflow")),
     });
     self.term_names.push((self.proof.1, push_name(&mut self.names_buf, name)));
+    self.term_vars.push(vec![]);
     self.term_thm_buf.extend_from_slice(args.as_bytes());
     self.term_thm_buf.extend_from_slice(ret.as_bytes());
     n
   }
 
   /// Add a new sort with the given name and sort modifiers. Returns the ID of the new sort.
-  pub fn add_sort(&mut self, name: Option<&str>, data: SortData) -> SortId {
+  pub fn add_sort(&mut self, name: Option<&str>, data: SortData) -> io::Result<SortId> {
     let n = self.sorts.push(data);
     self.sort_names.push((self.proof.1, push_name(&mut self.names_buf, name)));
-    n
+    write_cmd_bytes(&mut self.proof, STMT_SORT, &[])?;
+    Ok(n)
   }
 
   /// Add a new term with the given name and arguments. Returns the ID of the new term.
@@ -275,7 +307,9 @@ impl<W: Reopen> Mm0Writer<W> {
       reserved: [0; 2],
       p_args: U32::new(self.term_thm_buf.len().try_into().expect("overflow")),
     });
-    self.term_names.push((self.proof.1, push_name(&mut self.names_buf, name)));
+    self.thm_names.push((self.proof.1, push_name(&mut self.names_buf, name)));
+    self.thm_vars.push(vec![]);
+    self.thm_hyps.push(vec![]);
     self.term_thm_buf.extend_from_slice(args.as_bytes());
     ThmBuilder(StmtBuilder::new(self, cmd), n)
   }
@@ -294,6 +328,28 @@ impl<W: Reopen> Mm0Writer<W> {
     self.add_thm_core(if local { STMT_LOCAL_THM } else { STMT_THM }, name, args)
   }
 
+  fn var_list(&mut self, vars: &[&str]) -> Vec<usize> {
+    let Mm0Writer { names_buf, var_names, .. } = self;
+    vars.iter().map(|&v| match var_names.get(v) {
+      Some(&n) => n,
+      None => *var_names.entry(v.into()).or_insert_with(|| push_name(names_buf, Some(v))),
+    }).collect()
+  }
+
+  /// Set the names of the variables of a term, for the index. (Use `_` for an anonymous
+  /// variable.) If this is not called for any term or theorem, the output file will not
+  /// have variable or hypothesis name tables.
+  pub fn set_term_vars(&mut self, t: TermId, vars: &[&str]) {
+    self.term_vars[t] = self.var_list(vars);
+  }
+
+  /// Set the names of the variables and hypotheses of a theorem, for the index.
+  /// (Use `_` for an anonymous variable or hypothesis.)
+  pub fn set_thm_vars(&mut self, t: ThmId, vars: &[&str], hyps: &[&str]) {
+    self.thm_vars[t] = self.var_list(vars);
+    self.thm_hyps[t] = self.var_list(hyps);
+  }
+
   /// This function consumes the `Mm0Writer` instance and actually writes the MMB data to the given
   /// writer, given a function `reopen` which reads the data just written to `proof`.
   pub fn finish(self, w: &mut impl Write) -> io::Result<()> {
@@ -308,6 +364,10 @@ impl<W: Reopen> Mm0Writer<W> {
       sort_names,
       term_names,
       thm_names,
+      term_vars,
+      thm_vars,
+      thm_hyps,
+      var_names,
     } = self;
     proof.write_u8(0)?;
     let (mut proof, proof_size) = (proof.0.reopen()?, proof.1);
@@ -352,21 +412,25 @@ impl<W: Reopen> Mm0Writer<W> {
     w.write_all(&term_thm_buf)?; // term/theorem data
     io::copy(&mut proof, w)?; // proof stream
 
-    let num_entries = 1;
+    let num_entries = if var_names.is_empty() { 1 } else { 3 };
     let p_names_buf = p_index + 8 + num_entries * size_of::<TableEntry>();
-    let p_names = p_names_buf + names_buf.len();
+    let (pad3, p_names) = pad_to(p_names_buf + names_buf.len(), 8);
     let p_names_buf: u64 = p_names_buf.try_into().expect("overflow");
-    let index = [(INDEX_NAME, p_names)];
-    assert_eq!(index.len(), num_entries);
+    let p_lists = p_names + (num_sorts + num_terms + num_thms) * size_of::<NameEntry>();
+    let lists = || term_vars.0.iter().chain(&thm_vars.0).chain(&thm_hyps.0);
+    let p_vars = p_lists + lists().map(|l| 8 * (l.len() + 1)).sum::<usize>();
+    let p_hyps = p_vars + 8 * (num_terms + num_thms);
+    let index = [(INDEX_NAME, p_names), (INDEX_VAR_NAME, p_vars), (INDEX_HYP_NAME, p_hyps)];
     w.write_all(&vec![0; pad2])?; // index padding
     w.write_u64::<LE>(num_entries.try_into().expect("overflow"))?; // index size
-    for (ty, p) in index {
+    for &(ty, p) in &index[..num_entries] {
       w.write_all(&ty)?; // type
       w.write_u32::<LE>(0)?; // data (padding, unused)
       w.write_u64::<LE>(p.try_into().expect("overflow"))?; // ptr
     }
 
     w.write_all(&names_buf)?; // name string data
+    w.write_all(&vec![0; pad3])?; // name table padding
     let p_proof = u64::from(p_proof);
     let mut write = |vec| -> io::Result<()> {
       let offset = |off, i| match i {
@@ -382,6 +446,20 @@ impl<W: Reopen> Mm0Writer<W> {
     write(sort_names.0)?; // sort name data
     write(term_names.0)?; // term name data
     write(thm_names.0)?; // thm name data
+
+    if !var_names.is_empty() {
+      let mut p_list = p_lists;
+      for list in lists() {
+        w.write_u64::<LE>(list.len().try_into().expect("overflow"))?;
+        for &name in list {
+          w.write_u64::<LE>(p_names_buf + u64::try_from(name).expect("overflow"))?;
+        }
+      }
+      for list in lists() {
+        w.write_u64::<LE>(p_list.try_into().expect("overflow"))?; // var/hyp name data
+        p_list += 8 * (list.len() + 1);
+      }
+    }
     Ok(())
   }
 }
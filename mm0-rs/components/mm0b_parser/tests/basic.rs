@@ -1,7 +1,13 @@
 use mm0b_parser::{BareMmbFile, ParseError};
+#[cfg(feature = "std")]
+use mm0b_parser::{Arg, BasicMmbFile, Mm0Writer, ProofCmd, SortData, UnifyCmd};
+#[cfg(feature = "std")]
+use mm0_util::Modifiers;
 use std::fs::OpenOptions;
 use std::io::Read;
 use std::path::PathBuf;
+#[cfg(feature = "std")]
+use zerocopy::AsBytes;
 
 #[repr(align(1))]
 struct AlignFile<T>(T);
@@ -28,3 +34,90 @@ fn peano0() {
   assert!(!mmb_bytes.is_empty());
   assert!(BareMmbFile::parse(mmb_bytes.as_slice()).is_ok());
 }
+
+/// Copy `bytes` into an 8-byte aligned buffer.
+#[cfg(feature = "std")]
+fn aligned(bytes: &[u8]) -> Vec<u64> {
+  let mut words = vec![0_u64; bytes.len().div_ceil(8)];
+  words.as_bytes_mut()[..bytes.len()].copy_from_slice(bytes);
+  words
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn validate() {
+  let mut w = Mm0Writer::new(vec![]);
+  let wff = w.add_sort(Some("wff"), SortData(Modifiers::PROVABLE.bits())).unwrap();
+  let a = Arg::new_of_sort(wff.0);
+  let imp = w.add_term(Some("imp"), &[a, a], a).unwrap();
+  let mut ax = w.add_axiom(Some("id"), &[a]);
+  for cmd in [UnifyCmd::Term { tid: imp, save: false }, UnifyCmd::Ref(0), UnifyCmd::Ref(0)] {
+    cmd.write_to(ax.unify()).unwrap()
+  }
+  for cmd in [ProofCmd::Ref(0), ProofCmd::Ref(0), ProofCmd::Term { tid: imp, save: false }] {
+    cmd.write_to(ax.proof()).unwrap()
+  }
+  ax.finish().unwrap();
+  let mut bytes = vec![];
+  w.finish(&mut bytes).unwrap();
+
+  let buf = aligned(&bytes);
+  let errors = BareMmbFile::parse(&buf.as_bytes()[..bytes.len()]).unwrap().validate();
+  assert!(errors.is_empty(), "{:?}", errors);
+
+  // Set a reserved byte in the header
+  bytes[6] = 1;
+  let buf = aligned(&bytes);
+  let errors = BareMmbFile::parse(&buf.as_bytes()[..bytes.len()]).unwrap().validate();
+  assert_eq!(errors.len(), 1);
+  assert_eq!(errors[0].pos, 6);
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn var_names() {
+  let mut w = Mm0Writer::new(vec![]);
+  let wff = w.add_sort(Some("wff"), SortData(Modifiers::PROVABLE.bits())).unwrap();
+  let a = Arg::new_of_sort(wff.0);
+  let imp = w.add_term(Some("imp"), &[a, a], a).unwrap();
+  w.set_term_vars(imp, &["ph", "ps"]);
+  let mut ax = w.add_axiom(Some("ax-mp"), &[a, a]);
+  for cmd in [UnifyCmd::Ref(1), UnifyCmd::Hyp, UnifyCmd::Ref(0), UnifyCmd::Hyp,
+    UnifyCmd::Term { tid: imp, save: false }, UnifyCmd::Ref(0), UnifyCmd::Ref(1)] {
+    cmd.write_to(ax.unify()).unwrap()
+  }
+  let mp = ax.finish().unwrap();
+  w.set_thm_vars(mp, &["ph", "_"], &["min", "maj"]);
+  let mut bytes = vec![];
+  w.finish(&mut bytes).unwrap();
+
+  let buf = aligned(&bytes);
+  let file = BasicMmbFile::parse(&buf.as_bytes()[..bytes.len()]).unwrap();
+  assert!(file.validate().is_empty());
+  assert_eq!(file.term_vars(imp).get_opt(1), Some("ps"));
+  assert_eq!(file.thm_vars(mp).get_opt(0), Some("ph"));
+  assert_eq!(file.thm_vars(mp).get_opt(1), Some("_"));
+  assert_eq!(file.thm_hyps(mp).get_opt(1), Some("maj"));
+  assert_eq!(file.thm_name(mp), "ax-mp");
+}
+
+#[test]
+#[cfg(feature = "arbitrary")]
+fn arbitrary_validate() {
+  use arbitrary::{Arbitrary, Unstructured};
+  use mm0b_parser::ArbitraryMmb;
+  let mut seed = 0x2545_f491_4f6c_dd1d_u64;
+  for _ in 0..200 {
+    let data = (0..4096)
+      .map(|_| {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        seed.to_le_bytes()[0]
+      })
+      .collect::<Vec<_>>();
+    let mmb = ArbitraryMmb::arbitrary(&mut Unstructured::new(&data)).unwrap();
+    let errors = BasicMmbFile::parse(mmb.bytes()).unwrap().validate();
+    assert!(errors.is_empty(), "{:?}", errors);
+  }
+}
@@ -0,0 +1,578 @@
+//! A source formatter for MM0/MM1 files.
+//!
+//! The formatter walks the [`Ast`] of a file and prints its tokens back out in order,
+//! keeping the line breaks of the original file as well as all comments and doc comments.
+//! Only the whitespace between tokens is changed:
+//!
+//! - Tokens on the same line are separated by a single space, except after an opening
+//!   bracket, before a closing bracket, and before the `:` of a binder or declaration.
+//! - Each line is indented according to the brackets which are open at its start. The
+//!   continuation lines of a statement are indented by 2, except for the value of a
+//!   `def` or `theorem`, which starts at column 0 if it is on its own line (as in
+//!   `theorem foo: $ a $ =` followed by `'(proof)` on the next line). A line inside a
+//!   lisp list is indented 2 more than the line that opened the list, and a chain of
+//!   `@` lists like `(f @ g @ h x)` is indented like a single list (with the arguments
+//!   indented 2 more if the head of an `@` list is on a line by itself).
+//! - Runs of blank lines are collapsed to a single blank line, trailing whitespace is
+//!   removed, and the file ends with a single newline.
+//!
+//! Math formulas and string literals are copied verbatim. A statement containing tokens
+//! which are not represented in the AST (such as the inner brackets of `(a . (b c))`)
+//! is also copied verbatim.
+
+use crate::ast::{Ast, Atom, Binder, Decl, Literal, SExpr, SExprKind, Stmt, StmtKind, Type};
+use crate::ident_rest;
+use mm0_util::Span;
+
+/// The role of a token, which determines its indentation if it starts a line.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Role {
+  /// A closing bracket.
+  Close,
+  /// The `@` which begins a lisp `@` list.
+  At,
+  /// A doc comment line, which is always on a line by itself.
+  Doc,
+  /// Any other token.
+  Other,
+}
+
+/// A bracket, `@` list or statement which is open at the current position.
+#[derive(Clone, Copy)]
+struct Frame {
+  /// The indentation of a line starting with the closing bracket (or with another `@`,
+  /// if this is an `@` list).
+  close: usize,
+  /// The indentation of a line starting with any other token.
+  child: usize,
+  /// True if this is a lisp `@` list.
+  at: bool,
+  /// True if this is an `@` list and its first element has not been printed yet.
+  head: bool,
+}
+
+/// Formatting a statement fails if the source contains tokens which are not in the AST.
+type Result<T = ()> = std::result::Result<T, ()>;
+
+/// The printer state which needs to be restored if formatting a statement fails.
+#[derive(Clone, Copy)]
+struct Mark {
+  len: usize,
+  pos: usize,
+  indent: usize,
+  empty_line: bool,
+  space_after: bool,
+  newline: bool,
+}
+
+struct Printer<'a> {
+  /// The source text.
+  src: &'a str,
+  /// The formatted output.
+  out: String,
+  /// The position in `src` up to which all tokens have been printed.
+  pos: usize,
+  /// The stack of open brackets.
+  frames: Vec<Frame>,
+  /// The indentation of the current output line.
+  indent: usize,
+  /// True if the current output line has no tokens yet.
+  empty_line: bool,
+  /// True if the last token may be followed by a space.
+  space_after: bool,
+  /// True if the next token has to start a new line.
+  newline: bool,
+  /// True if the next token is the first token of a statement.
+  stmt_start: bool,
+}
+
+/// Returns true if `src[i..]` starts with a line comment (but not a doc comment).
+fn is_comment(src: &[u8], i: usize) -> bool {
+  src.get(i) == Some(&b'-') && src.get(i + 1) == Some(&b'-') && src.get(i + 2) != Some(&b'|')
+}
+
+impl<'a> Printer<'a> {
+  fn new(src: &'a str) -> Self {
+    Printer {
+      src,
+      out: String::with_capacity(src.len()),
+      pos: 0,
+      frames: vec![],
+      indent: 0,
+      empty_line: true,
+      space_after: false,
+      newline: false,
+      stmt_start: false,
+    }
+  }
+
+  fn mark(&self) -> Mark {
+    let Printer { ref out, pos, indent, empty_line, space_after, newline, .. } = *self;
+    Mark { len: out.len(), pos, indent, empty_line, space_after, newline }
+  }
+
+  fn restore(&mut self, m: Mark) {
+    self.out.truncate(m.len);
+    self.pos = m.pos;
+    self.indent = m.indent;
+    self.empty_line = m.empty_line;
+    self.space_after = m.space_after;
+    self.newline = m.newline;
+  }
+
+  fn indent_for(&self, role: Role) -> usize {
+    match (self.frames.last(), role) {
+      (None, _) => 0,
+      (Some(f), Role::Close) => f.close,
+      (Some(f), Role::At) if f.at => f.close,
+      (Some(f), _) if f.head => f.close,
+      (Some(f), _) => f.child,
+    }
+  }
+
+  /// Start a new output line for a token with the given role. If `nl > 1` then
+  /// there was a blank line in the source, which is preserved.
+  fn new_line(&mut self, nl: usize, role: Role) {
+    if !self.out.is_empty() {
+      self.out.push('\n');
+      if nl > 1 {
+        self.out.push('\n')
+      }
+    }
+    self.indent = self.indent_for(role);
+    self.out.extend(std::iter::repeat(' ').take(self.indent));
+    self.empty_line = true;
+  }
+
+  /// Get the start of the next token after `self.pos`, skipping whitespace and comments.
+  fn next_token(&self) -> usize {
+    let src = self.src.as_bytes();
+    let mut i = self.pos;
+    loop {
+      match src.get(i) {
+        Some(b' ' | b'\n') => i += 1,
+        _ if is_comment(src, i) =>
+          i = src[i..].iter().position(|&c| c == b'\n').map_or(src.len(), |n| i + n),
+        _ => return i,
+      }
+    }
+  }
+
+  /// Get the span of the next token, which should be `s`.
+  fn find(&self, s: &str) -> Result<Span> {
+    let i = self.next_token();
+    if self.src[i..].starts_with(s) {
+      Ok((i..i + s.len()).into())
+    } else {
+      Err(())
+    }
+  }
+
+  /// Print the comments between `self.pos` and `end`, which should contain only
+  /// whitespace and comments. Returns the number of newlines after the last comment.
+  fn gap(&mut self, end: usize) -> Result<usize> {
+    let src = self.src.as_bytes();
+    let mut nl = 0;
+    let mut i = self.pos;
+    while i < end {
+      match src[i] {
+        b' ' => i += 1,
+        b'\n' => {
+          nl += 1;
+          i += 1
+        }
+        _ if is_comment(src, i) => {
+          let eol = src[i..end].iter().position(|&c| c == b'\n').map_or(end, |n| i + n);
+          if nl == 0 && !self.empty_line {
+            self.out.push(' ')
+          } else {
+            self.new_line(nl, Role::Other)
+          }
+          self.out.push_str(self.src[i..eol].trim_end());
+          self.empty_line = false;
+          self.newline = true;
+          nl = 0;
+          i = eol
+        }
+        _ => return Err(()),
+      }
+    }
+    self.pos = end;
+    Ok(nl)
+  }
+
+  /// Print the token at `sp`. There is a space between two tokens on the same line
+  /// if the first has `after` set and the second has `before` set.
+  fn emit(&mut self, sp: Span, role: Role, before: bool, after: bool) -> Result {
+    let nl = self.gap(sp.start)?;
+    let line_start = nl > 0 || self.newline || (role == Role::Doc && !self.empty_line);
+    if line_start {
+      self.new_line(nl, role)
+    } else if !self.empty_line && self.space_after && before {
+      self.out.push(' ')
+    }
+    let text = &self.src[sp.start..sp.end];
+    self.out.push_str(if role == Role::Doc { text.trim_end() } else { text });
+    if let Some(f) = self.frames.last_mut() {
+      // The arguments of an `@` list whose head is on a line by itself are indented below it
+      if f.head && line_start {
+        let rest = self.src[sp.end..].split('\n').next().unwrap_or_default().trim_start();
+        if rest.is_empty() || rest.starts_with("--") {
+          f.child = f.close + 2
+        }
+      }
+      f.head = false
+    }
+    self.pos = sp.end;
+    self.empty_line = false;
+    self.space_after = after;
+    self.newline = role == Role::Doc;
+    if std::mem::take(&mut self.stmt_start) {
+      let indent = self.indent + 2;
+      self.frames.push(Frame { close: indent, child: indent, at: false, head: false })
+    }
+    Ok(())
+  }
+
+  /// Copy the source up to `end` verbatim, except for leading whitespace.
+  fn raw(&mut self, end: usize) {
+    let text = &self.src[self.pos..end];
+    let rest = text.trim_start_matches(|c| c == ' ' || c == '\n');
+    let nl = text[..text.len() - rest.len()].matches('\n').count();
+    if nl > 0 || self.newline {
+      self.new_line(nl, Role::Other)
+    } else if !self.empty_line {
+      self.out.push(' ')
+    }
+    self.out.push_str(rest.trim_end());
+    self.pos = end;
+    self.empty_line = false;
+    self.space_after = true;
+    self.newline = false;
+  }
+
+  fn word(&mut self, sp: Span) -> Result { self.emit(sp, Role::Other, true, true) }
+
+  fn token(&mut self, s: &str) -> Result { self.word(self.find(s)?) }
+
+  /// A token like `'` or `.x` which is not followed by a space.
+  fn prefix(&mut self, s: &str) -> Result { self.emit(self.find(s)?, Role::Other, true, false) }
+
+  /// A token like `:` or `;` which is not preceded by a space.
+  fn suffix(&mut self, s: &str) -> Result { self.emit(self.find(s)?, Role::Other, false, true) }
+
+  fn open(&mut self, s: &str, space: bool) -> Result {
+    self.emit(self.find(s)?, Role::Other, true, space)?;
+    self.frames.push(Frame { close: self.indent, child: self.indent + 2, at: false, head: false });
+    Ok(())
+  }
+
+  fn close(&mut self, s: &str, space: bool) -> Result {
+    self.emit(self.find(s)?, Role::Close, space, true)?;
+    self.frames.pop();
+    Ok(())
+  }
+
+  /// A keyword (or modifier, or number) which is not in the AST.
+  fn keyword(&mut self) -> Result {
+    let i = self.next_token();
+    let n = self.src.as_bytes()[i..].iter().take_while(|&&c| ident_rest(c)).count();
+    if n == 0 {
+      return Err(())
+    }
+    self.word((i..i + n).into())
+  }
+
+  /// All the keywords up to `end`.
+  fn keywords(&mut self, end: usize) -> Result {
+    while self.next_token() < end {
+      self.keyword()?
+    }
+    Ok(())
+  }
+
+  /// The doc comment lines before `end`, if any.
+  fn doc_lines(&mut self, end: usize) -> Result {
+    loop {
+      let i = self.next_token();
+      if i >= end || !self.src[i..].starts_with("--|") {
+        return Ok(())
+      }
+      let eol = self.src[i..].find('\n').map_or(self.src.len(), |n| i + n);
+      self.emit((i..eol).into(), Role::Doc, true, true)?
+    }
+  }
+
+  /// A `$ .. $` formula which is not in the AST.
+  fn formula(&mut self) -> Result {
+    let i = self.next_token();
+    if self.src.as_bytes().get(i) != Some(&b'$') {
+      return Err(())
+    }
+    let n = self.src[i + 1..].find('$').ok_or(())?;
+    self.word((i..i + n + 2).into())
+  }
+
+  fn ty(&mut self, ty: &Type) -> Result {
+    match ty {
+      Type::DepType(d) => {
+        self.word(d.sort)?;
+        d.deps.iter().try_for_each(|&x| self.word(x))
+      }
+      Type::Formula(f) => self.word(f.0),
+    }
+  }
+
+  /// Print the binder groups in `bis`, and return the number of binders which
+  /// were printed. The remaining binders come from an arrow type like `foo > bar`.
+  fn binders(&mut self, bis: &[Binder]) -> Result<usize> {
+    let mut i = 0;
+    while let Some(bi) = bis.get(i).filter(|bi| bi.local.is_some()) {
+      let span = bi.span;
+      let curly = self.src.as_bytes()[span.start] == b'{';
+      self.open(if curly { "{" } else { "(" }, false)?;
+      let mut ty = None;
+      while let Some(bi) = bis.get(i).filter(|bi| bi.span == span) {
+        if let Some(x) = bi.local {
+          if self.src.as_bytes().get(self.next_token()) == Some(&b'.') {
+            self.prefix(".")?
+          }
+          self.word(x)?;
+        }
+        ty = bi.ty.as_ref();
+        i += 1
+      }
+      if let Some(ty) = ty {
+        self.suffix(":")?;
+        self.ty(ty)?
+      }
+      self.close(if curly { "}" } else { ")" }, false)?
+    }
+    Ok(i)
+  }
+
+  fn decl(&mut self, d: &Decl) -> Result {
+    self.keywords(d.id.start)?;
+    self.word(d.id)?;
+    let n = self.binders(&d.bis)?;
+    if let Some(ty) = &d.ty {
+      self.suffix(":")?;
+      for bi in &d.bis[n..] {
+        self.ty(bi.ty.as_ref().ok_or(())?)?;
+        self.token(">")?
+      }
+      self.ty(ty)?
+    }
+    if let Some(val) = &d.val {
+      self.token("=")?;
+      if let Some(f) = self.frames.last_mut() {
+        *f = Frame { close: 0, child: 0, at: false, head: false }
+      }
+      self.sexpr(val)?
+    }
+    self.suffix(";")
+  }
+
+  /// The elements of a list, with an optional final element after a `.`.
+  fn items(&mut self, es: &[SExpr], dot: Option<&SExpr>) -> Result {
+    es.iter().try_for_each(|e| self.sexpr(e))?;
+    if let Some(e) = dot {
+      self.token(".")?;
+      self.sexpr(e)?
+    }
+    Ok(())
+  }
+
+  fn list(&mut self, span: Span, es: &[SExpr], dot: Option<&SExpr>) -> Result {
+    let src = self.src;
+    let c = src.as_bytes()[span.start];
+    match (c, es) {
+      (b'\'' | b',', [q, e])
+        if dot.is_none() && matches!(q.k, SExprKind::Atom(Atom::Quote | Atom::Unquote)) =>
+      {
+        self.prefix(&src[q.span.start..q.span.end])?;
+        self.sexpr(e)
+      }
+      (b'@', _) => {
+        self.emit(self.find("@")?, Role::At, true, true)?;
+        let f = match self.frames.last() {
+          Some(&f) if f.at => Frame { close: f.close, child: f.close, at: true, head: true },
+          f => Frame { close: f.map_or(0, |f| f.child), child: self.indent + 2, at: true, head: true },
+        };
+        self.frames.push(f);
+        self.items(es, dot)?;
+        self.frames.pop();
+        Ok(())
+      }
+      (b'(' | b'[' | b'{', _) => {
+        self.open(&src[span.start..=span.start], false)?;
+        match es {
+          [nfx, es @ ..] if c == b'{' && matches!(nfx.k, SExprKind::Atom(Atom::Nfx)) =>
+            self.items(es, dot)?,
+          // A curly list `{a op b op c}` is stored as `(op a b c)`
+          [op, e1, e2, es @ ..] if c == b'{' && dot.is_none() => {
+            self.sexpr(e1)?;
+            self.sexpr(op)?;
+            self.sexpr(e2)?;
+            for e in es {
+              self.token(&src[op.span.start..op.span.end])?;
+              self.sexpr(e)?
+            }
+          }
+          _ => self.items(es, dot)?,
+        }
+        self.close(&src[span.end - 1..span.end], false)
+      }
+      _ => Err(()),
+    }
+  }
+
+  fn sexpr(&mut self, e: &SExpr) -> Result {
+    match &e.k {
+      SExprKind::Atom(Atom::Ident)
+      | SExprKind::Number(_)
+      | SExprKind::String(_)
+      | SExprKind::Bool(_)
+      | SExprKind::Undef
+      | SExprKind::Formula(_) => self.word(e.span),
+      SExprKind::Atom(_) => Err(()),
+      SExprKind::DocComment(_, e) => {
+        self.doc_lines(e.span.start)?;
+        self.sexpr(e)
+      }
+      SExprKind::List(es) => self.list(e.span, es, None),
+      SExprKind::DottedList(es, dot) => self.list(e.span, es, Some(dot)),
+    }
+  }
+
+  fn stmt(&mut self, s: &Stmt) -> Result {
+    self.frames.clear();
+    match &s.k {
+      StmtKind::DocComment(_, s) => {
+        self.doc_lines(s.span.start)?;
+        return self.stmt(s)
+      }
+      StmtKind::Annot(e, s) => {
+        self.stmt_start = true;
+        self.prefix("@")?;
+        self.sexpr(e)?;
+        return self.stmt(s)
+      }
+      _ => self.stmt_start = true,
+    }
+    match &s.k {
+      StmtKind::Sort(id, _) => {
+        self.keywords(id.start)?;
+        self.word(*id)?
+      }
+      StmtKind::Decl(d) => return self.decl(d),
+      StmtKind::Delimiter(_) => {
+        self.keyword()?;
+        while self.src.as_bytes().get(self.next_token()) == Some(&b'$') {
+          self.formula()?
+        }
+      }
+      StmtKind::SimpleNota(n) => {
+        self.keywords(n.id.start)?;
+        self.word(n.id)?;
+        self.suffix(":")?;
+        self.word(n.c.fmla.0)?;
+        self.keyword()?;
+        self.keyword()?
+      }
+      StmtKind::Coercion { id, from, to } => {
+        self.keywords(id.start)?;
+        self.word(*id)?;
+        self.suffix(":")?;
+        self.word(*from)?;
+        self.token(">")?;
+        self.word(*to)?
+      }
+      StmtKind::Notation(n) => {
+        self.keywords(n.id.start)?;
+        self.word(n.id)?;
+        if self.binders(&n.bis)? != n.bis.len() {
+          return Err(())
+        }
+        if let Some(ty) = &n.ty {
+          self.suffix(":")?;
+          self.ty(ty)?
+        }
+        self.token("=")?;
+        for lit in &n.lits {
+          match *lit {
+            Literal::Const(c, _) => {
+              self.open("(", false)?;
+              self.word(c.fmla.0)?;
+              self.emit(self.find(":")?, Role::Other, false, false)?;
+              self.keyword()?;
+              self.close(")", false)?
+            }
+            Literal::Var(x) => self.word(x)?,
+          }
+        }
+        if n.prec.is_some() {
+          self.suffix(":")?;
+          self.keyword()?;
+          self.keyword()?
+        }
+      }
+      StmtKind::Inout { k, hs, .. } => {
+        self.keywords(k.start)?;
+        self.word(*k)?;
+        self.suffix(":")?;
+        hs.iter().try_for_each(|e| self.sexpr(e))?
+      }
+      StmtKind::Do(es) => {
+        self.keyword()?;
+        if self.src.as_bytes().get(self.next_token()) == Some(&b'{') {
+          self.open("{", true)?;
+          es.iter().try_for_each(|e| self.sexpr(e))?;
+          self.close("}", true)?
+        } else {
+          es.iter().try_for_each(|e| self.sexpr(e))?
+        }
+      }
+      StmtKind::Import(sp, _) => {
+        self.keyword()?;
+        self.word(*sp)?
+      }
+      StmtKind::DocComment(..) | StmtKind::Annot(..) => unreachable!(),
+    }
+    self.suffix(";")
+  }
+
+  fn finish(mut self) -> String {
+    // Anything after the last statement which is not a comment (like an `exit` command
+    // and the rest of the file) is copied verbatim.
+    let end = self.next_token();
+    if end < self.src.len() {
+      self.frames.clear();
+      self.raw(self.src.len())
+    } else {
+      let _ = self.gap(end);
+    }
+    if !self.out.is_empty() {
+      self.out.push('\n')
+    }
+    self.out
+  }
+}
+
+/// Format an MM0/MM1 file, given the result of parsing it. The file should not have
+/// any parse errors; statements which could not be parsed are copied verbatim, as are
+/// any statements which can not be reconstructed from the AST.
+#[must_use]
+pub fn format(ast: &Ast) -> String {
+  let mut p = Printer::new(&ast.source);
+  for s in &ast.stmts {
+    let m = p.mark();
+    if p.stmt(s).is_err() {
+      p.restore(m);
+      p.frames.clear();
+      p.stmt_start = false;
+      p.raw(s.span.end)
+    }
+  }
+  p.finish()
+}
@@ -57,6 +57,7 @@
 )]
 
 pub mod ast;
+pub mod fmt;
 
 use annotate_snippets::snippet::AnnotationType;
 use ast::{
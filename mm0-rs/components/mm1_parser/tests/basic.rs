@@ -20,3 +20,25 @@ fn peano_mm0() {
   let (_, ast) = parse(Arc::new(LinedString::from(mmz)), None);
   assert!(ast.errors.is_empty());
 }
+
+fn format(src: &str) -> String {
+  let (_, ast) = parse(Arc::new(LinedString::from(src.to_owned())), None);
+  assert!(ast.errors.is_empty());
+  mm1_parser::fmt::format(&ast)
+}
+
+#[test]
+fn fmt_peano_mm1() {
+  let mmz = read_to_string(PathBuf::from("./test_resources/peano.mm1")).unwrap();
+  let out = format(&mmz);
+  assert_eq!(format(&out), out);
+}
+
+#[test]
+fn fmt_comments() {
+  let src = "--| A sort\n  --| of numbers\nsort  nat; -- trailing\n\n\n\
+    -- a comment\ndef  foo (a   b: nat):  nat = $ a $;\ndo {\n      (display 1) -- one\n   (display 2)\n};\n";
+  let expected = "--| A sort\n--| of numbers\nsort nat; -- trailing\n\n\
+    -- a comment\ndef foo (a b: nat): nat = $ a $;\ndo {\n  (display 1) -- one\n  (display 2)\n};\n";
+  assert_eq!(format(src), expected);
+}
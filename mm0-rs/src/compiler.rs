@@ -6,10 +6,24 @@
 //! Additionally, unlike the server, the MM1 compiler will go on and generate MMB or MMU proofs,
 //! which can then be checked using an external MM0 checker such as [`mm0-c`].
 //!
+//! With `--message-format=json`, diagnostics are instead printed as one JSON object per line,
+//! for consumption by other tools.
+//!
+//! With `--cache-dir`, the result of elaborating each file is saved to disk (see
+//! [`elab::cache`](crate::elab::cache)), and later runs will load the saved environment
+//! instead of elaborating the file again, as long as neither the file nor any of its
+//! transitive imports has changed.
+//!
+//! With `--watch`, the compiler keeps running after the first compilation, and whenever
+//! one of the input files changes it elaborates the changed files (and the files that
+//! import them) again, reusing the results for all other files.
+//!
 //! [`mm0_rs::server`]: crate::server
 //! [`mm0-c`]: https://github.com/digama0/mm0/tree/master/mm0-c
 use std::sync::{Arc, Mutex, atomic::{AtomicBool, Ordering, AtomicU8}};
-use std::collections::{HashMap, hash_map::Entry};
+use std::collections::{HashMap, HashSet, hash_map::Entry};
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
 use std::{io, fs};
 use futures::{FutureExt, future::BoxFuture};
 use futures::channel::oneshot::{Sender as FSender, channel};
@@ -18,10 +32,11 @@ use futures::lock::Mutex as FMutex;
 use annotate_snippets::{
   snippet::{Snippet, Annotation, AnnotationType, SourceAnnotation, Slice},
   display_list::{DisplayList, FormatOptions}};
-use once_cell::sync::Lazy;
+use once_cell::sync::{Lazy, OnceCell};
+use serde_json::{json, Value};
 use typed_arena::Arena;
 use mm1_parser::{parse, ErrorLevel, ParseError};
-use crate::elab::{ElabError, ElabErrorKind, ElabResult, ElaborateBuilder};
+use crate::elab::{ElabError, ElabErrorKind, ElabResult, ElaborateBuilder, cache};
 use crate::{ArcList, FileRef, FileSpan, FrozenEnv, LinedString, MutexExt, Position, Range, Span};
 use crate::mmb::import::elab as mmb_elab;
 use crate::mmu::import::elab as mmu_elab;
@@ -34,7 +49,18 @@ static POOL: Lazy<ThreadPool> = Lazy::new(|| ThreadPool::new().expect("could not
 static VFS: Lazy<Vfs> = Lazy::new(|| Vfs(Mutex::new(HashMap::new())));
 
 static QUIET: AtomicBool = AtomicBool::new(false);
+/// True if diagnostics should be printed as JSON (see [`MessageFormat::Json`]).
+static JSON: AtomicBool = AtomicBool::new(false);
 static MAX_EMITTED_ERROR: AtomicU8 = AtomicU8::new(0);
+/// The directory for cached elaboration results, if caching is enabled.
+static CACHE_DIR: OnceCell<PathBuf> = OnceCell::new();
+/// The cache keys of files whose cache entry is up to date. The key of a file
+/// is a hash of its contents and the keys of its imports, so a change to any
+/// file invalidates the cache entries of all files that transitively import it.
+static CACHE_KEYS: Lazy<Mutex<HashMap<FileRef, u64>>> = Lazy::new(Default::default);
+/// The direct imports of each file that has been elaborated (or loaded from the cache).
+/// This is used in `--watch` mode to find the files affected by a change.
+static IMPORTS: Lazy<Mutex<HashMap<FileRef, Vec<FileRef>>>> = Lazy::new(Default::default);
 
 /// The cached [`Environment`](crate::elab::Environment) representing a
 /// completed parse, or an incomplete parse.
@@ -176,12 +202,15 @@ impl Vfs {
   }
 }
 
-fn mk_to_range() -> impl FnMut(&FileSpan) -> Option<Range> {
+/// Make a function for converting spans to line/column ranges, which gets the source text
+/// of each file from the [`VFS`].
+pub(crate) fn mk_to_range() -> impl FnMut(&FileSpan) -> Option<Range> {
   let mut srcs = HashMap::new();
   move |fsp: &FileSpan| -> Option<Range> {
+    // Files that were loaded from the cache may not have been read yet
     srcs.entry(fsp.file.ptr())
-      .or_insert_with(|| VFS.0.ulock()[&fsp.file].text.clone())
-      .try_ascii().map(|f| f.to_range(fsp.span))
+      .or_insert_with(|| VFS.get_or_insert(fsp.file.clone()).ok().map(|f| f.1.text.clone()))
+      .as_ref()?.try_ascii().map(|f| f.to_range(fsp.span))
   }
 }
 
@@ -212,6 +241,57 @@ impl ElabErrorKind {
       _ => vec![]
     }
   }
+
+  /// Convert the payload of an elaboration error to a list of JSON objects for the
+  /// related locations, for `--message-format=json`. This contains the same data as
+  /// the `to_related_info` function used by the server.
+  ///
+  /// # Parameters
+  ///
+  /// - `to_range`: a function for converting (index-based) spans to (line/col) ranges
+  fn to_json_related(&self, mut to_range: impl FnMut(&FileSpan) -> Option<Range>) -> Vec<Value> {
+    match self {
+      ElabErrorKind::Boxed(_, Some(info)) =>
+        info.iter().map(|(fs, e)| json!({
+          "file": fs.file.rel(),
+          "span": span_to_json(fs.span, to_range(fs)),
+          "message": e.to_string(),
+        })).collect(),
+      _ => vec![]
+    }
+  }
+}
+
+/// Convert a span, and the corresponding line/col range if it is available, to JSON.
+/// Lines and columns are zero-based, as in LSP.
+fn span_to_json(span: Span, range: Option<Range>) -> Value {
+  let pos = |p: Position| json!({"line": p.line, "character": p.character});
+  json!({
+    "start": span.start,
+    "end": span.end,
+    "range": range.map(|r| json!({"start": pos(r.start), "end": pos(r.end)})),
+  })
+}
+
+/// Print a diagnostic as a JSON object on one line, for `--message-format=json`.
+///
+/// # Parameters
+///
+/// - `path`: The file that sourced the error
+/// - `span`: The position of the error and its line/col range, if available
+/// - `level`: The error level
+/// - `msg`: The error message
+/// - `related`: Related locations (calculated by [`ElabErrorKind::to_json_related`])
+fn print_json(path: &FileRef, span: Option<(Span, Option<Range>)>,
+    level: ErrorLevel, msg: &str, related: &[Value]) {
+  println!("{}", json!({
+    "type": "diagnostic",
+    "file": path.rel(),
+    "span": span.map(|(span, range)| span_to_json(span, range)),
+    "level": level.to_string(),
+    "message": msg,
+    "related": related,
+  }))
 }
 
 /// Create a [`Snippet`] from a message.
@@ -316,6 +396,26 @@ impl ElabError {
     };
     f(make_snippet_no_source(&s, self.level))
   }
+
+  /// Print this error in the format selected by `--message-format`.
+  ///
+  /// # Parameters
+  ///
+  /// - `path`: The file that sourced the error
+  /// - `file`: The file contents, or `None` for binary files
+  /// - `to_range`: a function for converting (index-based) spans to (line/col) ranges
+  fn emit(&self, path: &FileRef, file: Option<&LinedString>,
+      to_range: impl FnMut(&FileSpan) -> Option<Range>) {
+    fn print(s: Snippet<'_>) { println!("{}\n", DisplayList::from(s)) }
+    if JSON.load(Ordering::Relaxed) {
+      print_json(path, Some((self.pos, file.map(|f| f.to_range(self.pos)))),
+        self.level, &self.kind.msg(), &self.kind.to_json_related(to_range))
+    } else if let Some(file) = file {
+      self.to_snippet(path, file, to_range, print)
+    } else {
+      self.to_snippet_no_source(path, self.pos, print)
+    }
+  }
 }
 
 /// Create a [`Snippet`] from this error. See [`ElabError::to_snippet`] for information
@@ -337,6 +437,113 @@ fn log_msg(#[allow(unused_mut)] mut s: String) {
   println!("{}", s)
 }
 
+/// A 64-bit FNV-1a hash, used for the hashes that are stored in the cache.
+/// Unlike `DefaultHasher`, the result does not depend on the version of Rust.
+struct StableHasher(u64);
+
+impl StableHasher {
+  fn new() -> Self { Self(0xcbf2_9ce4_8422_2325) }
+
+  fn write(mut self, bytes: &[u8]) -> Self {
+    for &b in bytes { self.0 = (self.0 ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3) }
+    self
+  }
+
+  fn write_u64(self, n: u64) -> Self { self.write(&n.to_le_bytes()) }
+}
+
+/// The hash of a file's contents, as recorded in its cache entry. This includes the
+/// elaborator options, since they affect the result of elaboration.
+fn src_hash(text: &FileContents) -> u64 {
+  let opts = crate::get_options();
+  StableHasher::new()
+    .write(&[opts.check_proofs.into(), opts.check_parens.into(), opts.unused_vars.into()])
+    .write(text).0
+}
+
+/// The cache key of a file, given its source hash and the cache keys of its imports.
+fn combine_keys(src_hash: u64, keys: impl IntoIterator<Item=u64>) -> u64 {
+  keys.into_iter().fold(StableHasher::new().write_u64(src_hash), StableHasher::write_u64).0
+}
+
+/// The location of the cache entry for `path`, if caching is enabled.
+fn cache_path(path: &FileRef) -> Option<PathBuf> {
+  let name = path.path().file_name()?.to_string_lossy();
+  Some(CACHE_DIR.get()?.join(format!("{}-{:016x}.cache", name,
+    StableHasher::new().write(path.path().to_string_lossy().as_bytes()).0)))
+}
+
+/// Read the cache entry for `path` and check that it is up to date, returning the
+/// file's cache key and the data following the header.
+/// `stack` contains the files whose entries are currently being checked, to guard
+/// against cycles between stale cache entries.
+fn read_cache(path: &FileRef, text: &FileContents, stack: &mut Vec<FileRef>) -> Option<(u64, Vec<u8>)> {
+  let buf = fs::read(cache_path(path)?).ok()?;
+  let (header, rest) = cache::read_header(&buf).ok()?;
+  let src_hash = src_hash(text);
+  if header.src_hash != src_hash || stack.contains(path) { return None }
+  stack.push(path.clone());
+  let mut keys = vec![];
+  let mut deps = vec![];
+  for (dep, key) in header.deps {
+    let dep = FileRef::from(dep);
+    if cache_key(&dep, stack)? != key { return None }
+    keys.push(key);
+    deps.push(dep);
+  }
+  stack.pop();
+  IMPORTS.ulock().insert(path.clone(), deps);
+  let rest = rest.to_vec();
+  Some((combine_keys(src_hash, keys), rest))
+}
+
+/// Get the cache key of a file, or `None` if it does not have an up to date cache entry.
+/// Binary files (`.mmb` and `.mmu`) are not cached; their key is the hash of their contents.
+fn cache_key(path: &FileRef, stack: &mut Vec<FileRef>) -> Option<u64> {
+  if let Some(&key) = CACHE_KEYS.ulock().get(path) { return Some(key) }
+  let (path, file) = VFS.get_or_insert(path.clone()).ok()?;
+  let key = if file.text.try_ascii().is_some() {
+    read_cache(&path, &file.text, stack)?.0
+  } else {
+    src_hash(&file.text)
+  };
+  CACHE_KEYS.ulock().insert(path, key);
+  Some(key)
+}
+
+/// Load the environment for `path` from the cache, if it has an up to date cache entry.
+fn load_cache(path: &FileRef, text: &FileContents) -> Option<FrozenEnv> {
+  let (key, rest) = read_cache(path, text, &mut vec![])?;
+  let env = cache::read_env(&rest).ok()?;
+  CACHE_KEYS.ulock().insert(path.clone(), key);
+  Some(env)
+}
+
+/// Save the result of elaborating `path` to the cache. This is only done if all the
+/// imports of the file are also cached, since otherwise we would have no way
+/// to tell if the imports have changed.
+fn save_cache(path: &FileRef, text: &FileContents, deps: &[FileRef], env: &FrozenEnv) -> io::Result<()> {
+  let cache_path = match cache_path(path) { Some(p) => p, None => return Ok(()) };
+  let deps = {
+    let keys = CACHE_KEYS.ulock();
+    match deps.iter().map(|p| Some((p.path().to_owned(), *keys.get(p)?))).collect::<Option<Vec<_>>>() {
+      Some(deps) => deps,
+      None => return Ok(())
+    }
+  };
+  let src_hash = src_hash(text);
+  let key = combine_keys(src_hash, deps.iter().map(|p| p.1));
+  let header = cache::Header { src_hash, deps };
+  let buf = cache::write(&header, env)?;
+  // Write to a temporary file first, so that a concurrent or interrupted run
+  // never sees a partially written entry
+  let tmp = cache_path.with_extension("tmp");
+  fs::write(&tmp, buf)?;
+  fs::rename(tmp, cache_path)?;
+  CACHE_KEYS.ulock().insert(path.clone(), key);
+  Ok(())
+}
+
 /// Elaborate a file for an [`Environment`](crate::elab::Environment) result.
 ///
 /// This is the main elaboration function, as an `async fn`. Given a `path`,
@@ -371,7 +578,13 @@ async fn elaborate(path: FileRef, rd: ArcList<FileRef>) -> io::Result<ElabResult
     }
   }
   let text = file.text.clone();
-  let (cyc, errors, env) = if path.has_extension("mmb") {
+  let cached = if text.try_ascii().is_some() { load_cache(&path, &text) } else { None };
+  let from_cache = cached.is_some();
+  let (cyc, errors, env) = if let Some(env) = cached {
+    if !QUIET.load(Ordering::Relaxed) { log_msg(format!("cached {}", path)) }
+    if crate::get_options().timings { crate::elab::timing::record_cached(path.clone()) }
+    (None, vec![], env)
+  } else if path.has_extension("mmb") {
     let (error, env) = mmb_elab(&path, &text);
     (None, if let Err(e) = error {vec![e]} else {vec![]}, FrozenEnv::new(env))
   } else if path.has_extension("mmu") {
@@ -383,8 +596,13 @@ async fn elaborate(path: FileRef, rd: ArcList<FileRef>) -> io::Result<ElabResult
       let mut level = 0;
       for e in &ast.errors {
         level = level.max(e.level as u8);
-        to_snippet(e, &path, &ast.source,
-          |s| println!("{}", DisplayList::from(s)))
+        if JSON.load(Ordering::Relaxed) {
+          print_json(&path, Some((e.pos, Some(ast.source.to_range(e.pos)))),
+            e.level, &format!("{}", e.msg), &[])
+        } else {
+          to_snippet(e, &path, &ast.source,
+            |s| println!("{}", DisplayList::from(s)))
+        }
       }
       MAX_EMITTED_ERROR.fetch_max(level, Ordering::Relaxed);
     }
@@ -415,23 +633,24 @@ async fn elaborate(path: FileRef, rd: ArcList<FileRef>) -> io::Result<ElabResult
         recv_goal: None,
       }.elab();
     let (cyc, _, errors, env) = fut.await;
+    IMPORTS.ulock().insert(path.clone(), deps.clone());
+    if CACHE_DIR.get().is_some() && cyc.is_none() && errors.is_empty() && ast.errors.is_empty() {
+      if let Err(e) = save_cache(&path, &text, &deps, &env) {
+        if !QUIET.load(Ordering::Relaxed) { log_msg(format!("not caching {}: {}", path, e)) }
+      }
+    }
     (cyc, errors, env)
   };
-  if !QUIET.load(Ordering::Relaxed) { log_msg(format!("elabbed {}", path)) }
+  if !QUIET.load(Ordering::Relaxed) && !from_cache { log_msg(format!("elabbed {}", path)) }
+  if CACHE_DIR.get().is_some() && text.try_ascii().is_none() {
+    CACHE_KEYS.ulock().insert(path.clone(), src_hash(&text));
+  }
   let errors: Option<Arc<[_]>> = if errors.is_empty() { None } else {
-    fn print(s: Snippet<'_>) { println!("{}\n", DisplayList::from(s)) }
     let mut to_range = mk_to_range();
     let mut level = 0;
-    if let FileContents::Ascii(text) = &file.text {
-      for e in &errors {
-        level = level.max(e.level as u8);
-        e.to_snippet(&path, text, &mut to_range, print)
-      }
-    } else {
-      for e in &errors {
-        level = level.max(e.level as u8);
-        e.to_snippet_no_source(&path, e.pos, print)
-      }
+    for e in &errors {
+      level = level.max(e.level as u8);
+      e.emit(&path, file.text.try_ascii().map(|t| &**t), &mut to_range)
     }
     MAX_EMITTED_ERROR.fetch_max(level, Ordering::Relaxed);
     Some(errors.into())
@@ -478,6 +697,143 @@ pub(crate) fn elab_for_result(path: FileRef) -> io::Result<(FileContents, Option
   Ok((file.text.clone(), env))
 }
 
+/// Set whether the progress messages of elaboration (like `elab foo.mm1`) are suppressed.
+/// These are printed to stdout, so this is needed when stdout is used for the output.
+pub(crate) fn set_quiet(b: bool) { QUIET.store(b, Ordering::Relaxed) }
+
+/// Returns true if an error (not just a warning) has been printed so far.
+pub(crate) fn has_errors() -> bool {
+  ErrorLevel::Error as u8 <= MAX_EMITTED_ERROR.load(Ordering::Relaxed)
+}
+
+/// Print an error that was produced outside the elaborator, such as a verification
+/// failure, in the same format as elaboration errors for the file `path`.
+pub(crate) fn report(path: &FileRef, text: &FileContents, e: &ElabError) {
+  e.emit(path, text.try_ascii().map(|t| &**t), mk_to_range());
+  MAX_EMITTED_ERROR.fetch_max(e.level as u8, Ordering::Relaxed);
+}
+
+/// Print an error in the file `path`, like [`report`], reading the file contents from
+/// the [`VFS`]. This is used for errors in imported files.
+pub(crate) fn report_in(path: &FileRef, e: &ElabError) -> io::Result<()> {
+  let (path, file) = VFS.get_or_insert(path.clone())?;
+  report(&path, &file.text, e);
+  Ok(())
+}
+
+/// Block until one of the files in the [`VFS`] is modified on disk. The changed files
+/// and all files that transitively import them are then removed from the [`VFS`], so that
+/// the next call to [`elaborate`] will read and elaborate them again, while files that
+/// were not affected keep their [`FileCache::Ready`] result.
+///
+/// `mtimes` holds the modification times of the files from the previous call.
+fn wait_for_changes(mtimes: &mut HashMap<FileRef, Option<SystemTime>>) {
+  loop {
+    let files = VFS.0.ulock().keys().cloned().collect::<Vec<_>>();
+    let mut changed = vec![];
+    for file in files {
+      let time = fs::metadata(file.path()).and_then(|m| m.modified()).ok();
+      match mtimes.entry(file) {
+        Entry::Occupied(mut e) => if *e.get() != time {
+          e.insert(time);
+          changed.push(e.key().clone())
+        }
+        Entry::Vacant(e) => { e.insert(time); }
+      }
+    }
+    if !changed.is_empty() {
+      if !QUIET.load(Ordering::Relaxed) {
+        for file in &changed { log_msg(format!("changed {}", file)) }
+      }
+      let mut dirty = HashSet::new();
+      let imports = IMPORTS.ulock();
+      while let Some(file) = changed.pop() {
+        if dirty.insert(file.clone()) {
+          changed.extend(imports.iter()
+            .filter(|(_, deps)| deps.contains(&file))
+            .map(|(importer, _)| importer.clone()));
+        }
+      }
+      drop(imports);
+      let (mut vfs, mut keys, mut imports) = (VFS.0.ulock(), CACHE_KEYS.ulock(), IMPORTS.ulock());
+      for file in &dirty {
+        vfs.remove(file);
+        keys.remove(file);
+        imports.remove(file);
+      }
+      return
+    }
+    std::thread::sleep(Duration::from_millis(200));
+  }
+}
+
+/// The number of rows in each table printed by [`print_timings`].
+const TIMINGS_ROWS: usize = 20;
+
+/// Print the timing data collected during elaboration, for `--timings`. This prints
+/// the slowest statements and lisp procedures, or all of them in JSON mode.
+fn print_timings(json: bool) {
+  let mut timings = crate::elab::timing::take();
+  timings.stmts.sort_by_key(|t| std::cmp::Reverse(t.time));
+  let mut procs = timings.procs.into_iter().collect::<Vec<_>>();
+  procs.sort_by_key(|t| std::cmp::Reverse(t.1.time));
+  let mut to_range = mk_to_range();
+  if json {
+    let mut loc = |fsp: &FileSpan| json!({
+      "file": fsp.file.rel(),
+      "span": span_to_json(fsp.span, to_range(fsp)),
+    });
+    let stmts = timings.stmts.iter().map(|t| json!({
+      "name": t.name,
+      "location": loc(&t.span),
+      "time": t.time.as_secs_f64(),
+    })).collect::<Vec<_>>();
+    let procs = procs.iter().map(|(name, t)| json!({
+      "name": String::from_utf8_lossy(name),
+      "location": loc(&t.span),
+      "calls": t.calls,
+      "time": t.time.as_secs_f64(),
+    })).collect::<Vec<_>>();
+    let cached = timings.cached.iter().map(FileRef::rel).collect::<Vec<_>>();
+    println!("{}", json!({
+      "type": "timings", "stmts": stmts, "procs": procs, "cached": cached }));
+    return
+  }
+  let mut loc = |fsp: &FileSpan| match to_range(fsp) {
+    Some(r) => format!("{}:{}:{}", fsp.file, r.start.line + 1, r.start.character + 1),
+    None => fsp.file.to_string(),
+  };
+  let total = timings.stmts.iter().map(|t| t.time).sum::<std::time::Duration>();
+  print!("elaboration time: {:.3}s in {} statements", total.as_secs_f64(), timings.stmts.len());
+  if !timings.cached.is_empty() { print!(", cached files: {}", timings.cached.len()) }
+  println!();
+  println!("\n{:>10}  {:<24}  statement", "time", "location");
+  for t in timings.stmts.iter().take(TIMINGS_ROWS) {
+    println!("{:>9.3}s  {:<24}  {}", t.time.as_secs_f64(), loc(&t.span), t.name);
+  }
+  for file in &timings.cached {
+    println!("{:>10}  {:<24}  (all statements)", "cached", file.to_string());
+  }
+  if !procs.is_empty() {
+    println!("\n{:>10}  {:>8}  {:<24}  procedure", "time", "calls", "location");
+    for (name, t) in procs.iter().take(TIMINGS_ROWS) {
+      println!("{:>9.3}s  {:>8}  {:<24}  {}", t.time.as_secs_f64(), t.calls, loc(&t.span),
+        String::from_utf8_lossy(name));
+    }
+  }
+  println!();
+}
+
+/// The format of diagnostic messages printed by `mm0-rs compile`.
+#[derive(Clone, Copy, Debug, clap::ArgEnum)]
+pub enum MessageFormat {
+  /// Human readable errors, with source snippets.
+  Human,
+  /// One JSON object per line for each diagnostic, followed by a summary object
+  /// with the number of sorts, terms and theorems.
+  Json,
+}
+
 /// Compile MM1 files into MMB
 #[allow(clippy::struct_excessive_bools)]
 #[derive(clap::Args, Debug)]
@@ -500,9 +856,22 @@ pub struct Args {
   /// Print 'output' commands to a file (use '-' to print to stdout)
   #[clap(short, long = "output", value_name = "FILE")]
   pub output_str: Option<std::ffi::OsString>,
+  /// Sets the format of diagnostic messages
+  #[clap(long, arg_enum, default_value_t = MessageFormat::Human)]
+  pub message_format: MessageFormat,
+  /// Cache elaborated files in this directory, and reuse them on later runs
+  /// if the file and its imports have not changed
+  #[clap(long, value_name = "DIR")]
+  pub cache_dir: Option<PathBuf>,
+  /// Keep running, and compile again whenever the input file or one of its imports changes
+  #[clap(long)]
+  pub watch: bool,
+  /// Report the time spent elaborating each statement and lisp procedure
+  #[clap(long)]
+  pub timings: bool,
   /// Sets the input file (.mm1 or .mm0)
   pub input: String,
-  /// Sets the output file (.mmb or .mmu)
+  /// Sets the output file (.mmb, .mmu or .mm)
   pub output: Option<String>,
 }
 
@@ -516,35 +885,63 @@ impl Args {
   /// - `in.mm1` is the MM1 (or MM0) file to elaborate
   /// - `out.mmb` (or `out.mmu`) is the MMB file to generate, if the elaboration is
   ///   successful. The file extension is used to determine if we are outputting
-  ///   binary. If this argument is omitted, the input is only elaborated.
+  ///   binary. An `out.mm` file is a Metamath database, see [`crate::mm::export`].
+  ///   If this argument is omitted, the input is only elaborated.
+  ///
+  /// With `--watch`, this function does not return; it waits for changes to the input
+  /// files and compiles again after each change.
   pub fn main(self) -> io::Result<()> {
-    let path: FileRef = fs::canonicalize(self.input)?.into();
-    QUIET.store(self.quiet, Ordering::Relaxed);
+    let path: FileRef = fs::canonicalize(&self.input)?.into();
+    let json = matches!(self.message_format, MessageFormat::Json);
+    QUIET.store(self.quiet || json, Ordering::Relaxed);
+    JSON.store(json, Ordering::Relaxed);
+    if let Some(dir) = &self.cache_dir {
+      fs::create_dir_all(dir)?;
+      CACHE_DIR.set(dir.clone()).expect("cache directory already set");
+    }
+    if !self.watch {
+      if !self.run(&path)? { std::process::exit(1) }
+      return Ok(())
+    }
+    let mut mtimes = HashMap::new();
+    loop {
+      MAX_EMITTED_ERROR.store(0, Ordering::Relaxed);
+      if let Err(e) = self.run(&path) { eprintln!("error: {}", e) }
+      if !QUIET.load(Ordering::Relaxed) { println!("watching for changes...") }
+      wait_for_changes(&mut mtimes);
+    }
+  }
+
+  /// Elaborate the input file and write the outputs. Returns `false` if elaboration
+  /// failed or reported errors (or warnings, with `--warn-as-error`).
+  fn run(&self, path: &FileRef) -> io::Result<bool> {
+    let json = matches!(self.message_format, MessageFormat::Json);
     let (file, env) = elab_for_result(path.clone())?;
-    let env = env.unwrap_or_else(|| std::process::exit(1));
-    if let Some(s) = self.output_str {
+    if self.timings { print_timings(json) }
+    let env = if let Some(env) = env { env } else { return Ok(false) };
+    if let Some(s) = &self.output_str {
       if let Err((fsp, e)) =
         if s == "-" { env.run_output(io::stdout()) }
         else { env.run_output(fs::File::create(s)?) }
       {
         let e = ElabError::new_e(fsp.span, e);
         let file = VFS.get_or_insert(fsp.file.clone())?.1;
-        e.to_snippet(&fsp.file, file.text.ascii(), &mut mk_to_range(),
-          |s| println!("{}\n", DisplayList::from(s)));
-        std::process::exit(1);
+        e.emit(&fsp.file, Some(file.text.ascii()), mk_to_range());
+        return Ok(false)
       }
     }
-    if !self.quiet {
+    if !self.quiet && !json {
       println!("{} sorts, {} term/def, {} ax/thm",
         env.sorts().len(), env.terms().len(), env.thms().len());
     }
-    if let Some(out) = self.output {
+    if let Some(out) = &self.output {
       use {fs::File, io::BufWriter};
-      let w = BufWriter::new(File::create(&out)?);
-      if out.rsplit('.').next().map_or(false, |ext| ext.eq_ignore_ascii_case("mmu")) {
-        env.export_mmu(w)?;
-      } else {
-        let mut report = |lvl: ErrorLevel, err: &str| {
+      let w = BufWriter::new(File::create(out)?);
+      let ext = out.rsplit('.').next().map(str::to_ascii_lowercase);
+      let mut report = |lvl: ErrorLevel, err: &str| {
+        if json {
+          print_json(path, None, lvl, err, &[])
+        } else {
           println!("{}\n", DisplayList::from(Snippet {
             title: Some(Annotation {
               label: Some(err),
@@ -555,17 +952,30 @@ impl Args {
             slices: vec![],
             opt: FormatOptions { color: true, ..Default::default() },
           }));
-          MAX_EMITTED_ERROR.fetch_max(lvl as u8, Ordering::Relaxed);
-        };
-        let mut ex = MmbExporter::new(path, file.try_ascii().map(|fc| &**fc), &env, &mut report, w);
+        }
+        MAX_EMITTED_ERROR.fetch_max(lvl as u8, Ordering::Relaxed);
+      };
+      if ext.as_deref() == Some("mmu") {
+        env.export_mmu(w)?;
+      } else if ext.as_deref() == Some("mm") {
+        env.export_mm(w, &mut report)?;
+      } else {
+        let mut to_range = mk_to_range();
+        let mut ex = MmbExporter::new(path.clone(), file.try_ascii().map(|fc| &**fc), &env, &mut report, w);
+        ex.set_to_range(&mut to_range);
         ex.run(!self.strip)?;
         ex.finish()?;
       }
     }
-    let max_error = if self.warn_as_error { ErrorLevel::Warning } else { ErrorLevel::Error };
-    if max_error as u8 <= MAX_EMITTED_ERROR.load(Ordering::Relaxed) {
-      std::process::exit(1);
+    if json {
+      println!("{}", json!({
+        "type": "summary",
+        "sorts": env.sorts().len(),
+        "terms": env.terms().len(),
+        "thms": env.thms().len(),
+      }))
     }
-    Ok(())
+    let max_error = if self.warn_as_error { ErrorLevel::Warning } else { ErrorLevel::Error };
+    Ok((max_error as u8) > MAX_EMITTED_ERROR.load(Ordering::Relaxed))
   }
 }
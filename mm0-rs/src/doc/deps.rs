@@ -0,0 +1,185 @@
+//! Export the dependency graph of an MM1/MM0 file, for auditing which definitions
+//! and axioms a theorem relies on.
+//!
+//! The nodes of the graph are the terms, definitions, axioms and theorems of the file
+//! (including imported files), and there is an edge from each declaration to every
+//! declaration it refers to directly: for a definition, the terms in its body, and for
+//! an axiom or theorem, the terms in its statement and the terms and theorems in its proof.
+//! The graph can be written in DOT format, for rendering using graphviz, or as JSON.
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use serde_json::json;
+use crate::{DeclKey, Environment, FileRef, StmtTrace, ThmId, ThmKind};
+use super::AxiomUse;
+
+/// The output format of `mm0-rs deps`.
+#[derive(Clone, Copy, Debug, clap::ArgEnum)]
+pub enum DepsFormat {
+  /// A graphviz DOT file.
+  Dot,
+  /// A JSON object with a `nodes` array, where each node lists the names of its
+  /// direct dependencies.
+  Json,
+}
+
+/// Write the dependency graph of an MM1/MM0 file
+#[derive(clap::Args, Debug)]
+pub struct Args {
+  /// Sets the output format
+  #[clap(long, arg_enum, default_value_t = DepsFormat::Dot)]
+  pub format: DepsFormat,
+  /// Show only THMS and the declarations they depend on (a comma separated list)
+  #[clap(long, value_name = "THMS", use_value_delimiter = true)]
+  pub ancestors_of: Vec<String>,
+  /// Show only THMS and the axioms they depend on (a comma separated list)
+  #[clap(long, value_name = "THMS", use_value_delimiter = true, conflicts_with = "ancestors_of")]
+  pub axioms_of: Vec<String>,
+  /// Sets the input file (.mm1 or .mm0)
+  pub input: String,
+  /// Sets the output file, or stdout if omitted
+  pub output: Option<String>,
+}
+
+/// The dependency graph, as a list of nodes in declaration order, each with
+/// its outgoing edges.
+struct Graph {
+  nodes: Vec<(DeclKey, Vec<DeclKey>)>,
+  /// The theorems that depend on a missing proof. This is only computed in
+  /// `--axioms-of` mode, where it is shown as an extra node.
+  incomplete: Vec<DeclKey>,
+}
+
+impl Graph {
+  /// The full dependency graph.
+  fn new(env: &Environment, decls: &[DeclKey]) -> Self {
+    let nodes = decls.iter().map(|&k| (k, env.direct_deps(k))).collect();
+    Graph { nodes, incomplete: vec![] }
+  }
+
+  /// Restrict the graph to `roots` and the declarations they depend on.
+  fn ancestors(self, roots: &[DeclKey]) -> Self {
+    let edges = self.nodes.iter().map(|(k, deps)| (*k, deps)).collect::<HashMap<_, _>>();
+    let mut keep = HashSet::new();
+    let mut stack = roots.to_vec();
+    while let Some(k) = stack.pop() {
+      if keep.insert(k) { stack.extend(edges[&k].iter().copied()) }
+    }
+    let nodes = self.nodes.into_iter().filter(|(k, _)| keep.contains(k)).collect();
+    Graph { nodes, incomplete: self.incomplete }
+  }
+
+  /// The graph with edges from each of the `roots` to the axioms they depend on.
+  fn axioms(env: &Environment, decls: &[DeclKey], roots: &[ThmId]) -> Self {
+    let (to_tid, mut axuse) = AxiomUse::new(env);
+    let mut uses = HashMap::new();
+    let mut incomplete = vec![];
+    for &tid in roots {
+      let deps = if matches!(env.thms[tid].kind, ThmKind::Axiom) {
+        vec![]
+      } else {
+        let bs = axuse.get(env, tid);
+        // Index 0 is used for theorems that have a missing proof
+        if bs.contains(0) { incomplete.push(DeclKey::Thm(tid)) }
+        bs.iter().filter(|&i| i != 0).map(|i| DeclKey::Thm(to_tid[i])).collect()
+      };
+      uses.insert(DeclKey::Thm(tid), deps);
+    }
+    let axioms = uses.values().flatten().copied().collect::<HashSet<_>>();
+    let nodes = decls.iter().filter_map(|k| match uses.remove(k) {
+      Some(deps) => Some((*k, deps)),
+      None if axioms.contains(k) => Some((*k, vec![])),
+      None => None,
+    }).collect();
+    Graph { nodes, incomplete }
+  }
+
+  fn write_dot(&self, env: &Environment, w: &mut impl Write) -> io::Result<()> {
+    writeln!(w, "digraph deps {{")?;
+    for &(k, ref deps) in &self.nodes {
+      let attrs = match env.decl_kind(k) {
+        "term" => "shape=ellipse",
+        "def" => "shape=ellipse, style=dashed",
+        "axiom" => "shape=box, style=bold",
+        _ => "shape=box",
+      };
+      writeln!(w, "  \"{}\" [{}];", env.decl_name(k), attrs)?;
+      for &d in deps {
+        writeln!(w, "  \"{}\" -> \"{}\";", env.decl_name(k), env.decl_name(d))?;
+      }
+    }
+    if !self.incomplete.is_empty() {
+      writeln!(w, "  \"(incomplete)\" [shape=box, style=dotted];")?;
+      for &k in &self.incomplete {
+        writeln!(w, "  \"{}\" -> \"(incomplete)\";", env.decl_name(k))?;
+      }
+    }
+    writeln!(w, "}}")
+  }
+
+  fn write_json(&self, env: &Environment, w: &mut impl Write) -> io::Result<()> {
+    let nodes = self.nodes.iter().map(|&(k, ref deps)| {
+      let span = match k {
+        DeclKey::Term(t) => &env.terms[t].span,
+        DeclKey::Thm(t) => &env.thms[t].span,
+      };
+      json!({
+        "name": env.decl_name(k).to_string(),
+        "kind": env.decl_kind(k),
+        "file": span.file.rel(),
+        "deps": deps.iter().map(|&d| env.decl_name(d).to_string()).collect::<Vec<_>>(),
+        "incomplete": self.incomplete.contains(&k),
+      })
+    }).collect::<Vec<_>>();
+    serde_json::to_writer_pretty(&mut *w, &json!({ "nodes": nodes }))?;
+    writeln!(w)
+  }
+}
+
+impl Args {
+  /// Main entry point for `mm0-rs deps` subcommand.
+  ///
+  /// # Arguments
+  ///
+  /// `mm0-rs deps <in.mm1> [out]`, where:
+  ///
+  /// - `in.mm1` is the initial file to elaborate.
+  /// - `out` is the file to write the graph to (default stdout).
+  pub fn main(self) -> io::Result<()> {
+    let path: FileRef = fs::canonicalize(self.input)?.into();
+    if self.output.is_none() { crate::compiler::set_quiet(true) }
+    let (_, old) = crate::compiler::elab_for_result(path)?;
+    let old = old.unwrap_or_else(|| std::process::exit(1));
+    let env = old.thaw_copy();
+    let decls = env.stmts.iter().filter_map(|s| match *s {
+      StmtTrace::Decl(a) => env.data[a].decl,
+      _ => None,
+    }).collect::<Vec<_>>();
+    let get_decl = |name: &str| {
+      let decl = env.atoms.get(name.as_bytes()).and_then(|&a| env.data[a].decl);
+      if decl.is_none() { eprintln!("warning: unknown declaration '{}'", name) }
+      decl
+    };
+    let graph = if !self.axioms_of.is_empty() {
+      let roots = self.axioms_of.iter().filter_map(|s| match get_decl(s)? {
+        DeclKey::Thm(tid) => Some(tid),
+        DeclKey::Term(_) => { eprintln!("warning: expected a theorem, got term '{}'", s); None }
+      }).collect::<Vec<_>>();
+      Graph::axioms(&env, &decls, &roots)
+    } else if !self.ancestors_of.is_empty() {
+      let roots = self.ancestors_of.iter().filter_map(|s| get_decl(s)).collect::<Vec<_>>();
+      Graph::new(&env, &decls).ancestors(&roots)
+    } else {
+      Graph::new(&env, &decls)
+    };
+    let mut w: BufWriter<Box<dyn Write>> = BufWriter::new(match &self.output {
+      Some(out) => Box::new(File::create(out)?),
+      None => Box::new(io::stdout()),
+    });
+    match self.format {
+      DepsFormat::Dot => graph.write_dot(&env, &mut w)?,
+      DepsFormat::Json => graph.write_json(&env, &mut w)?,
+    }
+    w.flush()
+  }
+}
@@ -0,0 +1,212 @@
+//! Lint an MM1/MM0 file for unused and duplicate declarations.
+//!
+//! This implements `mm0-rs lint`, which elaborates a file and then reports:
+//!
+//! - local (non-`pub`) theorems which are not used in the proof of any other theorem,
+//! - definitions which are not referenced by any other declaration,
+//! - theorems and axioms whose statement is the same as that of an earlier theorem
+//!   or axiom, up to the names of the variables and hypotheses, and
+//! - axioms which are not used (directly or indirectly) by any public theorem.
+//!
+//! A theorem is public if it is marked `pub`, or if it is declared in an `.mm0` file.
+//! If a public theorem has no proof (for example when linting an `.mm0` file by itself),
+//! then unused axioms are not reported, since it is not known which axioms are needed.
+//! The findings are reported as warnings at the name of the offending declaration.
+use std::collections::{hash_map::Entry, HashMap, HashSet};
+use std::fs;
+use std::io;
+use crate::elab::{ElabError, ElabErrorKind};
+use crate::{AtomId, DeclKey, Environment, ExprNode, FileRef, FileSpan, SortId,
+  StmtTrace, TermId, TermKind, ThmId, ThmKind, Type, Modifiers, ErrorLevel};
+use super::AxiomUse;
+
+/// A kind of problem reported by `mm0-rs lint`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ArgEnum)]
+pub enum Lint {
+  /// A local theorem which is not used by any other theorem.
+  UnusedThm,
+  /// A definition which is not referenced by any other declaration.
+  UnusedDef,
+  /// A theorem or axiom with the same statement as an earlier one.
+  DuplicateThm,
+  /// An axiom which is not needed by any public theorem.
+  UnusedAxiom,
+}
+
+impl Lint {
+  fn name(self) -> &'static str {
+    match self {
+      Lint::UnusedThm => "unused-thm",
+      Lint::UnusedDef => "unused-def",
+      Lint::DuplicateThm => "duplicate-thm",
+      Lint::UnusedAxiom => "unused-axiom",
+    }
+  }
+}
+
+/// A problem found by `mm0-rs lint`.
+#[derive(Debug)]
+pub struct Finding {
+  /// The kind of problem.
+  pub lint: Lint,
+  /// The location of the name of the offending declaration.
+  pub span: FileSpan,
+  /// The message to display.
+  pub msg: String,
+  /// Other declarations relevant to the finding (the earlier theorem, for a duplicate).
+  pub related: Vec<(FileSpan, String)>,
+}
+
+/// A token in the canonical form of a theorem statement. Two statements are
+/// alpha-equivalent if they have the same sequence of tokens.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum Tok {
+  Bound(SortId),
+  Reg(SortId, u64),
+  Hyp,
+  Var(usize),
+  Dummy(usize, SortId),
+  App(TermId),
+}
+
+/// Compute the canonical form of the statement of a theorem, in which variables are
+/// replaced by their index and dummy variables by the order of their first occurrence.
+fn statement_key(env: &Environment, tid: ThmId) -> Vec<Tok> {
+  fn expr(env: &Environment, heap: &[ExprNode], store: &[ExprNode], nargs: usize,
+      dummies: &mut HashMap<AtomId, usize>, out: &mut Vec<Tok>, e: &ExprNode) {
+    match *e {
+      ExprNode::Ref(i) if i < nargs => out.push(Tok::Var(i)),
+      ExprNode::Ref(i) => expr(env, heap, store, nargs, dummies, out, &heap[i]),
+      ExprNode::Dummy(a, s) => {
+        let n = dummies.len();
+        out.push(Tok::Dummy(*dummies.entry(a).or_insert(n), s))
+      }
+      ExprNode::App(t, p) => {
+        out.push(Tok::App(t));
+        for e in env.terms[t].unpack_app(&store[p..]) {
+          expr(env, heap, store, nargs, dummies, out, e)
+        }
+      }
+    }
+  }
+  let td = &env.thms[tid];
+  let mut out = td.args.iter().map(|(_, ty)| match *ty {
+    Type::Bound(s) => Tok::Bound(s),
+    Type::Reg(s, deps) => Tok::Reg(s, deps),
+  }).collect::<Vec<_>>();
+  let mut dummies = HashMap::new();
+  for e in td.hyps.iter().map(|(_, e)| e).chain([&td.ret]) {
+    out.push(Tok::Hyp);
+    expr(env, &td.heap, &td.store, td.args.len(), &mut dummies, &mut out, e)
+  }
+  out
+}
+
+/// Run all the lints on an environment, returning the findings in declaration order.
+#[must_use] pub fn lint(env: &Environment) -> Vec<Finding> {
+  let decls = env.stmts.iter().filter_map(|s| match *s {
+    StmtTrace::Decl(a) => env.data[a].decl,
+    _ => None,
+  }).collect::<Vec<_>>();
+  let mut used = HashSet::new();
+  for &k in &decls { used.extend(env.direct_deps(k).into_iter().filter(|&d| d != k)) }
+  let is_public = |tid: ThmId| {
+    let td = &env.thms[tid];
+    td.vis.contains(Modifiers::PUB) || td.span.file.has_extension("mm0")
+  };
+  let (to_tid, mut axuse) = AxiomUse::new(env);
+  let mut needed = bit_set::BitSet::new();
+  for &k in &decls {
+    if let DeclKey::Thm(tid) = k {
+      if is_public(tid) && matches!(env.thms[tid].kind, ThmKind::Thm(_)) {
+        needed.union_with(axuse.get(env, tid))
+      }
+    }
+  }
+  // Index 0 means that some public theorem has no proof, so any axiom could be needed
+  let all_needed = needed.contains(0);
+  let needed = needed.iter().filter(|&i| i != 0).map(|i| to_tid[i]).collect::<HashSet<_>>();
+  let mut statements = HashMap::new();
+  let mut findings = vec![];
+  for &k in &decls {
+    let mut push = |lint, span: &FileSpan, msg, related| findings.push(Finding {
+      lint, span: span.clone(), msg, related
+    });
+    match k {
+      DeclKey::Term(t) => {
+        let td = &env.terms[t];
+        if matches!(td.kind, TermKind::Def(_)) && !used.contains(&k) {
+          push(Lint::UnusedDef, &td.span,
+            format!("definition '{}' is never used", env.decl_name(k)), vec![])
+        }
+      }
+      DeclKey::Thm(t) => {
+        let td = &env.thms[t];
+        match td.kind {
+          ThmKind::Thm(_) => if !is_public(t) && !used.contains(&k) {
+            push(Lint::UnusedThm, &td.span,
+              format!("local theorem '{}' is never used", env.decl_name(k)), vec![])
+          }
+          ThmKind::Axiom => if !all_needed && !needed.contains(&t) {
+            push(Lint::UnusedAxiom, &td.span,
+              format!("axiom '{}' is not needed by any public theorem", env.decl_name(k)), vec![])
+          }
+        }
+        match statements.entry(statement_key(env, t)) {
+          Entry::Vacant(e) => { e.insert(t); }
+          Entry::Occupied(e) => {
+            let old = DeclKey::Thm(*e.get());
+            push(Lint::DuplicateThm, &td.span,
+              format!("'{}' has the same statement as '{}'", env.decl_name(k), env.decl_name(old)),
+              vec![(env.thms[*e.get()].span.clone(), format!("'{}' declared here", env.decl_name(old)))])
+          }
+        }
+      }
+    }
+  }
+  findings
+}
+
+/// Lint an MM1/MM0 file for unused and duplicate declarations
+#[derive(clap::Args, Debug)]
+pub struct Args {
+  /// Don't report these lints (a comma separated list)
+  #[clap(long, arg_enum, value_name = "LINTS", use_value_delimiter = true)]
+  pub allow: Vec<Lint>,
+  /// Report error code 1 if there are any findings
+  #[clap(short = 'W', long)]
+  pub warn_as_error: bool,
+  /// Sets the input file (.mm1 or .mm0)
+  pub input: String,
+}
+
+impl Args {
+  /// Main entry point for `mm0-rs lint` subcommand.
+  ///
+  /// See the [module documentation](self) for the list of lints.
+  ///
+  /// # Arguments
+  ///
+  /// `mm0-rs lint <in.mm1>`, where:
+  ///
+  /// - `in.mm1` is the initial file to elaborate.
+  pub fn main(self) -> io::Result<()> {
+    let path: FileRef = fs::canonicalize(self.input)?.into();
+    let (_, old) = crate::compiler::elab_for_result(path)?;
+    let old = old.unwrap_or_else(|| std::process::exit(1));
+    let env = old.thaw_copy();
+    let mut count = 0;
+    for f in lint(&env) {
+      if self.allow.contains(&f.lint) { continue }
+      let msg = format!("{} [{}]", f.msg, f.lint.name());
+      let related = f.related.into_iter().map(|(sp, s)| (sp, s.into())).collect();
+      let kind = ElabErrorKind::Boxed(msg.into(), Some(related));
+      let e = ElabError { pos: f.span.span, level: ErrorLevel::Warning, kind };
+      crate::compiler::report_in(&f.span.file, &e)?;
+      count += 1;
+    }
+    println!("{} findings", count);
+    if self.warn_as_error && count != 0 { std::process::exit(1) }
+    Ok(())
+  }
+}
@@ -1,4 +1,8 @@
 //! Build documentation pages for MM1/MM0 files
+
+pub mod deps;
+pub mod lint;
+
 use std::{collections::{hash_map::Entry, HashMap}, hash::Hash, path::PathBuf};
 use bit_set::BitSet;
 use lsp_types::Url;
@@ -6,7 +10,7 @@ use pulldown_cmark::escape::WriteWrapper;
 use std::fs::{self, File};
 use std::io::{self, BufWriter, Write};
 use std::mem;
-use crate::{lisp::pretty::Annot, ArcString, AtomData, AtomId, DeclKey, DocComment, EnvMergeIter,
+use crate::{lisp::pretty::Annot, ArcString, AtomData, AtomId, DeclKey, DocComment,
   Environment, ExprNode, FileRef, FormatEnv, LinedString, LispVal, Proof, ProofNode, SliceUninit,
   StmtTrace, TermId, Thm, ThmId, ThmKind, Type, LispKind, Uncons};
 
@@ -759,9 +763,7 @@ impl Args {
     let (fc, old) = crate::compiler::elab_for_result(path.clone())?;
     let old = old.unwrap_or_else(|| std::process::exit(1));
     println!("writing docs");
-    let mut env = Environment::new();
-    assert!(matches!(
-      EnvMergeIter::new(&mut env, &old, (0..0).into()).next(&mut env, &mut vec![]), Ok(None)));
+    let env = old.thaw_copy();
     let mut dir = PathBuf::from(self.output.as_deref().unwrap_or("doc"));
     fs::create_dir_all(&dir)?;
     macro_rules! import {($($str:expr),*) => {$({
@@ -16,6 +16,8 @@ pub mod refine;
 pub mod proof;
 pub mod inout;
 pub mod verify;
+pub mod cache;
+pub mod timing;
 
 
 use std::collections::HashMap;
@@ -218,6 +220,7 @@ impl std::fmt::Debug for GoalListener {
 }
 
 /// The persistent elaborator options (which can be set at the command line)
+#[allow(clippy::struct_excessive_bools)]
 #[derive(Copy, Clone, Debug)]
 pub struct ElabOptions {
   /// True if we are checking proofs (otherwise we pretend every proof says `theorem foo = '?;`)
@@ -226,11 +229,14 @@ pub struct ElabOptions {
   pub check_parens: bool,
   /// If true, we will report a warning on declarations with unused variables.
   pub unused_vars: bool,
+  /// If true, we record the time spent on each statement and lisp procedure
+  /// (see [`timing`]).
+  pub timings: bool,
 }
 
 impl Default for ElabOptions {
   fn default() -> Self {
-    Self { check_proofs: true, check_parens: false, unused_vars: true }
+    Self { check_proofs: true, check_parens: false, unused_vars: true, timings: false }
   }
 }
 
@@ -274,6 +280,8 @@ pub struct Elaborator {
   arena: lisp::LispArena,
   /// A listener for goal view events.
   recv_goal: Option<GoalListener>,
+  /// The timing data for this file, if we are collecting it.
+  pub(crate) timings: Option<Box<timing::Timings>>,
 }
 
 impl Deref for Elaborator {
@@ -319,6 +327,7 @@ impl Elaborator {
       reporting: ReportMode::new(),
       arena: Default::default(),
       recv_goal,
+      timings: if options.timings { Some(Box::default()) } else { None },
     }
   }
 
@@ -622,6 +631,38 @@ impl Elaborator {
     }
     Ok(ElabStmt::Ok)
   }
+
+  /// A short description of a statement, for the timing report.
+  fn stmt_label(&self, stmt: &Stmt) -> String {
+    match &stmt.k {
+      &StmtKind::Sort(sp, _) => format!("sort {}", String::from_utf8_lossy(self.span(sp))),
+      StmtKind::Decl(d) => {
+        let kind = match d.k {
+          DeclKind::Term => "term",
+          DeclKind::Axiom => "axiom",
+          DeclKind::Thm => "theorem",
+          DeclKind::Def => "def",
+        };
+        format!("{} {}", kind, String::from_utf8_lossy(self.span(d.id)))
+      }
+      StmtKind::Do(_) => "do".into(),
+      StmtKind::Annot(_, s) | StmtKind::DocComment(_, s) => self.stmt_label(s),
+      _ => {
+        let text = self.span(stmt.span);
+        let word = text.split(u8::is_ascii_whitespace).next().unwrap_or_default();
+        String::from_utf8_lossy(word).into()
+      }
+    }
+  }
+
+  /// Record the time spent elaborating a top level statement.
+  fn record_stmt_time(&mut self, stmt: &Stmt, time: Duration) {
+    let name = self.stmt_label(stmt);
+    let span = self.fspan(stmt.span);
+    if let Some(t) = &mut self.timings {
+      t.stmts.push(timing::StmtTiming { span, name, time })
+    }
+  }
 }
 
 /// The result of elaboration of a dependent file.
@@ -786,7 +827,10 @@ where F: FnMut(FileRef) -> Result<Receiver<ElabResult<T>>, BoxError> {
           let ast = elab.ast.clone();
           while let Some(s) = ast.stmts.get(*idx) {
             if elab.cancel.load(Ordering::Relaxed) {break}
-            match elab.elab_stmt(String::new(), s, s.span) {
+            let start = elab.timings.as_ref().map(|_| Instant::now());
+            let res = elab.elab_stmt(String::new(), s, s.span);
+            if let Some(start) = start { elab.record_stmt_time(s, start.elapsed()) }
+            match res {
               Ok(ElabStmt::Ok) => {}
               Ok(ElabStmt::Import(sp)) => {
                 if let Some((file, recv)) = recv.remove(&sp) {
@@ -804,8 +848,9 @@ where F: FnMut(FileRef) -> Result<Receiver<ElabResult<T>>, BoxError> {
           break
         }
         lisp::LispArena::uninstall_thread_local();
-        let ElabFutureInner {elab: FrozenElaborator(elab), cyc, toks, ..} =
+        let ElabFutureInner {elab: FrozenElaborator(mut elab), cyc, toks, ..} =
           this.take().expect("impossible");
+        if let Some(timings) = elab.timings.take() { timing::record(*timings) }
         elab.arena.clear();
         Poll::Ready((cyc, toks, elab.errors, FrozenEnv::new(elab.env)))
       }
@@ -0,0 +1,1234 @@
+//! Serialization of elaborated environments, for the on-disk cache used by
+//! `mm0-rs compile --cache-dir`.
+//!
+//! A cache file consists of a [`Header`], which records the hash of the source file and the
+//! cache keys of the files it imports, followed by a binary encoding of the [`Environment`]
+//! that resulted from elaborating the file. Lisp values are encoded with sharing, so that
+//! mutable references (and closures that capture them) keep their identity, and reference
+//! cycles survive the round trip. Cycles can only pass through a [`LispKind::Ref`], so the
+//! contents of a reference are written after the outermost value being encoded is complete,
+//! at which point every value on the cycle has an index that can be referred back to.
+//!
+//! The format is private to this version of `mm0-rs`; a cache file written by a different
+//! version, or one that fails to decode for any other reason, is simply ignored.
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::io;
+use std::path::PathBuf;
+use std::rc::{Rc, Weak};
+use std::sync::Arc;
+use num::BigInt;
+use crate::{ArcString, AtomData, AtomId, AtomVec, Coe, DeclKey, Delims, DocComment, Environment,
+  Expr, ExprNode, FileRef, FileSpan, FrozenEnv, LispData, Literal, MergeStrategyInner, Modifiers,
+  NotaInfo, OneOrMore, OutputString, ParserEnv, Prec, Proof, ProofNode, Sort, SortId, SortVec,
+  Span, StmtTrace, Term, TermId, TermKind, TermVec, Thm, ThmId, ThmKind, ThmVec, Type};
+use super::lisp::{Annot, BuiltinProc, InferTarget, LispKind, LispRef, LispVal, LispWeak,
+  Proc, ProcPos, ProcSpec, Syntax, parser::{Ir, MVarPattern}};
+
+/// The magic number at the start of a cache file.
+const MAGIC: [u8; 4] = *b"MM1C";
+
+/// The version of the cache format. This should be bumped whenever the encoding
+/// of any of the types below changes.
+const VERSION: u32 = 2;
+
+fn invalid(msg: &str) -> io::Error { io::Error::new(io::ErrorKind::InvalidData, msg) }
+
+/// The header of a cache file, which is used to determine if the cache is still valid
+/// without decoding the environment.
+#[derive(Debug)]
+pub struct Header {
+  /// The hash of the source text that was elaborated.
+  pub src_hash: u64,
+  /// The files that were imported, along with their cache keys at the time
+  /// the cache file was written.
+  pub deps: Vec<(PathBuf, u64)>,
+}
+
+/// The state of the encoder. The tables are used to encode shared data by reference
+/// after the first occurrence.
+struct Encoder {
+  w: Vec<u8>,
+  files: HashMap<FileRef, usize>,
+  lisp: HashMap<*const LispKind, usize>,
+  /// The references whose contents have not been written yet.
+  refs: Vec<*const LispRef>,
+  /// The number of nested calls to [`Encoder::lisp`].
+  depth: usize,
+  code: HashMap<*const Ir, usize>,
+}
+
+/// The state of the decoder, containing the tables corresponding to those in [`Encoder`].
+struct Decoder<'a> {
+  buf: &'a [u8],
+  files: Vec<FileRef>,
+  lisp: Vec<LispVal>,
+  /// The references whose contents have not been read yet.
+  refs: Vec<LispVal>,
+  /// The number of nested calls to [`Decoder::lisp`].
+  depth: usize,
+  code: Vec<Arc<[Ir]>>,
+}
+
+impl Encoder {
+  fn u8(&mut self, n: u8) { self.w.push(n) }
+
+  fn u64(&mut self, mut n: u64) {
+    while n >= 0x80 {
+      #[allow(clippy::cast_possible_truncation)]
+      self.w.push((n as u8) | 0x80);
+      n >>= 7;
+    }
+    #[allow(clippy::cast_possible_truncation)]
+    self.w.push(n as u8)
+  }
+
+  fn usize(&mut self, n: usize) { self.u64(n as u64) }
+
+  fn bytes(&mut self, s: &[u8]) {
+    self.usize(s.len());
+    self.w.extend_from_slice(s)
+  }
+
+  /// Encode a lisp value, followed by the contents of all references in it
+  /// if this is not nested inside another lisp value.
+  fn lisp(&mut self, e: &LispKind) -> io::Result<()> {
+    self.depth += 1;
+    let res = self.lisp_shallow(e);
+    self.depth -= 1;
+    res?;
+    if self.depth == 0 {
+      self.depth = 1;
+      while let Some(r) = self.refs.pop() {
+        // Safety: the reference is part of the environment being encoded, which outlives us
+        match &*unsafe { &*r }.get_weak() {
+          LispWeak::Strong(e) => { self.u8(0); self.lisp_shallow(e)? }
+          LispWeak::Weak(e) if e.strong_count() == 0 => self.u8(1),
+          // Safety: the pointer is valid because the strong count is nonzero
+          LispWeak::Weak(e) => { self.u8(2); self.lisp_shallow(unsafe { &*e.as_ptr() })? }
+        }
+      }
+      self.depth = 0;
+    }
+    Ok(())
+  }
+
+  /// Encode a lisp value. Each value is assigned the next index when it is complete, so that
+  /// later occurrences can refer back to it. A reference is assigned its index immediately,
+  /// and its contents are deferred (see [`Encoder::lisp`]).
+  fn lisp_shallow(&mut self, e: &LispKind) -> io::Result<()> {
+    let ptr: *const LispKind = e;
+    if let Some(&i) = self.lisp.get(&ptr) {
+      self.u8(0);
+      self.usize(i);
+      return Ok(())
+    }
+    match e {
+      LispKind::Atom(a) => { self.u8(1); a.encode(self)? }
+      LispKind::List(es) => { self.u8(2); es.encode(self)? }
+      LispKind::DottedList(es, r) => { self.u8(3); es.encode(self)?; r.encode(self)? }
+      LispKind::Annot(Annot::Span(fsp), e) => { self.u8(4); fsp.encode(self)?; e.encode(self)? }
+      LispKind::Number(n) => { self.u8(5); n.encode(self)? }
+      LispKind::String(s) => { self.u8(6); s.encode(self)? }
+      LispKind::Bool(b) => { self.u8(7); b.encode(self)? }
+      LispKind::Syntax(s) => { self.u8(8); s.encode(self)? }
+      LispKind::Undef => self.u8(9),
+      LispKind::Proc(p) => { self.u8(10); p.encode(self)? }
+      LispKind::AtomMap(m) => { self.u8(11); m.encode(self)? }
+      LispKind::Ref(r) => {
+        self.u8(12);
+        let n = self.lisp.len();
+        self.lisp.insert(ptr, n);
+        self.refs.push(r);
+        return Ok(())
+      }
+      LispKind::MVar(n, tgt) => { self.u8(13); n.encode(self)?; tgt.encode(self)? }
+      LispKind::Goal(e) => { self.u8(14); e.encode(self)? }
+    }
+    let n = self.lisp.len();
+    self.lisp.insert(ptr, n);
+    Ok(())
+  }
+}
+
+impl<'a> Decoder<'a> {
+  fn u8(&mut self) -> io::Result<u8> {
+    let (&n, rest) = self.buf.split_first().ok_or_else(|| invalid("unexpected EOF"))?;
+    self.buf = rest;
+    Ok(n)
+  }
+
+  fn u64(&mut self) -> io::Result<u64> {
+    let (mut n, mut shift) = (0, 0);
+    loop {
+      let b = self.u8()?;
+      if shift >= 64 { return Err(invalid("varint overflow")) }
+      n |= u64::from(b & 0x7f) << shift;
+      if b & 0x80 == 0 { return Ok(n) }
+      shift += 7;
+    }
+  }
+
+  fn usize(&mut self) -> io::Result<usize> {
+    usize::try_from(self.u64()?).map_err(|_| invalid("integer overflow"))
+  }
+
+  fn bytes(&mut self) -> io::Result<&'a [u8]> {
+    let n = self.usize()?;
+    if n > self.buf.len() { return Err(invalid("unexpected EOF")) }
+    let (s, rest) = self.buf.split_at(n);
+    self.buf = rest;
+    Ok(s)
+  }
+
+  fn str(&mut self) -> io::Result<&'a str> {
+    std::str::from_utf8(self.bytes()?).map_err(|_| invalid("invalid UTF-8"))
+  }
+
+  fn lisp_ref(&self, i: usize) -> io::Result<LispVal> {
+    self.lisp.get(i).cloned().ok_or_else(|| invalid("invalid lisp reference"))
+  }
+
+  /// Decode a lisp value written by [`Encoder::lisp`].
+  fn lisp(&mut self) -> io::Result<LispVal> {
+    self.depth += 1;
+    let res = self.lisp_shallow();
+    self.depth -= 1;
+    let e = res?;
+    if self.depth == 0 {
+      self.depth = 1;
+      while let Some(r) = self.refs.pop() {
+        match self.u8()? {
+          0 => { let e = self.lisp_shallow()?; r.as_lref(|r| r.get_mut(|v| *v = e)) }
+          1 => r.as_lref(|r| *r.get_mut_weak() = LispWeak::Weak(Weak::new())),
+          2 => { let e = self.lisp_shallow()?; r.as_lref(|r| r.set_weak(&e)) }
+          _ => return Err(invalid("invalid ref"))
+        };
+      }
+      self.depth = 0;
+    }
+    Ok(e)
+  }
+
+  /// Decode a lisp value written by [`Encoder::lisp_shallow`].
+  fn lisp_shallow(&mut self) -> io::Result<LispVal> {
+    let e = match self.u8()? {
+      0 => { let i = self.usize()?; return self.lisp_ref(i) }
+      1 => LispVal::atom(Decode::decode(self)?),
+      2 => LispVal::list(Vec::<LispVal>::decode(self)?),
+      3 => LispVal::dotted_list(Vec::<LispVal>::decode(self)?, Decode::decode(self)?),
+      4 => LispVal::new(LispKind::Annot(Annot::Span(Decode::decode(self)?), Decode::decode(self)?)),
+      5 => LispVal::number(Decode::decode(self)?),
+      6 => LispVal::string(Decode::decode(self)?),
+      7 => LispVal::bool(Decode::decode(self)?),
+      8 => LispVal::syntax(Decode::decode(self)?),
+      9 => LispVal::undef(),
+      10 => LispVal::proc(Decode::decode(self)?),
+      11 => LispVal::new(LispKind::AtomMap(Decode::decode(self)?)),
+      12 => {
+        let r = LispVal::new_ref(LispVal::undef());
+        self.lisp.push(r.clone());
+        self.refs.push(r.clone());
+        return Ok(r)
+      }
+      13 => LispVal::new(LispKind::MVar(Decode::decode(self)?, Decode::decode(self)?)),
+      14 => LispVal::new(LispKind::Goal(Decode::decode(self)?)),
+      _ => return Err(invalid("invalid lisp value"))
+    };
+    self.lisp.push(e.clone());
+    Ok(e)
+  }
+}
+
+/// A type that can be written to a cache file.
+trait Encode {
+  fn encode(&self, e: &mut Encoder) -> io::Result<()>;
+}
+
+/// A type that can be read from a cache file.
+trait Decode: Sized {
+  fn decode(d: &mut Decoder<'_>) -> io::Result<Self>;
+}
+
+macro_rules! impl_int {
+  ($($ty:ty),*) => {$(
+    impl Encode for $ty {
+      fn encode(&self, e: &mut Encoder) -> io::Result<()> { e.u64((*self).into()); Ok(()) }
+    }
+    impl Decode for $ty {
+      fn decode(d: &mut Decoder<'_>) -> io::Result<Self> {
+        <$ty>::try_from(d.u64()?).map_err(|_| invalid("integer overflow"))
+      }
+    }
+  )*}
+}
+impl_int!(u8, u32, u64);
+
+impl Encode for usize {
+  fn encode(&self, e: &mut Encoder) -> io::Result<()> { e.usize(*self); Ok(()) }
+}
+impl Decode for usize {
+  fn decode(d: &mut Decoder<'_>) -> io::Result<Self> { d.usize() }
+}
+
+impl Encode for bool {
+  fn encode(&self, e: &mut Encoder) -> io::Result<()> { e.u8((*self).into()); Ok(()) }
+}
+impl Decode for bool {
+  fn decode(d: &mut Decoder<'_>) -> io::Result<Self> { Ok(d.u8()? != 0) }
+}
+
+macro_rules! impl_id {
+  ($($ty:ident: $inner:ty),*) => {$(
+    impl Encode for $ty {
+      fn encode(&self, e: &mut Encoder) -> io::Result<()> { self.0.encode(e) }
+    }
+    impl Decode for $ty {
+      fn decode(d: &mut Decoder<'_>) -> io::Result<Self> { Ok($ty(<$inner>::decode(d)?)) }
+    }
+  )*}
+}
+impl_id!(SortId: u8, TermId: u32, ThmId: u32, AtomId: u32);
+
+impl<T: Encode> Encode for [T] {
+  fn encode(&self, e: &mut Encoder) -> io::Result<()> {
+    e.usize(self.len());
+    self.iter().try_for_each(|t| t.encode(e))
+  }
+}
+impl<T: Encode> Encode for Vec<T> {
+  fn encode(&self, e: &mut Encoder) -> io::Result<()> { (**self).encode(e) }
+}
+impl<T: Encode + ?Sized> Encode for Box<T> {
+  fn encode(&self, e: &mut Encoder) -> io::Result<()> { (**self).encode(e) }
+}
+impl<T: Decode> Decode for Vec<T> {
+  fn decode(d: &mut Decoder<'_>) -> io::Result<Self> {
+    let n = d.usize()?;
+    // Don't trust `n` for the allocation, since the file might be corrupt
+    let mut v = Vec::with_capacity(n.min(d.buf.len()));
+    for _ in 0..n { v.push(T::decode(d)?) }
+    Ok(v)
+  }
+}
+impl<T: Decode> Decode for Box<[T]> {
+  fn decode(d: &mut Decoder<'_>) -> io::Result<Self> { Ok(Vec::decode(d)?.into()) }
+}
+
+impl<T: Encode> Encode for Option<T> {
+  fn encode(&self, e: &mut Encoder) -> io::Result<()> {
+    match self {
+      None => { e.u8(0); Ok(()) }
+      Some(t) => { e.u8(1); t.encode(e) }
+    }
+  }
+}
+impl<T: Decode> Decode for Option<T> {
+  fn decode(d: &mut Decoder<'_>) -> io::Result<Self> {
+    match d.u8()? {
+      0 => Ok(None),
+      1 => Ok(Some(T::decode(d)?)),
+      _ => Err(invalid("invalid option")),
+    }
+  }
+}
+
+impl<A: Encode, B: Encode> Encode for (A, B) {
+  fn encode(&self, e: &mut Encoder) -> io::Result<()> { self.0.encode(e)?; self.1.encode(e) }
+}
+impl<A: Decode, B: Decode> Decode for (A, B) {
+  fn decode(d: &mut Decoder<'_>) -> io::Result<Self> { Ok((A::decode(d)?, B::decode(d)?)) }
+}
+
+impl<K: Encode, V: Encode> Encode for HashMap<K, V> {
+  fn encode(&self, e: &mut Encoder) -> io::Result<()> {
+    e.usize(self.len());
+    self.iter().try_for_each(|(k, v)| { k.encode(e)?; v.encode(e) })
+  }
+}
+impl<K: Decode + Eq + Hash, V: Decode> Decode for HashMap<K, V> {
+  fn decode(d: &mut Decoder<'_>) -> io::Result<Self> {
+    let n = d.usize()?;
+    let mut m = HashMap::with_capacity(n.min(d.buf.len()));
+    for _ in 0..n { m.insert(K::decode(d)?, V::decode(d)?); }
+    Ok(m)
+  }
+}
+
+impl Encode for str {
+  fn encode(&self, e: &mut Encoder) -> io::Result<()> { e.bytes(self.as_bytes()); Ok(()) }
+}
+impl Encode for ArcString {
+  fn encode(&self, e: &mut Encoder) -> io::Result<()> { e.bytes(self); Ok(()) }
+}
+impl Decode for ArcString {
+  fn decode(d: &mut Decoder<'_>) -> io::Result<Self> { Ok(d.bytes()?.into()) }
+}
+impl Encode for DocComment {
+  fn encode(&self, e: &mut Encoder) -> io::Result<()> { (**self).encode(e) }
+}
+impl Decode for DocComment {
+  fn decode(d: &mut Decoder<'_>) -> io::Result<Self> { Ok(d.str()?.into()) }
+}
+
+impl Encode for BigInt {
+  fn encode(&self, e: &mut Encoder) -> io::Result<()> {
+    e.bytes(&self.to_signed_bytes_le()); Ok(())
+  }
+}
+impl Decode for BigInt {
+  fn decode(d: &mut Decoder<'_>) -> io::Result<Self> { Ok(BigInt::from_signed_bytes_le(d.bytes()?)) }
+}
+
+impl Encode for Span {
+  fn encode(&self, e: &mut Encoder) -> io::Result<()> {
+    e.usize(self.start); e.usize(self.end); Ok(())
+  }
+}
+impl Decode for Span {
+  fn decode(d: &mut Decoder<'_>) -> io::Result<Self> { Ok((d.usize()?..d.usize()?).into()) }
+}
+
+impl Encode for FileRef {
+  fn encode(&self, e: &mut Encoder) -> io::Result<()> {
+    let n = e.files.len();
+    let i = *e.files.entry(self.clone()).or_insert(n);
+    e.usize(i);
+    if i == n {
+      self.path().to_str().ok_or_else(|| invalid("non-unicode path"))?.encode(e)?
+    }
+    Ok(())
+  }
+}
+impl Decode for FileRef {
+  fn decode(d: &mut Decoder<'_>) -> io::Result<Self> {
+    let i = d.usize()?;
+    if i == d.files.len() {
+      let file = FileRef::from(PathBuf::from(d.str()?));
+      d.files.push(file);
+    }
+    d.files.get(i).cloned().ok_or_else(|| invalid("invalid file reference"))
+  }
+}
+
+impl Encode for FileSpan {
+  fn encode(&self, e: &mut Encoder) -> io::Result<()> { self.file.encode(e)?; self.span.encode(e) }
+}
+impl Decode for FileSpan {
+  fn decode(d: &mut Decoder<'_>) -> io::Result<Self> {
+    Ok(FileSpan { file: Decode::decode(d)?, span: Decode::decode(d)? })
+  }
+}
+
+impl Encode for Modifiers {
+  fn encode(&self, e: &mut Encoder) -> io::Result<()> { e.u8(self.bits()); Ok(()) }
+}
+impl Decode for Modifiers {
+  fn decode(d: &mut Decoder<'_>) -> io::Result<Self> { Ok(Modifiers::new(d.u8()?)) }
+}
+
+impl Encode for Prec {
+  fn encode(&self, e: &mut Encoder) -> io::Result<()> {
+    match *self {
+      Prec::Prec(n) => { e.u8(0); e.u64(n.into()) }
+      Prec::Max => e.u8(1),
+    }
+    Ok(())
+  }
+}
+impl Decode for Prec {
+  fn decode(d: &mut Decoder<'_>) -> io::Result<Self> {
+    match d.u8()? {
+      0 => Ok(Prec::Prec(Decode::decode(d)?)),
+      1 => Ok(Prec::Max),
+      _ => Err(invalid("invalid precedence")),
+    }
+  }
+}
+
+impl Encode for Type {
+  fn encode(&self, e: &mut Encoder) -> io::Result<()> {
+    match *self {
+      Type::Bound(s) => { e.u8(0); s.encode(e) }
+      Type::Reg(s, deps) => { e.u8(1); s.encode(e)?; deps.encode(e) }
+    }
+  }
+}
+impl Decode for Type {
+  fn decode(d: &mut Decoder<'_>) -> io::Result<Self> {
+    match d.u8()? {
+      0 => Ok(Type::Bound(Decode::decode(d)?)),
+      1 => Ok(Type::Reg(Decode::decode(d)?, Decode::decode(d)?)),
+      _ => Err(invalid("invalid type")),
+    }
+  }
+}
+
+impl Encode for ExprNode {
+  fn encode(&self, e: &mut Encoder) -> io::Result<()> {
+    match *self {
+      ExprNode::Ref(i) => { e.u8(0); e.usize(i); Ok(()) }
+      ExprNode::Dummy(a, s) => { e.u8(1); a.encode(e)?; s.encode(e) }
+      ExprNode::App(t, p) => { e.u8(2); t.encode(e)?; e.usize(p); Ok(()) }
+    }
+  }
+}
+impl Decode for ExprNode {
+  fn decode(d: &mut Decoder<'_>) -> io::Result<Self> {
+    match d.u8()? {
+      0 => Ok(ExprNode::Ref(d.usize()?)),
+      1 => Ok(ExprNode::Dummy(Decode::decode(d)?, Decode::decode(d)?)),
+      2 => Ok(ExprNode::App(Decode::decode(d)?, d.usize()?)),
+      _ => Err(invalid("invalid expr")),
+    }
+  }
+}
+
+impl Encode for ProofNode {
+  fn encode(&self, e: &mut Encoder) -> io::Result<()> {
+    match *self {
+      ProofNode::Ref(i) => { e.u8(0); e.usize(i) }
+      ProofNode::Dummy(a, s) => { e.u8(1); a.encode(e)?; s.encode(e)? }
+      ProofNode::Term(t, p) => { e.u8(2); t.encode(e)?; e.usize(p) }
+      ProofNode::Hyp(i, p) => { e.u8(3); e.usize(i); e.usize(p) }
+      ProofNode::Thm(t, p) => { e.u8(4); t.encode(e)?; e.usize(p) }
+      ProofNode::Conv(p) => { e.u8(5); e.usize(p) }
+      ProofNode::Refl(p) => { e.u8(6); e.usize(p) }
+      ProofNode::Sym(p) => { e.u8(7); e.usize(p) }
+      ProofNode::Cong(t, p) => { e.u8(8); t.encode(e)?; e.usize(p) }
+      ProofNode::Unfold(t, p) => { e.u8(9); t.encode(e)?; e.usize(p) }
+    }
+    Ok(())
+  }
+}
+impl Decode for ProofNode {
+  fn decode(d: &mut Decoder<'_>) -> io::Result<Self> {
+    Ok(match d.u8()? {
+      0 => ProofNode::Ref(d.usize()?),
+      1 => ProofNode::Dummy(Decode::decode(d)?, Decode::decode(d)?),
+      2 => ProofNode::Term(Decode::decode(d)?, d.usize()?),
+      3 => ProofNode::Hyp(d.usize()?, d.usize()?),
+      4 => ProofNode::Thm(Decode::decode(d)?, d.usize()?),
+      5 => ProofNode::Conv(d.usize()?),
+      6 => ProofNode::Refl(d.usize()?),
+      7 => ProofNode::Sym(d.usize()?),
+      8 => ProofNode::Cong(Decode::decode(d)?, d.usize()?),
+      9 => ProofNode::Unfold(Decode::decode(d)?, d.usize()?),
+      _ => return Err(invalid("invalid proof")),
+    })
+  }
+}
+
+impl Encode for Expr {
+  fn encode(&self, e: &mut Encoder) -> io::Result<()> { self.heap.encode(e)?; self.store.encode(e) }
+}
+impl Decode for Expr {
+  fn decode(d: &mut Decoder<'_>) -> io::Result<Self> {
+    Ok(Expr { heap: Decode::decode(d)?, store: Decode::decode(d)? })
+  }
+}
+
+impl Encode for Proof {
+  fn encode(&self, e: &mut Encoder) -> io::Result<()> {
+    self.heap.encode(e)?; self.hyps.encode(e)?; self.store.encode(e)
+  }
+}
+impl Decode for Proof {
+  fn decode(d: &mut Decoder<'_>) -> io::Result<Self> {
+    Ok(Proof { heap: Decode::decode(d)?, hyps: Decode::decode(d)?, store: Decode::decode(d)? })
+  }
+}
+
+impl Encode for Sort {
+  fn encode(&self, e: &mut Encoder) -> io::Result<()> {
+    self.atom.encode(e)?; self.name.encode(e)?; self.span.encode(e)?;
+    self.full.encode(e)?; self.doc.encode(e)?; self.mods.encode(e)
+  }
+}
+impl Decode for Sort {
+  fn decode(d: &mut Decoder<'_>) -> io::Result<Self> {
+    Ok(Sort {
+      atom: Decode::decode(d)?, name: Decode::decode(d)?, span: Decode::decode(d)?,
+      full: Decode::decode(d)?, doc: Decode::decode(d)?, mods: Decode::decode(d)?,
+    })
+  }
+}
+
+impl Encode for Term {
+  fn encode(&self, e: &mut Encoder) -> io::Result<()> {
+    self.atom.encode(e)?; self.span.encode(e)?; self.vis.encode(e)?;
+    self.full.encode(e)?; self.doc.encode(e)?; self.args.encode(e)?;
+    self.ret.encode(e)?;
+    match &self.kind {
+      TermKind::Term => { e.u8(0); Ok(()) }
+      TermKind::Def(val) => { e.u8(1); val.encode(e) }
+    }
+  }
+}
+impl Decode for Term {
+  fn decode(d: &mut Decoder<'_>) -> io::Result<Self> {
+    Ok(Term {
+      atom: Decode::decode(d)?, span: Decode::decode(d)?, vis: Decode::decode(d)?,
+      full: Decode::decode(d)?, doc: Decode::decode(d)?, args: Decode::decode(d)?,
+      ret: Decode::decode(d)?,
+      kind: match d.u8()? {
+        0 => TermKind::Term,
+        1 => TermKind::Def(Decode::decode(d)?),
+        _ => return Err(invalid("invalid term kind")),
+      },
+    })
+  }
+}
+
+impl Encode for Thm {
+  fn encode(&self, e: &mut Encoder) -> io::Result<()> {
+    self.atom.encode(e)?; self.span.encode(e)?; self.vis.encode(e)?;
+    self.full.encode(e)?; self.doc.encode(e)?; self.args.encode(e)?;
+    self.heap.encode(e)?; self.store.encode(e)?; self.hyps.encode(e)?;
+    self.ret.encode(e)?;
+    match &self.kind {
+      ThmKind::Axiom => { e.u8(0); Ok(()) }
+      ThmKind::Thm(pf) => { e.u8(1); pf.encode(e) }
+    }
+  }
+}
+impl Decode for Thm {
+  fn decode(d: &mut Decoder<'_>) -> io::Result<Self> {
+    Ok(Thm {
+      atom: Decode::decode(d)?, span: Decode::decode(d)?, vis: Decode::decode(d)?,
+      full: Decode::decode(d)?, doc: Decode::decode(d)?, args: Decode::decode(d)?,
+      heap: Decode::decode(d)?, store: Decode::decode(d)?, hyps: Decode::decode(d)?,
+      ret: Decode::decode(d)?,
+      kind: match d.u8()? {
+        0 => ThmKind::Axiom,
+        1 => ThmKind::Thm(Decode::decode(d)?),
+        _ => return Err(invalid("invalid theorem kind")),
+      },
+    })
+  }
+}
+
+impl Encode for StmtTrace {
+  fn encode(&self, e: &mut Encoder) -> io::Result<()> {
+    match self {
+      StmtTrace::Sort(a) => { e.u8(0); a.encode(e) }
+      StmtTrace::Decl(a) => { e.u8(1); a.encode(e) }
+      StmtTrace::Global(a) => { e.u8(2); a.encode(e) }
+      StmtTrace::OutputString(s) => {
+        e.u8(3);
+        s.span.encode(e)?; s.heap.encode(e)?; s.store.encode(e)?; s.exprs.encode(e)
+      }
+    }
+  }
+}
+impl Decode for StmtTrace {
+  fn decode(d: &mut Decoder<'_>) -> io::Result<Self> {
+    Ok(match d.u8()? {
+      0 => StmtTrace::Sort(Decode::decode(d)?),
+      1 => StmtTrace::Decl(Decode::decode(d)?),
+      2 => StmtTrace::Global(Decode::decode(d)?),
+      3 => StmtTrace::OutputString(Box::new(OutputString {
+        span: Decode::decode(d)?, heap: Decode::decode(d)?,
+        store: Decode::decode(d)?, exprs: Decode::decode(d)?,
+      })),
+      _ => return Err(invalid("invalid statement")),
+    })
+  }
+}
+
+impl Encode for DeclKey {
+  fn encode(&self, e: &mut Encoder) -> io::Result<()> {
+    match *self {
+      DeclKey::Term(t) => { e.u8(0); t.encode(e) }
+      DeclKey::Thm(t) => { e.u8(1); t.encode(e) }
+    }
+  }
+}
+impl Decode for DeclKey {
+  fn decode(d: &mut Decoder<'_>) -> io::Result<Self> {
+    match d.u8()? {
+      0 => Ok(DeclKey::Term(Decode::decode(d)?)),
+      1 => Ok(DeclKey::Thm(Decode::decode(d)?)),
+      _ => Err(invalid("invalid declaration")),
+    }
+  }
+}
+
+impl Encode for Literal {
+  fn encode(&self, e: &mut Encoder) -> io::Result<()> {
+    match self {
+      &Literal::Var(i, prec) => { e.u8(0); e.usize(i); prec.encode(e) }
+      Literal::Const(s) => { e.u8(1); s.encode(e) }
+    }
+  }
+}
+impl Decode for Literal {
+  fn decode(d: &mut Decoder<'_>) -> io::Result<Self> {
+    match d.u8()? {
+      0 => Ok(Literal::Var(d.usize()?, Decode::decode(d)?)),
+      1 => Ok(Literal::Const(Decode::decode(d)?)),
+      _ => Err(invalid("invalid literal")),
+    }
+  }
+}
+
+impl Encode for NotaInfo {
+  fn encode(&self, e: &mut Encoder) -> io::Result<()> {
+    self.span.encode(e)?; self.term.encode(e)?; self.nargs.encode(e)?;
+    self.rassoc.encode(e)?; self.lits.encode(e)
+  }
+}
+impl Decode for NotaInfo {
+  fn decode(d: &mut Decoder<'_>) -> io::Result<Self> {
+    Ok(NotaInfo {
+      span: Decode::decode(d)?, term: Decode::decode(d)?, nargs: Decode::decode(d)?,
+      rassoc: Decode::decode(d)?, lits: Decode::decode(d)?,
+    })
+  }
+}
+
+impl Encode for Arc<Coe> {
+  fn encode(&self, e: &mut Encoder) -> io::Result<()> {
+    match &**self {
+      Coe::One(fsp, t) => { e.u8(0); fsp.encode(e)?; t.encode(e) }
+      Coe::Trans(c1, s, c2) => { e.u8(1); c1.encode(e)?; s.encode(e)?; c2.encode(e) }
+    }
+  }
+}
+impl Decode for Arc<Coe> {
+  fn decode(d: &mut Decoder<'_>) -> io::Result<Self> {
+    Ok(Arc::new(match d.u8()? {
+      0 => Coe::One(Decode::decode(d)?, Decode::decode(d)?),
+      1 => Coe::Trans(Decode::decode(d)?, Decode::decode(d)?, Decode::decode(d)?),
+      _ => return Err(invalid("invalid coercion")),
+    }))
+  }
+}
+
+impl Encode for Delims {
+  fn encode(&self, e: &mut Encoder) -> io::Result<()> {
+    let chars = (0..=u8::MAX).filter(|&c| self.get(c)).collect::<Vec<_>>();
+    e.bytes(&chars);
+    Ok(())
+  }
+}
+impl Decode for Delims {
+  fn decode(d: &mut Decoder<'_>) -> io::Result<Self> {
+    let mut delims = Delims::default();
+    for &c in d.bytes()? { delims.set(c) }
+    Ok(delims)
+  }
+}
+
+impl Encode for ParserEnv {
+  fn encode(&self, e: &mut Encoder) -> io::Result<()> {
+    self.delims_l.encode(e)?; self.delims_r.encode(e)?; self.consts.encode(e)?;
+    self.prec_assoc.encode(e)?; self.prefixes.encode(e)?; self.infixes.encode(e)?;
+    self.coes.encode(e)?; self.coe_prov.encode(e)?; self.decl_nota.encode(e)
+  }
+}
+impl Decode for ParserEnv {
+  fn decode(d: &mut Decoder<'_>) -> io::Result<Self> {
+    Ok(ParserEnv {
+      delims_l: Decode::decode(d)?, delims_r: Decode::decode(d)?, consts: Decode::decode(d)?,
+      prec_assoc: Decode::decode(d)?, prefixes: Decode::decode(d)?, infixes: Decode::decode(d)?,
+      coes: Decode::decode(d)?, coe_prov: Decode::decode(d)?, decl_nota: Decode::decode(d)?,
+    })
+  }
+}
+
+impl Encode for LispVal {
+  fn encode(&self, e: &mut Encoder) -> io::Result<()> { e.lisp(self) }
+}
+impl Decode for LispVal {
+  fn decode(d: &mut Decoder<'_>) -> io::Result<Self> { d.lisp() }
+}
+
+impl Encode for Rc<MergeStrategyInner> {
+  fn encode(&self, e: &mut Encoder) -> io::Result<()> {
+    match &**self {
+      MergeStrategyInner::AtomMap(m) => { e.u8(0); m.encode(e) }
+      MergeStrategyInner::Custom(f) => { e.u8(1); f.encode(e) }
+    }
+  }
+}
+impl Decode for Rc<MergeStrategyInner> {
+  fn decode(d: &mut Decoder<'_>) -> io::Result<Self> {
+    Ok(Rc::new(match d.u8()? {
+      0 => MergeStrategyInner::AtomMap(Decode::decode(d)?),
+      1 => MergeStrategyInner::Custom(Decode::decode(d)?),
+      _ => return Err(invalid("invalid merge strategy")),
+    }))
+  }
+}
+
+impl Encode for LispData {
+  fn encode(&self, e: &mut Encoder) -> io::Result<()> {
+    self.src.encode(e)?; self.doc.encode(e)?; self.val.encode(e)?; self.merge.encode(e)
+  }
+}
+impl Decode for LispData {
+  fn decode(d: &mut Decoder<'_>) -> io::Result<Self> {
+    Ok(LispData {
+      src: Decode::decode(d)?, doc: Decode::decode(d)?,
+      val: Decode::decode(d)?, merge: Decode::decode(d)?,
+    })
+  }
+}
+
+macro_rules! impl_str_enum {
+  ($($ty:ident),*) => {$(
+    impl Encode for $ty {
+      fn encode(&self, e: &mut Encoder) -> io::Result<()> { self.to_str().encode(e) }
+    }
+    impl Decode for $ty {
+      fn decode(d: &mut Decoder<'_>) -> io::Result<Self> {
+        $ty::from_str(d.str()?).ok_or_else(|| invalid(concat!("invalid ", stringify!($ty))))
+      }
+    }
+  )*}
+}
+impl_str_enum!(Syntax, BuiltinProc);
+
+impl Encode for InferTarget {
+  fn encode(&self, e: &mut Encoder) -> io::Result<()> {
+    match *self {
+      InferTarget::Unknown => { e.u8(0); Ok(()) }
+      InferTarget::Provable => { e.u8(1); Ok(()) }
+      InferTarget::Bound(a) => { e.u8(2); a.encode(e) }
+      InferTarget::Reg(a) => { e.u8(3); a.encode(e) }
+    }
+  }
+}
+impl Decode for InferTarget {
+  fn decode(d: &mut Decoder<'_>) -> io::Result<Self> {
+    match d.u8()? {
+      0 => Ok(InferTarget::Unknown),
+      1 => Ok(InferTarget::Provable),
+      2 => Ok(InferTarget::Bound(Decode::decode(d)?)),
+      3 => Ok(InferTarget::Reg(Decode::decode(d)?)),
+      _ => Err(invalid("invalid infer target")),
+    }
+  }
+}
+
+impl Encode for ProcSpec {
+  fn encode(&self, e: &mut Encoder) -> io::Result<()> {
+    match *self {
+      ProcSpec::Exact(n) => { e.u8(0); e.usize(n) }
+      ProcSpec::AtLeast(n) => { e.u8(1); e.usize(n) }
+    }
+    Ok(())
+  }
+}
+impl Decode for ProcSpec {
+  fn decode(d: &mut Decoder<'_>) -> io::Result<Self> {
+    match d.u8()? {
+      0 => Ok(ProcSpec::Exact(d.usize()?)),
+      1 => Ok(ProcSpec::AtLeast(d.usize()?)),
+      _ => Err(invalid("invalid proc spec")),
+    }
+  }
+}
+
+impl Encode for ProcPos {
+  fn encode(&self, e: &mut Encoder) -> io::Result<()> {
+    match self {
+      ProcPos::Named(fsp, sp, a) => { e.u8(0); fsp.encode(e)?; sp.encode(e)?; a.encode(e) }
+      ProcPos::Unnamed(fsp) => { e.u8(1); fsp.encode(e) }
+      ProcPos::Builtin(p) => { e.u8(2); p.encode(e) }
+    }
+  }
+}
+impl Decode for ProcPos {
+  fn decode(d: &mut Decoder<'_>) -> io::Result<Self> {
+    match d.u8()? {
+      0 => Ok(ProcPos::Named(Decode::decode(d)?, Decode::decode(d)?, Decode::decode(d)?)),
+      1 => Ok(ProcPos::Unnamed(Decode::decode(d)?)),
+      2 => Ok(ProcPos::Builtin(Decode::decode(d)?)),
+      _ => Err(invalid("invalid proc position")),
+    }
+  }
+}
+
+impl Encode for Proc {
+  fn encode(&self, e: &mut Encoder) -> io::Result<()> {
+    match self {
+      Proc::Builtin(p) => { e.u8(0); p.encode(e) }
+      Proc::Lambda { pos, env, spec, code } => {
+        e.u8(1); pos.encode(e)?; env.encode(e)?; spec.encode(e)?; code.encode(e)
+      }
+      // A match continuation is only valid during the match that created it,
+      // so we store a dead one, like `Remap` does
+      Proc::MatchCont(_) => { e.u8(2); Ok(()) }
+      Proc::RefineCallback => { e.u8(3); Ok(()) }
+      Proc::MergeMap(m) => { e.u8(4); m.encode(e) }
+      Proc::ProofThunk(a, thunk) => {
+        e.u8(5); a.encode(e)?;
+        // Safety: the environment is frozen, so we must not change the borrow flag
+        match unsafe { thunk.try_borrow_unguarded() }.map_err(|_| invalid("proof thunk in use"))? {
+          Ok(pf) => { e.u8(0); pf.encode(e) }
+          Err(args) => { e.u8(1); args.encode(e) }
+        }
+      }
+      Proc::Dyn(_) => Err(invalid("cannot cache a compiler object")),
+    }
+  }
+}
+impl Decode for Proc {
+  fn decode(d: &mut Decoder<'_>) -> io::Result<Self> {
+    Ok(match d.u8()? {
+      0 => Proc::Builtin(Decode::decode(d)?),
+      1 => Proc::Lambda {
+        pos: Decode::decode(d)?, env: Decode::decode(d)?,
+        spec: Decode::decode(d)?, code: Decode::decode(d)?,
+      },
+      2 => Proc::MatchCont(Rc::new(Cell::new(false))),
+      3 => Proc::RefineCallback,
+      4 => Proc::MergeMap(Decode::decode(d)?),
+      5 => Proc::ProofThunk(Decode::decode(d)?, RefCell::new(match d.u8()? {
+        0 => Ok(Decode::decode(d)?),
+        1 => Err(Decode::decode(d)?),
+        _ => return Err(invalid("invalid proof thunk")),
+      })),
+      _ => return Err(invalid("invalid proc")),
+    })
+  }
+}
+
+impl Encode for Arc<[Ir]> {
+  fn encode(&self, e: &mut Encoder) -> io::Result<()> {
+    // Code blocks are nested, so like lisp values they get an index after their contents,
+    // and 0 is used to mark a new block
+    if let Some(&i) = e.code.get(&self.as_ptr()) { e.usize(i + 1); return Ok(()) }
+    e.u8(0);
+    (**self).encode(e)?;
+    let n = e.code.len();
+    e.code.insert(self.as_ptr(), n);
+    Ok(())
+  }
+}
+impl Decode for Arc<[Ir]> {
+  fn decode(d: &mut Decoder<'_>) -> io::Result<Self> {
+    match d.usize()? {
+      0 => {
+        let code: Arc<[Ir]> = Vec::<Ir>::decode(d)?.into();
+        d.code.push(code.clone());
+        Ok(code)
+      }
+      i => d.code.get(i - 1).cloned().ok_or_else(|| invalid("invalid code reference"))
+    }
+  }
+}
+
+impl Encode for Ir {
+  #[allow(clippy::too_many_lines)]
+  fn encode(&self, e: &mut Encoder) -> io::Result<()> {
+    match self {
+      &Ir::Drop(n) => { e.u8(0); e.usize(n) }
+      &Ir::DropAbove(n) => { e.u8(1); e.usize(n) }
+      Ir::Undef => e.u8(2),
+      Ir::Dup => e.u8(3),
+      &Ir::AssertScope(n) => { e.u8(4); e.usize(n) }
+      &Ir::EndScope(n) => { e.u8(5); e.usize(n) }
+      &Ir::Local(n) => { e.u8(6); e.usize(n) }
+      Ir::Global(sp, a) => { e.u8(7); sp.encode(e)?; a.encode(e)? }
+      Ir::Const(v) => { e.u8(8); v.encode(e)? }
+      &Ir::List(sp, n) => { e.u8(9); sp.encode(e)?; e.usize(n) }
+      &Ir::DottedList(n) => { e.u8(10); e.usize(n) }
+      Ir::App(tail, sp, n) => { e.u8(11); tail.encode(e)?; sp.encode(e)?; n.encode(e)? }
+      Ir::BuiltinApp(tail, p, sp, n) => {
+        e.u8(12); tail.encode(e)?; p.encode(e)?; sp.encode(e)?; n.encode(e)?
+      }
+      Ir::ArityError(sp, spec) => { e.u8(13); sp.encode(e)?; spec.encode(e)? }
+      Ir::AppHead(sp) => { e.u8(14); sp.encode(e)? }
+      &Ir::JumpUnless(ip) => { e.u8(15); e.usize(ip) }
+      &Ir::Jump(ip) => { e.u8(16); e.usize(ip) }
+      Ir::FocusStart(sp) => { e.u8(17); sp.encode(e)? }
+      Ir::FocusFinish => e.u8(18),
+      Ir::SetMergeStrategy(sp, a) => { e.u8(19); sp.encode(e)?; a.encode(e)? }
+      &Ir::LocalDef(n) => { e.u8(20); e.usize(n) }
+      Ir::GlobalDef(sp1, sp2, a) => { e.u8(21); sp1.encode(e)?; sp2.encode(e)?; a.encode(e)? }
+      Ir::SetDoc(doc, a) => { e.u8(22); doc.encode(e)?; a.encode(e)? }
+      Ir::Lambda(name, args) => {
+        e.u8(23); name.encode(e)?; args.0.encode(e)?; args.1.encode(e)?; args.2.encode(e)?
+      }
+      Ir::Branch(n, ip, cont) => { e.u8(24); n.encode(e)?; ip.encode(e)?; cont.encode(e)? }
+      Ir::TestPatternResume => e.u8(25),
+      Ir::BranchFail(sp) => { e.u8(26); sp.encode(e)? }
+      Ir::Map => e.u8(27),
+      Ir::Have => e.u8(28),
+      Ir::RefineResume => e.u8(29),
+      Ir::RefineGoal(b) => { e.u8(30); b.encode(e)? }
+      Ir::AddThm => e.u8(31),
+      Ir::MergeMap => e.u8(32),
+      Ir::PatternResult(b) => { e.u8(33); b.encode(e)? }
+      &Ir::PatternAtom(n) => { e.u8(34); e.usize(n) }
+      Ir::PatternQuoteAtom(a) => { e.u8(35); a.encode(e)? }
+      Ir::PatternString(s) => { e.u8(36); s.encode(e)? }
+      Ir::PatternBool(b) => { e.u8(37); b.encode(e)? }
+      Ir::PatternUndef => e.u8(38),
+      Ir::PatternNumber(n) => { e.u8(39); n.encode(e)? }
+      Ir::PatternMVar(p) => e.u8(match p {
+        MVarPattern::Unknown => 40,
+        MVarPattern::Any => 41,
+        MVarPattern::Simple => 42,
+      }),
+      Ir::PatternGoal => e.u8(43),
+      &Ir::PatternDottedList(n) => { e.u8(44); e.usize(n) }
+      Ir::PatternList(n, k) => { e.u8(45); n.encode(e)?; k.encode(e)? }
+      Ir::PatternTry(ok, err) => { e.u8(46); ok.encode(e)?; err.encode(e)? }
+      Ir::PatternTestPause => e.u8(47),
+      Ir::PatternQExprAtom(a) => { e.u8(48); a.encode(e)? }
+    }
+    Ok(())
+  }
+}
+impl Decode for Ir {
+  fn decode(d: &mut Decoder<'_>) -> io::Result<Self> {
+    Ok(match d.u8()? {
+      0 => Ir::Drop(d.usize()?),
+      1 => Ir::DropAbove(d.usize()?),
+      2 => Ir::Undef,
+      3 => Ir::Dup,
+      4 => Ir::AssertScope(d.usize()?),
+      5 => Ir::EndScope(d.usize()?),
+      6 => Ir::Local(d.usize()?),
+      7 => Ir::Global(Decode::decode(d)?, Decode::decode(d)?),
+      8 => Ir::Const(Decode::decode(d)?),
+      9 => Ir::List(Decode::decode(d)?, d.usize()?),
+      10 => Ir::DottedList(d.usize()?),
+      11 => Ir::App(Decode::decode(d)?, Box::new(Decode::decode(d)?), d.usize()?),
+      12 => Ir::BuiltinApp(Decode::decode(d)?, Decode::decode(d)?,
+        Box::new(Decode::decode(d)?), d.usize()?),
+      13 => Ir::ArityError(Decode::decode(d)?, Decode::decode(d)?),
+      14 => Ir::AppHead(Decode::decode(d)?),
+      15 => Ir::JumpUnless(d.usize()?),
+      16 => Ir::Jump(d.usize()?),
+      17 => Ir::FocusStart(Decode::decode(d)?),
+      18 => Ir::FocusFinish,
+      19 => Ir::SetMergeStrategy(Decode::decode(d)?, Decode::decode(d)?),
+      20 => Ir::LocalDef(d.usize()?),
+      21 => Ir::GlobalDef(Decode::decode(d)?, Decode::decode(d)?, Decode::decode(d)?),
+      22 => Ir::SetDoc(Decode::decode(d)?, Decode::decode(d)?),
+      23 => Ir::Lambda(Decode::decode(d)?,
+        Box::new((Decode::decode(d)?, Decode::decode(d)?, Decode::decode(d)?))),
+      24 => Ir::Branch(d.usize()?, d.usize()?, Decode::decode(d)?),
+      25 => Ir::TestPatternResume,
+      26 => Ir::BranchFail(Decode::decode(d)?),
+      27 => Ir::Map,
+      28 => Ir::Have,
+      29 => Ir::RefineResume,
+      30 => Ir::RefineGoal(Decode::decode(d)?),
+      31 => Ir::AddThm,
+      32 => Ir::MergeMap,
+      33 => Ir::PatternResult(Decode::decode(d)?),
+      34 => Ir::PatternAtom(d.usize()?),
+      35 => Ir::PatternQuoteAtom(Decode::decode(d)?),
+      36 => Ir::PatternString(Decode::decode(d)?),
+      37 => Ir::PatternBool(Decode::decode(d)?),
+      38 => Ir::PatternUndef,
+      39 => Ir::PatternNumber(Decode::decode(d)?),
+      40 => Ir::PatternMVar(MVarPattern::Unknown),
+      41 => Ir::PatternMVar(MVarPattern::Any),
+      42 => Ir::PatternMVar(MVarPattern::Simple),
+      43 => Ir::PatternGoal,
+      44 => Ir::PatternDottedList(d.usize()?),
+      45 => Ir::PatternList(d.usize()?, Decode::decode(d)?),
+      46 => Ir::PatternTry(d.usize()?, d.usize()?),
+      47 => Ir::PatternTestPause,
+      48 => Ir::PatternQExprAtom(Decode::decode(d)?),
+      _ => return Err(invalid("invalid instruction")),
+    })
+  }
+}
+
+impl Encode for Environment {
+  fn encode(&self, e: &mut Encoder) -> io::Result<()> {
+    e.usize(self.data.len());
+    for ad in &self.data.0 {
+      ad.name.encode(e)?; ad.lisp.encode(e)?;
+      ad.graveyard.encode(e)?; ad.sort.encode(e)?; ad.decl.encode(e)?;
+    }
+    self.sorts.0.encode(e)?;
+    match self.provable_sort {
+      OneOrMore::Zero => e.u8(0),
+      OneOrMore::One(s) => { e.u8(1); s.encode(e)? }
+      OneOrMore::More => e.u8(2),
+    }
+    self.pe.encode(e)?;
+    self.terms.0.encode(e)?;
+    self.thms.0.encode(e)?;
+    self.stmts.encode(e)
+  }
+}
+impl Decode for Environment {
+  fn decode(d: &mut Decoder<'_>) -> io::Result<Self> {
+    // The builtin atoms are allocated by `Environment::new`, and we check that
+    // the cache file agrees on their names.
+    let mut env = Environment::new();
+    let builtins = env.data.len();
+    let n = d.usize()?;
+    if n < builtins { return Err(invalid("missing builtin atoms")) }
+    let mut data = Vec::with_capacity(n.min(d.buf.len()));
+    for i in 0..n {
+      let name = ArcString::decode(d)?;
+      if i < builtins && env.data.0[i].name != name {
+        return Err(invalid("builtin atoms do not match"))
+      }
+      data.push(AtomData {
+        name,
+        lisp: Decode::decode(d)?,
+        graveyard: Option::<(FileSpan, Span)>::decode(d)?.map(Box::new),
+        sort: Decode::decode(d)?,
+        decl: Decode::decode(d)?,
+      });
+    }
+    env.atoms = data.iter().enumerate().map(|(i, ad)| {
+      #[allow(clippy::cast_possible_truncation)]
+      (ad.name.clone(), AtomId(i as u32))
+    }).collect();
+    env.data = AtomVec(data);
+    env.sorts = SortVec(Decode::decode(d)?);
+    env.provable_sort = match d.u8()? {
+      0 => OneOrMore::Zero,
+      1 => OneOrMore::One(Decode::decode(d)?),
+      2 => OneOrMore::More,
+      _ => return Err(invalid("invalid provable sort")),
+    };
+    env.pe = Decode::decode(d)?;
+    env.terms = TermVec(Decode::decode(d)?);
+    env.thms = ThmVec(Decode::decode(d)?);
+    env.stmts = Decode::decode(d)?;
+    Ok(env)
+  }
+}
+
+impl Encode for Header {
+  fn encode(&self, e: &mut Encoder) -> io::Result<()> {
+    e.w.extend_from_slice(&MAGIC);
+    e.u64(VERSION.into());
+    env!("CARGO_PKG_VERSION").encode(e)?;
+    self.src_hash.encode(e)?;
+    e.usize(self.deps.len());
+    for (path, key) in &self.deps {
+      path.to_str().ok_or_else(|| invalid("non-unicode path"))?.encode(e)?;
+      key.encode(e)?;
+    }
+    Ok(())
+  }
+}
+impl Decode for Header {
+  fn decode(d: &mut Decoder<'_>) -> io::Result<Self> {
+    if d.buf.get(..4) != Some(&MAGIC) { return Err(invalid("not a cache file")) }
+    d.buf = &d.buf[4..];
+    if d.u64()? != u64::from(VERSION) || d.str()? != env!("CARGO_PKG_VERSION") {
+      return Err(invalid("cache file version mismatch"))
+    }
+    let src_hash = d.u64()?;
+    let n = d.usize()?;
+    let mut deps = Vec::with_capacity(n.min(d.buf.len()));
+    for _ in 0..n { deps.push((PathBuf::from(d.str()?), d.u64()?)) }
+    Ok(Header { src_hash, deps })
+  }
+}
+
+/// Serialize a cache file with the given header and environment.
+///
+/// This fails if the environment contains data that cannot be cached,
+/// such as the state of the MMC compiler.
+pub fn write(header: &Header, env: &FrozenEnv) -> io::Result<Vec<u8>> {
+  let mut e = Encoder {
+    w: vec![], files: HashMap::new(), lisp: HashMap::new(), refs: vec![], depth: 0,
+    code: HashMap::new()
+  };
+  header.encode(&mut e)?;
+  // Safety: We only read from the environment, without touching any reference counts
+  unsafe { env.thaw() }.encode(&mut e)?;
+  Ok(e.w)
+}
+
+/// Read the header of a cache file, returning the header and the remainder of the file.
+pub fn read_header(buf: &[u8]) -> io::Result<(Header, &[u8])> {
+  let mut d = Decoder { buf, files: vec![], lisp: vec![], refs: vec![], depth: 0, code: vec![] };
+  let header = Header::decode(&mut d)?;
+  Ok((header, d.buf))
+}
+
+/// Read the environment from a cache file, given the data after the header
+/// (as returned by [`read_header`]).
+pub fn read_env(buf: &[u8]) -> io::Result<FrozenEnv> {
+  let mut d = Decoder { buf, files: vec![], lisp: vec![], refs: vec![], depth: 0, code: vec![] };
+  let env = Environment::decode(&mut d)?;
+  if !d.buf.is_empty() { return Err(invalid("trailing data")) }
+  Ok(FrozenEnv::new(env))
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod test {
+  use std::io::Cursor;
+  use std::sync::Arc;
+  use futures::channel::oneshot::channel;
+  use futures::executor::block_on;
+  use mm1_parser::parse;
+  use crate::{FileRef, FrozenEnv};
+  use crate::compiler::elab_for_result;
+  use crate::elab::{ElabResult, ElaborateBuilder};
+  use crate::mmb::export::Exporter;
+
+  fn example(file: &str) -> FileRef {
+    let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("../examples").join(file);
+    path.canonicalize().unwrap().into()
+  }
+
+  /// Write an environment to a cache file and read it back.
+  fn round_trip(env: &FrozenEnv) -> FrozenEnv {
+    let buf = super::write(&super::Header { src_hash: 0, deps: vec![] }, env).unwrap();
+    let (_, rest) = super::read_header(&buf).unwrap();
+    super::read_env(rest).unwrap()
+  }
+
+  /// Elaborate `file`, resolving all of its imports to `dep`, and return the MMB output.
+  fn elab_mmb(file: &FileRef, dep: &FrozenEnv) -> Vec<u8> {
+    let text = std::fs::read_to_string(file.path()).unwrap();
+    let ast = Arc::new(parse(Arc::new(text.into()), None).1);
+    let (cyc, _, errors, env) = block_on(ElaborateBuilder {
+      ast: &ast,
+      path: file.clone(),
+      mm0_mode: false,
+      options: crate::get_options(),
+      report_upstream_errors: true,
+      cancel: Arc::default(),
+      old: None,
+      recv_dep: |_| {
+        let (send, recv) = channel();
+        assert!(send.send(ElabResult::Ok((), None, dep.clone())).is_ok());
+        Ok(recv)
+      },
+      recv_goal: None,
+    }.elab());
+    assert!(cyc.is_none());
+    assert!(errors.is_empty(), "{}", errors.iter().map(|e| e.kind.msg()).collect::<Vec<_>>().join("\n"));
+    let mut out = Cursor::new(vec![]);
+    let mut report = |_, e: &str| panic!("{}", e);
+    let mut ex = Exporter::new(file.clone(), Some(&ast.source), &env, &mut report, &mut out);
+    ex.run(true).unwrap();
+    ex.finish().unwrap();
+    out.into_inner()
+  }
+
+  /// The tactics in `peano_hex.mm1` use the lisp definitions of `peano.mm1`, including
+  /// recursive closures, so elaborating it against the decoded environment must give exactly
+  /// the same result as against the original one.
+  #[test] fn round_trip_import() {
+    let peano = elab_for_result(example("peano.mm1")).unwrap().1.unwrap();
+    let hex = example("peano_hex.mm1");
+    let expected = elab_mmb(&hex, &peano);
+    let decoded = round_trip(&peano);
+    assert!(elab_mmb(&hex, &decoded) == expected);
+    assert!(elab_mmb(&hex, &round_trip(&decoded)) == expected);
+  }
+}
@@ -4,7 +4,7 @@ use std::ops::Deref;
 use std::rc::Rc;
 use std::sync::Arc;
 use std::fmt::Write;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use super::{BoxError, ElabError, FrozenEnv, FrozenLispVal, spans::Spans, verify::VERIFY_ON_ADD};
 use crate::{ArcString, AtomId, AtomVec, DocComment, FileRef, FileSpan, HashMapExt, Modifiers,
   Prec, SortId, SortVec, Span, TermId, TermVec, ThmId, ThmVec,
@@ -330,7 +330,7 @@ pub enum StmtTrace {
 /// A declaration is either a [`Term`] or a [`Thm`]. This is done because in MM1
 /// Terms and Thms share a namespace (although they are put in separate number-spaces
 /// for compilation to MM0).
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum DeclKey {
   /// A term or def, with its Id
   Term(TermId),
@@ -1123,6 +1123,61 @@ impl Environment {
   #[must_use] pub fn thm(&self, a: AtomId) -> Option<ThmId> {
     if let Some(DeclKey::Thm(i)) = self.data[a].decl { Some(i) } else { None }
   }
+
+  /// Get the name of a declaration.
+  #[must_use] pub fn decl_name(&self, key: DeclKey) -> &ArcString {
+    let a = match key {
+      DeclKey::Term(tid) => self.terms[tid].atom,
+      DeclKey::Thm(tid) => self.thms[tid].atom,
+    };
+    &self.data[a].name
+  }
+
+  /// Get the keyword for the kind of a declaration: `term`, `def`, `axiom` or `theorem`.
+  #[must_use] pub fn decl_kind(&self, key: DeclKey) -> &'static str {
+    match key {
+      DeclKey::Term(tid) => match self.terms[tid].kind {
+        TermKind::Term => "term",
+        TermKind::Def(_) => "def",
+      },
+      DeclKey::Thm(tid) => match self.thms[tid].kind {
+        ThmKind::Axiom => "axiom",
+        ThmKind::Thm(_) => "theorem",
+      },
+    }
+  }
+
+  /// Get the direct dependencies of a declaration, in order of first use.
+  #[must_use] pub fn direct_deps(&self, key: DeclKey) -> Vec<DeclKey> {
+    let mut deps = vec![];
+    let mut seen = HashSet::new();
+    let mut push = |k| if seen.insert(k) { deps.push(k) };
+    match key {
+      DeclKey::Term(tid) => if let TermKind::Def(Some(e)) = &self.terms[tid].kind {
+        for e in e.heap.iter().chain(&*e.store) {
+          if let ExprNode::App(t, _) = *e { push(DeclKey::Term(t)) }
+        }
+      }
+      DeclKey::Thm(tid) => {
+        let td = &self.thms[tid];
+        let stmt = td.hyps.iter().map(|(_, e)| e).chain([&td.ret]);
+        for e in td.heap.iter().chain(&*td.store).chain(stmt) {
+          if let ExprNode::App(t, _) = *e { push(DeclKey::Term(t)) }
+        }
+        if let ThmKind::Thm(Some(pf)) = &td.kind {
+          for p in pf.heap.iter().chain(&*pf.store) {
+            match *p {
+              ProofNode::Term(t, _) | ProofNode::Cong(t, _) | ProofNode::Unfold(t, _) =>
+                push(DeclKey::Term(t)),
+              ProofNode::Thm(t, _) => push(DeclKey::Thm(t)),
+              _ => {}
+            }
+          }
+        }
+      }
+    }
+    deps
+  }
 }
 
 /// Adding an item (sort, term, theorem, atom) can result in a redeclaration error,
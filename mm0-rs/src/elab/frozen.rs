@@ -37,7 +37,7 @@ use std::sync::Arc;
 use std::rc::Rc;
 use std::collections::{HashMap, hash_map::Entry};
 use num::BigInt;
-use crate::{mk_lisp_kind, ArcString, AtomData, AtomId, AtomVec, DeclKey, DocComment, Environment,
+use crate::{mk_lisp_kind, ArcString, AtomData, AtomId, AtomVec, DeclKey, DocComment, EnvMergeIter, Environment,
   FileSpan, LinedString, LispData, LispKind, LispVal, MergeStrategy, MergeStrategyInner, ParserEnv, Sort,
   SortId, SortVec, Span, StmtTrace, Term, TermId, TermVec, Thm, ThmId, ThmVec,
   lisp::{print::FormatEnv, Annot, InferTarget, LispRef, LispWeak, Proc, Syntax}};
@@ -64,6 +64,14 @@ impl FrozenEnv {
   /// [`Rc::clone()`] should be avoided because it could race with other readers.
   #[must_use] pub unsafe fn thaw(&self) -> &Environment { &self.0 }
 
+  /// Make a mutable copy of the environment, by merging it into a new [`Environment`].
+  #[must_use] pub fn thaw_copy(&self) -> Environment {
+    let mut env = Environment::new();
+    assert!(matches!(
+      EnvMergeIter::new(&mut env, self, (0..0).into()).next(&mut env, &mut vec![]), Ok(None)));
+    env
+  }
+
   /// Create a [`FormatEnv`] object, which can be used to print objects.
   /// # Safety
   /// TODO: this gives out an `&Environment`, even though it is frozen. Don't abuse it
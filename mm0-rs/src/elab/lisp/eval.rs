@@ -475,6 +475,21 @@ struct CallStack<'a> {
   arc: Option<Arc<[Ir]>>,
   span: FileSpan,
   pos: ProcPos,
+  /// The time at which the procedure was called, if we are recording timings.
+  timer: Option<Instant>,
+}
+
+/// The atom naming a procedure, if it has one.
+fn proc_name(pos: &ProcPos) -> Option<AtomId> {
+  if let ProcPos::Named(_, _, a) = *pos { Some(a) } else { None }
+}
+
+/// Record the time spent in the procedure call represented by `frame`.
+fn record_proc_time(elab: &mut Elaborator, frame: &CallStack<'_>) {
+  if let (Some(start), ProcPos::Named(fsp, _, a)) = (frame.timer, &frame.pos) {
+    let name = elab.data[*a].name.clone();
+    if let Some(t) = &mut elab.timings { t.add_proc(&name, fsp, 1, start.elapsed()) }
+  }
 }
 
 /// The lisp evaluation context, representing a lisp evaluation in progress.
@@ -509,6 +524,13 @@ impl<'a> Deref for Evaluator<'a> {
 impl<'a> DerefMut for Evaluator<'a> {
   fn deref_mut(&mut self) -> &mut Elaborator { self.elab }
 }
+impl Drop for Evaluator<'_> {
+  /// If evaluation stopped with an error, the procedures that were still running
+  /// are recorded with the time spent in them up to the error.
+  fn drop(&mut self) {
+    while let Some(frame) = self.call_stack.pop() { record_proc_time(self.elab, &frame) }
+  }
+}
 
 macro_rules! stack_match {
   (let $x:ident as $pat:pat = $e:expr) => {
@@ -1412,6 +1434,13 @@ impl<'a> Evaluator<'a> {
     //   println!();
     // }
     if let Some(fsp) = pos.fspan() { self.file = fsp.file.clone() }
+    // Only the outermost call to a recursive procedure is timed, so that
+    // time is not counted twice
+    let timer = match (&self.timings, proc_name(&pos)) {
+      (Some(_), Some(a)) if !self.call_stack.iter()
+        .any(|f| f.timer.is_some() && proc_name(&f.pos) == Some(a)) => Some(Instant::now()),
+      _ => None,
+    };
     if tail {
       if let Some(frame) = self.call_stack.last_mut() {
         self.code = code;
@@ -1419,13 +1448,17 @@ impl<'a> Evaluator<'a> {
         self.ip = 0;
         frame.arc = arc;
         frame.span = span;
+        if proc_name(&frame.pos) != proc_name(&pos) || frame.timer.is_none() {
+          record_proc_time(self.elab, frame);
+          frame.timer = timer;
+        }
         frame.pos = pos;
         return
       }
     }
     self.stack.push(Stack::Ret);
     self.call_stack.push(CallStack {
-      arc, span, pos,
+      arc, span, pos, timer,
       parent_code: mem::replace(&mut self.code, code),
       parent_ip: mem::take(&mut self.ip),
       parent_ctx: mem::replace(&mut self.ctx, ctx),
@@ -1434,6 +1467,7 @@ impl<'a> Evaluator<'a> {
 
   fn ret(&mut self) {
     let frame = self.call_stack.pop().expect("underflow");
+    record_proc_time(self.elab, &frame);
     self.file = frame.span.file;
     self.code = frame.parent_code;
     self.ctx = frame.parent_ctx;
@@ -0,0 +1,78 @@
+//! Elaboration timing data, collected for `mm0-rs compile --timings`.
+//!
+//! When the [`timings`](super::ElabOptions::timings) option is set, each [`Elaborator`]
+//! records the wall time spent on each top level statement, as well as the time spent
+//! in each named lisp procedure. At the end of elaboration these are added to a global
+//! table, which can be retrieved with [`take`]. Files loaded from the elaboration cache
+//! are not elaborated, so they are only listed in [`Timings::cached`].
+//!
+//! [`Elaborator`]: super::Elaborator
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+use once_cell::sync::Lazy;
+use crate::{ArcString, FileRef, FileSpan, MutexExt};
+
+/// The time spent elaborating a single statement.
+#[derive(Debug)]
+pub struct StmtTiming {
+  /// The location of the statement.
+  pub span: FileSpan,
+  /// A short description of the statement, like `theorem foo` or `do`.
+  pub name: String,
+  /// The total time spent elaborating the statement.
+  pub time: Duration,
+}
+
+/// The time spent in a lisp procedure.
+#[derive(Debug)]
+pub struct ProcTiming {
+  /// The location of the procedure definition.
+  pub span: FileSpan,
+  /// The number of calls to the procedure (not including recursive calls).
+  pub calls: u64,
+  /// The total time spent in the procedure, including the time spent in any
+  /// procedures called from it.
+  pub time: Duration,
+}
+
+/// The timing data for one or more files.
+#[derive(Debug, Default)]
+pub struct Timings {
+  /// The statements, in the order they were elaborated.
+  pub stmts: Vec<StmtTiming>,
+  /// The named lisp procedures, indexed by name.
+  pub procs: HashMap<ArcString, ProcTiming>,
+  /// The files which were loaded from the cache instead of being elaborated.
+  pub cached: Vec<FileRef>,
+}
+
+impl Timings {
+  /// Record a call to the procedure `name`, defined at `span`, that took time `time`.
+  pub fn add_proc(&mut self, name: &ArcString, span: &FileSpan, calls: u64, time: Duration) {
+    let t = self.procs.entry(name.clone()).or_insert_with(||
+      ProcTiming { span: span.clone(), calls: 0, time: Duration::ZERO });
+    t.calls += calls;
+    t.time += time;
+  }
+
+  /// Add the data in `other` to this table.
+  pub fn append(&mut self, other: Timings) {
+    self.stmts.extend(other.stmts);
+    for (name, t) in other.procs { self.add_proc(&name, &t.span, t.calls, t.time) }
+    self.cached.extend(other.cached);
+  }
+}
+
+/// The global table of timing data from all elaborated files.
+static TIMINGS: Lazy<Mutex<Timings>> = Lazy::new(Default::default);
+
+/// Add timing data from an elaborated file to the global table.
+pub(crate) fn record(timings: Timings) { TIMINGS.ulock().append(timings) }
+
+/// Add a file which was loaded from the cache to the global table.
+pub(crate) fn record_cached(path: FileRef) { TIMINGS.ulock().cached.push(path) }
+
+/// Take all the timing data recorded so far, leaving the global table empty.
+pub fn take() -> Timings { std::mem::take(&mut *TIMINGS.ulock()) }
@@ -287,6 +287,10 @@ pub struct Bound {
 macro_rules! vassert { ($e:expr, $v:expr) => { if !$e { return Err($v) } }}
 
 impl Bound {
+  /// A bound that allows only the theorems before `thm` to be used, as when checking
+  /// the proof of `thm` itself.
+  #[must_use] pub fn before_thm(thm: ThmId) -> Self { Bound { thm: Some(thm), ..Default::default() } }
+
   fn check_sort<'a>(&self, s: SortId) -> Result<(), VerifyError<'a>> {
     match self.sort {
       Some(s2) if s2 <= s => Err(VerifyError::FwdReferenceSort(s)),
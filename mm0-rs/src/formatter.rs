@@ -0,0 +1,66 @@
+//! Format MM1/MM0 files
+//!
+//! This implements `mm0-rs fmt`, which rewrites MM1/MM0 files in place using the
+//! formatter in [`mm1_parser::fmt`]. The formatter keeps the line breaks, comments and
+//! doc comments of the file and fixes the indentation and spacing around them, so it
+//! is safe to run on any file that parses. With `--check`, the files are not changed,
+//! and instead the command fails if any of them is not already formatted, which is
+//! useful for CI.
+use std::fs;
+use std::io;
+use std::sync::Arc;
+use mm0_util::LinedString;
+use mm1_parser::{parse, ErrorLevel};
+
+/// Format MM1/MM0 files
+#[derive(clap::Args, Debug)]
+pub struct Args {
+  /// Don't write the files, but exit with an error if any of them is not formatted
+  #[clap(long)]
+  pub check: bool,
+  /// Sets the input files (.mm1 or .mm0)
+  #[clap(required = true)]
+  pub files: Vec<String>,
+}
+
+impl Args {
+  /// Main entry point for `mm0-rs fmt` subcommand.
+  ///
+  /// See the [module documentation](self) for the purpose of this command.
+  ///
+  /// # Arguments
+  ///
+  /// `mm0-rs fmt [--check] <file.mm1>...`, where:
+  ///
+  /// - `file.mm1` (or `file.mm0`) is a file to format in place.
+  /// - `--check` reports the files which are not formatted instead of changing them.
+  pub fn main(self) -> io::Result<()> {
+    let mut ok = true;
+    for file in &self.files {
+      let src = Arc::new(LinedString::from(fs::read_to_string(file)?));
+      let (_, ast) = parse(src.clone(), None);
+      let errors = ast.errors.iter().filter(|e| e.level == ErrorLevel::Error).collect::<Vec<_>>();
+      if !errors.is_empty() {
+        for e in errors {
+          let pos = src.to_pos(e.pos.start);
+          eprintln!("{}:{}:{}: error: {}", file, pos.line + 1, pos.character + 1, e.msg);
+        }
+        eprintln!("error: not formatting {} because it has parse errors", file);
+        ok = false;
+        continue
+      }
+      let out = mm1_parser::fmt::format(&ast);
+      if out == **src { continue }
+      if self.check {
+        let line = src.lines().zip(out.lines()).take_while(|(a, b)| a == b).count();
+        println!("{}:{}: file is not formatted", file, line + 1);
+        ok = false;
+      } else {
+        fs::write(file, out)?;
+        println!("formatted {}", file);
+      }
+    }
+    if !ok { std::process::exit(1) }
+    Ok(())
+  }
+}
@@ -12,17 +12,399 @@
 //! of the `import "peano.mm0";` statement. The resulting file will be a proper MM0 file and
 //! can be run through the `mm0-c` verifier and other conforming verifiers.
 //!
+//! With `--only THM[,THM..]`, the file is elaborated first, and only the statements needed
+//! by the given theorems are printed, in their original order and with their doc comments.
+//! These are the statements which add the terms, definitions and theorems they depend on
+//! (transitively), the sorts that those use, and the notations and coercions for the terms.
+//! Since the output is elaborated again, the lisp code of these statements has to work too,
+//! so the declarations it mentions are needed as well, and so are the definitions in `do`
+//! blocks of the lisp functions it uses, like the tactics in a proof or the
+//! `refine-extra-args` callback. Of the other forms in a `do` block, only those that
+//! register something for the needed declarations, like `(add-eq-thm 'foo)`, are kept, and
+//! annotations like `@(register-eqd 'foo)` are kept with the declaration they annotate.
+//! `delimiter` statements are always kept, and `input` and `output` statements are
+//! dropped. This is useful for getting a minimal standalone file for a single result,
+//! for example to send a bug report.
+//!
 //! [`mm0.mm0`]: https://github.com/digama0/mm0/blob/master/examples/mm0.mm0
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::io::{self, Write};
 use std::path::PathBuf;
 use std::sync::Arc;
-use mm0_util::{FileRef, LinedString};
-use mm1_parser::{parse, ast::StmtKind};
+use mm0_util::{FileRef, LinedString, Span};
+use mm1_parser::{parse, ast::{Atom, SExpr, SExprKind, Stmt, StmtKind}};
+use crate::{AtomId, DeclKey, Environment, ExprNode, ProofNode, SortId, StmtTrace,
+  TermKind, ThmKind};
+
+/// Get the file imported by `import "f";` in the file `path`.
+fn resolve_import(path: &FileRef, f: &[u8]) -> io::Result<FileRef> {
+  let f = std::str::from_utf8(f).map_err(|_|
+    io::Error::new(io::ErrorKind::InvalidInput, "invalid utf8"))?;
+  Ok(path.path().parent()
+    .map_or_else(|| PathBuf::from(f), |p| p.join(f))
+    .canonicalize()?.into())
+}
+
+/// A parsed file: its path, contents and statements.
+type ParsedFile = (FileRef, Arc<LinedString>, Vec<Stmt>);
+
+/// Parse the file at `path` and the files it imports (recursively) into `out`.
+fn parse_all(path: FileRef, seen: &mut HashSet<FileRef>, out: &mut Vec<ParsedFile>,
+) -> io::Result<()> {
+  let src = Arc::<LinedString>::new(fs::read_to_string(path.path())?.into());
+  let (_, ast) = parse(src.clone(), None);
+  for s in &ast.stmts {
+    if let StmtKind::Import(_, f) = &s.k {
+      let r = resolve_import(&path, f)?;
+      if seen.insert(r.clone()) { parse_all(r, seen, out)? }
+    }
+  }
+  out.push((path, src, ast.stmts));
+  Ok(())
+}
+
+/// For each statement of `files` (by file and start position), the declarations it adds.
+///
+/// A declaration added by lisp code, for example by `(add-eq-thm 'foo)` in a `do` block,
+/// is added by the statement whose span contains its `full` span. However, if the lisp
+/// code was called by the elaborator, as for an annotation `@(derive-eq 'foo) def foo ..`,
+/// the `full` span is the location of the procedure instead. So we also use the order in
+/// which the declarations were added: the ones added after the declaration statement
+/// `foo` and before the next one were added by `foo` or by the statements between them.
+fn added_decls(env: &Environment, files: &[ParsedFile]) -> HashMap<(FileRef, usize), Vec<DeclKey>> {
+  fn flush(env: &Environment, added: &mut HashMap<(FileRef, usize), Vec<DeclKey>>,
+    group: &[(&FileRef, Span)], traces: &[StmtTrace],
+  ) {
+    for s in traces {
+      let StmtTrace::Decl(a) = *s else { continue };
+      let Some(k) = env.data[a].decl else { continue };
+      let (file, full) = match k {
+        DeclKey::Term(t) => (&env.terms[t].span.file, env.terms[t].full),
+        DeclKey::Thm(t) => (&env.thms[t].span.file, env.thms[t].full),
+      };
+      let owner = group.iter().find(|&&(path, sp)|
+        path == file && sp.start <= full.start && full.end <= sp.end).or_else(|| group.first());
+      if let Some(&(path, sp)) = owner {
+        added.entry((path.clone(), sp.start)).or_default().push(k)
+      }
+    }
+  }
+  // The position of each sort (`true`) and declaration (`false`) in `env.stmts`
+  let pos = env.stmts.iter().enumerate().filter_map(|(i, s)| match *s {
+    StmtTrace::Sort(a) => Some(((true, a), i)),
+    StmtTrace::Decl(a) => Some(((false, a), i)),
+    _ => None,
+  }).collect::<HashMap<_, _>>();
+  let mut added = HashMap::new();
+  // The statements since the last sort or declaration statement, and the position in
+  // `env.stmts` after that statement
+  let (mut group, mut from) = (vec![], 0);
+  for (path, src, stmts) in files {
+    for s in stmts {
+      let mut k = &s.k;
+      while let StmtKind::Annot(_, s) | StmtKind::DocComment(_, s) = k { k = &s.k }
+      let name = match k {
+        StmtKind::Sort(sp, _) => Some((true, *sp)),
+        StmtKind::Decl(d) => Some((false, d.id)),
+        _ => None,
+      };
+      match name.and_then(|(sort, sp)| pos.get(&(sort, *env.atoms.get(&src[sp])?))) {
+        Some(&i) if i >= from => {
+          flush(env, &mut added, &group, &env.stmts[from..i]);
+          group.clear();
+          from = i;
+        }
+        _ => {}
+      }
+      group.push((path, s.span));
+    }
+  }
+  flush(env, &mut added, &group, &env.stmts[from..]);
+  added
+}
+
+/// The things which a piece of lisp code needs in order to be printed.
+#[derive(Default)]
+struct Mentions {
+  /// The declarations which appear in a quoted position like `'foo`
+  decls: Vec<DeclKey>,
+  /// The terms used in formulas, whose notations are needed to parse them
+  terms: Vec<DeclKey>,
+  /// The unquoted identifiers, which can be lisp globals defined by a `do` block
+  lisp: Vec<AtomId>,
+}
+
+/// The statements to keep in `--only` mode.
+struct Only {
+  /// The elaborated environment, used to look up the statements by name
+  env: Environment,
+  /// The sorts used by the kept declarations
+  sorts: HashSet<SortId>,
+  /// The roots and the declarations they depend on
+  decls: HashSet<DeclKey>,
+  /// The lisp globals used by the kept statements
+  lisp: HashSet<AtomId>,
+  /// The lisp globals defined by a `do` block in one of the files
+  globals: HashSet<AtomId>,
+  /// The statements to keep in each file, by start position. For a `do` block, these are
+  /// the spans of the forms to keep, or empty if the whole block is kept.
+  kept: HashMap<FileRef, HashMap<usize, Vec<Span>>>,
+}
+
+impl Only {
+  /// Get the statements needed by `roots` in `file` and the files it imports.
+  ///
+  /// A statement is kept if it adds one of the needed declarations (see [`added_decls`]),
+  /// or if it is a `do` block with a form that is needed (see [`Only::action`]), like the
+  /// definition of a tactic used in a proof. The declarations and lisp globals that the
+  /// kept statements use are needed too, so this is repeated until nothing changes.
+  fn new(env: Environment, roots: Vec<DeclKey>, file: FileRef) -> io::Result<Self> {
+    let mut files = vec![];
+    parse_all(file.clone(), &mut std::iter::once(file).collect(), &mut files)?;
+    let added = added_decls(&env, &files);
+    let mut this = Self {
+      sorts: HashSet::new(), decls: HashSet::new(), lisp: HashSet::new(),
+      globals: HashSet::new(), kept: HashMap::new(), env
+    };
+    for (_, src, stmts) in &files {
+      for s in stmts {
+        let StmtKind::Do(es) = &s.k else { continue };
+        for e in es {
+          if let Some((Some(x), _, _)) = this.definition(src, e) { this.globals.insert(x); }
+        }
+      }
+    }
+    let mut m = Mentions { decls: roots, ..Mentions::default() };
+    loop {
+      let n = (this.decls.len(), this.lisp.len());
+      let mut stack = std::mem::take(&mut m.decls);
+      stack.append(&mut m.terms);
+      while let Some(k) = stack.pop() {
+        if this.decls.insert(k) { stack.extend(this.env.direct_deps(k)) }
+      }
+      this.lisp.extend(m.lisp.drain(..));
+      if (this.decls.len(), this.lisp.len()) == n { break }
+      this.kept = this.scan(&files, &added, &mut m);
+    }
+    for &k in &this.decls {
+      match k {
+        DeclKey::Term(tid) => {
+          let td = &this.env.terms[tid];
+          this.sorts.insert(td.ret.0);
+          this.sorts.extend(td.args.iter().map(|(_, ty)| ty.sort()));
+          if let TermKind::Def(Some(e)) = &td.kind {
+            for e in e.heap.iter().chain(&*e.store) {
+              if let ExprNode::Dummy(_, s) = *e { this.sorts.insert(s); }
+            }
+          }
+        }
+        DeclKey::Thm(tid) => {
+          let td = &this.env.thms[tid];
+          this.sorts.extend(td.args.iter().map(|(_, ty)| ty.sort()));
+          if let ThmKind::Thm(Some(pf)) = &td.kind {
+            for p in pf.heap.iter().chain(&*pf.store) {
+              if let ProofNode::Dummy(_, s) = *p { this.sorts.insert(s); }
+            }
+          }
+        }
+      }
+    }
+    this.kept = this.scan(&files, &added, &mut Mentions::default());
+    Ok(this)
+  }
+
+  /// Find the statements to keep with the current [`Only::decls`] and [`Only::lisp`], and
+  /// push the declarations and lisp globals that they add or use to `out`.
+  fn scan(&self, files: &[ParsedFile], added: &HashMap<(FileRef, usize), Vec<DeclKey>>,
+    out: &mut Mentions,
+  ) -> HashMap<FileRef, HashMap<usize, Vec<Span>>> {
+    let mut kept = HashMap::new();
+    for (path, src, stmts) in files {
+      let kept = kept.entry(path.clone()).or_insert_with(HashMap::new);
+      for s in stmts {
+        let added = added.get(&(path.clone(), s.span.start)).map_or(&[][..], |v| v);
+        let mut forms = vec![];
+        if self.keep(src, &s.k, added.iter().any(|k| self.decls.contains(k)), &mut forms, out) {
+          kept.insert(s.span.start, forms);
+          out.decls.extend(added)
+        }
+      }
+    }
+    kept
+  }
+
+  /// Returns true if the statement should be printed. Here `contains` is true if the
+  /// statement adds a needed declaration. The declarations and lisp globals that the
+  /// statement uses are pushed to `out`. If only some of the forms of a `do` block are
+  /// needed (see [`Only::action`]), their spans are pushed to `forms`.
+  fn keep(&self, src: &LinedString, k: &StmtKind, contains: bool, forms: &mut Vec<Span>,
+    out: &mut Mentions,
+  ) -> bool {
+    let atom = |sp: Span| self.env.atoms.get(&src[sp]).map(|&a| &self.env.data[a]);
+    let decl = |sp: Span| atom(sp).and_then(|ad| ad.decl)
+      .map_or(false, |k| self.decls.contains(&k));
+    let mut m = Mentions::default();
+    let keep = match k {
+      StmtKind::Sort(sp, _) =>
+        atom(*sp).and_then(|ad| ad.sort).map_or(false, |s| self.sorts.contains(&s)),
+      StmtKind::Decl(d) => {
+        // The proof can mention theorems which are not used in the result, for example in
+        // a `have` which is not used later
+        if let (true, Some(e)) = (contains, &d.val) {
+          self.mentions(src, false, e, &mut m);
+          // these are called by the elaborator
+          m.lisp.extend([AtomId::REFINE_EXTRA_ARGS, AtomId::TO_EXPR_FALLBACK])
+        }
+        contains
+      }
+      StmtKind::SimpleNota(n) => decl(n.id),
+      StmtKind::Coercion { id, .. } => decl(*id),
+      StmtKind::Notation(n) => decl(n.id),
+      StmtKind::Delimiter(_) => true,
+      StmtKind::Do(es) => {
+        for e in es {
+          if self.action(src, e, contains, &mut m) { forms.push(e.span) }
+        }
+        if forms.len() == es.len() { forms.clear(); true } else { !forms.is_empty() }
+      }
+      // An annotation like `@(register-eqd 'foo)` is only kept with the declaration
+      StmtKind::Annot(e, s) => {
+        let keep = self.keep(src, &s.k, contains, forms, out);
+        if keep {
+          self.mentions(src, false, e, &mut m);
+          m.lisp.push(AtomId::ANNOTATE)
+        }
+        keep
+      }
+      StmtKind::DocComment(_, s) => self.keep(src, &s.k, contains, forms, out),
+      StmtKind::Inout {..} | StmtKind::Import(..) => false,
+    };
+    if keep {
+      out.decls.append(&mut m.decls);
+      out.terms.append(&mut m.terms);
+      out.lisp.append(&mut m.lisp);
+    }
+    keep
+  }
+
+  /// If `e` is a top level definition `(def x ..)` or `(def (x ..) ..)`, returns `x`, the
+  /// value, and true if the value is a function.
+  fn definition<'b>(&self, src: &LinedString, e: &'b SExpr,
+  ) -> Option<(Option<AtomId>, &'b [SExpr], bool)> {
+    let ident = |e: &SExpr| match e.k {
+      SExprKind::Atom(Atom::Ident) => Some(&src[e.span]),
+      _ => None,
+    };
+    match &e.k {
+      SExprKind::DocComment(_, e) => self.definition(src, e),
+      SExprKind::List(es) if es.first().and_then(ident) == Some(b"def") => {
+        let (mut x, func) = (es.get(1), match es.get(1).map(|e| &e.k) {
+          Some(SExprKind::List(_) | SExprKind::DottedList(..)) => true,
+          _ => matches!(es.get(2).map(|e| &e.k), Some(SExprKind::List(f))
+            if matches!(f.first().and_then(ident), Some(b"fn" | b"match-fn" | b"match-fn*"))),
+        });
+        // `(def ((foo a) b) ..)` defines a curried function `foo`
+        while let Some(SExpr {k: SExprKind::List(xs) | SExprKind::DottedList(xs, _), ..}) = x {
+          x = xs.first()
+        }
+        let x = x.and_then(ident).and_then(|x| self.env.atoms.get(x).copied());
+        Some((x, es.get(2..).unwrap_or(&[]), func))
+      }
+      _ => None,
+    }
+  }
+
+  /// Returns true if a top level form of a `do` block should be printed, and if so, push
+  /// what it uses to `out`. If `all` is true then the block adds a needed declaration, so
+  /// all forms are kept. Otherwise, a definition `(def foo ..)` is kept if `foo` is needed,
+  /// and other forms are kept if they register something for the needed declarations,
+  /// like `(add-eq-thm 'foo)`, as long as this does not add anything to the closure.
+  /// The body of a function like `(def (foo) ..)` is only run if `foo` is needed, so
+  /// otherwise only the terms in its formulas are pushed, since they are still parsed.
+  fn action(&self, src: &LinedString, e: &SExpr, all: bool, out: &mut Mentions) -> bool {
+    let mut m = Mentions::default();
+    let keep = if let Some((x, val, func)) = self.definition(src, e) {
+      let needed = x.map_or(false, |x| self.lisp.contains(&x));
+      if needed || all {
+        for e in val { self.mentions(src, false, e, &mut m) }
+        if !needed && func { m.decls.clear(); m.lisp.clear() }
+      }
+      needed || all
+    } else {
+      self.mentions(src, false, e, &mut m);
+      all || !m.decls.is_empty() &&
+        m.decls.iter().chain(&m.terms).all(|k| self.decls.contains(k)) &&
+        m.lisp.iter().all(|x| self.lisp.contains(x) || !self.globals.contains(x))
+    };
+    if keep {
+      out.decls.append(&mut m.decls);
+      out.terms.append(&mut m.terms);
+      out.lisp.append(&mut m.lisp);
+    }
+    keep
+  }
+
+  /// Push what the lisp expression uses to `out`: the declarations which appear in a
+  /// quoted position like `'foo`, the terms used in its formulas, and the unquoted
+  /// identifiers. (Unquoted atoms are usually local variables, which can have the same
+  /// name as a declaration, or else lisp globals.)
+  fn mentions(&self, src: &LinedString, quoted: bool, e: &SExpr, out: &mut Mentions) {
+    match &e.k {
+      SExprKind::Atom(Atom::Ident) => if let Some(&a) = self.env.atoms.get(&src[e.span]) {
+        if !quoted { out.lisp.push(a) } else if let Some(k) = self.env.data[a].decl {
+          out.decls.push(k)
+        }
+      },
+      SExprKind::List(es) => match es.split_first() {
+        Some((SExpr {k: SExprKind::Atom(Atom::Quote), ..}, es)) =>
+          for e in es { self.mentions(src, true, e, out) },
+        Some((SExpr {k: SExprKind::Atom(Atom::Unquote), ..}, es)) =>
+          for e in es { self.mentions(src, false, e, out) },
+        _ => for e in es { self.mentions(src, quoted, e, out) },
+      },
+      SExprKind::DottedList(es, e) =>
+        for e in es.iter().chain(Some(&**e)) { self.mentions(src, quoted, e, out) },
+      SExprKind::DocComment(_, e) => self.mentions(src, quoted, e, out),
+      SExprKind::Formula(f) => self.formula(&src[f.inner()], &mut out.terms),
+      _ => {}
+    }
+  }
+
+  /// Push the terms used in a formula to `out`: the terms of its notations, and the
+  /// terms which are written by name. The formula is split into tokens in the same way as
+  /// the math parser does.
+  fn formula(&self, s: &[u8], out: &mut Vec<DeclKey>) {
+    let pe = &self.env.pe;
+    let mut i = 0;
+    while i < s.len() {
+      match s[i] {
+        b' ' | b'\n' => i += 1,
+        b'-' if s.get(i + 1) == Some(&b'-') => while i < s.len() && s[i] != b'\n' { i += 1 },
+        _ => {
+          let start = i;
+          loop {
+            match s.get(i) {
+              None | Some(b' ' | b'\n') => break,
+              Some(&c) if pe.delims_r.get(c) && i != start => break,
+              Some(&c) if pe.delims_l.get(c) => { i += 1; break }
+              _ => i += 1,
+            }
+          }
+          let tk = &s[start..i];
+          if let Some(n) = pe.prefixes.get(tk).or_else(|| pe.infixes.get(tk)) {
+            out.push(DeclKey::Term(n.term))
+          } else if let Some(&a) = self.env.atoms.get(tk) {
+            if let Some(k @ DeclKey::Term(_)) = self.env.data[a].decl { out.push(k) }
+          }
+        }
+      }
+    }
+  }
+}
 
 /// Running data for the file join process.
-struct Joiner<W: Write> {
+struct Joiner<'a, W: Write> {
   /// True if we should add auxiliary comments
   comments: bool,
   /// The current stack of file references, to reify the recursive process of
@@ -38,12 +420,46 @@ struct Joiner<W: Write> {
   done: Vec<FileRef>,
   /// The writer to print the output file to
   w: W,
+  /// If set, only the statements needed by some theorems are printed
+  only: Option<&'a Only>,
 }
 
-impl<W: Write> Joiner<W> {
+impl<'a, W: Write> Joiner<'a, W> {
   /// Create a new [`Joiner`] from a writer.
-  fn new(comments: bool, w: W) -> Self {
-    Self {comments, stack: vec![], working: HashSet::new(), done: vec![], w}
+  fn new(comments: bool, only: Option<&'a Only>, w: W) -> Self {
+    Self {comments, stack: vec![], working: HashSet::new(), done: vec![], w, only}
+  }
+
+  /// Write the banner comment for the file at `path`.
+  fn banner(&mut self, path: &FileRef) -> io::Result<()> {
+    write!(self.w, "{}\n-- {} --\n{0}\n",
+      // Safety: '-' is utf8
+      unsafe { String::from_utf8_unchecked(vec![b'-'; path.rel().len() + 6]) },
+      path.rel())
+  }
+
+  /// Write the `do` block `s` with only the top level forms at `forms`.
+  fn write_forms(&mut self, src: &LinedString, s: &Stmt, forms: &[Span]) -> io::Result<()> {
+    let mut k = &s.k;
+    while let StmtKind::DocComment(_, s) = k { k = &s.k }
+    let StmtKind::Do(es) = k else { unreachable!() };
+    let (first, last) = (es[0].span.start, es[es.len() - 1].span.end);
+    self.w.write_all(&src.as_bytes()[s.span.start..first])?;
+    for (i, &sp) in forms.iter().enumerate() {
+      if i != 0 {
+        // the indentation of the form, if it starts a line
+        let line = src.as_bytes()[..sp.start].iter().rposition(|&c| c == b'\n').map_or(0, |i| i + 1);
+        let indent = &src.as_bytes()[line..sp.start];
+        if indent.iter().all(u8::is_ascii_whitespace) {
+          self.w.write_all(b"\n")?;
+          self.w.write_all(indent)?
+        } else {
+          self.w.write_all(b" ")?
+        }
+      }
+      self.w.write_all(&src[sp])?
+    }
+    self.w.write_all(&src.as_bytes()[last..s.span.end])
   }
 
   /// Write the file at `path` to `self.w`, following all imports recursively.
@@ -56,49 +472,76 @@ impl<W: Write> Joiner<W> {
     let src = Arc::<LinedString>::new(fs::read_to_string(path.path())?.into());
     let (_, ast) = parse(src.clone(), None);
     let mut start = 0;
+    // In `--only` mode, the statements to print, and the end of the last printed statement
+    let kept = self.only.and_then(|only| only.kept.get(&path));
+    let mut last = None;
     for s in &ast.stmts {
       if let StmtKind::Import(_, f) = &s.k {
-        let f = std::str::from_utf8(f).map_err(|_|
-          io::Error::new(io::ErrorKind::InvalidInput, "invalid utf8"))?;
-        let r: FileRef = path.path().parent()
-          .map_or_else(|| PathBuf::from(f), |p| p.join(f))
-          .canonicalize()?.into();
-        self.w.write_all(&src.as_bytes()[start..s.span.start])?;
+        let r = resolve_import(&path, f)?;
+        if self.only.is_none() { self.w.write_all(&src.as_bytes()[start..s.span.start])? }
         if self.working.insert(r.clone()) {
           self.write(r)?;
-          if self.comments {
+          if self.comments && self.only.is_none() {
             self.w.write_all(&[b'\n'])?;
           }
         }
         start = s.span.end;
+      } else if self.only.is_some() {
+        let Some(forms) = kept.and_then(|kept| kept.get(&s.span.start)) else { continue };
+        match last {
+          None => if self.comments { self.banner(&path)? },
+          Some(end) => {
+            // keep the blank lines between statements
+            let lines = src.as_bytes()[end..s.span.start].split(|&c| c == b'\n')
+              .collect::<Vec<_>>();
+            if lines.len() > 2 &&
+              lines[1..lines.len() - 1].iter().any(|l| l.iter().all(u8::is_ascii_whitespace)) {
+              self.w.write_all(b"\n")?
+            }
+          }
+        }
+        if forms.is_empty() {
+          self.w.write_all(&src[s.span])?
+        } else {
+          self.write_forms(&src, s, forms)?
+        }
+        self.w.write_all(b"\n")?;
+        last = Some(s.span.end);
       }
     }
-    if self.comments {
-      write!(self.w, "{}\n-- {} --\n{0}\n",
-        // Safety: '-' is utf8
-        unsafe { String::from_utf8_unchecked(vec![b'-'; path.rel().len() + 6]) },
-        path.rel())?;
+    if self.only.is_none() {
+      if self.comments { self.banner(&path)? }
+      self.w.write_all(&src.as_bytes()[start..])?;
+    } else if self.comments && last.is_some() && self.stack.len() > 1 {
+      self.w.write_all(b"\n")?;
     }
-    self.w.write_all(&src.as_bytes()[start..])?;
     self.stack.pop();
     self.done.push(path);
     Ok(())
   }
 }
 
-fn join_with_header(comments: bool, header: bool, mut w: impl Write, file: FileRef) -> io::Result<()> {
+fn join_with_header(comments: bool, header: bool, only: Option<(&Only, &[String])>,
+  mut w: impl Write, file: FileRef
+) -> io::Result<()> {
+  let (only, names) = (only.map(|p| p.0), only.map(|p| p.1.join(",")));
   let mut buf = vec![];
   if comments && header {
-    let mut joiner = Joiner::new(comments, &mut buf);
+    let mut joiner = Joiner::new(comments, only, &mut buf);
     joiner.write(file.clone())?;
-    writeln!(w, "\
-      -- This is an autogenerated file constructed by `mm0-rs join {}`.\n\
-      -- It concatenates the files:", file)?;
+    match names {
+      None => writeln!(w, "\
+        -- This is an autogenerated file constructed by `mm0-rs join {}`.\n\
+        -- It concatenates the files:", file)?,
+      Some(names) => writeln!(w, "\
+        -- This is an autogenerated file constructed by `mm0-rs join --only {} {}`.\n\
+        -- It contains the statements needed by `{0}` from the files:", names, file)?,
+    }
     for p in joiner.done { writeln!(w, "-- * {}", p)? }
     writeln!(w)?;
     w.write_all(&buf)
   } else {
-    Joiner::new(comments, w).write(file)
+    Joiner::new(comments, only, w).write(file)
   }
 }
 
@@ -111,6 +554,9 @@ pub struct Args {
   /// Don't add any comments
   #[clap(short, long)]
   pub bare: bool,
+  /// Print only the statements needed by THMS (a comma separated list)
+  #[clap(long, value_name = "THMS", use_value_delimiter = true)]
+  pub only: Vec<String>,
   /// Sets the input file (.mm1 or .mm0)
   pub input: String,
   /// Sets the output file (.mm1 or .mm0), or stdin if omitted
@@ -124,17 +570,31 @@ impl Args {
   ///
   /// # Arguments
   ///
-  /// `mm0-rs join <in.mm0> [out.mm0]`, where:
+  /// `mm0-rs join [--only THMS] <in.mm0> [out.mm0]`, where:
   ///
+  /// - `THMS` is a comma separated list of theorems. If given, only these theorems and the
+  ///   statements they depend on are printed.
   /// - `in.mm0` (or `in.mm1`) is the file to join, an MM0 file with `import`s
   /// - `out.mm0` is the output location, or stdin if omitted.
   pub fn main(self) -> io::Result<()> {
-    let file = fs::canonicalize(self.input)?.into();
+    let file: FileRef = fs::canonicalize(self.input)?.into();
     let comments = !self.bare;
     let header = !self.no_header;
+    let only = if self.only.is_empty() { None } else {
+      if self.output.is_none() { crate::compiler::set_quiet(true) }
+      let (_, old) = crate::compiler::elab_for_result(file.clone())?;
+      let old = old.unwrap_or_else(|| std::process::exit(1));
+      let env = old.thaw_copy();
+      let roots = self.only.iter().map(|s| {
+        env.atoms.get(s.as_bytes()).and_then(|&a| env.data[a].decl).ok_or_else(||
+          io::Error::new(io::ErrorKind::InvalidInput, format!("unknown declaration '{}'", s)))
+      }).collect::<io::Result<_>>()?;
+      Some(Only::new(env, roots, file.clone())?)
+    };
+    let only = only.as_ref().map(|o| (o, &*self.only));
     match self.output {
-      None => join_with_header(comments, header, io::stdout(), file),
-      Some(out) => join_with_header(comments, header, fs::File::create(out)?, file),
+      None => join_with_header(comments, header, only, io::stdout(), file),
+      Some(out) => join_with_header(comments, header, only, fs::File::create(out)?, file),
     }
   }
 }
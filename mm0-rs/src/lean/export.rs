@@ -0,0 +1,587 @@
+//! Lean 4 exporter, which translates an MM0 environment into Lean 4 source.
+//!
+//! This implements `mm0-rs export-lean`. The translation is the same HOL-style reading of MM0
+//! that the `to-lean` command of `mm0-hs` uses:
+//!
+//! - Every sort `s` becomes an axiomatized type `s : Type`. If `s` is not `free`, there is an
+//!   axiom `s.nonempty : Nonempty s`, and if `s` is `provable`, there is a predicate
+//!   `s.proof : s → Prop`, which is written `⊦ e` using the `Provable` class.
+//! - A bound variable `x: s` is a variable `x : s`, and a regular variable `ph: s x y` is a
+//!   function `ph : s1 → s2 → s` of the bound variables it depends on, so that an occurrence
+//!   of `ph` becomes `ph x y`.
+//! - A term `t` becomes an axiom whose type takes the regular arguments (as functions),
+//!   followed by the bound variables that the result depends on. In an application, a
+//!   regular argument which depends on bound variables becomes a lambda `fun x => e`, so
+//!   binders in MM0 become binders in Lean. A `def` becomes a Lean `def` with the same type.
+//! - An axiom or theorem `T` takes its regular variables as implicit arguments, then its
+//!   hypotheses `(h : ∀ x, ⊦ e)` closed over the bound variables free in them, and then the
+//!   bound variables free in the conclusion as explicit arguments.
+//! - A proof step `T args hyps` becomes `@T (fun x => e) .. (fun x => p) .. y`. Conversions
+//!   are dropped, because they are definitional unfoldings in Lean. Subproofs on the proof
+//!   heap become `have` statements closed over their variables, and the dummy variables (and
+//!   bound variables which do not appear in the statement) are introduced with
+//!   `s.nonempty.elim`.
+//!
+//! Names are escaped as `«name»` if they are Lean keywords, and variable names get a `'`
+//! added if they clash with a declaration. A proof that needs a variable of a `free` sort
+//! which does not appear in the statement can not be translated, and is written as `sorry`
+//! with a warning.
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt::Write as _;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use crate::{AtomId, DeclKey, DocComment, ErrorLevel, ExprNode, FileRef, FrozenEnv, Modifiers,
+  ProofNode, SortId, StmtTrace, TermId, TermKind, ThmId, ThmKind, Type};
+
+/// The Lean keywords, which have to be escaped when used as names.
+const KEYWORDS: &[&str] = &[
+  "Prop", "Sort", "Type", "abbrev", "at", "attribute", "axiom", "break", "by", "calc",
+  "catch", "class", "continue", "decreasing_by", "def", "deriving", "do", "else", "end",
+  "example", "export", "extends", "finally", "for", "forall", "from", "fun", "have", "hiding",
+  "if", "import", "in", "include", "inductive", "infix", "infixl", "infixr", "instance", "let",
+  "local", "macro", "match", "mut", "mutual", "namespace", "nofun", "nomatch", "noncomputable",
+  "notation", "obtain", "omit", "opaque", "open", "partial", "postfix", "prefix", "private",
+  "protected", "renaming", "return", "scoped", "section", "set_option", "show", "sorry",
+  "structure", "suffices", "syntax", "termination_by", "then", "theorem", "this", "try",
+  "unless", "universe", "unsafe", "variable", "where", "with",
+];
+
+/// Escape a name if it is a Lean keyword.
+fn escape(s: String) -> String {
+  if KEYWORDS.contains(&&*s) { format!("«{}»", s) } else { s }
+}
+
+fn fresh(taken: &mut HashSet<String>, mut s: String) -> String {
+  while taken.contains(&s) { s.push('\'') }
+  taken.insert(s.clone());
+  escape(s)
+}
+
+/// The indices of the bound variables in a dependency set.
+fn deps(deps: u64) -> impl Iterator<Item = usize> {
+  (0..64).filter(move |&i| deps & (1 << i) != 0)
+}
+
+/// Write a doc comment, if there is one.
+fn doc(out: &mut String, doc: Option<&DocComment>) {
+  if let Some(doc) = doc {
+    writeln!(out, "/-- {} -/", doc.trim().replace("-/", "- /")).expect("writing to a string")
+  }
+}
+
+/// Write binders `(x y : A)`, grouping consecutive variables of the same type.
+fn binders<'b>(out: &mut String, (open, close): (char, char),
+  bs: impl IntoIterator<Item = (&'b str, String)>
+) {
+  let mut last: Option<String> = None;
+  for (x, ty) in bs {
+    if last.as_ref() == Some(&ty) { out.push(' ') } else {
+      if let Some(ty) = last.replace(ty) {
+        write!(out, " : {}{}", ty, close).expect("writing to a string")
+      }
+      out.push(' '); out.push(open)
+    }
+    out.push_str(x)
+  }
+  if let Some(ty) = last { write!(out, " : {}{}", ty, close).expect("writing to a string") }
+}
+
+/// The names of everything in the translation.
+struct Tables<'a> {
+  env: &'a FrozenEnv,
+  /// The Lean type of each sort
+  sorts: Vec<String>,
+  /// The Lean name of each term
+  terms: Vec<String>,
+  /// The Lean name of each theorem
+  thms: Vec<String>,
+  /// All declaration names, which variables have to avoid
+  taken: HashSet<String>,
+}
+
+impl<'a> Tables<'a> {
+  fn new(env: &'a FrozenEnv) -> Self {
+    let mut taken: HashSet<String> = std::iter::once("Provable".into()).collect();
+    let sorts = env.sorts().0.iter()
+      .map(|sd| fresh(&mut taken, sd.name.as_str().into())).collect();
+    let terms = env.terms().0.iter()
+      .map(|td| fresh(&mut taken, env.data()[td.atom].name().as_str().into())).collect();
+    let thms = env.thms().0.iter()
+      .map(|td| fresh(&mut taken, env.data()[td.atom].name().as_str().into())).collect();
+    Self { env, sorts, terms, thms, taken }
+  }
+
+  fn name(&self, a: AtomId) -> &'a str { self.env.data()[a].name().as_str() }
+  fn sort(&self, s: SortId) -> &str { &self.sorts[usize::from(s.0)] }
+  fn term(&self, t: TermId) -> &str { &self.terms[t.0 as usize] }
+  fn thm(&self, t: ThmId) -> &str { &self.thms[t.0 as usize] }
+
+  /// The Lean type of a variable of sort `s` depending on variables of sorts `dom`.
+  fn arrow(&self, dom: impl Iterator<Item = SortId>, s: SortId) -> String {
+    let mut out = String::new();
+    for s in dom { write!(out, "{} → ", self.sort(s)).expect("writing to a string") }
+    out.push_str(self.sort(s));
+    out
+  }
+}
+
+/// A local variable: an argument of a term or theorem, or a dummy variable.
+struct Local {
+  name: String,
+  sort: SortId,
+  /// The bound variables that a regular variable depends on, or `None` for a bound variable
+  deps: Option<Box<[usize]>>,
+}
+
+/// A translated expression or proof.
+#[derive(Clone)]
+struct Tr {
+  /// The Lean source
+  s: String,
+  /// True if this is an application, which needs parentheses when used as an argument
+  app: bool,
+  /// The local variables which are free in the term
+  fv: BTreeSet<usize>,
+}
+
+impl Tr {
+  fn new(s: String) -> Self { Self { s, app: false, fv: BTreeSet::new() } }
+
+  /// Add the argument `e` abstracted over the variables `xs`, separated by `sep`.
+  fn push_arg(&mut self, locals: &[Local], sep: &str, xs: impl Iterator<Item = usize>, e: Self) {
+    let Tr { s, app, mut fv } = e;
+    let mut lam = String::new();
+    for x in xs { fv.remove(&x); lam.push(' '); lam.push_str(&locals[x].name) }
+    self.s.push_str(sep);
+    if !lam.is_empty() { write!(self.s, "(fun{} => {})", lam, s).expect("writing to a string") }
+    else if app { write!(self.s, "({})", s).expect("writing to a string") }
+    else { self.s.push_str(&s) }
+    self.app = true;
+    self.fv.extend(fv)
+  }
+
+  /// Add the variable `x` as an argument.
+  fn push_var(&mut self, locals: &[Local], x: usize) {
+    self.s.push(' ');
+    self.s.push_str(&locals[x].name);
+    self.app = true;
+    self.fv.insert(x);
+  }
+}
+
+/// The expression node of an [`ExprNode`] or [`ProofNode`].
+enum View<'a, N> {
+  /// The argument with this index
+  Var(usize),
+  /// The heap element with this index
+  Heap(usize),
+  /// A dummy variable
+  Dummy(AtomId, SortId),
+  /// A term application
+  App(TermId, &'a [N]),
+}
+
+trait Node: Sized {
+  fn view<'a>(&'a self, env: &FrozenEnv, nargs: usize, store: &'a [Self]) -> View<'a, Self>;
+}
+
+impl Node for ExprNode {
+  fn view<'a>(&'a self, env: &FrozenEnv, nargs: usize, store: &'a [Self]) -> View<'a, Self> {
+    match *self {
+      ExprNode::Ref(i) if i < nargs => View::Var(i),
+      ExprNode::Ref(i) => View::Heap(i),
+      ExprNode::Dummy(a, s) => View::Dummy(a, s),
+      ExprNode::App(t, p) => View::App(t, env.term(t).unpack_app(&store[p..])),
+    }
+  }
+}
+
+impl Node for ProofNode {
+  fn view<'a>(&'a self, env: &FrozenEnv, nargs: usize, store: &'a [Self]) -> View<'a, Self> {
+    match *self {
+      ProofNode::Ref(i) if i < nargs => View::Var(i),
+      ProofNode::Ref(i) => View::Heap(i),
+      ProofNode::Dummy(a, s) => View::Dummy(a, s),
+      ProofNode::Term(t, p) => View::App(t, env.term(t).unpack_term(&store[p..])),
+      _ => panic!("expected an expression"),
+    }
+  }
+}
+
+/// The local context of a definition, or of the statement or proof of a theorem.
+struct Ctx<'a, N> {
+  t: &'a Tables<'a>,
+  nargs: usize,
+  locals: Vec<Local>,
+  /// The argument indices of the bound variables
+  bvs: Vec<usize>,
+  /// The local names, in addition to [`Tables::taken`]
+  taken: HashSet<String>,
+  dummies: HashMap<AtomId, usize>,
+  heap: &'a [N],
+  store: &'a [N],
+  cache: HashMap<usize, Tr>,
+}
+
+impl<'a, N: Node> Ctx<'a, N> {
+  fn new(t: &'a Tables<'a>, args: &[(Option<AtomId>, Type)], heap: &'a [N], store: &'a [N]
+  ) -> Self {
+    let mut cx = Self {
+      t, nargs: args.len(), locals: vec![], bvs: vec![], taken: HashSet::new(),
+      dummies: HashMap::new(), heap, store, cache: HashMap::new(),
+    };
+    for (i, &(a, ty)) in args.iter().enumerate() {
+      let name = cx.fresh(match a { Some(a) => t.name(a), None if ty.bound() => "x", None => "a" });
+      let deps = match ty {
+        Type::Bound(_) => { cx.bvs.push(i); None }
+        Type::Reg(_, d) => Some(deps(d).map(|k| cx.bvs[k]).collect()),
+      };
+      cx.locals.push(Local { name, sort: ty.sort(), deps })
+    }
+    cx
+  }
+
+  /// Get a fresh local name.
+  fn fresh(&mut self, base: &str) -> String {
+    let mut s = base.to_owned();
+    while self.t.taken.contains(&s) { s.push('\'') }
+    fresh(&mut self.taken, s)
+  }
+
+  /// The Lean type of a local variable.
+  fn ty(&self, i: usize) -> String {
+    let l = &self.locals[i];
+    match &l.deps {
+      None => self.t.sort(l.sort).into(),
+      Some(ds) => self.t.arrow(ds.iter().map(|&k| self.locals[k].sort), l.sort),
+    }
+  }
+
+  /// Write `∀ (x : A), ` for the variables `xs`, if there are any.
+  fn forall(&self, out: &mut String, xs: &BTreeSet<usize>) {
+    if !xs.is_empty() {
+      out.push('∀');
+      binders(out, ('(', ')'), xs.iter().map(|&x| (&*self.locals[x].name, self.ty(x))));
+      out.push_str(", ")
+    }
+  }
+
+  fn var(&self, i: usize) -> Tr {
+    let l = &self.locals[i];
+    let mut out = Tr::new(l.name.clone());
+    match &l.deps {
+      None => { out.fv.insert(i); }
+      Some(ds) => for &x in &**ds { out.push_var(&self.locals, x) }
+    }
+    out
+  }
+
+  fn dummy(&mut self, a: AtomId, s: SortId) -> usize {
+    if let Some(&i) = self.dummies.get(&a) { return i }
+    let name = self.fresh(self.t.name(a));
+    self.locals.push(Local { name, sort: s, deps: None });
+    self.dummies.insert(a, self.locals.len() - 1);
+    self.locals.len() - 1
+  }
+
+  /// The local variable substituted for a bound variable.
+  fn bound_var(&mut self, e: &N) -> usize {
+    match e.view(self.t.env, self.nargs, self.store) {
+      View::Var(i) => i,
+      View::Heap(i) => { let heap = self.heap; self.bound_var(&heap[i]) }
+      View::Dummy(a, s) => self.dummy(a, s),
+      View::App(..) => panic!("expected a bound variable"),
+    }
+  }
+
+  fn expr(&mut self, e: &N) -> Tr {
+    match e.view(self.t.env, self.nargs, self.store) {
+      View::Var(i) => self.var(i),
+      View::Heap(i) => {
+        if let Some(tr) = self.cache.get(&i) { return tr.clone() }
+        let heap = self.heap;
+        let tr = self.expr(&heap[i]);
+        self.cache.insert(i, tr.clone());
+        tr
+      }
+      View::Dummy(a, s) => { let i = self.dummy(a, s); self.var(i) }
+      View::App(t, args) => {
+        let td = self.t.env.term(t);
+        let mut out = Tr::new(self.t.term(t).into());
+        let mut bvs = vec![];
+        for (e, &(_, ty)) in args.iter().zip(&*td.args) {
+          match ty {
+            Type::Bound(_) => bvs.push(self.bound_var(e)),
+            Type::Reg(_, d) => {
+              let e = self.expr(e);
+              out.push_arg(&self.locals, " ", deps(d).map(|k| bvs[k]), e)
+            }
+          }
+        }
+        for k in deps(td.ret.1) { out.push_var(&self.locals, bvs[k]) }
+        out
+      }
+    }
+  }
+}
+
+/// The bound variables (as indices into the bound arguments) that the hypotheses and the
+/// conclusion of a theorem are closed over.
+type ThmVars = (Box<[Box<[usize]>]>, Box<[usize]>);
+
+/// The state of the translation of a proof.
+struct ProofBuilder<'a> {
+  cx: Ctx<'a, ProofNode>,
+  vars: &'a [ThmVars],
+  /// The name of each hypothesis and the variables it is closed over
+  hyps: Vec<(String, BTreeSet<usize>)>,
+  /// The translation of the proofs on the heap
+  proofs: HashMap<usize, Tr>,
+  /// The `have` statements for the proofs on the heap
+  haves: String,
+}
+
+impl ProofBuilder<'_> {
+  /// Translate a proof, where `ind` is the indentation of the current line.
+  fn proof(&mut self, p: &ProofNode, ind: usize) -> Tr {
+    let store = self.cx.store;
+    match *p {
+      ProofNode::Ref(i) => {
+        if let Some(tr) = self.proofs.get(&i) { return tr.clone() }
+        let p = &self.cx.heap[i];
+        let tr = if let ProofNode::Hyp(..) = p { self.proof(p, ind) } else {
+          let pf = self.proof(p, 4);
+          let stmt = self.cx.expr(match *p {
+            ProofNode::Thm(_, q) | ProofNode::Conv(q) => &store[q],
+            _ => panic!("expected a proof"),
+          });
+          let vs = pf.fv.union(&stmt.fv).copied().collect::<BTreeSet<_>>();
+          let name = self.cx.fresh(&format!("p{}", i));
+          let mut out = Tr::new(name.clone());
+          write!(self.haves, "  have {} : ", name).expect("writing to a string");
+          self.cx.forall(&mut self.haves, &vs);
+          write!(self.haves, "⊦ {} :=", stmt.s).expect("writing to a string");
+          if !vs.is_empty() {
+            self.haves.push_str(" fun");
+            for &x in &vs { self.haves.push(' '); self.haves.push_str(&self.cx.locals[x].name) }
+            self.haves.push_str(" =>")
+          }
+          writeln!(self.haves, "\n    {}", pf.s).expect("writing to a string");
+          for x in vs { out.push_var(&self.cx.locals, x) }
+          out
+        };
+        self.proofs.insert(i, tr.clone());
+        tr
+      }
+      ProofNode::Hyp(i, _) => {
+        let (name, xs) = &self.hyps[i];
+        let mut out = Tr::new(name.clone());
+        for &x in xs { out.push_var(&self.cx.locals, x) }
+        out
+      }
+      ProofNode::Thm(th, p) => {
+        let td = self.cx.t.env.thm(th);
+        let (_, args, hyps) = td.unpack_thm(&store[p..]);
+        let (hvars, rvars) = &self.vars[th.0 as usize];
+        let mut out = Tr::new(if td.args.iter().all(|(_, ty)| ty.bound()) {
+          self.cx.t.thm(th).into()
+        } else {
+          format!("@{}", self.cx.t.thm(th))
+        });
+        let mut bvs = vec![];
+        for (e, &(_, ty)) in args.iter().zip(&*td.args) {
+          match ty {
+            Type::Bound(_) => bvs.push(self.cx.bound_var(e)),
+            Type::Reg(_, d) => {
+              let e = self.cx.expr(e);
+              out.push_arg(&self.cx.locals, " ", deps(d).map(|k| bvs[k]), e)
+            }
+          }
+        }
+        let sep = format!("\n{:1$}", "", ind + 2);
+        for (p, xs) in hyps.iter().zip(&**hvars) {
+          let p = self.proof(p, ind + 2);
+          let sep = if p.s.contains('\n') || p.s.len() > 40 { &*sep } else { " " };
+          out.push_arg(&self.cx.locals, sep, xs.iter().map(|&k| bvs[k]), p)
+        }
+        for &k in &**rvars { out.push_var(&self.cx.locals, bvs[k]) }
+        out
+      }
+      ProofNode::Conv(p) => self.proof(ProofNode::unpack_conv(&store[p..]).2, ind),
+      _ => panic!("expected a proof"),
+    }
+  }
+}
+
+struct Exporter<'a, W> {
+  t: Tables<'a>,
+  /// The [`ThmVars`] of each theorem
+  vars: Vec<ThmVars>,
+  w: W,
+  report: &'a mut dyn FnMut(ErrorLevel, &str),
+}
+
+impl<W: Write> Exporter<'_, W> {
+  fn sort(&mut self, s: SortId) -> io::Result<()> {
+    let sd = self.t.env.sort(s);
+    let name = self.t.sort(s);
+    let mut out = String::new();
+    doc(&mut out, sd.doc.as_ref());
+    writeln!(out, "axiom {} : Type", name).expect("writing to a string");
+    if !sd.mods.contains(Modifiers::FREE) {
+      writeln!(out, "axiom {0}.nonempty : Nonempty {0}", name).expect("writing to a string")
+    }
+    if sd.mods.contains(Modifiers::PROVABLE) {
+      writeln!(out, "axiom {0}.proof : {0} → Prop", name).expect("writing to a string");
+      writeln!(out, "instance : Provable {0} := ⟨{0}.proof⟩", name).expect("writing to a string")
+    }
+    writeln!(self.w, "\n{}", out.trim_end())
+  }
+
+  fn term(&mut self, tid: TermId) -> io::Result<()> {
+    let td = self.t.env.term(tid);
+    let mut out = String::new();
+    doc(&mut out, td.doc.as_ref());
+    if let TermKind::Def(Some(e)) = &td.kind {
+      let mut cx = Ctx::new(&self.t, &td.args, &e.heap, &e.store);
+      write!(out, "def {}", self.t.term(tid)).expect("writing to a string");
+      let regs = (0..td.args.len()).filter(|&i| !td.args[i].1.bound());
+      binders(&mut out, ('(', ')'), regs.map(|i| (&*cx.locals[i].name, cx.ty(i))));
+      let ret_bvs = deps(td.ret.1).map(|k| cx.bvs[k]);
+      binders(&mut out, ('(', ')'), ret_bvs.map(|i| (&*cx.locals[i].name, cx.ty(i))));
+      let body = cx.expr(e.head());
+      write!(out, " : {} :=\n  {}", self.t.sort(td.ret.0), body.s).expect("writing to a string");
+    } else {
+      write!(out, "axiom {} : ", self.t.term(tid)).expect("writing to a string");
+      let mut bvs = vec![];
+      for &(_, ty) in &*td.args {
+        match ty {
+          Type::Bound(s) => bvs.push(s),
+          Type::Reg(s, 0) => write!(out, "{} → ", self.t.sort(s)).expect("writing to a string"),
+          Type::Reg(s, d) => write!(out, "({}) → ", self.t.arrow(deps(d).map(|k| bvs[k]), s))
+            .expect("writing to a string"),
+        }
+      }
+      out.push_str(&self.t.arrow(deps(td.ret.1).map(|k| bvs[k]), td.ret.0))
+    }
+    writeln!(self.w, "\n{}", out)
+  }
+
+  fn thm(&mut self, tid: ThmId) -> io::Result<()> {
+    let td = self.t.env.thm(tid);
+    let mut cx = Ctx::new(&self.t, &td.args, &td.heap, &td.store);
+    let hyps = td.hyps.iter().map(|(a, e)| {
+      let e = cx.expr(e);
+      (cx.fresh(a.map_or("h", |a| self.t.name(a))), e)
+    }).collect::<Vec<_>>();
+    let ret = cx.expr(&td.ret);
+    let mut out = String::new();
+    doc(&mut out, td.doc.as_ref());
+    let kw = if matches!(td.kind, ThmKind::Axiom) { "axiom" } else { "theorem" };
+    write!(out, "{} {}", kw, self.t.thm(tid)).expect("writing to a string");
+    let regs = (0..td.args.len()).filter(|&i| !td.args[i].1.bound());
+    binders(&mut out, ('{', '}'), regs.map(|i| (&*cx.locals[i].name, cx.ty(i))));
+    for (h, e) in &hyps {
+      write!(out, " ({} : ", h).expect("writing to a string");
+      cx.forall(&mut out, &e.fv);
+      write!(out, "⊦ {})", e.s).expect("writing to a string");
+    }
+    binders(&mut out, ('(', ')'), ret.fv.iter().map(|&i| (&*cx.locals[i].name, cx.ty(i))));
+    write!(out, " : ⊦ {}", ret.s).expect("writing to a string");
+    let to_bvs = |fv: &BTreeSet<usize>| (0..cx.bvs.len()).filter(|&k| fv.contains(&cx.bvs[k]))
+      .collect::<Box<[_]>>();
+    self.vars[tid.0 as usize] =
+      (hyps.iter().map(|(_, e)| to_bvs(&e.fv)).collect(), to_bvs(&ret.fv));
+    match &td.kind {
+      ThmKind::Axiom => {}
+      ThmKind::Thm(None) => {
+        (self.report)(ErrorLevel::Warning,
+          &format!("{}: the theorem has no proof", self.t.thm(tid)));
+        out.push_str(" :=\n  sorry")
+      }
+      ThmKind::Thm(Some(pf)) => {
+        let mut cx = Ctx::new(&self.t, &td.args, &pf.heap, &pf.store);
+        let hyps = hyps.into_iter().zip(&*td.hyps)
+          .map(|((_, e), &(a, _))| (cx.fresh(a.map_or("h", |a| self.t.name(a))), e.fv))
+          .collect();
+        let mut pb = ProofBuilder { cx, vars: &self.vars, hyps, proofs: HashMap::new(),
+          haves: String::new() };
+        let body = pb.proof(pf.head(), 2);
+        out.push_str(" :=\n");
+        out.push_str(&pb.haves);
+        let mut sorry = false;
+        for &x in body.fv.difference(&ret.fv) {
+          let l = &pb.cx.locals[x];
+          if self.t.env.sort(l.sort).mods.contains(Modifiers::FREE) { sorry = true; break }
+          writeln!(out, "  {}.nonempty.elim fun {} =>", self.t.sort(l.sort), l.name)
+            .expect("writing to a string")
+        }
+        if sorry {
+          (self.report)(ErrorLevel::Warning, &format!(
+            "{}: the proof needs a dummy variable of a free sort; the proof is omitted",
+            self.t.thm(tid)));
+          out.push_str("  sorry")
+        } else {
+          write!(out, "  {}", body.s).expect("writing to a string")
+        }
+      }
+    }
+    writeln!(self.w, "\n{}", out)
+  }
+}
+
+impl FrozenEnv {
+  /// Write this environment as a Lean 4 file. See the
+  /// [module documentation](crate::lean::export) for the translation.
+  pub fn export_lean(&self, w: impl Write, report: &mut dyn FnMut(ErrorLevel, &str)
+  ) -> io::Result<()> {
+    let vars = vec![Default::default(); self.thms().len()];
+    let mut ex = Exporter { t: Tables::new(self), vars, w, report };
+    writeln!(ex.w, "-- Exported from MM0 by mm0-rs\n\nnamespace MM0\n\n\
+      /-- The provability predicate of a sort. `⊦ e` means that `e` is provable. -/\n\
+      class Provable (α : Type) where\n  proof : α → Prop\n\n\
+      @[inherit_doc] prefix:26 \"⊦ \" => Provable.proof\n\nnoncomputable section")?;
+    for s in self.stmts() {
+      match *s {
+        StmtTrace::Sort(a) => ex.sort(self.data()[a].sort().expect("expected a sort"))?,
+        StmtTrace::Decl(a) => match self.data()[a].decl().expect("expected a term/thm") {
+          DeclKey::Term(t) => ex.term(t)?,
+          DeclKey::Thm(t) => ex.thm(t)?,
+        },
+        StmtTrace::Global(_) | StmtTrace::OutputString(_) => {}
+      }
+    }
+    writeln!(ex.w, "\nend\n\nend MM0")?;
+    ex.w.flush()
+  }
+}
+
+/// Export an MM1/MM0 file as Lean 4 source
+#[derive(clap::Args, Debug)]
+pub struct Args {
+  /// Sets the input file (.mm1 or .mm0)
+  pub input: String,
+  /// Sets the output file (.lean)
+  pub output: String,
+}
+
+impl Args {
+  /// Main entry point for `mm0-rs export-lean` subcommand.
+  ///
+  /// See the [module documentation](self) for how the environment is translated.
+  ///
+  /// # Arguments
+  ///
+  /// `mm0-rs export-lean <in.mm1> <out.lean>`, where:
+  ///
+  /// - `in.mm1` is the initial file to elaborate.
+  /// - `out.lean` is the Lean file to write.
+  pub fn main(self) -> io::Result<()> {
+    let path: FileRef = fs::canonicalize(&self.input)?.into();
+    let (_, env) = crate::compiler::elab_for_result(path)?;
+    let env = env.unwrap_or_else(|| std::process::exit(1));
+    let w = BufWriter::new(File::create(&self.output)?);
+    let mut report = |lvl: ErrorLevel, err: &str| eprintln!("{}: {}", lvl, err);
+    env.export_lean(w, &mut report)
+  }
+}
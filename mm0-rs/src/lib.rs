@@ -10,9 +10,22 @@
 //!
 //! SUBCOMMANDS:
 //!     compile    Compile MM1 files into MMB
+//!     convert    Convert between MMB and MMU proof files
+//!     decompile  Decompile an MMB file into MM0 and MM1 source files
+//!     deps       Write the dependency graph of an MM1/MM0 file
+//!     dump       Print a readable listing of an MMB file
+//!     export-lean Export an MM1/MM0 file as Lean 4 source
+//!     export-ot  Export an MM1/MM0 file as a HOL article (.art)
+//!     fmt        Format MM1/MM0 files
+//!     from-mm    Import a Metamath database into MM0 and MMB/MMU files
 //!     help       Prints this message or the help of the given subcommand(s)
 //!     join       Join MM1/MM0 files with imports by concatenation
+//!     lint       Lint an MM1/MM0 file for unused and duplicate declarations
+//!     minimize   Shorten proofs by using earlier theorems
+//!     mmb-diff   Compare the declarations of two MMB files
+//!     mmb-link   Link several MMB files into one
 //!     server     MM1 LSP server
+//!     verify     Verify an MMB or MMU file against its MM0 specification
 //! ```
 //!
 //! [`mm0-rs/README.md`]: https://github.com/digama0/mm0/blob/master/mm0-rs/README.md
@@ -65,7 +78,9 @@ pub use mm0_deepsize::deep_size_0;
 #[cfg(feature = "server")]
 #[macro_use] pub mod server;
 pub mod compiler;
+pub mod formatter;
 pub mod joiner;
+pub mod minimize;
 pub mod elab;
 #[cfg(feature = "doc")]
 pub mod doc;
@@ -74,13 +89,31 @@ pub mod doc;
 /// See [`mm0-c/verifier.c`] for information on the MMB format.
 ///
 /// [`mm0-c/verifier.c`]: https://github.com/digama0/mm0/blob/master/mm0-c/verifier.c
-pub mod mmb { pub mod export; pub mod import; }
+pub mod mmb { pub mod convert; pub mod decompile; pub mod diff; pub mod dump; pub mod export; pub mod import; pub mod link; pub mod verify; }
 /// Import and export functionality for MMU ascii proof format
 ///
 /// See [The `.mmu` file format] for information on the MMU format.
 ///
 /// [The `.mmu` file format]: https://github.com/digama0/mm0/blob/master/mm0-hs/README.md#the-mmu-file-format
-pub mod mmu { pub mod import; pub mod export; }
+pub mod mmu { pub mod import; pub mod export; pub mod verify; }
+/// Import and export functionality for Metamath databases
+///
+/// See [Metamath] for information on the `.mm` format.
+///
+/// [Metamath]: https://us.metamath.org/downloads/metamath.pdf
+pub mod mm { pub mod export; pub mod import; }
+/// Export functionality for Lean 4
+///
+/// See [Lean] for information on the Lean theorem prover.
+///
+/// [Lean]: https://lean-lang.org/
+pub mod lean { pub mod export; }
+/// Export functionality for HOL articles
+///
+/// See [OpenTheory] for information on the `.art` format.
+///
+/// [OpenTheory]: https://www.gilith.com/opentheory/article.html
+pub mod opentheory { pub mod export; mod kernel; }
 #[cfg(feature = "mmc")]
 pub mod mmc;
 
@@ -97,12 +130,14 @@ pub use mm0b_parser::MAX_BOUND_VARS;
 
 static CHECK_PROOFS: AtomicBool = AtomicBool::new(true);
 static CHECK_PARENS: AtomicBool = AtomicBool::new(false);
+static TIMINGS: AtomicBool = AtomicBool::new(false);
 
 pub(crate) fn get_options() -> ElabOptions {
   ElabOptions {
     check_proofs: CHECK_PROOFS.load(Ordering::Relaxed),
     check_parens: CHECK_PARENS.load(Ordering::Relaxed),
     unused_vars: true,
+    timings: TIMINGS.load(Ordering::Relaxed),
   }
 }
 
@@ -113,3 +148,7 @@ pub fn set_check_proofs(b: bool) { CHECK_PROOFS.store(b, Ordering::Relaxed) }
 /// Set the initial parenthesis warn behavior at the start of an MM1 file
 /// before a `(warn-unnecessary-parens)` command is found.
 pub fn set_check_parens(b: bool) { CHECK_PARENS.store(b, Ordering::Relaxed) }
+
+/// Set whether elaboration should record the time spent on each statement
+/// and lisp procedure (see [`elab::timing`]).
+pub fn set_timings(b: bool) { TIMINGS.store(b, Ordering::Relaxed) }
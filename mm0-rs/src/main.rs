@@ -6,10 +6,23 @@ use clap::Parser;
 #[clap(arg_required_else_help = true)]
 enum Cli {
   Compile(mm0_rs::compiler::Args),
+  Convert(mm0_rs::mmb::convert::Args),
   Join(mm0_rs::joiner::Args),
   Doc(mm0_rs::doc::Args),
+  Decompile(mm0_rs::mmb::decompile::Args),
+  Deps(mm0_rs::doc::deps::Args),
+  Dump(mm0_rs::mmb::dump::Args),
+  ExportLean(mm0_rs::lean::export::Args),
+  ExportOt(mm0_rs::opentheory::export::Args),
+  Fmt(mm0_rs::formatter::Args),
+  FromMm(mm0_rs::mm::import::Args),
+  Lint(mm0_rs::doc::lint::Args),
+  Minimize(mm0_rs::minimize::Args),
+  MmbDiff(mm0_rs::mmb::diff::Args),
+  MmbLink(mm0_rs::mmb::link::Args),
   #[cfg(feature = "server")]
   Server(mm0_rs::server::Args),
+  Verify(mm0_rs::mmb::verify::Args),
 }
 
 fn main() -> std::io::Result<()> {
@@ -17,10 +30,23 @@ fn main() -> std::io::Result<()> {
     Cli::Compile(args) => {
       if args.no_proofs { mm0_rs::set_check_proofs(false) }
       if args.check_parens { mm0_rs::set_check_parens(true) }
+      if args.timings { mm0_rs::set_timings(true) }
       args.main()
     }
+    Cli::Convert(args) => args.main(),
     Cli::Join(args) => args.main(),
     Cli::Doc(args) => args.main(),
+    Cli::Decompile(args) => args.main(),
+    Cli::Deps(args) => args.main(),
+    Cli::Dump(args) => args.main(),
+    Cli::ExportLean(args) => args.main(),
+    Cli::ExportOt(args) => args.main(),
+    Cli::Fmt(args) => args.main(),
+    Cli::FromMm(args) => args.main(),
+    Cli::Lint(args) => args.main(),
+    Cli::Minimize(args) => args.main(),
+    Cli::MmbDiff(args) => args.main(),
+    Cli::MmbLink(args) => args.main(),
     #[cfg(feature = "server")]
     Cli::Server(args) =>  {
       if args.no_proofs { mm0_rs::set_check_proofs(false) }
@@ -28,5 +54,6 @@ fn main() -> std::io::Result<()> {
       args.main();
       Ok(())
     }
+    Cli::Verify(args) => args.main(),
   }
 }
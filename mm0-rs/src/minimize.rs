@@ -0,0 +1,439 @@
+//! Shorten proofs by using earlier theorems
+//!
+//! This implements `mm0-rs minimize`, which is similar in spirit to the `minimize_with`
+//! command of metamath. For each theorem with a proof, and each step of the proof, it looks
+//! for an earlier theorem whose conclusion unifies with the statement proved by that step
+//! and whose hypotheses unify with statements that are proved by substeps. If replacing the
+//! step with an application of that theorem makes the step shorter, the replacement is
+//! made. If the proof of the theorem as a whole becomes shorter, the new proof is checked
+//! using [`verify_thmdef`](crate::Environment::verify_thmdef), and reported.
+//!
+//! The size of a proof is the number of distinct nodes in the proof tree, including
+//! expressions, which is proportional to the size of the proof in an MMB file.
+//! A replacement is never allowed to add new axioms to the list of axioms the theorem
+//! depends on. The minimized proofs can be written to an MMB or MMU file.
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{self, BufWriter};
+use bit_set::BitSet;
+use crate::elab::proof::{build, Dedup, IDedup, NodeHash, ProofHash};
+use crate::elab::verify::Bound;
+use crate::{Environment, ErrorLevel, ExprNode, FileRef, FrozenEnv, Proof,
+  ProofNode, StmtTrace, TermId, Thm, ThmId, ThmKind, Type, DeclKey, u32_as_usize};
+
+/// Shorten proofs by using earlier theorems
+#[derive(clap::Args, Debug)]
+pub struct Args {
+  /// Only minimize THMS (a comma separated list)
+  #[clap(long, value_name = "THMS", use_value_delimiter = true)]
+  pub only: Vec<String>,
+  /// Sets the input file (.mm1 or .mm0)
+  pub input: String,
+  /// Sets the output file (.mmb or .mmu), containing the minimized proofs
+  pub output: Option<String>,
+}
+
+/// Convert a [`Proof`] to a [`Dedup`], returning the indexes of the hypotheses and the
+/// proof in the dedup.
+fn load(env: &Environment, td: &Thm, pf: &Proof) -> (Dedup<ProofHash>, Vec<usize>, usize) {
+  struct Loader<'a> {
+    env: &'a Environment,
+    pf: &'a Proof,
+    de: Dedup<ProofHash>,
+    heap: Vec<Option<usize>>,
+  }
+  impl<'a> Loader<'a> {
+    fn nodes(&mut self, ps: &'a [ProofNode]) -> Vec<usize> {
+      ps.iter().map(|p| self.node(p)).collect()
+    }
+    fn node(&mut self, p: &'a ProofNode) -> usize {
+      let store = &self.pf.store;
+      let h = match *p {
+        ProofNode::Ref(i) => {
+          if let Some(n) = self.heap[i] { return n }
+          let n = self.node(&self.pf.heap[i]);
+          self.heap[i] = Some(n);
+          return n
+        }
+        ProofNode::Dummy(a, s) => ProofHash::Dummy(a, s),
+        ProofNode::Term(t, p) => ProofHash::Term(t,
+          self.nodes(self.env.terms[t].unpack_term(&store[p..])).into()),
+        ProofNode::Hyp(i, p) => ProofHash::Hyp(i, self.node(&store[p])),
+        ProofNode::Thm(t, p) => {
+          let (res, args, hyps) = self.env.thms[t].unpack_thm(&store[p..]);
+          let mut ns = self.nodes(args);
+          ns.extend(self.nodes(hyps));
+          ProofHash::Thm(t, ns.into(), self.node(res))
+        }
+        ProofNode::Conv(p) => {
+          let (tgt, conv, prf) = ProofNode::unpack_conv(&store[p..]);
+          ProofHash::Conv(self.node(tgt), self.node(conv), self.node(prf))
+        }
+        ProofNode::Refl(p) => ProofHash::Refl(self.node(&store[p])),
+        ProofNode::Sym(p) => ProofHash::Sym(self.node(&store[p])),
+        ProofNode::Cong(t, p) => ProofHash::Cong(t,
+          self.nodes(self.env.terms[t].unpack_term(&store[p..])).into()),
+        ProofNode::Unfold(t, p) => {
+          let (sub_lhs, c, args) = self.env.terms[t].unpack_unfold(&store[p..]);
+          let ns: Box<[usize]> = self.nodes(args).into();
+          let lhs = self.de.add_direct(ProofHash::Term(t, ns.clone()));
+          ProofHash::Unfold(t, ns, lhs, self.node(sub_lhs), self.node(c))
+        }
+      };
+      self.de.add_direct(h)
+    }
+  }
+  let mut heap = vec![None; pf.heap.len()];
+  for (i, h) in heap.iter_mut().enumerate().take(td.args.len()) { *h = Some(i) }
+  let mut ld = Loader { env, pf, de: Dedup::new(&td.args), heap };
+  let hyps = ld.nodes(&pf.hyps);
+  let root = ld.node(pf.head());
+  (ld.de, hyps, root)
+}
+
+/// Construct a copy of `h` with the children replaced using `f`.
+fn map_children(h: &ProofHash, mut f: impl FnMut(usize) -> usize) -> ProofHash {
+  let mut map = |ns: &[usize]| ns.iter().map(|&i| f(i)).collect::<Vec<_>>();
+  match *h {
+    ProofHash::None => ProofHash::None,
+    ProofHash::Ref(k, i) => ProofHash::Ref(k, i),
+    ProofHash::Dummy(a, s) => ProofHash::Dummy(a, s),
+    ProofHash::Term(t, ref ns) => ProofHash::Term(t, map(ns).into()),
+    ProofHash::Hyp(i, e) => ProofHash::Hyp(i, map(&[e])[0]),
+    ProofHash::Thm(t, ref ns, r) => {
+      let ns = map(ns);
+      ProofHash::Thm(t, ns.into(), map(&[r])[0])
+    }
+    ProofHash::Conv(tgt, c, p) => {
+      let ns = map(&[tgt, c, p]);
+      ProofHash::Conv(ns[0], ns[1], ns[2])
+    }
+    ProofHash::Refl(e) => ProofHash::Refl(map(&[e])[0]),
+    ProofHash::Sym(e) => ProofHash::Sym(map(&[e])[0]),
+    ProofHash::Cong(t, ref ns) => ProofHash::Cong(t, map(ns).into()),
+    ProofHash::Unfold(t, ref ns, lhs, sub_lhs, c) => {
+      let ns = map(ns);
+      let rest = map(&[lhs, sub_lhs, c]);
+      ProofHash::Unfold(t, ns.into(), rest[0], rest[1], rest[2])
+    }
+  }
+}
+
+/// Copy node `i` of `de` into `new`, replacing the nodes in `replace`, and collecting
+/// the theorems used by the replacements in `used`.
+fn copy(de: &Dedup<ProofHash>, new: &mut Dedup<ProofHash>, replace: &HashMap<usize, usize>,
+  memo: &mut [Option<usize>], used: &mut Vec<ThmId>, i: usize
+) -> usize {
+  if let Some(n) = memo[i] { return n }
+  let n = if let Some(&j) = replace.get(&i) {
+    if let ProofHash::Thm(u, _, _) = de[j] { used.push(u) }
+    copy(de, new, replace, memo, used, j)
+  } else {
+    let h = map_children(&de[i], |j| copy(de, new, replace, memo, used, j));
+    new.add_direct(h)
+  };
+  memo[i] = Some(n);
+  n
+}
+
+/// The statement proved by the proof node `i`, or `None` if `i` is not a proof.
+fn statement(de: &Dedup<ProofHash>, i: usize) -> Option<usize> {
+  match de[i] {
+    ProofHash::Hyp(_, e) | ProofHash::Thm(_, _, e) | ProofHash::Conv(e, _, _) => Some(e),
+    _ => None,
+  }
+}
+
+/// The direct subproofs of proof node `i`.
+fn subproofs(env: &Environment, de: &Dedup<ProofHash>, i: usize) -> Vec<usize> {
+  match de[i] {
+    ProofHash::Thm(t, ref ns, _) => ns[env.thms[t].args.len()..].to_vec(),
+    ProofHash::Conv(_, _, p) => vec![p],
+    _ => vec![],
+  }
+}
+
+/// The number of distinct nodes reachable from `roots`, not counting the variables.
+fn size(de: &Dedup<ProofHash>, nargs: usize, roots: impl IntoIterator<Item=usize>) -> usize {
+  fn go(de: &Dedup<ProofHash>, seen: &mut BitSet, i: usize) {
+    if seen.insert(i) { de[i].on_children(|j| go(de, seen, j)) }
+  }
+  let mut seen = BitSet::new();
+  for i in 0..nargs { seen.insert(i); }
+  for i in roots { go(de, &mut seen, i) }
+  seen.len() - nargs
+}
+
+/// A substitution for the variables of a theorem (and the subterms on its heap).
+struct Unify<'a> {
+  env: &'a Environment,
+  td: &'a Thm,
+  subst: Vec<Option<usize>>,
+}
+
+impl Unify<'_> {
+  /// Unify the theorem expression `e` with the expression `tgt` in the dedup.
+  fn expr(&mut self, de: &Dedup<ProofHash>, e: &ExprNode, tgt: usize) -> bool {
+    match *e {
+      ExprNode::Ref(i) => match self.subst[i] {
+        Some(j) => j == tgt,
+        None => {
+          if i >= self.td.args.len() && !self.expr(de, &self.td.heap[i], tgt) { return false }
+          self.subst[i] = Some(tgt);
+          true
+        }
+      },
+      ExprNode::Dummy(..) => false,
+      ExprNode::App(t, p) => match de[tgt] {
+        ProofHash::Term(t2, ref ns) if t == t2 => {
+          let es = self.env.terms[t].unpack_app(&self.td.store[p..]);
+          es.iter().zip(&**ns).all(|(e, &n)| self.expr(de, e, n))
+        }
+        _ => false
+      }
+    }
+  }
+}
+
+/// The shape of the conclusion of a theorem, used to quickly find the theorems that
+/// could prove a given statement: the head term, and the head term of the first argument.
+type Shape = (TermId, Option<TermId>);
+
+fn expr_shape(env: &Environment, td: &Thm) -> Option<Shape> {
+  let deref = |e| {
+    let mut e: &ExprNode = e;
+    while let ExprNode::Ref(i) = *e {
+      if i < td.args.len() { break }
+      e = &td.heap[i]
+    }
+    *e
+  };
+  match deref(&td.ret) {
+    ExprNode::App(t, p) => Some((t, env.terms[t].unpack_app(&td.store[p..]).first()
+      .and_then(|e| if let ExprNode::App(t, _) = deref(e) { Some(t) } else { None }))),
+    _ => None,
+  }
+}
+
+fn dedup_shape(de: &Dedup<ProofHash>, i: usize) -> Option<Shape> {
+  match de[i] {
+    ProofHash::Term(t, ref ns) => Some((t, ns.first()
+      .and_then(|&j| if let ProofHash::Term(t, _) = de[j] { Some(t) } else { None }))),
+    _ => None,
+  }
+}
+
+/// The proof minimizer, which holds the index of theorems by the shape of their conclusion.
+struct Minimizer<'a> {
+  env: &'a Environment,
+  /// The theorems with a given conclusion shape. A theorem with a conclusion `foo x`
+  /// is listed under `(foo, None)`.
+  by_shape: HashMap<Shape, Vec<ThmId>>,
+  /// The theorems whose conclusion is a variable.
+  any_shape: Vec<ThmId>,
+  /// The set of axioms used by each theorem.
+  axioms: Vec<BitSet>,
+}
+
+impl<'a> Minimizer<'a> {
+  fn new(env: &'a Environment) -> Self {
+    let mut by_shape = HashMap::<_, Vec<_>>::new();
+    let mut any_shape = vec![];
+    let mut axioms: Vec<BitSet> = vec![];
+    for (tid, td) in env.thms.enum_iter() {
+      match expr_shape(env, td) {
+        Some(s) => by_shape.entry(s).or_default().push(tid),
+        None => any_shape.push(tid),
+      }
+      let mut bs = BitSet::new();
+      match &td.kind {
+        ThmKind::Axiom => { bs.insert(u32_as_usize(tid.0)); }
+        ThmKind::Thm(None) => {}
+        ThmKind::Thm(Some(pf)) => for p in pf.heap.iter().chain(&*pf.store) {
+          if let ProofNode::Thm(t, _) = *p { bs.union_with(&axioms[u32_as_usize(t.0)]) }
+        }
+      }
+      axioms.push(bs);
+    }
+    Self { env, by_shape, any_shape, axioms }
+  }
+
+  /// Try to prove the statement `stmt` using theorem `u`, where the available subproofs
+  /// are `subs`. Returns the new proof node.
+  fn apply(&self, td: &Thm, de: &mut Dedup<ProofHash>, u: ThmId, stmt: usize,
+    subs: &[(usize, usize)]
+  ) -> Option<usize> {
+    let ud = &self.env.thms[u];
+    let mut unify = Unify { env: self.env, td: ud, subst: vec![None; ud.heap.len()] };
+    if !unify.expr(de, &ud.ret, stmt) { return None }
+    let mut hyps = vec![];
+    for (_, h) in &*ud.hyps {
+      let subst = unify.subst.clone();
+      let &(_, p) = subs.iter().find(|&&(s, _)| {
+        unify.subst.clone_from(&subst);
+        unify.expr(de, h, s)
+      })?;
+      hyps.push(p);
+    }
+    let mut ns = unify.subst[..ud.args.len()].iter().copied().collect::<Option<Vec<_>>>()?;
+    // Check the sorts and the disjoint variable conditions
+    let mut bvars = vec![];
+    for (i, (&n, (_, ty))) in ns.iter().zip(&*ud.args).enumerate() {
+      let deps = de.vec[n].2;
+      let (sort, bound) = match de[n] {
+        ProofHash::Ref(_, j) => (td.args[j].1.sort(), td.args[j].1.bound()),
+        ProofHash::Dummy(_, s) => (s, true),
+        ProofHash::Term(t, _) => (self.env.terms[t].ret.0, false),
+        _ => return None,
+      };
+      if sort != ty.sort() { return None }
+      let ok = match *ty {
+        Type::Bound(_) => {
+          bvars.push(deps);
+          bound && ns[..i].iter().all(|&j| de.vec[j].2 & deps == 0)
+        }
+        Type::Reg(_, d) => bvars.iter().enumerate().all(|(k, &bv)| d & (1 << k) != 0 || bv & deps == 0),
+      };
+      if !ok { return None }
+    }
+    ns.extend(hyps);
+    Some(de.add_direct(ProofHash::Thm(u, ns.into(), stmt)))
+  }
+
+  /// Minimize the proof of theorem `tid`. Returns the new proof, the old and new sizes,
+  /// and the theorems that were used in the new proof.
+  fn minimize(&self, tid: ThmId) -> Option<(Proof, usize, usize, Vec<ThmId>)> {
+    let env = self.env;
+    let td = &env.thms[tid];
+    let pf = if let ThmKind::Thm(Some(pf)) = &td.kind { pf } else { return None };
+    let nargs = td.args.len();
+    let (mut de, hyps, root) = load(env, td, pf);
+    let old_size = size(&de, nargs, hyps.iter().copied().chain([root]));
+    let n = de.vec.len();
+    let mut replace = HashMap::new();
+    for i in nargs..n {
+      let stmt = match de[i] {
+        ProofHash::Hyp(..) => continue,
+        _ => if let Some(stmt) = statement(&de, i) { stmt } else { continue },
+      };
+      let cur = if let ProofHash::Thm(t, _, _) = de[i] { Some(t) } else { None };
+      let candidates = dedup_shape(&de, stmt).into_iter()
+        .flat_map(|(t, arg)| {
+          let exact = arg.and_then(|arg| self.by_shape.get(&(t, Some(arg))));
+          exact.into_iter().chain(self.by_shape.get(&(t, None))).flatten()
+        })
+        .chain(&self.any_shape)
+        .copied()
+        .filter(|&u| u < tid && Some(u) != cur &&
+          self.axioms[u32_as_usize(u.0)].is_subset(&self.axioms[u32_as_usize(tid.0)]));
+      let mut subs = None;
+      let mut best = (size(&de, nargs, [i]), None);
+      for u in candidates {
+        let subs = subs.get_or_insert_with(|| {
+          let mut subs = vec![];
+          let mut seen = HashSet::new();
+          let mut stack = subproofs(env, &de, i);
+          while let Some(p) = stack.pop() {
+            if seen.insert(p) {
+              if let Some(s) = statement(&de, p) { subs.push((s, p)) }
+              stack.extend(subproofs(env, &de, p))
+            }
+          }
+          subs
+        });
+        if let Some(new) = self.apply(td, &mut de, u, stmt, subs) {
+          let sz = size(&de, nargs, [new]);
+          if sz < best.0 { best = (sz, Some(new)) }
+        }
+      }
+      if let (_, Some(new)) = best { replace.insert(i, new); }
+    }
+    if replace.is_empty() { return None }
+
+    // Build the new proof, following the replacements
+    let mut new = Dedup::<ProofHash>::new(&td.args);
+    let mut memo = vec![None; de.vec.len()];
+    for (i, m) in memo.iter_mut().enumerate().take(nargs) { *m = Some(i) }
+    let mut used = vec![];
+    let hyps = hyps.iter().map(|&h| copy(&de, &mut new, &replace, &mut memo, &mut used, h))
+      .collect::<Vec<_>>();
+    let root = copy(&de, &mut new, &replace, &mut memo, &mut used, root);
+    let new_size = size(&new, nargs, hyps.iter().copied().chain([root]));
+    if new_size >= old_size { return None }
+    new.calc_use(nargs, hyps.iter().copied().chain([root]));
+    let (mut ids, heap, mut store) = build(&new);
+    let hyps = hyps.iter().map(|&i| ids[i].take()).collect();
+    store.push(ids[root].take());
+    used.sort();
+    used.dedup();
+    Some((Proof { heap, hyps, store: store.into() }, old_size, new_size, used))
+  }
+}
+
+impl Args {
+  /// Main entry point for `mm0-rs minimize` subcommand.
+  ///
+  /// See the [module documentation](self) for the purpose of this command.
+  ///
+  /// # Arguments
+  ///
+  /// `mm0-rs minimize <in.mm1> [out.mmb]`, where:
+  ///
+  /// - `in.mm1` is the initial file to elaborate.
+  /// - `out.mmb` (or `out.mmu`) is the file to write with the minimized proofs.
+  pub fn main(self) -> io::Result<()> {
+    let path: FileRef = fs::canonicalize(&self.input)?.into();
+    let (file, old) = crate::compiler::elab_for_result(path.clone())?;
+    let old = old.unwrap_or_else(|| std::process::exit(1));
+    let mut env = old.thaw_copy();
+    let only = self.only.iter().filter_map(|s| {
+      match env.atoms.get(s.as_bytes()).and_then(|&a| env.data[a].decl) {
+        Some(DeclKey::Thm(tid)) => Some(tid),
+        _ => { eprintln!("warning: unknown theorem '{}'", s); None }
+      }
+    }).collect::<HashSet<_>>();
+    let thms = env.stmts.iter().filter_map(|s| match *s {
+      StmtTrace::Decl(a) => match env.data[a].decl {
+        Some(DeclKey::Thm(tid)) if only.is_empty() || only.contains(&tid) => Some(tid),
+        _ => None,
+      },
+      _ => None,
+    }).collect::<Vec<_>>();
+    let mut results = vec![];
+    {
+      let min = Minimizer::new(&env);
+      let name = |t: ThmId| String::from_utf8_lossy(&env.data[env.thms[t].atom].name).into_owned();
+      for tid in thms {
+        let (pf, old_size, new_size, used) = if let Some(res) = min.minimize(tid) { res } else { continue };
+        let mut td = env.thms[tid].clone();
+        td.kind = ThmKind::Thm(Some(pf));
+        if let Err(e) = env.verify_thmdef(&Bound::before_thm(tid), &td) {
+          eprintln!("warning: minimized proof of {} failed to verify: {}",
+            name(tid), e.render_to_string(&env));
+          continue
+        }
+        println!("{}: {} -> {} (using {})", name(tid), old_size, new_size,
+          used.into_iter().map(name).collect::<Vec<_>>().join(", "));
+        results.push((tid, td.kind));
+      }
+    }
+    println!("minimized {} proofs", results.len());
+    for (tid, kind) in results { env.thms[tid].kind = kind }
+    if let Some(out) = &self.output {
+      let env = FrozenEnv::new(env);
+      let w = BufWriter::new(File::create(out)?);
+      if matches!(out.rsplit('.').next(), Some(ext) if ext.eq_ignore_ascii_case("mmu")) {
+        env.export_mmu(w)?;
+      } else {
+        let mut report = |lvl: ErrorLevel, err: &str| eprintln!("{}: {}", lvl, err);
+        let mut to_range = crate::compiler::mk_to_range();
+        let mut ex = crate::mmb::export::Exporter::new(path, file.try_ascii().map(|fc| &**fc),
+          &env, &mut report, w);
+        ex.set_to_range(&mut to_range);
+        ex.run(true)?;
+        ex.finish()?;
+      }
+    }
+    Ok(())
+  }
+}
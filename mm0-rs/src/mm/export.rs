@@ -0,0 +1,948 @@
+//! Metamath exporter, which produces `.mm` databases from an
+//! [`Environment`](crate::Environment) object.
+//!
+//! This is used to cross-check the proofs with the existing Metamath verifiers.
+//! The translation works as follows:
+//!
+//! - Every sort becomes a typecode. Expressions are written in prefix form with
+//!   parentheses, `( t e1 .. en )` (or just `t` if `t` has no arguments), so every term
+//!   is a constant with a syntax axiom `tm-t`.
+//! - Bound variables of a `pure` sort `s` are variables of typecode `s`. For the other
+//!   sorts, they have a separate typecode `{s}`, and the syntax axiom `bv-s` turns them
+//!   into expressions of sort `s`.
+//! - Axioms and theorems state their hypotheses and conclusion with the typecode `|-`.
+//!   Every pair of bound variables is disjoint, and so is every bound variable and regular
+//!   variable which does not depend on it. Dummy variables are disjoint from everything.
+//! - The typecode `==` is used for conversions. A definition `t` gets a definitional axiom
+//!   `df-t` stating `== ( t x1 .. xn ) e`, and every term gets a congruence axiom `cong-t`.
+//!   Every sort gets axioms `refl-s`, `sym-s` and `trans-s`, and every provable sort `s` an
+//!   axiom `conv-s`, which proves `|- A` from `== A B` and `|- B`.
+//! - Proofs are written as compressed proofs, where the subproofs on the proof heap are
+//!   saved and reused. A `conv` step uses `conv-s`, and an `unfold` step uses `trans-s`
+//!   applied to an instance of `df-t`.
+//!
+//! Theorem names are used as labels (with `_` added if the name is also a sort or term),
+//! and variable names get a `'` added if they clash with a label or a constant.
+//!
+//! Disjoint variable conditions in Metamath are syntactic, whereas in MM0 a bound variable
+//! only has to be not free. A proof which substitutes an expression in which the variable
+//! occurs (under a binder) for a regular variable that does not depend on it can not be
+//! checked by Metamath, and is written as `?` with a warning.
+use std::collections::{HashMap, HashSet, hash_map::Entry};
+use std::io::{self, Write};
+use crate::{AtomId, ErrorLevel, Expr, ExprNode, FrozenEnv, DeclKey, Modifiers, ProofNode,
+  SortId, StmtTrace, TermId, TermKind, Thm, ThmId, ThmKind, Type};
+
+/// The names of everything in the translation, and some precomputed data.
+struct Tables<'a> {
+  env: &'a FrozenEnv,
+  /// The typecode of each sort
+  sorts: Vec<String>,
+  /// The typecode for bound variables of each sort, if it is not the sort itself
+  bound: Vec<Option<String>>,
+  /// The constant for each term
+  terms: Vec<String>,
+  /// The label of each theorem
+  thms: Vec<String>,
+  /// All constants and theorem labels, which variables have to avoid
+  taken: HashSet<String>,
+  /// For each theorem, whether each argument occurs in the statement, in which case
+  /// its `$f` hypothesis is mandatory
+  used: Vec<Box<[bool]>>,
+  /// The dummy variables of each definition, in the order of the `$f` hypotheses of `df-t`
+  dummies: Vec<Box<[(AtomId, SortId)]>>,
+}
+
+fn fresh(taken: &mut HashSet<String>, mut s: String) -> String {
+  while taken.contains(&s) { s.push('_') }
+  taken.insert(s.clone());
+  s
+}
+
+fn used_args(env: &FrozenEnv, td: &Thm) -> Box<[bool]> {
+  fn go(env: &FrozenEnv, td: &Thm, used: &mut [bool], seen: &mut [bool], e: &ExprNode) {
+    match *e {
+      ExprNode::Ref(i) if i < used.len() => used[i] = true,
+      ExprNode::Ref(i) => if !std::mem::replace(&mut seen[i], true) {
+        go(env, td, used, seen, &td.heap[i])
+      }
+      ExprNode::Dummy(..) => {}
+      ExprNode::App(t, p) =>
+        for e in env.term(t).unpack_app(&td.store[p..]) { go(env, td, used, seen, e) }
+    }
+  }
+  let mut used = vec![false; td.args.len()];
+  let mut seen = vec![false; td.heap.len()];
+  for (_, e) in &*td.hyps { go(env, td, &mut used, &mut seen, e) }
+  go(env, td, &mut used, &mut seen, &td.ret);
+  used.into()
+}
+
+fn def_dummies(expr: &Expr) -> Box<[(AtomId, SortId)]> {
+  let mut out: Vec<(AtomId, SortId)> = vec![];
+  for e in expr.heap.iter().chain(&*expr.store) {
+    if let ExprNode::Dummy(a, s) = *e {
+      if !out.iter().any(|&(b, _)| a == b) { out.push((a, s)) }
+    }
+  }
+  out.into()
+}
+
+impl<'a> Tables<'a> {
+  fn new(env: &'a FrozenEnv) -> Self {
+    let mut taken: HashSet<String> = ["(", ")", "|-", "=="].iter().map(|&s| s.into()).collect();
+    let sorts: Vec<_> = env.sorts().0.iter()
+      .map(|sd| fresh(&mut taken, sd.name.as_str().into())).collect();
+    let bound = env.sorts().0.iter().zip(&sorts).map(|(sd, s)|
+      if sd.mods.intersects(Modifiers::PURE | Modifiers::STRICT) { None }
+      else { Some(fresh(&mut taken, format!("{{{}}}", s))) }).collect();
+    let terms = env.terms().0.iter()
+      .map(|td| fresh(&mut taken, env.data()[td.atom].name().as_str().into())).collect();
+    let thms = env.thms().0.iter()
+      .map(|td| fresh(&mut taken, env.data()[td.atom].name().as_str().into())).collect();
+    let used = env.thms().0.iter().map(|td| used_args(env, td)).collect();
+    let dummies = env.terms().0.iter().map(|td| match &td.kind {
+      TermKind::Def(Some(expr)) => def_dummies(expr),
+      _ => Box::new([]) as Box<[_]>,
+    }).collect();
+    Self { env, sorts, bound, terms, thms, taken, used, dummies }
+  }
+
+  fn name(&self, a: AtomId) -> &'a str { self.env.data()[a].name().as_str() }
+
+  /// The typecode of a variable of type `ty`.
+  fn tc(&self, ty: Type) -> &str {
+    match ty {
+      Type::Bound(s) => match &self.bound[usize::from(s.0)] {
+        Some(tc) => tc,
+        None => self.sort(s),
+      },
+      Type::Reg(s, _) => self.sort(s),
+    }
+  }
+
+  fn sort(&self, s: SortId) -> &str { &self.sorts[usize::from(s.0)] }
+  fn term(&self, t: TermId) -> &str { &self.terms[t.0 as usize] }
+
+  /// Write an expression, with a space before every token.
+  fn expr(&self, out: &mut String,
+    vars: &[String], dummies: &HashMap<AtomId, String>,
+    heap: &[ExprNode], store: &[ExprNode], e: &ExprNode
+  ) {
+    match *e {
+      ExprNode::Ref(i) if i < vars.len() => { out.push(' '); out.push_str(&vars[i]) }
+      ExprNode::Ref(i) => self.expr(out, vars, dummies, heap, store, &heap[i]),
+      ExprNode::Dummy(a, _) => { out.push(' '); out.push_str(&dummies[&a]) }
+      ExprNode::App(t, p) => {
+        let args = self.env.term(t).unpack_app(&store[p..]);
+        if args.is_empty() {
+          out.push(' '); out.push_str(self.term(t))
+        } else {
+          out.push_str(" ( "); out.push_str(self.term(t));
+          for e in args { self.expr(out, vars, dummies, heap, store, e) }
+          out.push_str(" )")
+        }
+      }
+    }
+  }
+}
+
+/// A `${ ... $}` block containing a single axiom or theorem and its hypotheses.
+struct Block {
+  label: String,
+  /// The variables, as `($f label, typecode, token)`
+  vars: Vec<(String, String, String)>,
+  /// The variable tokens used in this block
+  tokens: HashSet<String>,
+  /// The labels used in this block
+  labels: HashSet<String>,
+  /// The `$d` conditions
+  dvs: Vec<Vec<String>>,
+  /// The `$e` hypotheses, as `(label, statement)`
+  hyps: Vec<(String, String)>,
+}
+
+impl Block {
+  fn new(label: String) -> Self {
+    Self { label, vars: vec![], tokens: HashSet::new(), labels: HashSet::new(),
+      dvs: vec![], hyps: vec![] }
+  }
+
+  /// Get a label `label.name` for a hypothesis, or a numbered one if there is no name.
+  fn local(&mut self, name: Option<&str>) -> String {
+    if let Some(name) = name {
+      let l = format!("{}.{}", self.label, name);
+      if self.labels.insert(l.clone()) { return l }
+    }
+    let l = format!("{}.{}", self.label, self.vars.len() + self.hyps.len() + 1);
+    self.labels.insert(l.clone());
+    l
+  }
+
+  /// Add a variable with a `$f` hypothesis, and return its token.
+  fn var(&mut self, t: &Tables<'_>, name: Option<&str>, base: &str, tc: &str) -> String {
+    let label = self.local(name);
+    let mut tok = base.to_owned();
+    while t.taken.contains(&tok) || self.tokens.contains(&tok) { tok.push('\'') }
+    self.tokens.insert(tok.clone());
+    self.vars.push((label, tc.into(), tok.clone()));
+    tok
+  }
+
+  /// Add a `$e` hypothesis, and return its label.
+  fn hyp(&mut self, name: Option<&str>, stmt: String) -> String {
+    let label = self.local(name);
+    self.hyps.push((label.clone(), stmt));
+    label
+  }
+}
+
+/// A variable in a proof: an argument of the theorem, or a dummy variable.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+enum Var { Arg(usize), Dummy(AtomId) }
+
+/// The position a proof node is used in, which determines what it is translated to.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+enum Role {
+  /// The argument for a bound variable, which is a variable of the bound typecode
+  Bound,
+  /// The syntax proof of an expression
+  Expr,
+  /// The syntax proof of the left side of a conversion
+  Lhs,
+  /// The syntax proof of the right side of a conversion
+  Rhs,
+  /// The proof of `== A B` for a conversion
+  Conv,
+  /// The proof of `|- A`
+  Proof,
+}
+
+/// A step of a compressed proof, before the numbers are assigned.
+#[derive(Clone, Copy, Debug)]
+enum Step {
+  /// A mandatory hypothesis, by index
+  Hyp(usize),
+  /// A label in the label list, by index
+  Label(usize),
+  /// Save the last subproof (`Z`)
+  Save,
+  /// Use a saved subproof, by index
+  Load(usize),
+}
+
+/// The state for translating one proof to a compressed proof.
+struct ProofBuilder<'a> {
+  t: &'a Tables<'a>,
+  td: &'a Thm,
+  heap: &'a [ProofNode],
+  store: &'a [ProofNode],
+  /// The `$f` label of each argument
+  args: &'a [String],
+  /// The `$f` label and sort of each dummy variable
+  dummies: &'a HashMap<AtomId, (String, SortId)>,
+  /// The `$e` label of each hypothesis
+  hyps: &'a [String],
+  /// The index of the bound variable among the bound variables, for each argument
+  bv_index: Vec<u32>,
+  /// The mandatory hypotheses of the theorem
+  mand: HashMap<String, usize>,
+  labels: Vec<String>,
+  label_idx: HashMap<String, usize>,
+  steps: Vec<Step>,
+  saves: usize,
+  cache: HashMap<(usize, Role), usize>,
+  /// The reason the proof can't be translated, if there is one
+  error: Option<String>,
+}
+
+/// Append the compressed proof number `n >= 1` to `out`.
+fn encode(out: &mut Vec<u8>, n: usize) {
+  let start = out.len();
+  let mut n = n - 1;
+  out.push(b"ABCDEFGHIJKLMNOPQRST"[n % 20]);
+  n /= 20;
+  while n > 0 {
+    n -= 1;
+    out.push(b"UVWXY"[n % 5]);
+    n /= 5;
+  }
+  out[start..].reverse()
+}
+
+impl<'a> ProofBuilder<'a> {
+  fn fail(&mut self, msg: String) {
+    if self.error.is_none() { self.error = Some(msg) }
+  }
+
+  fn label(&mut self, l: String) {
+    if let Some(&i) = self.mand.get(&l) { return self.steps.push(Step::Hyp(i)) }
+    let n = self.labels.len();
+    let i = match self.label_idx.entry(l) {
+      Entry::Occupied(e) => *e.get(),
+      Entry::Vacant(e) => { self.labels.push(e.key().clone()); *e.insert(n) }
+    };
+    self.steps.push(Step::Label(i))
+  }
+
+  /// Follow references to the heap, stopping at variables.
+  fn unref(&self, mut node: &'a ProofNode) -> &'a ProofNode {
+    while let ProofNode::Ref(i) = *node {
+      if i < self.args.len() { break }
+      node = &self.heap[i]
+    }
+    node
+  }
+
+  fn as_var(&self, node: &'a ProofNode) -> Option<Var> {
+    match *self.unref(node) {
+      ProofNode::Ref(i) => Some(Var::Arg(i)),
+      ProofNode::Dummy(a, _) => Some(Var::Dummy(a)),
+      _ => None
+    }
+  }
+
+  fn is_expr(&self, node: &'a ProofNode) -> bool {
+    matches!(self.unref(node), ProofNode::Ref(_) | ProofNode::Dummy(..) | ProofNode::Term(..))
+  }
+
+  /// The sort of an expression or conversion.
+  fn sort_of(&self, node: &'a ProofNode) -> SortId {
+    match *self.unref(node) {
+      ProofNode::Ref(i) => self.td.args[i].1.sort(),
+      ProofNode::Dummy(_, s) => s,
+      ProofNode::Term(t, _) | ProofNode::Cong(t, _) | ProofNode::Unfold(t, _) =>
+        self.t.env.term(t).ret.0,
+      ProofNode::Refl(p) | ProofNode::Sym(p) => self.sort_of(&self.store[p]),
+      ProofNode::Hyp(..) | ProofNode::Thm(..) | ProofNode::Conv(_) =>
+        unreachable!("expected an expression or conversion"),
+    }
+  }
+
+  /// Does `z` occur in the expression `node`, as Metamath sees it?
+  fn occurs(&self, node: &'a ProofNode, z: Var, seen: &mut HashSet<usize>) -> bool {
+    match *node {
+      ProofNode::Ref(i) if i < self.args.len() => match (z, self.td.args[i].1) {
+        _ if z == Var::Arg(i) => true,
+        (Var::Arg(j), Type::Reg(_, deps)) =>
+          self.td.args[j].1.bound() && deps & (1 << self.bv_index[j]) != 0,
+        _ => false,
+      },
+      ProofNode::Ref(i) => seen.insert(i) && self.occurs(&self.heap[i], z, seen),
+      ProofNode::Dummy(a, _) => z == Var::Dummy(a),
+      ProofNode::Term(t, p) => self.t.env.term(t).unpack_term(&self.store[p..])
+        .iter().any(|e| self.occurs(e, z, seen)),
+      _ => false,
+    }
+  }
+
+  fn var(&mut self, v: Var, coerce: bool) {
+    let (label, ty) = match v {
+      Var::Arg(i) => (self.args[i].clone(), self.td.args[i].1),
+      Var::Dummy(a) => {
+        let (label, s) = &self.dummies[&a];
+        (label.clone(), Type::Bound(*s))
+      }
+    };
+    self.label(label);
+    if let (true, Type::Bound(s)) = (coerce, ty) {
+      if self.t.bound[usize::from(s.0)].is_some() {
+        self.label(format!("bv-{}", self.t.sort(s)))
+      }
+    }
+  }
+
+  /// The bound variable in a bound argument of a conversion.
+  fn bound_var(&self, node: &'a ProofNode) -> Option<Var> {
+    match *self.unref(node) {
+      ProofNode::Refl(p) | ProofNode::Sym(p) => self.bound_var(&self.store[p]),
+      _ => self.as_var(node)
+    }
+  }
+
+  fn args(&mut self, args: &'a [ProofNode], tys: &[(Option<AtomId>, Type)]) {
+    for (e, &(_, ty)) in args.iter().zip(tys) {
+      self.go(e, if ty.bound() { Role::Bound } else { Role::Expr })
+    }
+  }
+
+  /// Match the body of a definition against `node` to find the dummy variables.
+  fn unify(&self, m: &mut HashMap<AtomId, &'a ProofNode>, seen: &mut [bool],
+    expr: &Expr, e: &ExprNode, node: &'a ProofNode
+  ) {
+    match *e {
+      ExprNode::Ref(i) => if !std::mem::replace(&mut seen[i], true) {
+        self.unify(m, seen, expr, &expr.heap[i], node)
+      },
+      ExprNode::Dummy(a, _) => { m.insert(a, node); }
+      ExprNode::App(t, p) => if let ProofNode::Term(t2, p2) = *self.unref(node) {
+        if t == t2 {
+          let td = self.t.env.term(t);
+          for (e, node) in td.unpack_app(&expr.store[p..]).iter()
+            .zip(td.unpack_term(&self.store[p2..])) {
+            self.unify(m, seen, expr, e, node)
+          }
+        }
+      }
+    }
+  }
+
+  /// Check the `$d` conditions of theorem `t` applied to `args`.
+  fn check_dv(&mut self, t: ThmId, args: &'a [ProofNode]) {
+    let td = self.t.env.thm(t);
+    let mut b = 0;
+    for (e, &(_, ty)) in args.iter().zip(&*td.args) {
+      if !ty.bound() { continue }
+      let ok = if let Some(z) = self.as_var(e) {
+        args.iter().zip(&*td.args).all(|(e2, &(_, ty2))| match ty2 {
+          Type::Reg(_, deps) if deps & (1 << b) == 0 => !self.occurs(e2, z, &mut HashSet::new()),
+          _ => true,
+        })
+      } else { false };
+      if !ok {
+        self.fail(format!("the `$d` conditions of {} are not syntactically satisfied",
+          self.t.thms[t.0 as usize]))
+      }
+      b += 1;
+    }
+  }
+
+  fn unfold(&mut self, node: &'a ProofNode, t: TermId, p: usize) {
+    let td = self.t.env.term(t);
+    let (sub_lhs, c, args) = td.unpack_unfold(&self.store[p..]);
+    self.go(node, Role::Lhs);
+    self.go(sub_lhs, Role::Expr);
+    self.go(c, Role::Rhs);
+    self.args(args, &td.args);
+    let mut subst = HashMap::new();
+    if let TermKind::Def(Some(expr)) = &td.kind {
+      self.unify(&mut subst, &mut vec![false; expr.heap.len()], expr, expr.head(), sub_lhs)
+    }
+    let mut zs = vec![];
+    for &(a, _) in &*self.t.dummies[t.0 as usize] {
+      match subst.get(&a).and_then(|e| self.as_var(e)) {
+        Some(z) if !zs.contains(&z) &&
+          !args.iter().any(|e| self.occurs(e, z, &mut HashSet::new())) => {
+          zs.push(z);
+          self.var(z, false)
+        }
+        _ => self.fail(format!("the dummy variables of {} are not syntactically disjoint",
+          self.t.term(t))),
+      }
+    }
+    self.label(format!("df-{}", self.t.term(t)));
+    self.go(c, Role::Conv);
+    self.label(format!("trans-{}", self.t.sort(td.ret.0)));
+  }
+
+  fn go(&mut self, node: &'a ProofNode, mut role: Role) {
+    if matches!(role, Role::Lhs | Role::Rhs) && self.is_expr(node) { role = Role::Expr }
+    if role == Role::Conv && self.is_expr(node) {
+      let s = self.sort_of(node);
+      self.go(node, Role::Expr);
+      return self.label(format!("refl-{}", self.t.sort(s)))
+    }
+    match (role, *node) {
+      (_, ProofNode::Ref(i)) if i < self.args.len() => self.var(Var::Arg(i), role != Role::Bound),
+      (Role::Bound, ProofNode::Ref(i)) => self.go(&self.heap[i], role),
+      (_, ProofNode::Ref(i)) => if let Some(&n) = self.cache.get(&(i, role)) {
+        self.steps.push(Step::Load(n))
+      } else {
+        self.go(&self.heap[i], role);
+        self.steps.push(Step::Save);
+        self.cache.insert((i, role), self.saves);
+        self.saves += 1;
+      },
+      (_, ProofNode::Dummy(a, _)) => self.var(Var::Dummy(a), role != Role::Bound),
+      (Role::Expr, ProofNode::Term(t, p)) => {
+        let td = self.t.env.term(t);
+        self.args(td.unpack_term(&self.store[p..]), &td.args);
+        self.label(format!("tm-{}", self.t.term(t)))
+      }
+      (Role::Lhs | Role::Rhs, ProofNode::Refl(p)) => self.go(&self.store[p], Role::Expr),
+      (Role::Lhs, ProofNode::Sym(p)) => self.go(&self.store[p], Role::Rhs),
+      (Role::Rhs, ProofNode::Sym(p)) => self.go(&self.store[p], Role::Lhs),
+      (Role::Lhs | Role::Rhs, ProofNode::Cong(t, p)) => {
+        let td = self.t.env.term(t);
+        for (c, &(_, ty)) in td.unpack_term(&self.store[p..]).iter().zip(&*td.args) {
+          if ty.bound() {
+            if let Some(v) = self.bound_var(c) { self.var(v, false) }
+            else { self.fail("expected a bound variable".into()) }
+          } else {
+            self.go(c, role)
+          }
+        }
+        self.label(format!("tm-{}", self.t.term(t)))
+      }
+      (Role::Lhs, ProofNode::Unfold(t, p)) => {
+        let td = self.t.env.term(t);
+        self.args(td.unpack_unfold(&self.store[p..]).2, &td.args);
+        self.label(format!("tm-{}", self.t.term(t)))
+      }
+      (Role::Rhs, ProofNode::Unfold(t, p)) =>
+        self.go(self.t.env.term(t).unpack_unfold(&self.store[p..]).1, Role::Rhs),
+      (Role::Conv, ProofNode::Refl(p)) => self.go(&self.store[p], Role::Conv),
+      (Role::Conv, ProofNode::Sym(p)) => {
+        let c = &self.store[p];
+        self.go(c, Role::Lhs);
+        self.go(c, Role::Rhs);
+        self.go(c, Role::Conv);
+        self.label(format!("sym-{}", self.t.sort(self.sort_of(c))))
+      }
+      (Role::Conv, ProofNode::Cong(t, p)) => {
+        let td = self.t.env.term(t);
+        let cs = td.unpack_term(&self.store[p..]);
+        for (c, &(_, ty)) in cs.iter().zip(&*td.args) {
+          if ty.bound() {
+            if let Some(v) = self.bound_var(c) { self.var(v, false) }
+            else { self.fail("expected a bound variable".into()) }
+          } else {
+            self.go(c, Role::Lhs);
+            self.go(c, Role::Rhs);
+          }
+        }
+        for (c, &(_, ty)) in cs.iter().zip(&*td.args) {
+          if !ty.bound() { self.go(c, Role::Conv) }
+        }
+        self.label(format!("cong-{}", self.t.term(t)))
+      }
+      (Role::Conv, ProofNode::Unfold(t, p)) => self.unfold(node, t, p),
+      (Role::Proof, ProofNode::Hyp(i, _)) => self.label(self.hyps[i].clone()),
+      (Role::Proof, ProofNode::Thm(t, p)) => {
+        let td = self.t.env.thm(t);
+        let (_, args, hyps) = td.unpack_thm(&self.store[p..]);
+        self.check_dv(t, args);
+        for ((e, &(_, ty)), &used) in args.iter().zip(&*td.args).zip(&*self.t.used[t.0 as usize]) {
+          if used { self.go(e, if ty.bound() { Role::Bound } else { Role::Expr }) }
+        }
+        for e in hyps { self.go(e, Role::Proof) }
+        self.label(self.t.thms[t.0 as usize].clone())
+      }
+      (Role::Proof, ProofNode::Conv(p)) => {
+        let (tgt, c, pf) = ProofNode::unpack_conv(&self.store[p..]);
+        self.go(tgt, Role::Expr);
+        self.go(c, Role::Rhs);
+        self.go(c, Role::Conv);
+        self.go(pf, Role::Proof);
+        self.label(format!("conv-{}", self.t.sort(self.sort_of(tgt))))
+      }
+      _ => self.fail("unexpected proof node".into()),
+    }
+  }
+
+  /// Finish the proof, returning the label list and the compressed proof.
+  fn finish(self) -> Result<(Vec<String>, Vec<u8>), String> {
+    if let Some(e) = self.error { return Err(e) }
+    let (nmand, nlabels) = (self.mand.len(), self.labels.len());
+    let mut out = vec![];
+    for step in self.steps {
+      match step {
+        Step::Hyp(i) => encode(&mut out, i + 1),
+        Step::Label(i) => encode(&mut out, nmand + i + 1),
+        Step::Save => out.push(b'Z'),
+        Step::Load(i) => encode(&mut out, nmand + nlabels + i + 1),
+      }
+    }
+    Ok((self.labels, out))
+  }
+}
+
+/// The proof of a `$p` statement.
+#[derive(Clone, Copy, Debug)]
+enum Proof<'a> {
+  /// A proof which can't be translated, written as `?`
+  Incomplete,
+  /// A compressed proof, with its label list
+  Compressed(&'a [String], &'a [u8]),
+}
+
+struct Exporter<'a, W> {
+  t: Tables<'a>,
+  w: W,
+  report: &'a mut dyn FnMut(ErrorLevel, &str),
+}
+
+impl<W: Write> Exporter<'_, W> {
+  fn write_block(&mut self, b: &Block, stmt: &str, proof: Option<Proof<'_>>) -> io::Result<()> {
+    let w = &mut self.w;
+    let indent = if b.vars.is_empty() && b.hyps.is_empty() { "" } else {
+      writeln!(w, "${{")?;
+      write!(w, "  $v")?;
+      for (_, _, tok) in &b.vars { write!(w, " {}", tok)? }
+      writeln!(w, " $.")?;
+      for dv in &b.dvs { writeln!(w, "  $d {} $.", dv.join(" "))? }
+      for (l, tc, tok) in &b.vars { writeln!(w, "  {} $f {} {} $.", l, tc, tok)? }
+      for (l, e) in &b.hyps { writeln!(w, "  {} $e {} $.", l, e)? }
+      "  "
+    };
+    match proof {
+      None => writeln!(w, "{}{} $a {} $.", indent, b.label, stmt)?,
+      Some(Proof::Incomplete) => writeln!(w, "{}{} $p {} $= ? $.", indent, b.label, stmt)?,
+      Some(Proof::Compressed(labels, pf)) => {
+        writeln!(w, "{}{} $p {} $=", indent, b.label, stmt)?;
+        let mut line = format!("{}  (", indent);
+        for l in labels {
+          if line.len() + l.len() >= 79 {
+            writeln!(w, "{}", line)?;
+            line = format!("{}   ", indent)
+          }
+          line.push(' ');
+          line.push_str(l);
+        }
+        writeln!(w, "{} )", line)?;
+        for chunk in pf.chunks(76 - indent.len()) {
+          writeln!(w, "{}  {}", indent, std::str::from_utf8(chunk).expect("ascii"))?
+        }
+        writeln!(w, "{}  $.", indent)?
+      }
+    }
+    if !indent.is_empty() { writeln!(w, "$}}")? }
+    Ok(())
+  }
+
+  fn sort(&mut self, s: SortId) -> io::Result<()> {
+    let t = &self.t;
+    let sort = t.sort(s).to_owned();
+    let bound = t.bound[usize::from(s.0)].clone();
+    writeln!(self.w, "\n$c {} $.", sort)?;
+    if let Some(bound) = bound {
+      writeln!(self.w, "$c {} $.", bound)?;
+      let mut b = Block::new(format!("bv-{}", sort));
+      let x = b.var(t, None, "x", &bound);
+      self.write_block(&b, &format!("{} {}", sort, x), None)?;
+    }
+    let t = &self.t;
+    let mut b = Block::new(format!("refl-{}", sort));
+    let a = b.var(t, None, "A", &sort);
+    self.write_block(&b, &format!("== {} {}", a, a), None)?;
+    let t = &self.t;
+    let mut b = Block::new(format!("sym-{}", sort));
+    let (a, a2) = (b.var(t, None, "A", &sort), b.var(t, None, "B", &sort));
+    b.hyp(None, format!("== {} {}", a, a2));
+    self.write_block(&b, &format!("== {} {}", a2, a), None)?;
+    let t = &self.t;
+    let mut b = Block::new(format!("trans-{}", sort));
+    let (a, a2, a3) = (b.var(t, None, "A", &sort), b.var(t, None, "B", &sort),
+      b.var(t, None, "C", &sort));
+    b.hyp(None, format!("== {} {}", a, a2));
+    b.hyp(None, format!("== {} {}", a2, a3));
+    self.write_block(&b, &format!("== {} {}", a, a3), None)?;
+    if self.t.env.sort(s).mods.contains(Modifiers::PROVABLE) {
+      let t = &self.t;
+      let mut b = Block::new(format!("conv-{}", sort));
+      let (a, a2) = (b.var(t, None, "A", &sort), b.var(t, None, "B", &sort));
+      b.hyp(None, format!("== {} {}", a, a2));
+      b.hyp(None, format!("|- {}", a2));
+      self.write_block(&b, &format!("|- {}", a), None)?;
+    }
+    Ok(())
+  }
+
+  fn term(&mut self, tid: TermId) -> io::Result<()> {
+    let t = &self.t;
+    let td = t.env.term(tid);
+    let con = t.term(tid).to_owned();
+    writeln!(self.w, "\n$c {} $.", con)?;
+    let app = |vars: &[String]| if vars.is_empty() { con.clone() } else {
+      format!("( {} {} )", con, vars.join(" "))
+    };
+    let mut b = Block::new(format!("tm-{}", con));
+    let vars: Vec<_> = td.args.iter().map(|&(a, ty)| {
+      let name = a.map(|a| t.name(a));
+      b.var(t, name, name.unwrap_or("v"), t.tc(ty))
+    }).collect();
+    self.write_block(&b, &format!("{} {}", t.sort(td.ret.0), app(&vars)), None)?;
+
+    let t = &self.t;
+    let mut b = Block::new(format!("cong-{}", con));
+    let (mut lhs, mut rhs, mut hyps) = (vec![], vec![], vec![]);
+    for &(a, ty) in &*td.args {
+      let name = a.map_or("v", |a| t.name(a));
+      let v1 = b.var(t, None, name, t.tc(ty));
+      if ty.bound() {
+        lhs.push(v1.clone());
+        rhs.push(v1);
+      } else {
+        let v2 = b.var(t, None, &format!("{}'", name), t.tc(ty));
+        hyps.push(format!("== {} {}", v1, v2));
+        lhs.push(v1);
+        rhs.push(v2);
+      }
+    }
+    for h in hyps { b.hyp(None, h); }
+    self.write_block(&b, &format!("== {} {}", app(&lhs), app(&rhs)), None)?;
+
+    if let TermKind::Def(Some(expr)) = &td.kind {
+      let t = &self.t;
+      let mut b = Block::new(format!("df-{}", con));
+      let vars: Vec<_> = td.args.iter().map(|&(a, ty)| {
+        let name = a.map(|a| t.name(a));
+        b.var(t, name, name.unwrap_or("v"), t.tc(ty))
+      }).collect();
+      let mut dummies = HashMap::new();
+      let mut dvs = vec![];
+      for &(a, s) in &*t.dummies[tid.0 as usize] {
+        let name = t.name(a);
+        dvs.push(b.var(t, Some(name), name, t.tc(Type::Bound(s))));
+        dummies.insert(a, dvs.last().expect("nonempty").clone());
+      }
+      if !dvs.is_empty() {
+        if dvs.len() >= 2 { b.dvs.push(dvs.clone()) }
+        for v in &vars {
+          b.dvs.push(std::iter::once(v.clone()).chain(dvs.iter().cloned()).collect())
+        }
+      }
+      let mut stmt = format!("== {}", app(&vars));
+      t.expr(&mut stmt, &vars, &dummies, &expr.heap, &expr.store, expr.head());
+      self.write_block(&b, &stmt, None)?;
+    }
+    Ok(())
+  }
+
+  fn thm(&mut self, tid: ThmId) -> io::Result<()> {
+    let t = &self.t;
+    let td = t.env.thm(tid);
+    let label = t.thms[tid.0 as usize].clone();
+    let mut b = Block::new(label.clone());
+    let vars: Vec<_> = td.args.iter().map(|&(a, ty)| {
+      let name = a.map(|a| t.name(a));
+      b.var(t, name, name.unwrap_or("v"), t.tc(ty))
+    }).collect();
+    let args: Vec<_> = b.vars.iter().map(|(l, _, _)| l.clone()).collect();
+    let pf = if let ThmKind::Thm(Some(pf)) = &td.kind { Some(pf) } else { None };
+    let mut dummies = HashMap::new();
+    let mut dvs: Vec<_> = vars.iter().zip(&*td.args)
+      .filter(|p| p.1 .1.bound()).map(|p| p.0.clone()).collect();
+    if let Some(pf) = pf {
+      for e in pf.heap.iter().chain(&*pf.store) {
+        if let ProofNode::Dummy(a, s) = *e {
+          if let Entry::Vacant(e) = dummies.entry(a) {
+            let name = t.name(a);
+            dvs.push(b.var(t, Some(name), name, t.tc(Type::Bound(s))));
+            e.insert((b.vars.last().expect("nonempty").0.clone(), s));
+          }
+        }
+      }
+    }
+    if dvs.len() >= 2 { b.dvs.push(dvs) }
+    let bvs: Vec<_> = vars.iter().zip(&*td.args).filter(|p| p.1 .1.bound()).map(|p| p.0).collect();
+    for (v, &(_, ty)) in vars.iter().zip(&*td.args) {
+      if let Type::Reg(_, deps) = ty {
+        let mut dv = vec![v.clone()];
+        dv.extend(bvs.iter().enumerate()
+          .filter(|&(i, _)| deps & (1 << i) == 0).map(|(_, &v)| v.clone()));
+        dv.extend(b.vars[td.args.len()..].iter().map(|(_, _, tok)| tok.clone()));
+        if dv.len() > 1 { b.dvs.push(dv) }
+      }
+    }
+    let no_dummies = HashMap::new();
+    let hyps: Vec<_> = td.hyps.iter().map(|&(a, ref e)| {
+      let mut stmt = "|-".to_owned();
+      t.expr(&mut stmt, &vars, &no_dummies, &td.heap, &td.store, e);
+      b.hyp(a.map(|a| t.name(a)), stmt)
+    }).collect();
+    let mut stmt = "|-".to_owned();
+    t.expr(&mut stmt, &vars, &no_dummies, &td.heap, &td.store, &td.ret);
+    let proof = match pf {
+      None if matches!(td.kind, ThmKind::Axiom) => None,
+      None => Some(Err(format!("{} has no proof", label))),
+      Some(pf) => {
+        let used = &t.used[tid.0 as usize];
+        let mand = args.iter().zip(&**used).filter(|p| *p.1).map(|p| p.0)
+          .chain(&hyps).enumerate().map(|(i, l)| (l.clone(), i)).collect();
+        let mut bv_index = vec![];
+        let mut n = 0;
+        for &(_, ty) in &*td.args { bv_index.push(n); if ty.bound() { n += 1 } }
+        let mut pb = ProofBuilder {
+          t, td, heap: &pf.heap, store: &pf.store, args: &args, dummies: &dummies, hyps: &hyps,
+          bv_index, mand, labels: vec![], label_idx: HashMap::new(), steps: vec![], saves: 0,
+          cache: HashMap::new(), error: None,
+        };
+        pb.go(pf.head(), Role::Proof);
+        Some(pb.finish().map_err(|e| format!("{}: {}", label, e)))
+      }
+    };
+    match proof {
+      None => self.write_block(&b, &stmt, None),
+      Some(Ok((labels, pf))) => self.write_block(&b, &stmt, Some(Proof::Compressed(&labels, &pf))),
+      Some(Err(e)) => {
+        (self.report)(ErrorLevel::Warning, &format!("{}; the proof is omitted", e));
+        self.write_block(&b, &stmt, Some(Proof::Incomplete))
+      }
+    }
+  }
+}
+
+impl FrozenEnv {
+  /// Write this environment into a Metamath `.mm` file. See the
+  /// [module documentation](crate::mm::export) for the translation.
+  pub fn export_mm(&self, w: impl Write, report: &mut dyn FnMut(ErrorLevel, &str)
+  ) -> io::Result<()> {
+    let mut ex = Exporter { t: Tables::new(self), w, report };
+    writeln!(ex.w, "$( Exported from MM0 by mm0-rs $)\n\n$c ( ) |- == $.")?;
+    for s in self.stmts() {
+      match *s {
+        StmtTrace::Sort(a) => ex.sort(self.data()[a].sort().expect("expected a sort"))?,
+        StmtTrace::Decl(a) => match self.data()[a].decl().expect("expected a term/thm") {
+          DeclKey::Term(t) => ex.term(t)?,
+          DeclKey::Thm(t) => { writeln!(ex.w)?; ex.thm(t)? }
+        },
+        StmtTrace::Global(_) | StmtTrace::OutputString(_) => {}
+      }
+    }
+    ex.w.flush()
+  }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod test {
+  use std::collections::{HashMap, HashSet};
+  use crate::compiler::elab_for_result;
+
+  /// A `$f` (if true) or `$e` hypothesis, with its label and statement.
+  type Hyp<'a> = (&'a str, bool, Vec<&'a str>);
+
+  /// A small Metamath verifier, which checks the syntax of the database and the proofs,
+  /// but not the `$d` conditions.
+  #[derive(Default)]
+  struct Checker<'a> {
+    consts: HashSet<&'a str>,
+    vars: HashSet<&'a str>,
+    /// The active hypotheses
+    hyps: Vec<Hyp<'a>>,
+    /// The variables declared in each open `${` block, and the number of active hypotheses
+    /// at its start
+    scopes: Vec<(Vec<&'a str>, usize)>,
+    labels: HashSet<&'a str>,
+    /// The mandatory hypotheses and conclusion of each `$a` and `$p` statement
+    asserts: HashMap<&'a str, (Vec<Hyp<'a>>, Vec<&'a str>)>,
+  }
+
+  fn until<'a>(toks: &mut impl Iterator<Item=&'a str>, end: &str) -> Vec<&'a str> {
+    let mut out = vec![];
+    loop {
+      let tk = toks.next().unwrap_or_else(|| panic!("expected '{}'", end));
+      if tk == end { return out }
+      out.push(tk)
+    }
+  }
+
+  impl<'a> Checker<'a> {
+    fn check(&mut self, src: &'a str) {
+      let mut toks = src.split_whitespace();
+      while let Some(tk) = toks.next() {
+        match tk {
+          "$(" => { until(&mut toks, "$)"); }
+          "$c" => for c in until(&mut toks, "$.") {
+            assert!(!self.vars.contains(c) && self.consts.insert(c), "bad constant '{}'", c)
+          },
+          "$v" => for v in until(&mut toks, "$.") {
+            assert!(!self.consts.contains(v) && self.vars.insert(v), "bad variable '{}'", v);
+            self.scopes.last_mut().expect("variables are declared in blocks").0.push(v)
+          },
+          "$d" => for v in until(&mut toks, "$.") { assert!(self.vars.contains(v)) },
+          "${" => self.scopes.push((vec![], self.hyps.len())),
+          "$}" => {
+            let (vars, n) = self.scopes.pop().unwrap();
+            for v in vars { self.vars.remove(v); }
+            self.hyps.truncate(n)
+          }
+          _ if tk.starts_with('$') => panic!("unexpected '{}'", tk),
+          label => {
+            assert!(self.labels.insert(label), "duplicate label '{}'", label);
+            let kind = toks.next().unwrap();
+            let stmt = until(&mut toks, if kind == "$p" { "$=" } else { "$." });
+            assert!(self.consts.contains(stmt[0]));
+            for tk in &stmt[1..] { assert!(self.consts.contains(tk) || self.vars.contains(tk)) }
+            match kind {
+              "$f" => {
+                assert!(stmt.len() == 2 && self.vars.contains(stmt[1]));
+                self.hyps.push((label, true, stmt))
+              }
+              "$e" => self.hyps.push((label, false, stmt)),
+              "$a" => { self.asserts.insert(label, self.frame(stmt)); }
+              "$p" => {
+                let frame = self.frame(stmt);
+                let proof = until(&mut toks, "$.");
+                if proof != ["?"] { self.verify(&frame, &proof) }
+                self.asserts.insert(label, frame);
+              }
+              _ => panic!("unexpected '{}'", kind),
+            }
+          }
+        }
+      }
+      assert!(self.scopes.is_empty());
+    }
+
+    fn frame(&self, stmt: Vec<&'a str>) -> (Vec<Hyp<'a>>, Vec<&'a str>) {
+      let mut occurs = HashSet::<&str>::new();
+      for h in self.hyps.iter().filter(|h| !h.1).map(|h| &h.2).chain([&stmt]) {
+        occurs.extend(h.iter().filter(|tk| self.vars.contains(*tk)))
+      }
+      let hyps = self.hyps.iter().filter(|(_, f, h)| !f || occurs.contains(&h[1])).cloned();
+      (hyps.collect(), stmt)
+    }
+
+    fn step(&self, stack: &mut Vec<Vec<&'a str>>, label: &str) {
+      if let Some((_, _, h)) = self.hyps.iter().find(|h| h.0 == label) {
+        return stack.push(h.clone())
+      }
+      let (hyps, concl) = self.asserts.get(label)
+        .unwrap_or_else(|| panic!("unknown label '{}'", label));
+      let args = stack.split_off(stack.len().checked_sub(hyps.len()).unwrap());
+      let mut subst = HashMap::new();
+      for ((_, f, h), e) in hyps.iter().zip(&args) {
+        if *f { assert_eq!(h[0], e[0]); subst.insert(h[1], &e[1..]); }
+      }
+      let subst = |s: &[&'a str]| s.iter()
+        .flat_map(|tk| subst.get(tk).copied().unwrap_or_else(|| std::slice::from_ref(tk)))
+        .copied().collect::<Vec<_>>();
+      for ((_, f, h), e) in hyps.iter().zip(&args) {
+        if !f { assert_eq!(&subst(h), e, "hypothesis mismatch in '{}'", label) }
+      }
+      stack.push(subst(concl))
+    }
+
+    fn verify(&self, (hyps, concl): &(Vec<Hyp<'a>>, Vec<&'a str>), proof: &[&'a str]) {
+      assert_eq!(proof[0], "(");
+      let close = proof.iter().position(|&tk| tk == ")").unwrap();
+      let labels = &proof[1..close];
+      let (mut stack, mut saved, mut n) = (vec![], Vec::<Vec<_>>::new(), 0);
+      for c in proof[close + 1..].iter().flat_map(|s| s.bytes()) {
+        match c {
+          b'A'..=b'T' => {
+            n = n * 20 + usize::from(c - b'A') + 1;
+            if n <= hyps.len() {
+              stack.push(hyps[n - 1].2.clone())
+            } else if n - hyps.len() <= labels.len() {
+              self.step(&mut stack, labels[n - hyps.len() - 1])
+            } else {
+              stack.push(saved[n - hyps.len() - labels.len() - 1].clone())
+            }
+            n = 0
+          }
+          b'U'..=b'Y' => n = n * 5 + usize::from(c - b'U') + 1,
+          b'Z' => saved.push(stack.last().unwrap().clone()),
+          _ => panic!("invalid character in compressed proof"),
+        }
+      }
+      assert_eq!(stack, std::slice::from_ref(concl))
+    }
+  }
+
+  fn check_export(file: &str) {
+    let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("../examples").join(file);
+    let (_, env) = elab_for_result(path.canonicalize().unwrap().into()).unwrap();
+    let mut out = vec![];
+    env.unwrap().export_mm(&mut out, &mut |_, e| panic!("{}", e)).unwrap();
+    let mut checker = Checker::default();
+    checker.check(std::str::from_utf8(&out).unwrap());
+    assert!(!checker.asserts.is_empty())
+  }
+
+  #[test] fn export_hol() { check_export("hol.mm1") }
+  #[test] fn export_demo() { check_export("demo.mm1") }
+  #[test] fn export_peano() { check_export("peano.mm1") }
+}
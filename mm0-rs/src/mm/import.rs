@@ -0,0 +1,882 @@
+//! Importer for Metamath databases.
+//!
+//! This implements `mm0-rs from-mm`, which reads a Metamath `.mm` file into an
+//! [`Environment`], and writes the public declarations to an `.mm0` file and the proofs
+//! to an `.mmu` or `.mmb` file. The translation works as follows:
+//!
+//! - Every typecode of a `$f` hypothesis becomes a sort. A sort which is not the target of
+//!   any syntax axiom (like `setvar` in `set.mm`) is `pure`, and its variables become bound
+//!   variables. Other typecodes, like `|-`, are identified with the first sort that their
+//!   statements parse as, and that sort is marked `provable`.
+//! - A `$a` statement whose typecode is a sort is a syntax axiom, and becomes a `term`.
+//!   The other statements are parsed using the grammar given by the syntax axioms.
+//!   A `$p` statement whose typecode is a sort is a syntax theorem, which is not declared;
+//!   instead uses of it in proofs are replaced by its (parsed) statement.
+//! - The other `$a` and `$p` statements become axioms and theorems. The bound variables
+//!   come first, and a regular variable depends on a bound variable unless the two are
+//!   disjoint according to a `$d` condition. (A `$d` condition on two bound variables is
+//!   implicit, and on two regular variables it has no equivalent and is dropped.)
+//! - Metamath allows two bound variables without a `$d` condition to be substituted by the
+//!   same variable, but MM0 does not. When a proof does this, a "bundled" copy of the
+//!   theorem with the two variables identified is added, with a name like `foo_b1`. Its
+//!   proof is the original proof, with the same substitution applied. (For an axiom, the
+//!   bundled copy is another axiom.)
+//! - Optional `$f` hypotheses used in a proof become dummy variables.
+//!
+//! Since Metamath has no notion of binding, every variable which occurs in an expression
+//! is treated as free in it: the arguments and the result of a syntax axiom depend on all
+//! the bound arguments. Names are mangled to identifiers by replacing the characters other
+//! than letters, digits and `_` by `_`, and adding `_` in front if the first character is
+//! not a letter or `_`. Comments (including `$j` and `$t` comments) are ignored, and
+//! file inclusions `$[ $]` are not supported.
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use crate::elab::proof::{build, Dedup, ExprHash, IDedup, NodeHash, ProofHash};
+use crate::elab::environment::AddItemError;
+use crate::{AtomId, Environment, ErrorLevel, FileRef, FileSpan, FrozenEnv, LinedString,
+  Modifiers, Proof, SortId, Span, Term, TermId, TermKind, Thm, ThmId, ThmKind, Type,
+  MAX_BOUND_VARS};
+
+/// An index into [`Database::vars`], the list of all `$f` hypotheses.
+type VarId = usize;
+
+/// An error in the input file, at byte position `pos`.
+#[derive(Debug)]
+struct MmError {
+  pos: usize,
+  msg: String,
+}
+
+impl MmError {
+  fn new(pos: usize, msg: impl Into<String>) -> Self { Self { pos, msg: msg.into() } }
+}
+
+type Result<T> = std::result::Result<T, MmError>;
+
+/// Convert a Metamath label or math symbol to an MM0 identifier.
+fn mangle(s: &[u8]) -> String {
+  let mut out = String::with_capacity(s.len() + 1);
+  if !matches!(s.first(), Some(c) if c.is_ascii_alphabetic() || *c == b'_') { out.push('_') }
+  for &c in s {
+    out.push(if c.is_ascii_alphanumeric() || c == b'_' { char::from(c) } else { '_' })
+  }
+  out
+}
+
+/// A parsed expression.
+#[derive(Clone, PartialEq, Eq, Debug)]
+enum MExpr {
+  /// A variable, given by its `$f` hypothesis.
+  Var(VarId),
+  /// An application of a syntax axiom. The arguments are in the order of the
+  /// variables of the axiom, [`Assert::vars`].
+  App(usize, Box<[MExpr]>),
+}
+
+impl MExpr {
+  fn vars(&self, out: &mut HashSet<VarId>) {
+    match self {
+      &MExpr::Var(v) => { out.insert(v); }
+      MExpr::App(_, es) => for e in &**es { e.vars(out) },
+    }
+  }
+}
+
+/// A mandatory hypothesis of an assertion.
+#[derive(Clone, Copy, Debug)]
+enum Hyp {
+  /// A `$f` hypothesis
+  F(VarId),
+  /// A `$e` hypothesis, an index into [`Database::ess`]
+  E(usize),
+}
+
+/// A step in a proof.
+#[derive(Clone, Copy, Debug)]
+enum Step {
+  /// Reference a `$f` hypothesis (mandatory or not).
+  Var(VarId),
+  /// Reference a `$e` hypothesis, an index into [`Database::ess`].
+  Hyp(usize),
+  /// Apply an assertion, an index into [`Database::asserts`].
+  Assert(usize),
+  /// Save the top of the stack (the `Z` of a compressed proof).
+  Save,
+  /// Push the `n`th saved step.
+  Load(usize),
+}
+
+#[derive(Debug)]
+struct SortData<'a> {
+  name: &'a [u8],
+  span: Span,
+  /// True if there is no syntax axiom for this sort.
+  pure: bool,
+  /// True if a non-syntax typecode is mapped to this sort.
+  provable: bool,
+}
+
+#[derive(Debug)]
+struct Var<'a> {
+  name: &'a [u8],
+  sort: usize,
+}
+
+#[derive(Debug)]
+struct Ess<'a> {
+  label: &'a [u8],
+  expr: MExpr,
+}
+
+/// A `$a` or `$p` statement.
+#[derive(Debug)]
+struct Assert<'a> {
+  label: &'a [u8],
+  span: Span,
+  /// The sort of the statement (for a non-syntax typecode, the sort it is mapped to).
+  sort: usize,
+  /// True if the typecode is a sort, that is, this is a syntax axiom or theorem.
+  syntax: bool,
+  /// The mandatory variables, in the order of their `$f` hypotheses.
+  vars: Box<[VarId]>,
+  /// The `$e` hypotheses.
+  hyps: Box<[usize]>,
+  /// The mandatory hypotheses, in the order they are taken from the proof stack.
+  order: Box<[Hyp]>,
+  /// The pairs `(i, j)` with `i < j` of indexes into `vars` which have a `$d` condition.
+  dv: HashSet<(usize, usize)>,
+  /// The statement, except for syntax axioms.
+  concl: Option<MExpr>,
+  /// The proof, for a non-syntax `$p` statement.
+  proof: Option<Box<[Step]>>,
+}
+
+impl Assert<'_> {
+  fn disjoint(&self, i: usize, j: usize) -> bool {
+    self.dv.contains(&if i < j { (i, j) } else { (j, i) })
+  }
+}
+
+/// The result of reading a database, before translating it to MM0.
+#[derive(Debug, Default)]
+struct Database<'a> {
+  sorts: Vec<SortData<'a>>,
+  vars: Vec<Var<'a>>,
+  ess: Vec<Ess<'a>>,
+  asserts: Vec<Assert<'a>>,
+}
+
+/// A symbol in the statement of a syntax axiom.
+#[derive(Clone, Copy, Debug)]
+enum Sym<'a> {
+  Const(&'a [u8]),
+  /// A variable, given as an index into the [`Assert::vars`] of the axiom, and its sort.
+  Var(usize, usize),
+}
+
+#[derive(Debug)]
+struct Rule<'a> {
+  assert: usize,
+  nvars: usize,
+  pat: Box<[Sym<'a>]>,
+}
+
+/// The grammar given by the syntax axioms read so far.
+#[derive(Debug, Default)]
+struct Grammar<'a> {
+  rules: Vec<Rule<'a>>,
+  /// The rules for each sort and first constant
+  by_const: HashMap<(usize, &'a [u8]), Vec<usize>>,
+  /// The rules for each sort which start with a variable
+  by_var: HashMap<usize, Vec<usize>>,
+}
+
+/// A token of a statement to be parsed.
+#[derive(Clone, Copy, Debug)]
+enum Tok<'a> {
+  Const(&'a [u8]),
+  Var(VarId),
+}
+
+/// A memoizing parser for the statements of a database. Left recursive rules are
+/// supported by iterating the parse of a sort at a position until it stops growing.
+/// When a statement is ambiguous, the first parse found is used.
+struct ExprParser<'b, 'a> {
+  grammar: &'b Grammar<'a>,
+  vars: &'b [Var<'a>],
+  toks: &'b [Tok<'a>],
+  /// The parses `(end, expr)` of each sort at each position.
+  memo: HashMap<(usize, usize), Vec<(usize, MExpr)>>,
+}
+
+impl<'a> ExprParser<'_, 'a> {
+  fn parse(&mut self, s: usize, i: usize) -> Vec<(usize, MExpr)> {
+    if let Some(res) = self.memo.get(&(s, i)) { return res.clone() }
+    let grammar = self.grammar;
+    let rules = match self.toks.get(i) {
+      Some(&Tok::Const(c)) => grammar.by_const.get(&(s, c)),
+      _ => None,
+    }.into_iter().chain(grammar.by_var.get(&s)).flatten();
+    self.memo.insert((s, i), vec![]);
+    loop {
+      let mut res = self.memo[&(s, i)].clone();
+      let old = res.len();
+      if let Some(&Tok::Var(v)) = self.toks.get(i) {
+        if self.vars[v].sort == s && !res.iter().any(|r| r.0 == i + 1) {
+          res.push((i + 1, MExpr::Var(v)))
+        }
+      }
+      for &r in rules.clone() {
+        let rule = &grammar.rules[r];
+        self.rule(rule, 0, i, &mut vec![None; rule.nvars], &mut res)
+      }
+      let done = res.len() == old;
+      self.memo.insert((s, i), res);
+      if done { return self.memo[&(s, i)].clone() }
+    }
+  }
+
+  /// Match the symbols of `rule` starting at `k` against the input starting at `i`,
+  /// with `args` the variables assigned so far, and add the results to `res`.
+  fn rule(&mut self, rule: &Rule<'a>, k: usize, i: usize,
+    args: &mut Vec<Option<MExpr>>, res: &mut Vec<(usize, MExpr)>,
+  ) {
+    match rule.pat.get(k) {
+      None => if !res.iter().any(|r| r.0 == i) {
+        if let Some(es) = args.iter().cloned().collect::<Option<Box<[_]>>>() {
+          res.push((i, MExpr::App(rule.assert, es)))
+        }
+      }
+      Some(&Sym::Const(c)) => if matches!(self.toks.get(i), Some(&Tok::Const(c2)) if c == c2) {
+        self.rule(rule, k + 1, i + 1, args, res)
+      }
+      Some(&Sym::Var(j, s)) => for (end, e) in self.parse(s, i) {
+        if !matches!(&args[j], Some(e2) if *e2 != e) {
+          let old = args[j].replace(e);
+          self.rule(rule, k + 1, end, args, res);
+          args[j] = old;
+        }
+      }
+    }
+  }
+}
+
+/// A splitter for the whitespace separated tokens of a database.
+struct Lexer<'a> {
+  src: &'a [u8],
+  pos: usize,
+}
+
+impl<'a> Lexer<'a> {
+  fn token(&mut self) -> Option<(usize, &'a [u8])> {
+    while matches!(self.src.get(self.pos), Some(c) if c.is_ascii_whitespace()) { self.pos += 1 }
+    if self.pos == self.src.len() { return None }
+    let start = self.pos;
+    while matches!(self.src.get(self.pos), Some(c) if !c.is_ascii_whitespace()) { self.pos += 1 }
+    Some((start, &self.src[start..self.pos]))
+  }
+
+  /// Get the next token, skipping comments.
+  fn next(&mut self) -> Result<Option<(usize, &'a [u8])>> {
+    loop {
+      match self.token() {
+        Some((pos, b"$(")) => loop {
+          match self.token() {
+            Some((_, b"$)")) => break,
+            Some(_) => {}
+            None => return Err(MmError::new(pos, "unclosed comment")),
+          }
+        },
+        Some((pos, b"$[")) => return Err(MmError::new(pos, "file inclusion is not supported")),
+        tk => return Ok(tk),
+      }
+    }
+  }
+
+  /// Get the tokens up to the next `end` token, for a statement starting at `pos`.
+  fn until(&mut self, pos: usize, end: &[u8]) -> Result<Vec<&'a [u8]>> {
+    let mut toks = vec![];
+    loop {
+      match self.next()? {
+        Some((_, tk)) if tk == end => return Ok(toks),
+        Some((p, tk)) if tk.starts_with(b"$") => return Err(MmError::new(p,
+          format!("unexpected '{}'", String::from_utf8_lossy(tk)))),
+        Some((_, tk)) => toks.push(tk),
+        None => return Err(MmError::new(pos, "unterminated statement")),
+      }
+    }
+  }
+}
+
+#[derive(Debug, Default)]
+struct Scope<'a> {
+  /// The variables whose `$f` hypotheses were added in this scope
+  vars: Vec<&'a [u8]>,
+  hyps: usize,
+  dv: usize,
+}
+
+/// The state of the first pass, which reads the database and parses the statements.
+struct Reader<'a> {
+  lex: Lexer<'a>,
+  db: Database<'a>,
+  grammar: Grammar<'a>,
+  sorts: HashMap<&'a [u8], usize>,
+  /// The sort each non-syntax typecode is mapped to
+  provable: HashMap<&'a [u8], usize>,
+  labels: HashMap<&'a [u8], Step>,
+  /// The active `$f` hypothesis for each variable
+  active: HashMap<&'a [u8], VarId>,
+  hyps: Vec<Hyp>,
+  dv: Vec<(&'a [u8], &'a [u8])>,
+  scopes: Vec<Scope<'a>>,
+}
+
+impl<'a> Reader<'a> {
+  fn new(src: &'a [u8]) -> Self {
+    Self {
+      lex: Lexer { src, pos: 0 },
+      db: Default::default(),
+      grammar: Default::default(),
+      sorts: HashMap::new(),
+      provable: HashMap::new(),
+      labels: HashMap::new(),
+      active: HashMap::new(),
+      hyps: vec![],
+      dv: vec![],
+      scopes: vec![],
+    }
+  }
+
+  fn read(mut self) -> Result<Database<'a>> {
+    while let Some((pos, tk)) = self.lex.next()? {
+      match tk {
+        // Constants and variables are recognized by their `$f` hypotheses
+        b"$c" | b"$v" => { self.lex.until(pos, b"$.")?; }
+        b"$d" => {
+          let vs = self.lex.until(pos, b"$.")?;
+          for (i, &x) in vs.iter().enumerate() {
+            for &y in &vs[i + 1..] { self.dv.push((x, y)) }
+          }
+        }
+        b"${" => {
+          self.scopes.push(Scope { vars: vec![], hyps: self.hyps.len(), dv: self.dv.len() })
+        }
+        b"$}" => {
+          let sc = self.scopes.pop().ok_or_else(|| MmError::new(pos, "unmatched '$}'"))?;
+          for v in sc.vars { self.active.remove(v); }
+          self.hyps.truncate(sc.hyps);
+          self.dv.truncate(sc.dv);
+        }
+        _ if tk.starts_with(b"$") => return Err(MmError::new(pos,
+          format!("unexpected '{}'", String::from_utf8_lossy(tk)))),
+        label => {
+          let kind = self.lex.next()?.ok_or_else(|| MmError::new(pos, "expected a statement"))?;
+          match kind.1 {
+            b"$f" => self.float(pos, label)?,
+            b"$e" => self.essential(pos, label)?,
+            b"$a" => self.assert(pos, label, false)?,
+            b"$p" => self.assert(pos, label, true)?,
+            _ => return Err(MmError::new(kind.0, "expected '$f', '$e', '$a' or '$p'")),
+          }
+        }
+      }
+    }
+    if !self.scopes.is_empty() { return Err(MmError::new(self.lex.pos, "unclosed '${'")) }
+    Ok(self.db)
+  }
+
+  fn add_label(&mut self, pos: usize, label: &'a [u8], step: Step) -> Result<()> {
+    if self.labels.insert(label, step).is_some() {
+      return Err(MmError::new(pos, format!("duplicate label '{}'", String::from_utf8_lossy(label))))
+    }
+    Ok(())
+  }
+
+  fn float(&mut self, pos: usize, label: &'a [u8]) -> Result<()> {
+    let toks = self.lex.until(pos, b"$.")?;
+    if toks.len() != 2 { return Err(MmError::new(pos, "expected '$f typecode variable $.'")) }
+    let (tc, x) = (toks[0], toks[1]);
+    if self.provable.contains_key(tc) {
+      return Err(MmError::new(pos, "variable typecode is used for a non-syntax statement"))
+    }
+    let db = &mut self.db;
+    let span = (pos..self.lex.pos).into();
+    let sort = *self.sorts.entry(tc).or_insert_with(|| {
+      db.sorts.push(SortData { name: tc, span, pure: true, provable: false });
+      db.sorts.len() - 1
+    });
+    let v = self.db.vars.len();
+    self.db.vars.push(Var { name: x, sort });
+    if self.active.insert(x, v).is_some() {
+      return Err(MmError::new(pos, "variable already has an active '$f' hypothesis"))
+    }
+    if let Some(sc) = self.scopes.last_mut() { sc.vars.push(x) }
+    self.hyps.push(Hyp::F(v));
+    self.add_label(pos, label, Step::Var(v))
+  }
+
+  fn essential(&mut self, pos: usize, label: &'a [u8]) -> Result<()> {
+    let toks = self.lex.until(pos, b"$.")?;
+    let (&tc, math) = toks.split_first().ok_or_else(|| MmError::new(pos, "missing typecode"))?;
+    if self.sorts.contains_key(tc) {
+      return Err(MmError::new(pos, "'$e' hypotheses with a variable typecode are not supported"))
+    }
+    let sort = self.provable_sort(pos, tc, math)?;
+    let expr = self.parse(pos, sort, math)?;
+    let h = self.db.ess.len();
+    self.db.ess.push(Ess { label, expr });
+    self.hyps.push(Hyp::E(h));
+    self.add_label(pos, label, Step::Hyp(h))
+  }
+
+  fn tokens(&self, math: &[&'a [u8]]) -> Vec<Tok<'a>> {
+    math.iter().map(|&tk| self.active.get(tk).map_or(Tok::Const(tk), |&v| Tok::Var(v))).collect()
+  }
+
+  fn try_parse(&self, sort: usize, math: &[&'a [u8]]) -> Option<MExpr> {
+    let toks = self.tokens(math);
+    let mut p = ExprParser {
+      grammar: &self.grammar, vars: &self.db.vars, toks: &toks, memo: HashMap::new()
+    };
+    p.parse(sort, 0).into_iter().find(|r| r.0 == toks.len()).map(|r| r.1)
+  }
+
+  fn parse(&self, pos: usize, sort: usize, math: &[&'a [u8]]) -> Result<MExpr> {
+    self.try_parse(sort, math).ok_or_else(|| {
+      let name = String::from_utf8_lossy(self.db.sorts[sort].name);
+      MmError::new(pos, format!("could not parse statement as '{}'", name))
+    })
+  }
+
+  /// Get the sort for the non-syntax typecode `tc`. The first time a typecode is used,
+  /// it is mapped to the first sort that the statement `math` parses as.
+  fn provable_sort(&mut self, pos: usize, tc: &'a [u8], math: &[&'a [u8]]) -> Result<usize> {
+    if let Some(&s) = self.provable.get(tc) { return Ok(s) }
+    let s = (0..self.db.sorts.len()).find(|&s| self.try_parse(s, math).is_some())
+      .ok_or_else(|| MmError::new(pos, "could not parse statement as any sort"))?;
+    self.provable.insert(tc, s);
+    self.db.sorts[s].provable = true;
+    Ok(s)
+  }
+
+  fn assert(&mut self, pos: usize, label: &'a [u8], thm: bool) -> Result<()> {
+    let toks = self.lex.until(pos, if thm { b"$=" } else { b"$." })?;
+    let (&tc, math) = toks.split_first().ok_or_else(|| MmError::new(pos, "missing typecode"))?;
+    let (sort, syntax) = match self.sorts.get(tc) {
+      Some(&s) => (s, true),
+      None => (self.provable_sort(pos, tc, math)?, false),
+    };
+    let mut occurs = HashSet::new();
+    let concl = if syntax && !thm {
+      for tk in math { if let Some(&v) = self.active.get(tk) { occurs.insert(v); } }
+      None
+    } else {
+      let e = self.parse(pos, sort, math)?;
+      e.vars(&mut occurs);
+      Some(e)
+    };
+    for &h in &self.hyps {
+      if let Hyp::E(h) = h { self.db.ess[h].expr.vars(&mut occurs) }
+    }
+    let order = self.hyps.iter().copied()
+      .filter(|&h| if let Hyp::F(v) = h { occurs.contains(&v) } else { true })
+      .collect::<Box<[_]>>();
+    let vars = order.iter().filter_map(|&h| if let Hyp::F(v) = h { Some(v) } else { None })
+      .collect::<Box<[_]>>();
+    let hyps = order.iter().filter_map(|&h| if let Hyp::E(h) = h { Some(h) } else { None })
+      .collect::<Box<[_]>>();
+    if syntax && !hyps.is_empty() {
+      return Err(MmError::new(pos, "syntax statements with '$e' hypotheses are not supported"))
+    }
+    let index = |x: &[u8]| self.active.get(x).and_then(|v| vars.iter().position(|w| v == w));
+    let dv = self.dv.iter().filter_map(|&(x, y)| {
+      let (i, j) = (index(x)?, index(y)?);
+      Some(if i < j { (i, j) } else { (j, i) })
+    }).collect();
+    let proof = if thm {
+      let toks = self.lex.until(pos, b"$.")?;
+      if syntax { None } else { Some(self.proof(pos, &order, &toks)?) }
+    } else { None };
+    let a = self.db.asserts.len();
+    if syntax && !thm {
+      let pat = math.iter().map(|&tk| match self.active.get(tk) {
+        Some(v) => {
+          let i = vars.iter().position(|w| v == w).expect("variable is mandatory");
+          Sym::Var(i, self.db.vars[*v].sort)
+        }
+        None => Sym::Const(tk),
+      }).collect::<Box<[_]>>();
+      let r = self.grammar.rules.len();
+      match pat.first() {
+        Some(&Sym::Const(c)) => self.grammar.by_const.entry((sort, c)).or_default().push(r),
+        _ => self.grammar.by_var.entry(sort).or_default().push(r),
+      }
+      self.grammar.rules.push(Rule { assert: a, nvars: vars.len(), pat });
+      self.db.sorts[sort].pure = false;
+    }
+    self.db.asserts.push(Assert {
+      label, span: (pos..self.lex.pos).into(), sort, syntax, vars, hyps, order, dv, concl, proof
+    });
+    self.add_label(pos, label, Step::Assert(a))
+  }
+
+  fn step(&self, pos: usize, label: &[u8]) -> Result<Step> {
+    match label {
+      b"?" => Err(MmError::new(pos, "incomplete proofs are not supported")),
+      _ => self.labels.get(label).copied().ok_or_else(|| MmError::new(pos,
+        format!("unknown label '{}'", String::from_utf8_lossy(label)))),
+    }
+  }
+
+  /// Read a normal or compressed proof, for a theorem with mandatory hypotheses `order`.
+  fn proof(&self, pos: usize, order: &[Hyp], toks: &[&[u8]]) -> Result<Box<[Step]>> {
+    if toks.first() != Some(&&b"("[..]) {
+      return toks.iter().map(|l| self.step(pos, l)).collect()
+    }
+    let close = toks.iter().position(|&tk| tk == b")")
+      .ok_or_else(|| MmError::new(pos, "expected ')' in compressed proof"))?;
+    let labels = toks[1..close].iter().map(|l| self.step(pos, l)).collect::<Result<Vec<_>>>()?;
+    let mut steps = vec![];
+    let mut n = 0;
+    for &c in toks[close + 1..].iter().copied().flatten() {
+      match c {
+        b'A'..=b'T' => {
+          n = n * 20 + usize::from(c - b'A') + 1;
+          steps.push(if n <= order.len() {
+            match order[n - 1] { Hyp::F(v) => Step::Var(v), Hyp::E(h) => Step::Hyp(h) }
+          } else if n - order.len() <= labels.len() {
+            labels[n - order.len() - 1]
+          } else {
+            Step::Load(n - order.len() - labels.len() - 1)
+          });
+          n = 0
+        }
+        b'U'..=b'Y' => n = n * 5 + usize::from(c - b'U') + 1,
+        b'Z' => steps.push(Step::Save),
+        b'?' => return Err(MmError::new(pos, "incomplete proofs are not supported")),
+        _ => return Err(MmError::new(pos, "invalid character in compressed proof")),
+      }
+    }
+    Ok(steps.into())
+  }
+}
+
+/// The state of the second pass, which builds the environment.
+struct Importer<'a> {
+  db: &'a Database<'a>,
+  file: FileRef,
+  env: Environment,
+  sorts: Vec<SortId>,
+  /// The term for each syntax axiom, and the index into [`Assert::vars`] of each argument.
+  terms: HashMap<usize, (TermId, Box<[usize]>)>,
+  /// The theorem for each assertion and identification of its bound variables. The
+  /// identification maps each variable to the first variable it is identified with.
+  thms: HashMap<(usize, Box<[usize]>), ThmId>,
+  /// The number of bundled copies of each assertion
+  bundles: HashMap<usize, usize>,
+}
+
+fn add_err<A>(pos: usize, e: AddItemError<A>) -> MmError {
+  MmError::new(pos, match e {
+    AddItemError::Redeclaration(_, r) => r.msg,
+    AddItemError::Overflow => "too many declarations".into(),
+    AddItemError::Verify(msg) => msg,
+  })
+}
+
+impl Importer<'_> {
+  fn bound(&self, v: VarId) -> bool { self.db.sorts[self.db.vars[v].sort].pure }
+
+  /// An unused name for a declaration, obtained by appending `_` to `name`.
+  fn decl_atom(&mut self, mut name: String) -> AtomId {
+    loop {
+      let a = self.env.get_atom(name.as_bytes());
+      if self.env.data[a].decl.is_none() { return a }
+      name.push('_')
+    }
+  }
+
+  fn var_atom(&mut self, v: VarId) -> AtomId {
+    self.env.get_atom(mangle(self.db.vars[v].name).as_bytes())
+  }
+
+  fn fspan(&self, span: Span) -> FileSpan { FileSpan { file: self.file.clone(), span } }
+
+  /// The MM0 arguments of `asr` with identification `part`, as indexes into
+  /// [`Assert::vars`]: first the bound variables which are not identified with an earlier
+  /// one, then the regular variables.
+  fn arg_order(&self, asr: &Assert<'_>, part: &[usize]) -> (Vec<usize>, usize) {
+    let (mut args, regs): (Vec<_>, Vec<_>) = (0..asr.vars.len())
+      .filter(|&i| part[i] == i).partition(|&i| self.bound(asr.vars[i]));
+    let nbound = args.len();
+    args.extend(regs);
+    (args, nbound)
+  }
+
+  fn run(mut self) -> Result<Environment> {
+    let db = self.db;
+    for sd in &db.sorts {
+      let a = self.env.get_atom(mangle(sd.name).as_bytes());
+      let mut mods = Modifiers::empty();
+      if sd.pure { mods |= Modifiers::PURE }
+      if sd.provable { mods |= Modifiers::PROVABLE }
+      let s = self.env.add_sort(a, self.fspan(sd.span), sd.span, mods, None)
+        .map_err(|e| add_err(sd.span.start, e))?;
+      self.sorts.push(s)
+    }
+    for (a, asr) in db.asserts.iter().enumerate() {
+      if !asr.syntax {
+        self.thm(a, (0..asr.vars.len()).collect())?;
+      } else if asr.concl.is_none() {
+        self.term(a, asr)?
+      }
+    }
+    Ok(self.env)
+  }
+
+  fn term(&mut self, a: usize, asr: &Assert<'_>) -> Result<()> {
+    let (order, nbound) = self.arg_order(asr, &(0..asr.vars.len()).collect::<Vec<_>>());
+    if nbound > MAX_BOUND_VARS {
+      return Err(MmError::new(asr.span.start, "too many bound variables"))
+    }
+    let deps = (1 << nbound) - 1;
+    let args = order.iter().map(|&i| {
+      let v = asr.vars[i];
+      let s = self.sorts[self.db.vars[v].sort];
+      (Some(self.var_atom(v)), if self.bound(v) { Type::Bound(s) } else { Type::Reg(s, deps) })
+    }).collect();
+    let atom = self.decl_atom(mangle(asr.label));
+    let t = self.env.add_term(Term {
+      atom, span: self.fspan(asr.span), full: asr.span, doc: None, args, kind: TermKind::Term,
+      vis: Modifiers::empty(), ret: (self.sorts[asr.sort], deps),
+    }).map_err(|e| add_err(asr.span.start, e))?;
+    self.terms.insert(a, (t, order.into()));
+    Ok(())
+  }
+
+  /// Build an expression, where the variables are given by `vars`. Here `app` is
+  /// [`ExprHash::App`] or [`ProofHash::Term`].
+  fn expr<H: NodeHash>(&self, de: &mut Dedup<H>, app: fn(TermId, Box<[usize]>) -> H,
+    vars: &HashMap<VarId, usize>, e: &MExpr,
+  ) -> usize {
+    match e {
+      MExpr::Var(v) => de.reuse(vars[v]),
+      MExpr::App(a, es) => {
+        let ns = es.iter().map(|e| self.expr(de, app, vars, e)).collect::<Vec<_>>();
+        let (t, order) = &self.terms[a];
+        de.add_direct(app(*t, order.iter().map(|&i| ns[i]).collect()))
+      }
+    }
+  }
+
+  /// Get the theorem for assertion `a`, with the bound variables identified according to
+  /// `part`, adding it to the environment if it has not been added yet.
+  fn thm(&mut self, a: usize, part: Box<[usize]>) -> Result<ThmId> {
+    if let Some(&t) = self.thms.get(&(a, part.clone())) { return Ok(t) }
+    let db = self.db;
+    let asr = &db.asserts[a];
+    let (order, nbound) = self.arg_order(asr, &part);
+    if nbound > MAX_BOUND_VARS {
+      return Err(MmError::new(asr.span.start, "too many bound variables"))
+    }
+    let mut args = Vec::with_capacity(order.len());
+    for &i in &order {
+      let v = asr.vars[i];
+      let s = self.sorts[db.vars[v].sort];
+      let ty = if self.bound(v) { Type::Bound(s) } else {
+        let mut deps = 0;
+        for (k, &r) in order[..nbound].iter().enumerate() {
+          if !(0..part.len()).any(|j| part[j] == r && asr.disjoint(i, j)) { deps |= 1 << k }
+        }
+        Type::Reg(s, deps)
+      };
+      args.push((Some(self.var_atom(v)), ty))
+    }
+    let mut local = vec![0; part.len()];
+    for (k, &i) in order.iter().enumerate() { local[i] = k }
+    for i in 0..part.len() { local[i] = local[part[i]] }
+    let vars = asr.vars.iter().copied().zip(local).collect::<HashMap<_, _>>();
+    let mut de = Dedup::new(&args);
+    let hyps = asr.hyps.iter()
+      .map(|&h| self.expr(&mut de, ExprHash::App, &vars, &db.ess[h].expr)).collect::<Vec<_>>();
+    let ret = self.expr(&mut de, ExprHash::App, &vars,
+      asr.concl.as_ref().expect("non-syntax statements are parsed"));
+    let kind = match &asr.proof {
+      None => ThmKind::Axiom,
+      Some(steps) => ThmKind::Thm(Some(
+        self.proof(asr, args.len(), nbound, &vars, &hyps, steps, de.map_proof())?)),
+    };
+    de.calc_use(args.len(), hyps.iter().copied().chain([ret]));
+    let (mut ids, heap, store) = build(&de);
+    let hyps = asr.hyps.iter().zip(hyps).map(|(&h, i)| {
+      (Some(self.env.get_atom(mangle(db.ess[h].label).as_bytes())), ids[i].take())
+    }).collect();
+    let ret = ids[ret].take();
+    let identity = part.iter().enumerate().all(|(i, &j)| i == j);
+    let name = if identity { mangle(asr.label) } else {
+      let n = self.bundles.entry(a).or_default();
+      *n += 1;
+      format!("{}_b{}", mangle(asr.label), n)
+    };
+    let atom = self.decl_atom(name);
+    let vis = if identity && asr.proof.is_some() { Modifiers::PUB } else { Modifiers::empty() };
+    let t = self.env.add_thm(Thm {
+      atom, span: self.fspan(asr.span), full: asr.span, doc: None, args: args.into(),
+      heap, store: store.into(), hyps, ret, kind, vis,
+    }).map_err(|e| add_err(asr.span.start, e))?;
+    self.thms.insert((a, part), t);
+    Ok(t)
+  }
+
+  /// Translate the proof `steps` of `asr`. The mandatory variables are given by `vars`,
+  /// and `hyps` are the statements of the hypotheses in `de`.
+  #[allow(clippy::too_many_arguments)]
+  fn proof(&mut self, asr: &Assert<'_>, nargs: usize, nbound: usize,
+    vars: &HashMap<VarId, usize>, hyps: &[usize], steps: &[Step], mut de: Dedup<ProofHash>,
+  ) -> Result<Proof> {
+    let pos = asr.span.start;
+    let hyp_nodes = hyps.iter().enumerate()
+      .map(|(i, &e)| de.add_direct(ProofHash::Hyp(i, e))).collect::<Vec<_>>();
+    let mut dummies = HashMap::new();
+    let mut stack = vec![];
+    let mut saved = vec![];
+    for &step in steps {
+      let n = match step {
+        Step::Var(v) => if let Some(&n) = vars.get(&v).or_else(|| dummies.get(&v)) { n } else {
+          if nbound + dummies.len() >= MAX_BOUND_VARS {
+            return Err(MmError::new(pos, "too many bound variables"))
+          }
+          let dummy = ProofHash::Dummy(self.var_atom(v), self.sorts[self.db.vars[v].sort]);
+          let n = de.add_direct(dummy);
+          dummies.insert(v, n);
+          n
+        },
+        Step::Hyp(h) => match asr.hyps.iter().position(|&h2| h == h2) {
+          Some(i) => hyp_nodes[i],
+          None => return Err(MmError::new(pos, "hypothesis is not in scope")),
+        },
+        Step::Assert(a) => self.apply(pos, &mut de, &mut stack, a)?,
+        Step::Save => {
+          saved.push(*stack.last().ok_or_else(|| MmError::new(pos, "proof stack underflow"))?);
+          continue
+        }
+        Step::Load(i) => *saved.get(i)
+          .ok_or_else(|| MmError::new(pos, "invalid step in compressed proof"))?,
+      };
+      stack.push(n)
+    }
+    if stack.len() != 1 {
+      return Err(MmError::new(pos, "proof does not end with one statement on the stack"))
+    }
+    let root = stack[0];
+    de.calc_use(nargs, hyp_nodes.iter().copied().chain([root]));
+    let (mut ids, heap, mut store) = build(&de);
+    let hyps = hyp_nodes.into_iter().map(|i| ids[i].take()).collect();
+    store.push(ids[root].take());
+    Ok(Proof { heap, hyps, store: store.into() })
+  }
+
+  /// Apply the assertion `a` to the top of the proof stack.
+  fn apply(&mut self, pos: usize, de: &mut Dedup<ProofHash>, stack: &mut Vec<usize>, a: usize,
+  ) -> Result<usize> {
+    let db = self.db;
+    let asr = &db.asserts[a];
+    let k = stack.len().checked_sub(asr.order.len())
+      .ok_or_else(|| MmError::new(pos, "proof stack underflow"))?;
+    let (mut vals, mut hs) = (vec![], vec![]);
+    for (h, n) in asr.order.iter().zip(stack.drain(k..)) {
+      match h { Hyp::F(_) => vals.push(n), Hyp::E(_) => hs.push(n) }
+    }
+    if asr.syntax {
+      if let Some((t, order)) = self.terms.get(&a) {
+        return Ok(de.add_direct(ProofHash::Term(*t, order.iter().map(|&i| vals[i]).collect())))
+      }
+      let subst = asr.vars.iter().copied().zip(vals).collect();
+      let concl = asr.concl.as_ref().expect("syntax theorems are parsed");
+      return Ok(self.expr(de, ProofHash::Term, &subst, concl))
+    }
+    let part = (0..vals.len()).map(|i| {
+      if !self.bound(asr.vars[i]) { return i }
+      (0..i).find(|&j| self.bound(asr.vars[j]) && vals[j] == vals[i]).unwrap_or(i)
+    }).collect::<Box<[_]>>();
+    let (order, _) = self.arg_order(asr, &part);
+    let t = self.thm(a, part)?;
+    let mut ns = order.into_iter().map(|i| vals[i]).collect::<Vec<_>>();
+    let td = &self.env.thms[t];
+    let mut heap = vec![None; td.heap.len()];
+    for (h, &n) in heap.iter_mut().zip(&ns) { *h = Some(n) }
+    let res = ProofHash::subst(&self.env, de, &td.heap, &mut heap, &td.store, &td.ret);
+    ns.extend(hs);
+    Ok(de.add_direct(ProofHash::Thm(t, ns.into(), res)))
+  }
+}
+
+/// Construct an [`Environment`] from the Metamath database `source`.
+fn import(file: &FileRef, source: &[u8]) -> Result<Environment> {
+  let db = Reader::new(source).read()?;
+  Importer {
+    db: &db,
+    file: file.clone(),
+    env: Environment::new(),
+    sorts: vec![],
+    terms: HashMap::new(),
+    thms: HashMap::new(),
+    bundles: HashMap::new(),
+  }.run()
+}
+
+/// Import a Metamath database into MM0 and MMB/MMU files
+#[derive(clap::Args, Debug)]
+pub struct Args {
+  /// Sets the input file (.mm)
+  pub input: String,
+  /// Sets the output specification file (.mm0)
+  pub mm0: String,
+  /// Sets the output proof file (.mmb or .mmu)
+  pub output: String,
+}
+
+impl Args {
+  /// Main entry point for `mm0-rs from-mm` subcommand.
+  ///
+  /// See the [module documentation](self) for how the database is translated.
+  ///
+  /// # Arguments
+  ///
+  /// `mm0-rs from-mm <in.mm> <out.mm0> <out.mmb>`, where:
+  ///
+  /// - `in.mm` is the Metamath database to import.
+  /// - `out.mm0` is the file to write the sorts, terms, axioms and theorem statements to.
+  /// - `out.mmb` (or `out.mmu`) is the file to write the proofs to.
+  pub fn main(self) -> io::Result<()> {
+    let path: FileRef = fs::canonicalize(&self.input)?.into();
+    let text = LinedString::from(fs::read_to_string(path.path())?);
+    let env = match import(&path, text.as_bytes()) {
+      Ok(env) => env,
+      Err(e) => {
+        let pos = text.to_pos(e.pos);
+        eprintln!("error: {}:{}:{}: {}", self.input, pos.line + 1, pos.character + 1, e.msg);
+        std::process::exit(1)
+      }
+    };
+    let mut w = BufWriter::new(File::create(&self.mm0)?);
+    let file_name = path.path().file_name().map_or("", |s| s.to_str().unwrap_or(""));
+    writeln!(w, "-- Imported from {}\n", file_name)?;
+    writeln!(w, "delimiter $ ( ) $;")?;
+    crate::mmb::decompile::Decompiler { env: &env }.write(&mut w, false)?;
+    w.flush()?;
+    let env = FrozenEnv::new(env);
+    let w = BufWriter::new(File::create(&self.output)?);
+    if matches!(self.output.rsplit('.').next(), Some(ext) if ext.eq_ignore_ascii_case("mmu")) {
+      env.export_mmu(w)?;
+    } else {
+      let mut report = |lvl: ErrorLevel, err: &str| eprintln!("{}: {}", lvl, err);
+      let mut ex = crate::mmb::export::Exporter::new(path, Some(&text), &env, &mut report, w);
+      ex.run(true)?;
+      ex.finish()?;
+    }
+    Ok(())
+  }
+}
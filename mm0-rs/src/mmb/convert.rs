@@ -0,0 +1,484 @@
+//! Streaming conversion between the MMB binary proof format and the MMU text proof format.
+//!
+//! This implements `mm0-rs convert`, which translates an `.mmb` file into an `.mmu` file
+//! or the reverse, depending on the extension of the input file. Unlike going through
+//! an [`Environment`] (using [`mmb::import`](super::import) and then
+//! [`mmu::export`](crate::mmu::export)), the conversion handles one declaration at a time:
+//!
+//! - An `.mmb` file is read using [`MmbFile::proof`](mm0b_parser::MmbFile::proof),
+//!   and each declaration is parsed and immediately written as MMU text. The terms and
+//!   theorems referenced by a proof are looked up in the MMB file itself, so nothing is
+//!   kept once a declaration has been written.
+//! - An `.mmu` file is read one statement at a time using [`Importer`], and each
+//!   statement is added to an [`Mm0Writer`], which buffers the proof stream in a temporary
+//!   file. Only the statements of the theorems are kept, since they are needed to check
+//!   later proofs; each proof is dropped as soon as it has been written.
+//!
+//! So the memory use is proportional to the largest single proof (plus the statements, when
+//! converting to MMB), rather than to the whole environment. Variable and hypothesis names
+//! are taken from the index of the MMB file if it has one; otherwise names like `v3` and
+//! `h1` are generated. The output MMB file has an index with the names of the sorts, terms
+//! and theorems, but not of the variables.
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::Write as _;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use mm0b_parser::{Arg, BasicMmbFile, Mm0Writer, NumdStmtCmd, ParseError, Reopen, SortData};
+use crate::compiler::FileContents;
+use crate::mmu::import::Importer;
+use crate::{AtomId, DeclKey, Environment, ExprNode, FileRef, Modifiers, Proof, ProofNode,
+  SortId, StmtTrace, TermId, TermKind, ThmKind, Type};
+use super::export::Tables;
+use super::import::{parse_proof, parse_unify};
+
+type Result<T> = std::result::Result<T, ParseError>;
+
+/// Get the name of a variable or hypothesis from the index. The anonymous name `_`
+/// is replaced, since MMU proofs refer to variables and hypotheses by name.
+fn var_name(name: std::borrow::Cow<'_, str>, fallback: impl FnOnce() -> String) -> String {
+  if name == "_" { fallback() } else { name.into_owned() }
+}
+
+/// The state for converting an MMB file into MMU text.
+struct MmuWriter<'a> {
+  file: &'a BasicMmbFile<'a>,
+  /// The number of arguments of the current declaration.
+  nargs: usize,
+  /// The names of the variables of the current declaration: the arguments, followed by
+  /// the dummy variables. A dummy variable `AtomId(i)` has the name `vars[i]`.
+  vars: Vec<String>,
+  /// The names of the hypotheses of the current theorem.
+  hyps: Vec<String>,
+}
+
+impl<'a> MmuWriter<'a> {
+  fn term_nargs(&self, t: TermId) -> usize { self.file.term(t).map_or(0, |td| td.args().len()) }
+
+  /// Start a new declaration with the given arguments and variable names, and write the
+  /// binders `((x s) (a s (x)))`.
+  fn binders(&mut self, out: &mut String, args: &[Arg], names: impl Fn(usize) -> String) {
+    self.nargs = args.len();
+    self.vars = (0..args.len()).map(names).collect();
+    self.hyps.clear();
+    let mut bvars = vec![];
+    *out += "(";
+    for (i, a) in args.iter().enumerate() {
+      if i != 0 { *out += " " }
+      write!(out, "({} {}", self.vars[i], self.file.sort_name(a.sort())).expect("writing to a string");
+      if a.bound() {
+        bvars.push(i)
+      } else {
+        *out += " ";
+        self.deps(out, &bvars, a.deps_unchecked());
+      }
+      *out += ")"
+    }
+    *out += ")"
+  }
+
+  /// Write the dependencies `(x y)` of a variable, given the indices of the bound variables.
+  fn deps(&self, out: &mut String, bvars: &[usize], deps: u64) {
+    *out += "(";
+    let mut first = true;
+    for (i, &v) in bvars.iter().enumerate() {
+      if deps & (1 << i) != 0 {
+        if !std::mem::take(&mut first) { *out += " " }
+        *out += &self.vars[v]
+      }
+    }
+    *out += ")"
+  }
+
+  /// A function to pass to the MMB parser, which names the next dummy variable.
+  fn dummy<'b>(vars: &'b mut Vec<String>, names: &'b impl Fn(usize) -> String
+  ) -> impl FnMut() -> AtomId + 'b {
+    move || {
+      let n = vars.len();
+      vars.push(names(n));
+      AtomId(n.try_into().expect("too many variables"))
+    }
+  }
+
+  /// Write the list of dummy variables `((x s) (y s))`.
+  fn dummies(&self, out: &mut String, dummies: &BTreeMap<AtomId, SortId>) {
+    *out += "(";
+    for (i, (a, &s)) in dummies.iter().enumerate() {
+      if i != 0 { *out += " " }
+      write!(out, "({} {})", self.vars[a.0 as usize], self.file.sort_name(s))
+        .expect("writing to a string")
+    }
+    *out += ")"
+  }
+
+  fn expr(&self, out: &mut String, heap: &[ExprNode], store: &[ExprNode], e: &ExprNode) {
+    match *e {
+      ExprNode::Ref(i) if i < self.nargs => *out += &self.vars[i],
+      ExprNode::Ref(i) => self.expr(out, heap, store, &heap[i]),
+      ExprNode::Dummy(a, _) => *out += &self.vars[a.0 as usize],
+      ExprNode::App(t, p) => {
+        write!(out, "({}", self.file.term_name(t)).expect("writing to a string");
+        for e in &store[p..][..self.term_nargs(t)] {
+          *out += " ";
+          self.expr(out, heap, store, e)
+        }
+        *out += ")"
+      }
+    }
+  }
+
+  /// Find the values of the dummy variables of the definition `t` in an unfolding
+  /// whose unfolded expression is `sub_lhs`, ordered by the dummy variable in the definition.
+  fn unfold_dummies<'b>(&self, t: TermId,
+    heap: &'b [ProofNode], store: &'b [ProofNode], sub_lhs: &'b ProofNode,
+  ) -> Result<Vec<&'b ProofNode>> {
+    #[allow(clippy::too_many_arguments)]
+    fn go<'b>(this: &MmuWriter<'_>, subst: &mut BTreeMap<AtomId, &'b ProofNode>, checked: &mut [bool],
+      dheap: &[ExprNode], dstore: &[ExprNode], pat: &ExprNode,
+      heap: &'b [ProofNode], store: &'b [ProofNode], mut tgt: &'b ProofNode,
+    ) {
+      match *pat {
+        ExprNode::Ref(i) => if !std::mem::replace(&mut checked[i], true) {
+          go(this, subst, checked, dheap, dstore, &dheap[i], heap, store, tgt)
+        }
+        ExprNode::Dummy(a, _) => { subst.insert(a, tgt); }
+        ExprNode::App(t, p) => loop {
+          match *tgt {
+            ProofNode::Ref(j) => tgt = &heap[j],
+            ProofNode::Term(t2, p2) if t == t2 => {
+              let n = this.term_nargs(t);
+              for (e1, e2) in dstore[p..][..n].iter().zip(&store[p2..][..n]) {
+                go(this, subst, checked, dheap, dstore, e1, heap, store, e2)
+              }
+              break
+            }
+            _ => break
+          }
+        }
+      }
+    }
+    let td = self.file.term(t).ok_or(ParseError::StrError("unknown term", 0))?;
+    let mut n = td.args().len();
+    let (dheap, dstore, value) = parse_unify(self.file, n, td.unify(), None, || {
+      n += 1;
+      AtomId((n - 1).try_into().expect("too many variables"))
+    })?;
+    let mut subst = BTreeMap::new();
+    go(self, &mut subst, &mut vec![false; dheap.len()], &dheap, &dstore, &value, heap, store, sub_lhs);
+    Ok(subst.into_values().collect())
+  }
+
+  /// Write a proof, where heap references are written using `heap_names`.
+  fn proof(&self, out: &mut String, heap_names: &[String],
+    heap: &[ProofNode], store: &[ProofNode], p: &ProofNode,
+  ) -> Result<()> {
+    // Write the proofs `es`, separated by spaces. If `group` is set, they are
+    // written as a parenthesized list, otherwise each is preceded by a space.
+    let list = |out: &mut String, es: &[ProofNode], group: bool| -> Result<()> {
+      if group { *out += " (" }
+      for (i, e) in es.iter().enumerate() {
+        if i != 0 || !group { *out += " " }
+        self.proof(out, heap_names, heap, store, e)?
+      }
+      if group { *out += ")" }
+      Ok(())
+    };
+    match *p {
+      ProofNode::Ref(i) => *out += &heap_names[i],
+      ProofNode::Dummy(a, _) => *out += &self.vars[a.0 as usize],
+      ProofNode::Term(t, p) | ProofNode::Cong(t, p) => {
+        write!(out, "({}", self.file.term_name(t)).expect("writing to a string");
+        list(out, &store[p..][..self.term_nargs(t)], false)?;
+        *out += ")"
+      }
+      ProofNode::Hyp(h, _) => *out += &self.hyps[h],
+      ProofNode::Thm(t, p) => {
+        let td = self.file.thm(t).ok_or(ParseError::StrError("unknown theorem", 0))?;
+        let nargs = td.args().len();
+        let nhyps = td.unify().filter(|e| matches!(e, Ok(mm0b_parser::UnifyCmd::Hyp))).count();
+        write!(out, "({}", self.file.thm_name(t)).expect("writing to a string");
+        list(out, &store[p+1..][..nargs], true)?;
+        list(out, &store[p+1+nargs..][..nhyps], false)?;
+        *out += ")"
+      }
+      ProofNode::Conv(p) => {
+        *out += "(:conv";
+        list(out, &store[p..p+3], false)?;
+        *out += ")"
+      }
+      ProofNode::Refl(p) => self.proof(out, heap_names, heap, store, &store[p])?,
+      ProofNode::Sym(p) => {
+        *out += "(:sym";
+        list(out, &store[p..=p], false)?;
+        *out += ")"
+      }
+      ProofNode::Unfold(t, p) => {
+        write!(out, "(:unfold {}", self.file.term_name(t)).expect("writing to a string");
+        list(out, &store[p+2..][..self.term_nargs(t)], true)?;
+        let dummies = self.unfold_dummies(t, heap, store, &store[p])?;
+        list(out, &dummies.into_iter().copied().collect::<Vec<_>>(), true)?;
+        list(out, &store[p+1..=p+1], false)?;
+        *out += ")"
+      }
+    }
+    Ok(())
+  }
+
+  /// Write the proof of a theorem. Subproofs which are used more than once are bound using
+  /// `(:let H1 proof ...)`.
+  fn thm_proof(&self, out: &mut String, pf: &Proof) -> Result<()> {
+    let used = self.vars.iter().chain(&self.hyps).map(|s| &**s).collect::<HashSet<_>>();
+    let mut heap_names = self.vars[..self.nargs].to_vec();
+    let mut idx = 1;
+    let mut lets = 0;
+    for p in &pf.heap[self.nargs..] {
+      let mut s = String::new();
+      self.proof(&mut s, &heap_names, &pf.heap, &pf.store, p)?;
+      if matches!(p, ProofNode::Thm(..) | ProofNode::Conv(_)) {
+        let name = loop {
+          let name = format!("H{}", idx);
+          idx += 1;
+          if !used.contains(&*name) { break name }
+        };
+        writeln!(out, "(:let {} {}", name, s).expect("writing to a string");
+        lets += 1;
+        s = name;
+      }
+      heap_names.push(s)
+    }
+    self.proof(out, &heap_names, &pf.heap, &pf.store, pf.head())?;
+    for _ in 0..lets { *out += ")" }
+    Ok(())
+  }
+
+  /// Write the next statement as MMU text.
+  fn stmt(&mut self, out: &mut String, stmt: NumdStmtCmd, pf: &mut mm0b_parser::ProofIter<'a>,
+  ) -> Result<()> {
+    use ParseError::StrError;
+    let file = self.file;
+    match stmt {
+      NumdStmtCmd::Sort {sort_id} => {
+        let mods = file.sort(sort_id).and_then(|sd| Modifiers::try_from(sd).ok())
+          .ok_or(StrError("Step sort overflow", pf.pos))?;
+        write!(out, "(sort {}", file.sort_name(sort_id)).expect("writing to a string");
+        if mods.contains(Modifiers::PURE) { *out += " pure" }
+        if mods.contains(Modifiers::STRICT) { *out += " strict" }
+        if mods.contains(Modifiers::PROVABLE) { *out += " provable" }
+        if mods.contains(Modifiers::FREE) { *out += " free" }
+        *out += ")"
+      }
+      NumdStmtCmd::TermDef {term_id, local} => {
+        let td = file.term(term_id).ok_or(StrError("Step term overflow", pf.pos))?;
+        let list = file.term_vars(term_id);
+        let names = |i| var_name(list.get(i), || format!("v{}", i));
+        write!(out, "({}{} {} ", if local {"local "} else {""},
+          if td.def() {"def"} else {"term"}, file.term_name(term_id))
+          .expect("writing to a string");
+        self.binders(out, td.args(), names);
+        let ret = td.ret();
+        write!(out, " ({} ", file.sort_name(ret.sort())).expect("writing to a string");
+        let bvars = td.args().iter().enumerate()
+          .filter(|(_, a)| a.bound()).map(|(i, _)| i).collect::<Vec<_>>();
+        self.deps(out, &bvars, ret.deps_unchecked());
+        *out += ")";
+        if td.def() {
+          let (heap, store, value) = parse_unify(file, self.nargs, td.unify(), None,
+            Self::dummy(&mut self.vars, &names))?;
+          let dummies = heap.iter().filter_map(|e| match *e {
+            ExprNode::Dummy(a, s) => Some((a, s)),
+            _ => None
+          }).collect();
+          *out += "\n";
+          self.dummies(out, &dummies);
+          *out += "\n";
+          self.expr(out, &heap, &store, &value);
+        }
+        *out += ")"
+      }
+      NumdStmtCmd::Axiom {thm_id} | NumdStmtCmd::Thm {thm_id, ..} => {
+        let td = file.thm(thm_id).ok_or(StrError("Step thm overflow", pf.pos))?;
+        let list = file.thm_vars(thm_id);
+        let names = |i| var_name(list.get(i), || format!("v{}", i));
+        let axiom = matches!(stmt, NumdStmtCmd::Axiom {..});
+        write!(out, "({} {} ", match stmt {
+          NumdStmtCmd::Thm {local: false, ..} => "theorem",
+          NumdStmtCmd::Thm {local: true, ..} => "local theorem",
+          _ => "axiom",
+        }, file.thm_name(thm_id)).expect("writing to a string");
+        self.binders(out, td.args(), names);
+        let mut hyps = vec![];
+        let (heap, store, ret) = parse_unify(file, self.nargs, td.unify(), Some(&mut hyps),
+          Self::dummy(&mut self.vars, &names))?;
+        let hyp_list = file.thm_hyps(thm_id);
+        self.hyps = (0..hyps.len())
+          .map(|i| var_name(hyp_list.get(i), || format!("_h{}", i + 1))).collect();
+        if hyps.is_empty() { *out += " ()" }
+        for (i, (_, e)) in hyps.iter().enumerate() {
+          *out += if i == 0 { "\n  (" } else { "\n   " };
+          if axiom {
+            self.expr(out, &heap, &store, e)
+          } else {
+            write!(out, "({} ", self.hyps[i]).expect("writing to a string");
+            self.expr(out, &heap, &store, e);
+            *out += ")"
+          }
+          if i + 1 == hyps.len() { *out += ")" }
+        }
+        *out += "\n  ";
+        self.expr(out, &heap, &store, &ret);
+        if !axiom {
+          let proof = parse_proof(file, self.nargs, pf, Self::dummy(&mut self.vars, &names))?;
+          let mut dummies = BTreeMap::new();
+          for p in proof.heap.iter().chain(&*proof.store) {
+            if let ProofNode::Dummy(a, s) = *p { dummies.insert(a, s); }
+          }
+          *out += "\n";
+          self.dummies(out, &dummies);
+          *out += "\n";
+          self.thm_proof(out, &proof)?;
+        }
+        *out += ")"
+      }
+    }
+    Ok(())
+  }
+}
+
+/// Convert an MMB file into MMU text, writing one declaration at a time.
+pub fn mmb_to_mmu(buf: &[u8], w: &mut impl Write) -> Result<()> {
+  let file = BasicMmbFile::parse(buf)?;
+  let mut mw = MmuWriter { file: &file, nargs: 0, vars: vec![], hyps: vec![] };
+  let mut out = String::new();
+  for e in file.proof() {
+    let (stmt, mut pf) = e?;
+    mw.stmt(&mut out, stmt, &mut pf)?;
+    out += "\n\n";
+    w.write_all(out.as_bytes())?;
+    out.clear();
+  }
+  Ok(())
+}
+
+/// Get the MMB encoding of a list of binders.
+fn mmb_args(args: &[(Option<AtomId>, Type)]) -> Vec<Arg> {
+  let mut bv = 1;
+  args.iter().map(|&(_, ty)| match ty {
+    Type::Bound(s) => {
+      let arg = Arg::from(1 << 63 | u64::from(s.0) << 56 | bv);
+      bv *= 2;
+      arg
+    }
+    Type::Reg(s, deps) => Arg::from(u64::from(s.0) << 56 | deps),
+  }).collect()
+}
+
+/// Add the last statement in `env` to the MMB writer. The proof of a theorem is removed from
+/// `env` once it has been written, since later proofs only need the statement.
+pub fn add_last_stmt<W: Reopen>(env: &mut Environment, w: &mut Mm0Writer<W>) -> io::Result<()> {
+  let tables = Tables { terms: &env.terms, thms: &env.thms };
+  let done = match *env.stmts.last().expect("no statements") {
+    StmtTrace::Sort(a) => {
+      let sd = &env.sorts[env.data[a].sort.expect("expected a sort")];
+      w.add_sort(Some(sd.name.as_str()), SortData(sd.mods.bits()))?;
+      None
+    }
+    StmtTrace::Decl(a) => {
+      let name = Some(env.data[a].name.as_str());
+      match env.data[a].decl.expect("expected a term/thm") {
+        DeclKey::Term(t) => {
+          let td = &env.terms[t];
+          let args = mmb_args(&td.args);
+          let ret = Arg::from(u64::from(td.ret.0 .0) << 56 | td.ret.1);
+          if let TermKind::Def(expr) = &td.kind {
+            let expr = expr.as_ref().expect("imported definitions have a value");
+            #[allow(clippy::cast_possible_truncation)] // no truncation
+            let nargs = args.len() as u32;
+            let mut b = w.add_def(td.vis.contains(Modifiers::LOCAL), name, &args, ret);
+            tables.write_def_unify(b.unify(), nargs, expr)?;
+            tables.write_def_proof(b.proof(), nargs, expr, &mut None)?;
+            b.finish()?;
+          } else {
+            w.add_term(name, &args, ret)?;
+          }
+          None
+        }
+        DeclKey::Thm(t) => {
+          let td = &env.thms[t];
+          let args = mmb_args(&td.args);
+          let mut b = match td.kind {
+            ThmKind::Axiom => w.add_axiom(name, &args),
+            ThmKind::Thm(_) => w.add_thm(!td.vis.contains(Modifiers::PUB), name, &args),
+          };
+          tables.write_thm_unify(b.unify(), td)?;
+          tables.write_thm_proof(b.proof(), td, &mut None)?;
+          b.finish()?;
+          Some(t)
+        }
+      }
+    }
+    StmtTrace::Global(_) | StmtTrace::OutputString(_) => None,
+  };
+  if let Some(t) = done {
+    if let ThmKind::Thm(pf) = &mut env.thms[t].kind { *pf = None }
+  }
+  Ok(())
+}
+
+/// Convert between MMB and MMU proof files
+#[derive(clap::Args, Debug)]
+pub struct Args {
+  /// Sets the input file (.mmb or .mmu)
+  pub input: String,
+  /// Sets the output file (.mmu or .mmb)
+  pub output: String,
+}
+
+impl Args {
+  /// Main entry point for `mm0-rs convert` subcommand.
+  ///
+  /// See the [module documentation](self) for how the conversion works.
+  ///
+  /// # Arguments
+  ///
+  /// `mm0-rs convert <in.mmb|in.mmu> <out.mmu|out.mmb>`, where:
+  ///
+  /// - `in.mmb` or `in.mmu` is the file to convert. The output format is MMU for an
+  ///   `.mmb` input file, and MMB for an `.mmu` input file.
+  /// - `out.mmu` or `out.mmb` is the file to write.
+  pub fn main(self) -> io::Result<()> {
+    let path: FileRef = fs::canonicalize(&self.input)?.into();
+    if path.has_extension("mmb") {
+      let mmb = FileContents::new_bin_from_file(path.path())?;
+      let mut w = BufWriter::new(File::create(&self.output)?);
+      if let Err(e) = mmb_to_mmu(&mmb, &mut w) {
+        eprintln!("error: {}", e);
+        std::process::exit(1)
+      }
+      return w.flush()
+    }
+    if !path.has_extension("mmu") {
+      eprintln!("error: expected an .mmb or .mmu input file");
+      std::process::exit(1)
+    }
+    let source = fs::read_to_string(path.path())?;
+    let tmp = std::env::temp_dir().join(format!("mm0-rs-convert-{}.tmp", std::process::id()));
+    let proof = OpenOptions::new().read(true).write(true).create(true).truncate(true).open(&tmp)?;
+    let mut mmb = Mm0Writer::new(proof);
+    let mut imp = Importer::new(&path, source.as_bytes());
+    loop {
+      match imp.next_stmt() {
+        Ok(true) => add_last_stmt(imp.env_mut(), &mut mmb)?,
+        Ok(false) => break,
+        Err(e) => {
+          drop(mmb);
+          fs::remove_file(&tmp)?;
+          crate::compiler::report(&path, &FileContents::new(source.clone()), &e);
+          std::process::exit(1)
+        }
+      }
+    }
+    let mut w = BufWriter::new(File::create(&self.output)?);
+    mmb.finish(&mut w)?;
+    fs::remove_file(&tmp)?;
+    w.flush()
+  }
+}
@@ -0,0 +1,334 @@
+//! Decompiler from MMB files back into MM0 and MM1 source.
+//!
+//! This implements `mm0-rs decompile`, which imports an `.mmb` file into an [`Environment`]
+//! using [`mmb::import`](super::import), and then writes two files:
+//!
+//! - an `.mm0` file containing every public declaration, that is, the sorts, terms, axioms,
+//!   non-local definitions and `pub` theorems (definitions whose body uses a local
+//!   definition are written without a body), and
+//! - an `.mm1` file containing all the declarations, with the theorems proved by
+//!   their proof terms in MM1 lisp syntax.
+//!
+//! Names are taken from the index of the MMB file if it is present, and otherwise
+//! [`mmb::import`](super::import) generates names like `t12` and `v0`. Names which are
+//! MM1 lisp keywords, like `if`, get a `_` suffix.
+//! Since MMB files do not contain notations, all expressions are written using
+//! prefix application, like `$ im a (not b) $`, and the only delimiters are the parentheses.
+//!
+//! Proofs are written as exact proof terms using `:verb`, so they are not re-elaborated when
+//! the `.mm1` file is compiled. Subproofs that are used more than once are bound with `let`
+//! and spliced in with `,`, so the output is linear in the size of the MMB proof.
+//! Compiling the `.mm1` file produces an MMB file that verifies against the `.mm0` file.
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::fmt::Write as _;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use crate::compiler::FileContents;
+use crate::elab::lisp::Syntax;
+use crate::{AtomId, Environment, ExprNode, FileRef, Modifiers, Proof, ProofNode, SortId,
+  StmtTrace, TermKind, Thm, ThmKind, Type};
+
+pub(crate) struct Decompiler<'a> {
+  pub(crate) env: &'a Environment,
+}
+
+impl<'a> Decompiler<'a> {
+  /// The name of an atom. Names which are lisp keywords like `if` cannot be used in proof
+  /// terms, so they are renamed by appending `_` until they are not already in use.
+  fn name(&self, a: AtomId) -> Cow<'a, str> {
+    let name = self.env.data[a].name.as_str();
+    if Syntax::from_str(name).is_none() { return name.into() }
+    let mut name = name.to_owned();
+    loop {
+      name.push('_');
+      if !self.env.atoms.contains_key(name.as_bytes()) { return name.into() }
+    }
+  }
+
+  fn var(&self, a: Option<AtomId>) -> Cow<'a, str> {
+    a.map_or_else(|| "_".into(), |a| self.name(a))
+  }
+
+  /// Write the variable binders `{x: s} (a: s x)`, followed by the dummy binders `{.y: s}`.
+  fn binders(&self, out: &mut String,
+    args: &[(Option<AtomId>, Type)], dummies: &[(AtomId, SortId)],
+  ) {
+    let mut bvars = vec![];
+    for &(a, ty) in args {
+      match ty {
+        Type::Bound(s) => {
+          bvars.push(self.var(a));
+          write!(out, " {{{}: {}}}", self.var(a), self.env.sorts[s].name)
+            .expect("writing to a string")
+        }
+        Type::Reg(s, deps) => {
+          write!(out, " ({}: {}", self.var(a), self.env.sorts[s].name)
+            .expect("writing to a string");
+          Self::deps(out, &bvars, deps);
+          *out += ")"
+        }
+      }
+    }
+    for &(a, s) in dummies {
+      write!(out, " {{.{}: {}}}", self.name(a), self.env.sorts[s].name)
+        .expect("writing to a string")
+    }
+  }
+
+  fn deps(out: &mut String, bvars: &[Cow<'_, str>], deps: u64) {
+    for (i, x) in bvars.iter().enumerate() {
+      if deps & (1 << i) != 0 { *out += " "; *out += x }
+    }
+  }
+
+  /// Write an expression in prefix form, with parentheses if `paren` is set
+  /// and the expression is an application with arguments.
+  fn expr(&self, out: &mut String, args: &[(Option<AtomId>, Type)],
+    heap: &[ExprNode], store: &[ExprNode], paren: bool, e: &ExprNode,
+  ) {
+    match *e {
+      ExprNode::Ref(i) if i < args.len() => *out += &self.var(args[i].0),
+      ExprNode::Ref(i) => self.expr(out, args, heap, store, paren, &heap[i]),
+      ExprNode::Dummy(a, _) => *out += &self.name(a),
+      ExprNode::App(t, p) => {
+        let td = &self.env.terms[t];
+        let es = td.unpack_app(&store[p..]);
+        if es.is_empty() { *out += &self.name(td.atom); return }
+        if paren { *out += "(" }
+        *out += &self.name(td.atom);
+        for e in es {
+          *out += " ";
+          self.expr(out, args, heap, store, true, e)
+        }
+        if paren { *out += ")" }
+      }
+    }
+  }
+
+  fn math(&self, out: &mut String, args: &[(Option<AtomId>, Type)],
+    heap: &[ExprNode], store: &[ExprNode], e: &ExprNode,
+  ) {
+    *out += "$ ";
+    self.expr(out, args, heap, store, false, e);
+    *out += " $"
+  }
+
+  /// The names to use for the hypotheses of a theorem. Anonymous hypotheses and
+  /// hypotheses whose name clashes with another variable are renamed, because
+  /// the proof needs to refer to them.
+  fn hyp_names(&self, td: &Thm) -> Vec<String> {
+    let mut used = td.args.iter().map(|&(a, _)| self.var(a).into_owned()).collect::<HashSet<_>>();
+    td.hyps.iter().enumerate().map(|(i, &(a, _))| {
+      let mut name = self.var(a).into_owned();
+      if name == "_" || used.contains(&name) {
+        name = format!("_h{}", i + 1);
+        while used.contains(&name) { name.insert(0, '_') }
+      }
+      used.insert(name.clone());
+      name
+    }).collect()
+  }
+
+  /// Write a proof term in lisp syntax. Heap references are written using `heap_names`.
+  fn proof(&self, out: &mut String, hyps: &[String], heap_names: &[String],
+    store: &[ProofNode], p: &ProofNode,
+  ) {
+    let list = |out: &mut String, head: &str, es: &[ProofNode]| {
+      *out += "(";
+      *out += head;
+      for e in es {
+        *out += " ";
+        self.proof(out, hyps, heap_names, store, e)
+      }
+      *out += ")"
+    };
+    match *p {
+      ProofNode::Ref(i) => *out += &heap_names[i],
+      ProofNode::Dummy(a, _) => *out += &self.name(a),
+      ProofNode::Term(t, p) | ProofNode::Cong(t, p) => {
+        let td = &self.env.terms[t];
+        list(out, &self.name(td.atom), td.unpack_term(&store[p..]))
+      }
+      ProofNode::Hyp(h, _) => *out += &hyps[h],
+      ProofNode::Thm(t, p) => {
+        let td = &self.env.thms[t];
+        list(out, &self.name(td.atom), &store[p+1..][..td.args.len() + td.hyps.len()])
+      }
+      ProofNode::Conv(p) => list(out, ":conv", &store[p..p+3]),
+      ProofNode::Refl(p) => self.proof(out, hyps, heap_names, store, &store[p]),
+      ProofNode::Sym(p) => list(out, ":sym", &store[p..=p]),
+      ProofNode::Unfold(t, p) => {
+        let td = &self.env.terms[t];
+        let (_, c, es) = td.unpack_unfold(&store[p..]);
+        write!(out, "(:unfold {} (", self.name(td.atom)).expect("writing to a string");
+        for (i, e) in es.iter().enumerate() {
+          if i != 0 { *out += " " }
+          self.proof(out, hyps, heap_names, store, e)
+        }
+        *out += ") ";
+        self.proof(out, hyps, heap_names, store, c);
+        *out += ")"
+      }
+    }
+  }
+
+  /// Write the value of a theorem, `'(:verb proof)`, binding the shared subproofs with `let`.
+  fn thm_value(&self, out: &mut String, td: &Thm, hyps: &[String], pf: &Proof) {
+    let mut heap_names = td.args.iter().map(|&(a, _)| self.var(a).into_owned()).collect::<Vec<_>>();
+    let mut lets = vec![];
+    for p in &pf.heap[td.args.len()..] {
+      let name = match *p {
+        ProofNode::Hyp(h, _) => hyps[h].clone(),
+        ProofNode::Dummy(a, _) => self.name(a).into_owned(),
+        _ => {
+          let mut val = format!("[p{} '", lets.len() + 1);
+          self.proof(&mut val, hyps, &heap_names, &pf.store, p);
+          val += "]";
+          lets.push(val);
+          format!(",p{}", lets.len())
+        }
+      };
+      heap_names.push(name)
+    }
+    if lets.is_empty() {
+      *out += "'(:verb ";
+    } else {
+      *out += "(let (";
+      *out += &lets.join("\n      ");
+      *out += ")\n  '(:verb ";
+    }
+    self.proof(out, hyps, &heap_names, &pf.store, pf.head());
+    *out += if lets.is_empty() { ")" } else { "))" }
+  }
+
+  /// Write the declarations in the environment. If `mm1` is false, this only writes
+  /// the public declarations, and omits the proofs.
+  pub(crate) fn write(&self, w: &mut impl Write, mm1: bool) -> io::Result<()> {
+    for s in &self.env.stmts {
+      let mut out = String::new();
+      match *s {
+        StmtTrace::Sort(a) => {
+          let sd = &self.env.sorts[self.env.data[a].sort.expect("expected a sort")];
+          write!(out, "{}sort {};", sd.mods, sd.name).expect("writing to a string")
+        }
+        StmtTrace::Decl(a) => match self.env.data[a].decl.expect("expected a declaration") {
+          crate::DeclKey::Term(t) => {
+            let td = &self.env.terms[t];
+            let local = td.vis.contains(Modifiers::LOCAL);
+            if local && !mm1 { continue }
+            // A public definition whose body uses a local definition is abstract in the `.mm0`
+            let uses_local = |e: &crate::Expr| e.heap.iter().chain(&*e.store).any(|e| matches!(*e,
+              ExprNode::App(t, _) if self.env.terms[t].vis.contains(Modifiers::LOCAL)));
+            let body = match &td.kind {
+              TermKind::Def(Some(e)) if mm1 || !uses_local(e) => Some(e),
+              _ => None,
+            };
+            let mut dummies = vec![];
+            if let Some(e) = body {
+              for e in e.heap.iter().chain(&*e.store) {
+                if let ExprNode::Dummy(a, s) = *e { dummies.push((a, s)) }
+              }
+            }
+            out += match (local, &td.kind) {
+              (_, TermKind::Term) => "term ",
+              (false, TermKind::Def(_)) => "def ",
+              (true, TermKind::Def(_)) => "local def ",
+            };
+            out += &self.name(td.atom);
+            self.binders(&mut out, &td.args, &dummies);
+            write!(out, ": {}", self.env.sorts[td.ret.0].name).expect("writing to a string");
+            let bvars = td.args.iter().filter(|(_, ty)| ty.bound())
+              .map(|&(a, _)| self.var(a)).collect::<Vec<_>>();
+            Self::deps(&mut out, &bvars, td.ret.1);
+            if let Some(e) = body {
+              out += " =\n  ";
+              self.math(&mut out, &td.args, &e.heap, &e.store, e.head());
+            }
+            out += ";"
+          }
+          crate::DeclKey::Thm(t) => {
+            let td = &self.env.thms[t];
+            let public = td.vis.contains(Modifiers::PUB);
+            let axiom = matches!(td.kind, ThmKind::Axiom);
+            if !axiom && !public && !mm1 { continue }
+            let pf = match &td.kind { ThmKind::Thm(Some(pf)) if mm1 => Some(pf), _ => None };
+            let mut dummies = vec![];
+            if let Some(pf) = pf {
+              for p in pf.heap.iter().chain(&*pf.store) {
+                if let ProofNode::Dummy(a, s) = *p { dummies.push((a, s)) }
+              }
+            }
+            out += match (axiom, public && mm1) {
+              (true, _) => "axiom ",
+              (false, true) => "pub theorem ",
+              (false, false) => "theorem ",
+            };
+            out += &self.name(td.atom);
+            self.binders(&mut out, &td.args, &dummies);
+            let hyps = self.hyp_names(td);
+            for (name, (_, e)) in hyps.iter().zip(&*td.hyps) {
+              write!(out, " ({}: ", name).expect("writing to a string");
+              self.math(&mut out, &td.args, &td.heap, &td.store, e);
+              out += ")";
+            }
+            out += ":\n  ";
+            self.math(&mut out, &td.args, &td.heap, &td.store, &td.ret);
+            if let Some(pf) = pf {
+              out += " =\n";
+              self.thm_value(&mut out, td, &hyps, pf);
+            }
+            out += ";"
+          }
+        },
+        StmtTrace::Global(_) | StmtTrace::OutputString(_) => continue,
+      }
+      writeln!(w, "{}", out)?
+    }
+    Ok(())
+  }
+}
+
+/// Decompile an MMB file into MM0 and MM1 source files
+#[derive(clap::Args, Debug)]
+pub struct Args {
+  /// Sets the input file (.mmb)
+  pub input: String,
+  /// Sets the output specification file (.mm0)
+  pub mm0: String,
+  /// Sets the output proof file (.mm1)
+  pub mm1: String,
+}
+
+impl Args {
+  /// Main entry point for `mm0-rs decompile` subcommand.
+  ///
+  /// See the [module documentation](self) for the contents of the output files.
+  ///
+  /// # Arguments
+  ///
+  /// `mm0-rs decompile <in.mmb> <out.mm0> <out.mm1>`, where:
+  ///
+  /// - `in.mmb` is the file to decompile.
+  /// - `out.mm0` is the file to write the public declarations to.
+  /// - `out.mm1` is the file to write all declarations and proofs to.
+  pub fn main(self) -> io::Result<()> {
+    let path: FileRef = fs::canonicalize(&self.input)?.into();
+    let mmb = FileContents::new_bin_from_file(path.path())?;
+    let (res, env) = super::import::elab(&path, &mmb);
+    if let Err(e) = res {
+      eprintln!("error: {}", e.kind.msg());
+      std::process::exit(1)
+    }
+    let d = Decompiler { env: &env };
+    let file_name = path.path().file_name().map_or("", |s| s.to_str().unwrap_or(""));
+    for (out, mm1) in [(&self.mm0, false), (&self.mm1, true)] {
+      let mut w = BufWriter::new(File::create(out)?);
+      writeln!(w, "-- Decompiled from {}\n", file_name)?;
+      writeln!(w, "delimiter $ ( ) $;")?;
+      d.write(&mut w, mm1)?;
+      w.flush()?;
+    }
+    Ok(())
+  }
+}
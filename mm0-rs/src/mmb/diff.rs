@@ -0,0 +1,313 @@
+//! Structural diff between two MMB files.
+//!
+//! This implements `mm0-rs mmb-diff`, which compares two versions of a compiled library
+//! declaration by declaration, rather than byte by byte. Declarations are matched by the
+//! names in the index (so both files must have one), and each declaration is reported as:
+//!
+//! - *added* or *removed*, if it only appears in one of the files;
+//! - *changed*, if the statement changed: the sort modifiers, the kind of declaration
+//!   (for example a theorem which became an axiom), its visibility, the argument and
+//!   return types, or the unify stream (which encodes the value of a definition and the
+//!   hypotheses and conclusion of a theorem);
+//! - *proof changed*, if only the proof of a theorem changed.
+//!
+//! Terms and theorems are compared up to their position in the file: references to other
+//! terms and theorems in the unify and proof streams are compared by name.
+//! The differences are printed as text or JSON, and the exit code is 1 if there are any,
+//! as with `diff`.
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use mm0b_parser::{Arg, BasicMmbFile, NumdStmtCmd, ParseError, ProofCmd, UnifyCmd};
+use serde_json::json;
+use crate::Modifiers;
+use crate::compiler::FileContents;
+
+/// The output format of `mm0-rs mmb-diff`.
+#[derive(Clone, Copy, Debug, clap::ArgEnum)]
+pub enum DiffFormat {
+  /// One line per difference.
+  Text,
+  /// A JSON object with a `changes` array.
+  Json,
+}
+
+/// The kind of a declaration. Visibility is stored in the separate `local` field.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Kind { Sort, Term, Def, Axiom, Thm }
+
+/// The namespace of a declaration. Sorts, terms and theorems are matched separately.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum Namespace { Sort, Term, Thm }
+
+impl Kind {
+  fn namespace(self) -> Namespace {
+    match self {
+      Kind::Sort => Namespace::Sort,
+      Kind::Term | Kind::Def => Namespace::Term,
+      Kind::Axiom | Kind::Thm => Namespace::Thm,
+    }
+  }
+
+  fn name(self) -> &'static str {
+    match self {
+      Kind::Sort => "sort",
+      Kind::Term => "term",
+      Kind::Def => "def",
+      Kind::Axiom => "axiom",
+      Kind::Thm => "theorem",
+    }
+  }
+}
+
+/// A declaration in an MMB file, with the references to other declarations
+/// replaced by names so that it can be compared against another file.
+#[derive(Debug)]
+struct Decl {
+  kind: Kind,
+  local: bool,
+  name: String,
+  /// The sort modifiers, or the argument and return types.
+  sig: String,
+  /// The unify stream, for definitions, axioms and theorems.
+  unify: String,
+  /// The proof stream, for theorems.
+  proof: String,
+}
+
+impl Decl {
+  fn describe(&self) -> String {
+    if self.local { format!("local {}", self.kind.name()) } else { self.kind.name().into() }
+  }
+}
+
+/// Read the declarations of an MMB file.
+fn decls(file: &BasicMmbFile<'_>) -> Result<Vec<Decl>, String> {
+  let no_name = |pos| format!("declaration at {:#x} has no name in the index", pos);
+  let args = |args: &[Arg]| args.iter().map(|a| format!("{}{}:{:x}",
+    file.sort_name(a.sort()), if a.bound() { "*" } else { "" }, a.deps_unchecked()))
+    .collect::<Vec<_>>().join(" ");
+  let unify = |it: mm0b_parser::UnifyIter<'_>| -> Result<String, ParseError> {
+    let mut out = vec![];
+    for cmd in it {
+      out.push(match cmd? {
+        UnifyCmd::Term { tid, save } => format!("Term({}, {})", file.term_name(tid), save),
+        UnifyCmd::Dummy(s) => format!("Dummy({})", file.sort_name(s)),
+        cmd => format!("{:?}", cmd),
+      })
+    }
+    Ok(out.join(" "))
+  };
+  let mut decls = vec![];
+  for e in file.proof() {
+    let (stmt, pf) = e.map_err(|e| e.to_string())?;
+    let name = file.stmt_index(stmt).and_then(|e| e.value()).ok_or_else(|| no_name(pf.pos))?;
+    let mut decl = Decl {
+      kind: Kind::Sort, local: false, name: name.into(),
+      sig: String::new(), unify: String::new(), proof: String::new()
+    };
+    match stmt {
+      NumdStmtCmd::Sort { sort_id } => {
+        let mods = file.sort(sort_id).and_then(|sd| Modifiers::try_from(sd).ok())
+          .ok_or_else(|| format!("bad sort at {:#x}", pf.pos))?;
+        decl.sig = mods.to_string().trim_end().into()
+      }
+      NumdStmtCmd::TermDef { term_id, local } => {
+        let td = file.term(term_id).ok_or_else(|| format!("bad term at {:#x}", pf.pos))?;
+        decl.local = local;
+        decl.sig = format!("{} > {}", args(td.args()), args(&[td.ret()]));
+        if td.def() {
+          decl.kind = Kind::Def;
+          decl.unify = unify(td.unify()).map_err(|e| e.to_string())?
+        } else {
+          decl.kind = Kind::Term
+        }
+      }
+      NumdStmtCmd::Axiom { thm_id } | NumdStmtCmd::Thm { thm_id, .. } => {
+        let td = file.thm(thm_id).ok_or_else(|| format!("bad theorem at {:#x}", pf.pos))?;
+        decl.sig = args(td.args());
+        decl.unify = unify(td.unify()).map_err(|e| e.to_string())?;
+        if let NumdStmtCmd::Thm { local, .. } = stmt {
+          decl.kind = Kind::Thm;
+          decl.local = local;
+          let mut out = vec![];
+          for cmd in pf {
+            out.push(match cmd.map_err(|e| e.to_string())? {
+              ProofCmd::Term { tid, save } => format!("Term({}, {})", file.term_name(tid), save),
+              ProofCmd::Thm { tid, save } => format!("Thm({}, {})", file.thm_name(tid), save),
+              ProofCmd::Dummy(s) => format!("Dummy({})", file.sort_name(s)),
+              cmd => format!("{:?}", cmd),
+            })
+          }
+          decl.proof = out.join(" ")
+        } else {
+          decl.kind = Kind::Axiom
+        }
+      }
+    }
+    decls.push(decl)
+  }
+  Ok(decls)
+}
+
+/// A difference between two MMB files.
+#[derive(Debug)]
+struct Change<'a> {
+  /// The declaration in the old file.
+  old: Option<&'a Decl>,
+  /// The declaration in the new file.
+  new: Option<&'a Decl>,
+  /// The parts of the statement which changed.
+  what: Vec<String>,
+}
+
+impl Change<'_> {
+  fn decl(&self) -> &Decl { self.new.or(self.old).expect("nonempty change") }
+
+  fn change(&self) -> ChangeKind {
+    match (self.old, self.new) {
+      (None, _) => ChangeKind::Added,
+      (_, None) => ChangeKind::Removed,
+      _ if self.what.is_empty() => ChangeKind::ProofChanged,
+      _ => ChangeKind::Changed,
+    }
+  }
+}
+
+/// The way a declaration differs between the two files.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ChangeKind {
+  /// The declaration only exists in the new file.
+  Added,
+  /// The declaration only exists in the old file.
+  Removed,
+  /// The statement of the declaration changed.
+  Changed,
+  /// Only the proof stream changed.
+  ProofChanged,
+}
+
+impl ChangeKind {
+  fn name(self) -> &'static str {
+    match self {
+      ChangeKind::Added => "added",
+      ChangeKind::Removed => "removed",
+      ChangeKind::Changed => "changed",
+      ChangeKind::ProofChanged => "proof changed",
+    }
+  }
+
+  /// The character at the start of a line of text output.
+  fn sigil(self) -> char {
+    match self {
+      ChangeKind::Added => '+',
+      ChangeKind::Removed => '-',
+      ChangeKind::Changed | ChangeKind::ProofChanged => '~',
+    }
+  }
+}
+
+/// Compute the differences between the declarations of two files. Removed declarations
+/// come first (in the order of the old file), followed by the added and changed
+/// declarations (in the order of the new file).
+fn diff<'a>(old: &'a [Decl], new: &'a [Decl]) -> Vec<Change<'a>> {
+  let key = |d: &'a Decl| (d.kind.namespace(), &*d.name);
+  let old_map = old.iter().map(|d| (key(d), d)).collect::<HashMap<_, _>>();
+  let new_map = new.iter().map(|d| (key(d), d)).collect::<HashMap<_, _>>();
+  let mut changes = old.iter().filter(|d| !new_map.contains_key(&key(d)))
+    .map(|d| Change { old: Some(d), new: None, what: vec![] }).collect::<Vec<_>>();
+  for d in new {
+    let o = match old_map.get(&key(d)) {
+      Some(&o) => o,
+      None => {
+        changes.push(Change { old: None, new: Some(d), what: vec![] });
+        continue
+      }
+    };
+    let mut what = vec![];
+    if o.kind != d.kind { what.push(format!("was {}", o.kind.name())) }
+    if o.local != d.local { what.push(if o.local { "was local" } else { "was public" }.into()) }
+    if o.sig != d.sig {
+      what.push(match d.kind {
+        Kind::Sort => format!("modifiers changed from '{}' to '{}'", o.sig, d.sig),
+        _ => "arguments changed".into(),
+      })
+    }
+    if o.unify != d.unify {
+      what.push(match d.kind {
+        Kind::Def => "value changed",
+        _ => "statement changed",
+      }.into())
+    }
+    if !what.is_empty() || o.proof != d.proof {
+      changes.push(Change { old: Some(o), new: Some(d), what })
+    }
+  }
+  changes
+}
+
+/// Compare the declarations of two MMB files
+#[derive(clap::Args, Debug)]
+pub struct Args {
+  /// Sets the output format
+  #[clap(long, arg_enum, default_value_t = DiffFormat::Text)]
+  pub format: DiffFormat,
+  /// Sets the old file (.mmb)
+  pub old: String,
+  /// Sets the new file (.mmb)
+  pub new: String,
+  /// Sets the output file, or stdout if omitted
+  #[clap(short, long)]
+  pub output: Option<String>,
+}
+
+impl Args {
+  /// Main entry point for `mm0-rs mmb-diff` subcommand.
+  ///
+  /// See the [module documentation](self) for how declarations are compared.
+  ///
+  /// # Arguments
+  ///
+  /// `mm0-rs mmb-diff <old.mmb> <new.mmb>`, where:
+  ///
+  /// - `old.mmb` and `new.mmb` are the files to compare. Both must have an index.
+  pub fn main(self) -> io::Result<()> {
+    let load = |path: &str| -> io::Result<Vec<Decl>> {
+      let mmb = FileContents::new_bin_from_file(path.as_ref())?;
+      let file = BasicMmbFile::parse(&mmb).map_err(|e| e.to_string())
+        .and_then(|file| decls(&file));
+      Ok(file.unwrap_or_else(|e| {
+        eprintln!("error: {}: {}", path, e);
+        std::process::exit(2)
+      }))
+    };
+    let (old, new) = (load(&self.old)?, load(&self.new)?);
+    let changes = diff(&old, &new);
+    let mut w: BufWriter<Box<dyn Write>> = BufWriter::new(match &self.output {
+      Some(out) => Box::new(File::create(out)?),
+      None => Box::new(io::stdout()),
+    });
+    match self.format {
+      DiffFormat::Text => for c in &changes {
+        let change = c.change();
+        write!(w, "{} {} {}: {}", change.sigil(), c.decl().describe(), c.decl().name, change.name())?;
+        if !c.what.is_empty() { write!(w, " ({})", c.what.join(", "))? }
+        writeln!(w)?
+      }
+      DiffFormat::Json => {
+        let changes = changes.iter().map(|c| json!({
+          "name": c.decl().name,
+          "kind": c.decl().kind.name(),
+          "local": c.decl().local,
+          "change": c.change().name(),
+          "details": c.what,
+        })).collect::<Vec<_>>();
+        serde_json::to_writer_pretty(&mut w, &json!({ "changes": changes }))?;
+        writeln!(w)?
+      }
+    }
+    w.flush()?;
+    if !changes.is_empty() { std::process::exit(1) }
+    Ok(())
+  }
+}
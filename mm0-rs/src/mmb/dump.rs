@@ -0,0 +1,374 @@
+//! MMB disassembler, which prints a readable listing of the contents of an `.mmb` file.
+//!
+//! This implements `mm0-rs dump`, which is intended for debugging MMB files that fail to
+//! verify, without having to read a hex dump. The listing contains the header fields, the
+//! sort, term and theorem tables (with the argument types and dependency masks, and the
+//! decoded unify command streams), the declaration stream (with the decoded proof command
+//! streams), and the index. Every command is shown with its byte offset and its raw bytes.
+//! Names are taken from the index if it is present, and otherwise default names
+//! like `t12` and `v0` are used.
+//!
+//! The file is only parsed as far as is needed to print it, so this also works on files
+//! that are invalid. If a command stream cannot be parsed, the error is printed in its place
+//! and the listing continues at the next declaration, if possible.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use mm0b_parser::{BasicMmbFile, NumdStmtCmd, ProofCmd, ProofIter, UnifyCmd, UnifyIter, Arg,
+  TableEntry, VarListRef, HypListRef, cmd};
+use zerocopy::LayoutVerified;
+use crate::{Modifiers, SortId, TermId, ThmId, u32_as_usize};
+use crate::compiler::FileContents;
+
+struct Dumper<'a, W> {
+  file: &'a BasicMmbFile<'a>,
+  w: W,
+}
+
+impl<'a, W: Write> Dumper<'a, W> {
+  /// Print the raw bytes in `start..end` followed by the description of the command.
+  fn line(&mut self, indent: usize, start: usize, end: usize, desc: &str) -> io::Result<()> {
+    let bytes = self.file.buf.get(start..end).unwrap_or_default().iter()
+      .map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(" ");
+    writeln!(self.w, "{:indent$}{:#08x}  {:<15} {}", "", start, bytes, desc, indent = indent)
+  }
+
+  /// Describe an argument binder. Bound variables are written `{x: s}` and regular
+  /// variables are written `x: s y z` where `y z` are the bound variables it depends on.
+  fn arg(&self, bvs: &mut Vec<String>, name: &str, arg: Arg) -> String {
+    let sort = self.file.sort_name(arg.sort());
+    if arg.bound() {
+      bvs.push(name.into());
+      format!("{{{}: {}}}", name, sort)
+    } else {
+      let mut s = format!("{}: {}", name, sort);
+      for (i, bv) in bvs.iter().enumerate() {
+        if arg.depends_on(i as u64) { s.push(' '); s.push_str(bv) }
+      }
+      s
+    }
+  }
+
+  /// Print the argument list of a term or theorem starting at `p_args`.
+  fn args(&mut self, p_args: usize, args: &[Arg], ret: Option<Arg>, vars: VarListRef<'a>,
+  ) -> io::Result<()> {
+    let mut bvs = vec![];
+    for (i, &arg) in args.iter().enumerate() {
+      let pos = p_args + 8 * i;
+      let desc = self.arg(&mut bvs, &vars.get(i), arg);
+      writeln!(self.w, "    {:#08x}  {:#018x}  {}", pos, arg.into_inner(), desc)?;
+    }
+    if let Some(arg) = ret {
+      let pos = p_args + 8 * args.len();
+      let desc = self.arg(&mut bvs, "ret", arg);
+      writeln!(self.w, "    {:#08x}  {:#018x}  {}", pos, arg.into_inner(), desc)?;
+    }
+    Ok(())
+  }
+
+  /// Print a unify command stream.
+  fn unify(&mut self, mut it: UnifyIter<'a>, hyps: Option<HypListRef<'a>>) -> io::Result<()> {
+    writeln!(self.w, "    unify @{:#x}:", it.pos)?;
+    let mut num_hyps = 0;
+    loop {
+      let pos = it.pos;
+      let desc = match it.next() {
+        None => { self.line(6, pos, pos + 1, "End")?; return Ok(()) }
+        Some(Err(e)) => { writeln!(self.w, "      {:#08x}  error: {}", pos, e)?; return Ok(()) }
+        Some(Ok(UnifyCmd::Term { tid, save })) => format!("UTerm{} {}",
+          if save { "Save" } else { "" }, self.file.term_name(tid)),
+        Some(Ok(UnifyCmd::Ref(i))) => format!("URef {}", i),
+        Some(Ok(UnifyCmd::Dummy(s))) => format!("UDummy {}", self.file.sort_name(s)),
+        Some(Ok(UnifyCmd::Hyp)) => {
+          num_hyps += 1;
+          match hyps {
+            Some(hyps) => format!("UHyp {}", hyps.get(num_hyps - 1)),
+            None => "UHyp".into(),
+          }
+        }
+      };
+      self.line(6, pos, it.pos, &desc)?;
+    }
+  }
+
+  /// Print a proof command stream.
+  fn proof(&mut self, mut it: ProofIter<'a>, stmt: NumdStmtCmd) -> io::Result<()> {
+    let vars = self.file.stmt_vars(stmt);
+    let hyps = self.file.stmt_hyps(stmt);
+    let nargs = match stmt {
+      NumdStmtCmd::Axiom { thm_id } | NumdStmtCmd::Thm { thm_id, .. } =>
+        self.file.thm(thm_id).map_or(0, |td| td.args().len()),
+      _ => 0,
+    };
+    let mut num_hyps = 0;
+    loop {
+      let pos = it.pos;
+      let desc = match it.next() {
+        None => { self.line(4, pos, pos + 1, "End")?; return Ok(()) }
+        Some(Err(e)) => { writeln!(self.w, "    {:#08x}  error: {}", pos, e)?; return Ok(()) }
+        Some(Ok(ProofCmd::Term { tid, save })) => format!("Term{} {}",
+          if save { "Save" } else { "" }, self.file.term_name(tid)),
+        Some(Ok(ProofCmd::Ref(i))) if u32_as_usize(i) < nargs =>
+          format!("Ref {} ({})", i, vars.get(u32_as_usize(i))),
+        Some(Ok(ProofCmd::Ref(i))) => format!("Ref {}", i),
+        Some(Ok(ProofCmd::Dummy(s))) => format!("Dummy {}", self.file.sort_name(s)),
+        Some(Ok(ProofCmd::Thm { tid, save })) => format!("Thm{} {}",
+          if save { "Save" } else { "" }, self.file.thm_name(tid)),
+        Some(Ok(ProofCmd::Hyp)) => { num_hyps += 1; format!("Hyp {}", hyps.get(num_hyps - 1)) }
+        Some(Ok(ProofCmd::Conv)) => "Conv".into(),
+        Some(Ok(ProofCmd::Refl)) => "Refl".into(),
+        Some(Ok(ProofCmd::Sym)) => "Sym".into(),
+        Some(Ok(ProofCmd::Cong)) => "Cong".into(),
+        Some(Ok(ProofCmd::Unfold)) => "Unfold".into(),
+        Some(Ok(ProofCmd::ConvCut)) => "ConvCut".into(),
+        Some(Ok(ProofCmd::ConvSave)) => "ConvSave".into(),
+        Some(Ok(ProofCmd::Save)) => "Save".into(),
+        Some(Ok(ProofCmd::Sorry)) => "Sorry".into(),
+      };
+      self.line(4, pos, it.pos, &desc)?;
+    }
+  }
+
+  fn header(&mut self) -> io::Result<()> {
+    let h = &self.file.header;
+    writeln!(self.w, "header:")?;
+    writeln!(self.w, "  magic      {:?}", String::from_utf8_lossy(&h.magic))?;
+    writeln!(self.w, "  version    {}", h.version)?;
+    writeln!(self.w, "  num_sorts  {}", h.num_sorts)?;
+    writeln!(self.w, "  num_terms  {}", h.num_terms.get())?;
+    writeln!(self.w, "  num_thms   {}", h.num_thms.get())?;
+    writeln!(self.w, "  p_terms    {:#x}", h.p_terms.get())?;
+    writeln!(self.w, "  p_thms     {:#x}", h.p_thms.get())?;
+    writeln!(self.w, "  p_proof    {:#x}", h.p_proof.get())?;
+    writeln!(self.w, "  p_index    {:#x}", h.p_index.get())
+  }
+
+  fn sorts(&mut self) -> io::Result<()> {
+    writeln!(self.w, "\nsorts:")?;
+    for (i, sd) in self.file.sorts.iter().enumerate() {
+      let id = SortId(i.try_into().expect("at most 128 sorts"));
+      let mods = match Modifiers::try_from(*sd) {
+        Ok(m) => m.to_string(),
+        Err(()) => "(invalid modifiers) ".into(),
+      };
+      writeln!(self.w, "  {:#08x}  {:02x}  {}: {}sort {};",
+        std::mem::size_of::<mm0b_parser::Header>() + i, sd.0, i, mods, self.file.sort_name(id))?;
+    }
+    Ok(())
+  }
+
+  fn terms(&mut self) -> io::Result<()> {
+    writeln!(self.w, "\nterms @{:#x}:", self.file.header.p_terms.get())?;
+    for (i, te) in self.file.terms.iter().enumerate() {
+      let id = TermId(i.try_into().expect("too many terms"));
+      let p_args = u32_as_usize(te.p_args.get());
+      writeln!(self.w, "  {}: {} {}, {} args @{:#x}",
+        i, if te.sort & 0x80 != 0 { "def" } else { "term" },
+        self.file.term_name(id), te.num_args.get(), p_args)?;
+      let td = if let Some(td) = self.file.term(id) { td } else {
+        writeln!(self.w, "    error: bad term table entry")?;
+        continue
+      };
+      self.args(p_args, td.args(), Some(td.ret()), self.file.term_vars(id))?;
+      if td.def() { self.unify(td.unify(), None)? }
+    }
+    Ok(())
+  }
+
+  fn thms(&mut self) -> io::Result<()> {
+    writeln!(self.w, "\ntheorems @{:#x}:", self.file.header.p_thms.get())?;
+    for (i, te) in self.file.thms.iter().enumerate() {
+      let id = ThmId(i.try_into().expect("too many theorems"));
+      let p_args = u32_as_usize(te.p_args.get());
+      writeln!(self.w, "  {}: {}, {} args @{:#x}",
+        i, self.file.thm_name(id), te.num_args.get(), p_args)?;
+      let td = if let Some(td) = self.file.thm(id) { td } else {
+        writeln!(self.w, "    error: bad theorem table entry")?;
+        continue
+      };
+      self.args(p_args, td.args(), None, self.file.thm_vars(id))?;
+      self.unify(td.unify(), Some(self.file.thm_hyps(id)))?;
+    }
+    Ok(())
+  }
+
+  fn decls(&mut self) -> io::Result<()> {
+    let mut it = self.file.proof();
+    writeln!(self.w, "\nproof stream @{:#x}:", it.pos)?;
+    loop {
+      let pos = it.pos;
+      let (stmt, pf) = match it.next() {
+        None => { self.line(2, pos, pos + 1, "End")?; return Ok(()) }
+        Some(Err(e)) => { writeln!(self.w, "  {:#08x}  error: {}", pos, e)?; return Ok(()) }
+        Some(Ok(x)) => x,
+      };
+      let desc = match stmt {
+        NumdStmtCmd::Sort { sort_id } =>
+          format!("Sort {}: {}", sort_id.0, self.file.sort_name(sort_id)),
+        NumdStmtCmd::Axiom { thm_id } =>
+          format!("Axiom {}: {}", thm_id.0, self.file.thm_name(thm_id)),
+        NumdStmtCmd::TermDef { term_id, local } => {
+          let kind = match self.file.term(term_id) {
+            Some(td) if !td.def() => "Term",
+            _ if local => "LocalDef",
+            _ => "Def",
+          };
+          format!("{} {}: {}", kind, term_id.0, self.file.term_name(term_id))
+        }
+        NumdStmtCmd::Thm { thm_id, local } => format!("{} {}: {}",
+          if local { "LocalThm" } else { "Thm" }, thm_id.0, self.file.thm_name(thm_id)),
+      };
+      self.line(2, pos, pf.pos, &format!("{}, ends @{:#x}", desc, pf.ends_at))?;
+      if !pf.is_null() { self.proof(pf, stmt)? }
+    }
+  }
+
+  fn index(&mut self) -> io::Result<()> {
+    let p_index = if let Some(p) = self.file.p_index() { p } else {
+      return writeln!(self.w, "\nno index")
+    };
+    let buf = self.file.buf;
+    let entries = buf.get(p_index..).and_then(|s| {
+      let (n, rest) = s.split_at(8.min(s.len()));
+      let n = usize::try_from(u64::from_le_bytes(n.try_into().ok()?)).ok()?;
+      LayoutVerified::<_, [TableEntry]>::new_slice_from_prefix(rest, n).map(|(e, _)| e.into_slice())
+    });
+    let entries = if let Some(e) = entries { e } else {
+      return writeln!(self.w, "\nindex @{:#x}:\n  error: bad index header", p_index)
+    };
+    writeln!(self.w, "\nindex @{:#x}: {} entries", p_index, entries.len())?;
+    for (i, e) in entries.iter().enumerate() {
+      let pos = p_index + 8 + 16 * i;
+      writeln!(self.w, "  {:#08x}  {:?} data {} @{:#x}",
+        pos, String::from_utf8_lossy(&e.id), e.data.get(), e.ptr.get())?;
+    }
+    for e in entries {
+      match e.id {
+        cmd::INDEX_NAME => self.symbol_names(e)?,
+        cmd::INDEX_VAR_NAME => self.var_names(e)?,
+        cmd::INDEX_HYP_NAME => self.hyp_names(e)?,
+        cmd::INDEX_SOURCE => self.source_locs(e)?,
+        _ => {}
+      }
+    }
+    Ok(())
+  }
+
+  fn symbol_names(&mut self, e: &TableEntry) -> io::Result<()> {
+    writeln!(self.w, "\n  \"Name\" @{:#x}:", e.ptr.get())?;
+    let f = self.file;
+    let entries = (0..f.sorts.len()).map(|i| {
+      ("sort", i, f.sort_index(SortId(i.try_into().expect("at most 128 sorts"))))
+    }).chain((0..f.terms.len()).map(|i| {
+      ("term", i, f.term_index(TermId(i.try_into().expect("too many terms"))))
+    })).chain((0..f.thms.len()).map(|i| {
+      ("thm", i, f.thm_index(ThmId(i.try_into().expect("too many theorems"))))
+    }));
+    for (kind, i, ne) in entries {
+      match ne {
+        None => writeln!(self.w, "    {} {}: error: missing entry", kind, i)?,
+        Some(ne) => writeln!(self.w, "    {} {}: {}, proof @{:#x}", kind, i,
+          ne.value().unwrap_or("(invalid name)"), ne.p_proof.get())?,
+      }
+    }
+    Ok(())
+  }
+
+  /// Print one entry of a variable or hypothesis name table.
+  fn name_list(&mut self, kind: &str, i: usize, len: Option<usize>,
+    get: impl Fn(usize) -> Option<&'a str>,
+  ) -> io::Result<()> {
+    write!(self.w, "    {} {}:", kind, i)?;
+    match len {
+      None => writeln!(self.w, " error: missing entry"),
+      Some(n) => {
+        for j in 0..n { write!(self.w, " {}", get(j).unwrap_or("(invalid name)"))? }
+        writeln!(self.w)
+      }
+    }
+  }
+
+  fn var_names(&mut self, e: &TableEntry) -> io::Result<()> {
+    writeln!(self.w, "\n  \"VarN\" @{:#x}:", e.ptr.get())?;
+    let f = self.file;
+    for i in 0..f.terms.len() {
+      let vars = f.term_vars_opt(TermId(i.try_into().expect("too many terms")));
+      self.name_list("term", i, vars.map(|v| v.len()), |j| vars?.get_opt(j))?
+    }
+    for i in 0..f.thms.len() {
+      let vars = f.thm_vars_opt(ThmId(i.try_into().expect("too many theorems")));
+      self.name_list("thm", i, vars.map(|v| v.len()), |j| vars?.get_opt(j))?
+    }
+    Ok(())
+  }
+
+  fn hyp_names(&mut self, e: &TableEntry) -> io::Result<()> {
+    writeln!(self.w, "\n  \"HypN\" @{:#x}:", e.ptr.get())?;
+    let f = self.file;
+    for i in 0..f.thms.len() {
+      let hyps = f.thm_hyps_opt(ThmId(i.try_into().expect("too many theorems")));
+      self.name_list("thm", i, hyps.map(|v| v.len()), |j| hyps?.get_opt(j))?
+    }
+    Ok(())
+  }
+
+  fn source_locs(&mut self, e: &TableEntry) -> io::Result<()> {
+    writeln!(self.w, "\n  \"SrcL\" @{:#x}:", e.ptr.get())?;
+    let f = self.file;
+    let entries = (0..f.sorts.len()).map(|i| {
+      ("sort", i, f.sort_source(SortId(i.try_into().expect("at most 128 sorts"))))
+    }).chain((0..f.terms.len()).map(|i| {
+      ("term", i, f.term_source(TermId(i.try_into().expect("too many terms"))))
+    })).chain((0..f.thms.len()).map(|i| {
+      ("thm", i, f.thm_source(ThmId(i.try_into().expect("too many theorems"))))
+    }));
+    for (kind, i, loc) in entries {
+      match loc {
+        None => writeln!(self.w, "    {} {}: unknown", kind, i)?,
+        Some(loc) => writeln!(self.w, "    {} {}: {}", kind, i, loc)?,
+      }
+    }
+    Ok(())
+  }
+}
+
+/// Print a readable listing of an MMB file
+#[derive(clap::Args, Debug)]
+pub struct Args {
+  /// Sets the input file (.mmb)
+  pub input: String,
+  /// Sets the output file, or stdout if omitted
+  pub output: Option<String>,
+}
+
+impl Args {
+  /// Main entry point for `mm0-rs dump` subcommand.
+  ///
+  /// See the [module documentation](self) for the contents of the listing.
+  ///
+  /// # Arguments
+  ///
+  /// `mm0-rs dump <in.mmb> [out]`, where:
+  ///
+  /// - `in.mmb` is the file to disassemble.
+  /// - `out` is the file to write the listing to (default stdout).
+  pub fn main(self) -> io::Result<()> {
+    let mmb = FileContents::new_bin_from_file(self.input.as_ref())?;
+    let file = BasicMmbFile::parse(&mmb).unwrap_or_else(|e| {
+      eprintln!("error: {}", e);
+      std::process::exit(1)
+    });
+    let w: BufWriter<Box<dyn Write>> = BufWriter::new(match &self.output {
+      Some(out) => Box::new(File::create(out)?),
+      None => Box::new(io::stdout()),
+    });
+    let mut d = Dumper { file: &file, w };
+    d.header()?;
+    d.sorts()?;
+    d.terms()?;
+    d.thms()?;
+    d.decls()?;
+    d.index()?;
+    d.w.flush()
+  }
+}
@@ -1,20 +1,23 @@
 //! MMB exporter, which produces `.mmb` binary proof files from an
 //! [`Environment`](crate::Environment) object.
 use std::mem;
+use std::collections::{HashMap, hash_map::Entry};
 use std::io::{self, Write, Seek, SeekFrom};
 use byteorder::{LE, ByteOrder, WriteBytesExt};
 use mm0b_parser::MAX_BOUND_VARS;
 use zerocopy::{AsBytes, U32, U64};
 use crate::{
-  Type, SortId, AtomId, AtomVec, TermKind, ThmKind,
-  TermVec, ExprNode, ProofNode, StmtTrace, DeclKey, Modifiers,
-  FrozenEnv, FileRef, LinedString, ErrorLevel};
+  Type, SortId, AtomId, AtomVec, Term, TermKind, Thm, ThmKind, Expr,
+  TermVec, ThmVec, ExprNode, ProofNode, StmtTrace, DeclKey, Modifiers,
+  FrozenEnv, FileRef, FileSpan, LinedString, ErrorLevel, Range, Span};
 
 #[allow(clippy::wildcard_imports)]
 use mm0b_parser::{ProofCmd, UnifyCmd, cmd::*, write_cmd_bytes};
 
+/// A map from heap indices of an expression or proof to the indices of the corresponding
+/// saved values in the MMB heap, which are numbered in order of first use.
 #[derive(Debug)]
-struct Reorder<T=u32> {
+pub(crate) struct Reorder<T=u32> {
   map: Box<[Option<T>]>,
   idx: u32,
 }
@@ -31,6 +34,9 @@ impl<T> Reorder<T> {
   }
 }
 
+/// A function for converting a span to a line/column range, if the source text is available.
+pub type ToRange<'a> = dyn FnMut(&FileSpan) -> Option<Range> + 'a;
+
 /// The main exporter structure. This keeps track of the underlying writer,
 /// as well as tracking values that are written out of order.
 pub struct Exporter<'a, W> {
@@ -38,6 +44,9 @@ pub struct Exporter<'a, W> {
   file: FileRef,
   /// The source text of the input file. This is only used in the debugging data.
   source: Option<&'a LinedString>,
+  /// Finds the line numbers of spans in files other than the input file.
+  /// This is only used in the debugging data.
+  to_range: Option<&'a mut ToRange<'a>>,
   /// The input environment.
   env: &'a FrozenEnv,
   /// Error reporting.
@@ -154,39 +163,271 @@ impl<W: Write + Seek> Write for Exporter<'_, W> {
   fn flush(&mut self) -> io::Result<()> { self.w.flush() }
 }
 
-#[allow(clippy::too_many_arguments)]
-fn write_expr_proof(
-  env: &FrozenEnv,
-  w: &mut impl Write,
-  heap: &[ExprNode],
-  store: &[ExprNode],
-  reorder: &mut Reorder,
-  vars: &mut Option<&mut Vec<AtomId>>,
-  node: &ExprNode,
-  save: bool
-) -> io::Result<u32> {
-  Ok(match *node {
-    ExprNode::Ref(i) => match reorder.map[i] {
-      None => {
-        let n = write_expr_proof(env, w, heap, store, reorder, vars, &heap[i], true)?;
-        reorder.map[i] = Some(n);
-        n
+/// The term and theorem tables of an environment, which is all that is needed to write
+/// the unify and proof streams of a declaration.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Tables<'a> {
+  /// The terms in the environment.
+  pub(crate) terms: &'a TermVec<Term>,
+  /// The theorems in the environment.
+  pub(crate) thms: &'a ThmVec<Thm>,
+}
+
+impl Tables<'_> {
+  #[allow(clippy::too_many_arguments)]
+  fn write_expr_proof(self,
+    w: &mut impl Write,
+    heap: &[ExprNode],
+    store: &[ExprNode],
+    reorder: &mut Reorder,
+    vars: &mut Option<&mut Vec<AtomId>>,
+    node: &ExprNode,
+    save: bool
+  ) -> io::Result<u32> {
+    Ok(match *node {
+      ExprNode::Ref(i) => match reorder.map[i] {
+        None => {
+          let n = self.write_expr_proof(w, heap, store, reorder, vars, &heap[i], true)?;
+          reorder.map[i] = Some(n);
+          n
+        }
+        Some(n) => {ProofCmd::Ref(n).write_to(w)?; n}
+      }
+      ExprNode::Dummy(a, s) => {
+        if let Some(vec) = vars {vec.push(a)}
+        ProofCmd::Dummy(s).write_to(w)?;
+        (reorder.idx, reorder.idx += 1).0
+      }
+      ExprNode::App(tid, p) => {
+        for e in self.terms[tid].unpack_app(&store[p..]) {
+          self.write_expr_proof(w, heap, store, reorder, vars, e, false)?;
+        }
+        ProofCmd::Term {tid, save}.write_to(w)?;
+        if save { (reorder.idx, reorder.idx += 1).0 } else { 0 }
+      }
+    })
+  }
+
+  fn write_expr_unify(self,
+    w: &mut impl Write,
+    heap: &[ExprNode],
+    store: &[ExprNode],
+    reorder: &mut Reorder,
+    node: &ExprNode,
+    save: &mut Vec<usize>
+  ) -> io::Result<()> {
+    macro_rules! commit {($n:expr) => {
+      for i in save.drain(..) {reorder.map[i] = Some($n)}
+    }}
+    match *node {
+      ExprNode::Ref(i) => match reorder.map[i] {
+        None => {
+          save.push(i);
+          self.write_expr_unify(w, heap, store, reorder, &heap[i], save)?
+        }
+        Some(n) => {
+          UnifyCmd::Ref(n).write_to(w)?;
+          commit!(n)
+        }
+      }
+      ExprNode::Dummy(_, s) => {
+        commit!(reorder.idx); reorder.idx += 1;
+        UnifyCmd::Dummy(s).write_to(w)?
+      }
+      ExprNode::App(tid, p) => {
+        if save.is_empty() {
+          UnifyCmd::Term {tid, save: false}.write_to(w)?
+        } else {
+          commit!(reorder.idx); reorder.idx += 1;
+          UnifyCmd::Term {tid, save: true}.write_to(w)?
+        }
+        for e in self.terms[tid].unpack_app(&store[p..]) {
+          self.write_expr_unify(w, heap, store, reorder, e, save)?
+        }
       }
-      Some(n) => {ProofCmd::Ref(n).write_to(w)?; n}
     }
-    ExprNode::Dummy(a, s) => {
-      if let Some(vec) = vars {vec.push(a)}
-      ProofCmd::Dummy(s).write_to(w)?;
-      (reorder.idx, reorder.idx += 1).0
+    Ok(())
+  }
+
+  #[allow(clippy::too_many_arguments)]
+  fn write_proof(self, w: &mut impl Write,
+    heap: &[ProofNode],
+    store: &[ProofNode],
+    reorder: &mut Reorder,
+    hyps: &[u32],
+    node: &ProofNode,
+    save: bool
+  ) -> io::Result<u32> {
+    Ok(match *node {
+      ProofNode::Ref(i) => match reorder.map[i] {
+        None => {
+          let n = self.write_proof(w, heap, store, reorder, hyps, &heap[i], true)?;
+          reorder.map[i] = Some(n);
+          n
+        }
+        Some(n) => {ProofCmd::Ref(n).write_to(w)?; n}
+      }
+      ProofNode::Dummy(_, s) => {
+        ProofCmd::Dummy(s).write_to(w)?;
+        (reorder.idx, reorder.idx += 1).0
+      }
+      ProofNode::Term(term, p) => {
+        for e in self.terms[term].unpack_term(&store[p..]) {
+          self.write_proof(w, heap, store, reorder, hyps, e, false)?;
+        }
+        ProofCmd::Term {tid: term, save}.write_to(w)?;
+        if save {(reorder.idx, reorder.idx += 1).0} else {0}
+      }
+      ProofNode::Hyp(n, _) => {
+        ProofCmd::Ref(hyps[n]).write_to(w)?;
+        hyps[n]
+      }
+      ProofNode::Thm(thm, p) => {
+        let (res, args, hs) = self.thms[thm].unpack_thm(&store[p..]);
+        for e in hs {self.write_proof(w, heap, store, reorder, hyps, e, false)?;}
+        for e in args {self.write_proof(w, heap, store, reorder, hyps, e, false)?;}
+        self.write_proof(w, heap, store, reorder, hyps, res, false)?;
+        ProofCmd::Thm {tid: thm, save}.write_to(w)?;
+        if save {(reorder.idx, reorder.idx += 1).0} else {0}
+      }
+      ProofNode::Conv(p) => {
+        let (e1, c, p) = ProofNode::unpack_conv(&store[p..]);
+        self.write_proof(w, heap, store, reorder, hyps, e1, false)?;
+        self.write_proof(w, heap, store, reorder, hyps, p, false)?;
+        ProofCmd::Conv.write_to(w)?;
+        self.write_conv(w, heap, store, reorder, hyps, c)?;
+        if save {
+          ProofCmd::Save.write_to(w)?;
+          (reorder.idx, reorder.idx += 1).0
+        } else {0}
+      }
+      ProofNode::Refl(_) |
+      ProofNode::Sym(_) |
+      ProofNode::Cong {..} |
+      ProofNode::Unfold {..} => unreachable!(),
+    })
+  }
+
+  fn write_conv(self, w: &mut impl Write,
+    heap: &[ProofNode],
+    store: &[ProofNode],
+    reorder: &mut Reorder,
+    hyps: &[u32],
+    node: &ProofNode,
+  ) -> io::Result<()> {
+    match *node {
+      ProofNode::Ref(i) => match reorder.map[i] {
+        None => {
+          let e = &heap[i];
+          match e {
+            ProofNode::Refl(_) | ProofNode::Ref(_) =>
+              self.write_conv(w, heap, store, reorder, hyps, e)?,
+            _ => {
+              ProofCmd::ConvCut.write_to(w)?;
+              self.write_conv(w, heap, store, reorder, hyps, e)?;
+              ProofCmd::ConvSave.write_to(w)?;
+              reorder.map[i] = Some(reorder.idx);
+              reorder.idx += 1;
+            }
+          };
+        }
+        Some(n) => ProofCmd::Ref(n).write_to(w)?,
+      }
+      ProofNode::Dummy(_, _) |
+      ProofNode::Term {..} |
+      ProofNode::Hyp(_, _) |
+      ProofNode::Thm {..} |
+      ProofNode::Conv(_) => unreachable!(),
+      ProofNode::Refl(_) => ProofCmd::Refl.write_to(w)?,
+      ProofNode::Sym(c) => {
+        ProofCmd::Sym.write_to(w)?;
+        self.write_conv(w, heap, store, reorder, hyps, &store[c])?;
+      }
+      ProofNode::Cong(term, p) => {
+        ProofCmd::Cong.write_to(w)?;
+        for a in self.terms[term].unpack_term(&store[p..]) {
+          self.write_conv(w, heap, store, reorder, hyps, a)?
+        }
+      }
+      ProofNode::Unfold(term, p) => {
+        let (sub_lhs, c, _) = self.terms[term].unpack_unfold(&store[p..]);
+        self.write_proof(w, heap, store, reorder, hyps, sub_lhs, false)?;
+        ProofCmd::Unfold.write_to(w)?;
+        self.write_conv(w, heap, store, reorder, hyps, c)?;
+      }
     }
-    ExprNode::App(tid, p) => {
-      for e in env.term(tid).unpack_app(&store[p..]) {
-        write_expr_proof(env, w, heap, store, reorder, vars, e, false)?;
+    Ok(())
+  }
+
+  /// Write the unify stream of a definition, not including the final `END` command.
+  pub(crate) fn write_def_unify(self, w: &mut impl Write,
+    nargs: u32, expr: &Expr,
+  ) -> io::Result<Reorder> {
+    let mut reorder = Reorder::new(nargs, expr.heap.len(), |i| i);
+    self.write_expr_unify(w, &expr.heap, &expr.store, &mut reorder, expr.head(), &mut vec![])?;
+    Ok(reorder)
+  }
+
+  /// Write the proof stream of a definition, not including the final `END` command.
+  /// The dummy variables are pushed to `vars` in order of appearance.
+  pub(crate) fn write_def_proof(self, w: &mut impl Write,
+    nargs: u32, expr: &Expr, vars: &mut Option<&mut Vec<AtomId>>,
+  ) -> io::Result<()> {
+    let mut reorder = Reorder::new(nargs, expr.heap.len(), |i| i);
+    self.write_expr_proof(w, &expr.heap, &expr.store, &mut reorder, vars, expr.head(), false)?;
+    Ok(())
+  }
+
+  /// Write the unify stream of an axiom or theorem, which contains the statement,
+  /// not including the final `END` command.
+  pub(crate) fn write_thm_unify(self, w: &mut impl Write, td: &Thm) -> io::Result<()> {
+    #[allow(clippy::cast_possible_truncation)] // no truncation
+    let mut reorder = Reorder::new(td.args.len() as u32, td.heap.len(), |i| i);
+    let save = &mut vec![];
+    self.write_expr_unify(w, &td.heap, &td.store, &mut reorder, &td.ret, save)?;
+    for (_, h) in td.hyps.iter().rev() {
+      UnifyCmd::Hyp.write_to(w)?;
+      self.write_expr_unify(w, &td.heap, &td.store, &mut reorder, h, save)?;
+    }
+    Ok(())
+  }
+
+  /// Write the proof stream of an axiom or theorem, not including the final `END` command.
+  /// A theorem with no proof is written using the `sorry` command.
+  /// The dummy variables of the statement are pushed to `vars` in order of appearance.
+  pub(crate) fn write_thm_proof(self, w: &mut impl Write,
+    td: &Thm, vars: &mut Option<&mut Vec<AtomId>>,
+  ) -> io::Result<()> {
+    #[allow(clippy::cast_possible_truncation)] // no truncation
+    let nargs = td.args.len() as u32;
+    match &td.kind {
+      ThmKind::Axiom | ThmKind::Thm(None) => {
+        let mut reorder = Reorder::new(nargs, td.heap.len(), |i| i);
+        for (_, h) in &*td.hyps {
+          self.write_expr_proof(w, &td.heap, &td.store, &mut reorder, vars, h, false)?;
+          ProofCmd::Hyp.write_to(w)?;
+        }
+        self.write_expr_proof(w, &td.heap, &td.store, &mut reorder, vars, &td.ret, false)?;
+        if let ThmKind::Thm(None) = td.kind { ProofCmd::Sorry.write_to(w)? }
+      }
+      ThmKind::Thm(Some(pf)) => {
+        let mut reorder = Reorder::new(nargs, pf.heap.len(), |i| i);
+        let mut ehyps = Vec::with_capacity(pf.hyps.len());
+        for h in &*pf.hyps {
+          let e = match *h.deref(&pf.heap) {
+            ProofNode::Hyp(_, i) => &pf.store[i],
+            _ => unreachable!()
+          };
+          self.write_proof(w, &pf.heap, &pf.store, &mut reorder, &ehyps, e, false)?;
+          ProofCmd::Hyp.write_to(w)?;
+          ehyps.push(reorder.idx);
+          reorder.idx += 1;
+        }
+        self.write_proof(w, &pf.heap, &pf.store, &mut reorder, &ehyps, pf.head(), false)?;
       }
-      ProofCmd::Term {tid, save}.write_to(w)?;
-      if save { (reorder.idx, reorder.idx += 1).0 } else { 0 }
     }
-  })
+    Ok(())
+  }
 }
 
 /// A wrapper around a writer that implements [`Write`]` + `[`Seek`] by internally buffering
@@ -253,10 +494,21 @@ impl<'a, W: Write + Seek> Exporter<'a, W> {
   ) -> Self {
     Self {
       term_reord: TermVec(Vec::with_capacity(env.terms().len())),
-      file, source, env, report, w, pos: 0, fixups: vec![]
+      file, source, to_range: None, env, report, w, pos: 0, fixups: vec![]
     }
   }
 
+  /// Set the function used to find the line numbers of declarations in imported files,
+  /// for the source location table in the index. Without it, only the declarations in
+  /// the input file are given a source location.
+  pub fn set_to_range(&mut self, to_range: &'a mut ToRange<'a>) {
+    self.to_range = Some(to_range)
+  }
+
+  fn tables(&self) -> Tables<'a> {
+    Tables { terms: self.env.terms(), thms: self.env.thms() }
+  }
+
   fn write_u32(&mut self, n: u32) -> io::Result<()> {
     WriteBytesExt::write_u32::<LE>(self, n)
   }
@@ -324,154 +576,45 @@ impl<'a, W: Write + Seek> Exporter<'a, W> {
     Ok(())
   }
 
-  fn write_expr_unify(&mut self,
-    heap: &[ExprNode],
-    store: &[ExprNode],
-    reorder: &mut Reorder,
-    node: &ExprNode,
-    save: &mut Vec<usize>
-  ) -> io::Result<()> {
-    macro_rules! commit {($n:expr) => {
-      for i in save.drain(..) {reorder.map[i] = Some($n)}
-    }}
-    match *node {
-      ExprNode::Ref(i) => match reorder.map[i] {
-        None => {
-          save.push(i);
-          self.write_expr_unify(heap, store, reorder, &heap[i], save)?
-        }
-        Some(n) => {
-          UnifyCmd::Ref(n).write_to(self)?;
-          commit!(n)
-        }
-      }
-      ExprNode::Dummy(_, s) => {
-        commit!(reorder.idx); reorder.idx += 1;
-        UnifyCmd::Dummy(s).write_to(self)?
-      }
-      ExprNode::App(tid, p) => {
-        if save.is_empty() {
-          UnifyCmd::Term {tid, save: false}.write_to(self)?
-        } else {
-          commit!(reorder.idx); reorder.idx += 1;
-          UnifyCmd::Term {tid, save: true}.write_to(self)?
-        }
-        for e in self.env.term(tid).unpack_app(&store[p..]) {
-          self.write_expr_unify(heap, store, reorder, e, save)?
-        }
-      }
-    }
-    Ok(())
-  }
-
-  #[allow(clippy::too_many_arguments)]
-  fn write_proof(&self, w: &mut impl Write,
-    heap: &[ProofNode],
-    store: &[ProofNode],
-    reorder: &mut Reorder,
-    hyps: &[u32],
-    node: &ProofNode,
-    save: bool
-  ) -> io::Result<u32> {
-    Ok(match *node {
-      ProofNode::Ref(i) => match reorder.map[i] {
-        None => {
-          let n = self.write_proof(w, heap, store, reorder, hyps, &heap[i], true)?;
-          reorder.map[i] = Some(n);
-          n
-        }
-        Some(n) => {ProofCmd::Ref(n).write_to(w)?; n}
-      }
-      ProofNode::Dummy(_, s) => {
-        ProofCmd::Dummy(s).write_to(w)?;
-        (reorder.idx, reorder.idx += 1).0
-      }
-      ProofNode::Term(term, p) => {
-        for e in self.env.term(term).unpack_term(&store[p..]) {
-          self.write_proof(w, heap, store, reorder, hyps, e, false)?;
-        }
-        ProofCmd::Term {tid: term, save}.write_to(w)?;
-        if save {(reorder.idx, reorder.idx += 1).0} else {0}
-      }
-      ProofNode::Hyp(n, _) => {
-        ProofCmd::Ref(hyps[n]).write_to(w)?;
-        hyps[n]
-      }
-      ProofNode::Thm(thm, p) => {
-        let (res, args, hs) = self.env.thm(thm).unpack_thm(&store[p..]);
-        for e in hs {self.write_proof(w, heap, store, reorder, hyps, e, false)?;}
-        for e in args {self.write_proof(w, heap, store, reorder, hyps, e, false)?;}
-        self.write_proof(w, heap, store, reorder, hyps, res, false)?;
-        ProofCmd::Thm {tid: thm, save}.write_to(w)?;
-        if save {(reorder.idx, reorder.idx += 1).0} else {0}
-      }
-      ProofNode::Conv(p) => {
-        let (e1, c, p) = ProofNode::unpack_conv(&store[p..]);
-        self.write_proof(w, heap, store, reorder, hyps, e1, false)?;
-        self.write_proof(w, heap, store, reorder, hyps, p, false)?;
-        ProofCmd::Conv.write_to(w)?;
-        self.write_conv(w, heap, store, reorder, hyps, c)?;
-        if save {
-          ProofCmd::Save.write_to(w)?;
-          (reorder.idx, reorder.idx += 1).0
-        } else {0}
-      }
-      ProofNode::Refl(_) |
-      ProofNode::Sym(_) |
-      ProofNode::Cong {..} |
-      ProofNode::Unfold {..} => unreachable!(),
-    })
+  /// Get the (1-based, inclusive) line range of the declaration with name `span`
+  /// and full span `full`, or `None` if the source text is not available.
+  fn line_range(&mut self, span: &FileSpan, full: Span) -> Option<(u32, u32)> {
+    let range = if span.file == self.file {
+      self.source?.to_range(full)
+    } else {
+      (self.to_range.as_mut()?)(&FileSpan { file: span.file.clone(), span: full })?
+    };
+    Some((range.start.line + 1, range.end.line + 1))
   }
 
-  fn write_conv(&self, w: &mut impl Write,
-    heap: &[ProofNode],
-    store: &[ProofNode],
-    reorder: &mut Reorder,
-    hyps: &[u32],
-    node: &ProofNode,
-  ) -> io::Result<()> {
-    match *node {
-      ProofNode::Ref(i) => match reorder.map[i] {
-        None => {
-          let e = &heap[i];
-          match e {
-            ProofNode::Refl(_) | ProofNode::Ref(_) =>
-              self.write_conv(w, heap, store, reorder, hyps, e)?,
-            _ => {
-              ProofCmd::ConvCut.write_to(w)?;
-              self.write_conv(w, heap, store, reorder, hyps, e)?;
-              ProofCmd::ConvSave.write_to(w)?;
-              reorder.map[i] = Some(reorder.idx);
-              reorder.idx += 1;
-            }
-          };
-        }
-        Some(n) => ProofCmd::Ref(n).write_to(w)?,
+  /// Write the source location table of the index, returning its position.
+  fn write_source_locs(&mut self) -> io::Result<u64> {
+    let env = self.env;
+    let locs = env.sorts().iter().map(|s| (&s.span, s.full))
+      .chain(env.terms().iter().map(|t| (&t.span, t.full)))
+      .chain(env.thms().iter().map(|t| (&t.span, t.full)))
+      .map(|(span, full)| Some((&span.file, self.line_range(span, full)?)))
+      .collect::<Vec<_>>();
+    let mut file_pos = HashMap::new();
+    for &(file, _) in locs.iter().flatten() {
+      if let Entry::Vacant(e) = file_pos.entry(file) {
+        e.insert(self.pos);
+        self.write_str(file.rel().as_bytes())?
       }
-      ProofNode::Dummy(_, _) |
-      ProofNode::Term {..} |
-      ProofNode::Hyp(_, _) |
-      ProofNode::Thm {..} |
-      ProofNode::Conv(_) => unreachable!(),
-      ProofNode::Refl(_) => ProofCmd::Refl.write_to(w)?,
-      ProofNode::Sym(c) => {
-        ProofCmd::Sym.write_to(w)?;
-        self.write_conv(w, heap, store, reorder, hyps, &store[c])?;
-      }
-      ProofNode::Cong(term, p) => {
-        ProofCmd::Cong.write_to(w)?;
-        for a in self.env.term(term).unpack_term(&store[p..]) {
-          self.write_conv(w, heap, store, reorder, hyps, a)?
-        }
-      }
-      ProofNode::Unfold(term, p) => {
-        let (sub_lhs, c, _) = self.env.term(term).unpack_unfold(&store[p..]);
-        self.write_proof(w, heap, store, reorder, hyps, sub_lhs, false)?;
-        ProofCmd::Unfold.write_to(w)?;
-        self.write_conv(w, heap, store, reorder, hyps, c)?;
+    }
+    self.align_to(8)?;
+    let p_srcs = self.pos;
+    for loc in locs {
+      if let Some((file, (start, end))) = loc {
+        self.write_u64(file_pos[file])?;
+        self.write_u32(start)?;
+        self.write_u32(end)?
+      } else {
+        self.write_u64(0)?;
+        self.write_u64(0)?
       }
     }
-    Ok(())
+    Ok(p_srcs)
   }
 
   #[inline]
@@ -519,8 +662,7 @@ impl<'a, W: Write + Seek> Exporter<'a, W> {
       let reorder = if let TermKind::Def(val) = &t.kind {
         let expr = val.as_ref().unwrap_or_else(||
           panic!("def {} missing value", self.env.data()[t.atom].name()));
-        let mut reorder = Reorder::new(nargs.into(), expr.heap.len(), |i| i);
-        self.write_expr_unify(&expr.heap, &expr.store, &mut reorder, expr.head(), &mut vec![])?;
+        let reorder = self.tables().write_def_unify(self, nargs.into(), expr)?;
         self.write_u8(0)?;
         Some(reorder)
       } else { None };
@@ -536,13 +678,7 @@ impl<'a, W: Write + Seek> Exporter<'a, W> {
       Self::write_thm_header(head, nargs,
         self.align_to(8)?.try_into().expect("address too large"));
       self.write_binders(&t.args)?;
-      let mut reorder = Reorder::new(nargs.into(), t.heap.len(), |i| i);
-      let save = &mut vec![];
-      self.write_expr_unify(&t.heap, &t.store, &mut reorder, &t.ret, save)?;
-      for (_, h) in t.hyps.iter().rev() {
-        UnifyCmd::Hyp.write_to(self)?;
-        self.write_expr_unify(&t.heap, &t.store, &mut reorder, h, save)?;
-      }
+      self.tables().write_thm_unify(self, t)?;
       self.write_u8(0)?;
     }
     thm_header.commit(self);
@@ -584,9 +720,7 @@ impl<'a, W: Write + Seek> Exporter<'a, W> {
                 TermKind::Def(Some(expr)) => {
                   #[allow(clippy::cast_possible_truncation)] // no truncation
                   let nargs = td.args.len() as u32;
-                  let mut reorder = Reorder::new(nargs, expr.heap.len(), |i| i);
-                  write_expr_proof(self.env, vec, &expr.heap, &expr.store,
-                    &mut reorder, vars, expr.head(), false)?;
+                  self.tables().write_def_proof(vec, nargs, expr, vars)?;
                   vec.write_u8(0)?;
                   let cmd = STMT_DEF | if td.vis == Modifiers::LOCAL {STMT_LOCAL} else {0};
                   write_cmd_bytes(self, cmd, vec)?;
@@ -607,42 +741,14 @@ impl<'a, W: Write + Seek> Exporter<'a, W> {
                 // Safety: we just pushed to thm_names
                 unsafe { &mut temp.thm_names.last_mut().unwrap_unchecked().1.vars }
               });
-              #[allow(clippy::cast_possible_truncation)] // no truncation
-              let nargs = td.args.len() as u32;
-              let cmd = match &td.kind {
-                ThmKind::Axiom | ThmKind::Thm(None) => {
-                  let mut reorder = Reorder::new(nargs, td.heap.len(), |i| i);
-                  for (_, h) in &*td.hyps {
-                    write_expr_proof(self.env, vec, &td.heap, &td.store,
-                      &mut reorder, vars, h, false)?;
-                    ProofCmd::Hyp.write_to(vec)?;
-                  }
-                  write_expr_proof(self.env, vec, &td.heap, &td.store,
-                    &mut reorder, vars, &td.ret, false)?;
-                  if let ThmKind::Axiom = td.kind {
-                    STMT_AXIOM
-                  } else {
-                    ProofCmd::Sorry.write_to(vec)?;
+              self.tables().write_thm_proof(vec, td, vars)?;
+              let cmd = match td.kind {
+                ThmKind::Axiom => STMT_AXIOM,
+                ThmKind::Thm(ref pf) => {
+                  if pf.is_none() {
                     (self.report)(ErrorLevel::Warning, &format!(
                       "theorem {} contains sorry", self.env.data()[td.atom].name()));
-                    STMT_THM | if td.vis == Modifiers::PUB {0} else {STMT_LOCAL}
-                  }
-                }
-                ThmKind::Thm(Some(pf)) => {
-                  let mut reorder = Reorder::new(nargs, pf.heap.len(), |i| i);
-                  let mut ehyps = Vec::with_capacity(pf.hyps.len());
-                  for h in &*pf.hyps {
-                    let e = match *h.deref(&pf.heap) {
-                      ProofNode::Hyp(_, i) => &pf.store[i],
-                      _ => unreachable!()
-                    };
-                    self.write_proof(vec, &pf.heap, &pf.store, &mut reorder, &ehyps, e, false)?;
-                    ProofCmd::Hyp.write_to(vec)?;
-                    ehyps.push(reorder.idx);
-                    reorder.idx += 1;
                   }
-                  self.write_proof(vec, &pf.heap, &pf.store,
-                    &mut reorder, &ehyps, pf.head(), false)?;
                   STMT_THM | if td.vis == Modifiers::PUB {0} else {STMT_LOCAL}
                 }
               };
@@ -704,8 +810,11 @@ impl<'a, W: Write + Seek> Exporter<'a, W> {
       let p_hyps = self.pos;
       for (_, hs) in &thm_names { self.write_u64(hs.p_vars)? }
 
+      let p_srcs = self.write_source_locs()?;
+
       p_index.commit(self);
-      let index = [(INDEX_NAME, p_names), (INDEX_VAR_NAME, p_vars), (INDEX_HYP_NAME, p_hyps)];
+      let index = [(INDEX_NAME, p_names), (INDEX_VAR_NAME, p_vars), (INDEX_HYP_NAME, p_hyps),
+        (INDEX_SOURCE, p_srcs)];
       self.write_u64(index.len() as u64)?;
       for (name, ptr) in &index {
         self.write_all(name)?;
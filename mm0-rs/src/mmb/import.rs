@@ -11,7 +11,10 @@ use mm0b_parser::{NumdStmtCmd, UnifyCmd, ProofCmd, BasicMmbFile,
 
 type Result<T> = std::result::Result<T, ParseError>;
 
-fn parse_unify(
+/// Parse the unify stream of a term or theorem with `nargs` arguments, returning the heap,
+/// the store, and the head expression. For a theorem, the hypotheses are pushed to `hyps`,
+/// and `dummy` is called to get the name of each dummy variable of a definition.
+pub(crate) fn parse_unify(
   file: &BasicMmbFile<'_>, nargs: usize, it: UnifyIter<'_>,
   hyps: Option<&mut Vec<(Option<AtomId>, ExprNode)>>,
   dummy: impl FnMut() -> AtomId,
@@ -130,7 +133,9 @@ impl<'a> IntoIterator for &'a Dedup {
   fn into_iter(self) -> DedupIter<'a> { DedupIter(self.0.iter()) }
 }
 
-fn parse_proof(
+/// Parse the proof stream of a theorem with `nargs` arguments, where `dummy` is called to get
+/// the name of each dummy variable. Returns [`ParseError::SorryError`] if the proof uses `sorry`.
+pub(crate) fn parse_proof(
   file: &BasicMmbFile<'_>, nargs: usize, it: &mut ProofIter<'_>,
   dummy: impl FnMut() -> AtomId,
 ) -> Result<Proof> {
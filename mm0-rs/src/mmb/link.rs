@@ -0,0 +1,350 @@
+//! Linking several MMB files into one.
+//!
+//! This implements `mm0-rs mmb-link`, which combines several compiled files into a single
+//! MMB file, for checkers like `mm0-c` which only take one file. The input files usually
+//! overlap (for example, several developments which all start with the contents of
+//! `peano.mmb`), so declarations are matched by name, using the index (which all of the
+//! input files must have). Each declaration of each input file, in order, is either:
+//!
+//! - *added* to the output, if no declaration with this name has been seen yet. The sort,
+//!   term and theorem IDs in its arguments and in the unify and proof streams are
+//!   renumbered to refer to the declarations of the output file.
+//! - *shared* with a declaration of an earlier file, which must be identical to it after
+//!   renumbering: the same kind of declaration and visibility, the same sort modifiers or
+//!   arguments, and the same unify stream (which encodes the value of a definition and the
+//!   hypotheses and conclusion of a theorem). The proof of a theorem is not compared, and
+//!   the proof from the first file is kept.
+//!
+//! The output has an index with the names of the sorts, terms and theorems, and the names
+//! of the variables and hypotheses for the declarations which have them in the input.
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+use mm0b_parser::{Arg, BasicMmbFile, Mm0Writer, NumdStmtCmd, ParseError, ProofCmd, ProofIter,
+  Reopen, SortData, UnifyCmd, UnifyIter};
+use crate::{u32_as_usize, DeclKey, SortId, SortVec, TermId, TermVec, ThmId, ThmVec};
+use crate::compiler::FileContents;
+
+/// An error while linking MMB files.
+#[derive(Debug)]
+enum LinkError {
+  Io(io::Error),
+  Parse(ParseError),
+  /// A malformed declaration, or one which conflicts with an earlier file.
+  Msg(String),
+}
+
+impl From<io::Error> for LinkError {
+  fn from(e: io::Error) -> Self { Self::Io(e) }
+}
+
+impl From<ParseError> for LinkError {
+  fn from(e: ParseError) -> Self { Self::Parse(e) }
+}
+
+impl fmt::Display for LinkError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::Io(e) => e.fmt(f),
+      Self::Parse(e) => e.fmt(f),
+      Self::Msg(msg) => f.write_str(msg),
+    }
+  }
+}
+
+type Result<T> = std::result::Result<T, LinkError>;
+
+/// The statement of a term or theorem in the output file, after renumbering, which is
+/// compared against the declarations with the same name in later files.
+#[derive(Debug, PartialEq, Eq)]
+struct Stmt {
+  /// The kind of declaration.
+  kind: StmtKind,
+  /// True if this is a local definition or theorem.
+  local: bool,
+  /// The arguments, followed by the return type for terms.
+  args: Vec<Arg>,
+  /// The unify stream, for definitions, axioms and theorems.
+  unify: Vec<u8>,
+}
+
+/// The kind of a term or theorem declaration.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum StmtKind { Term, Def, Axiom, Thm }
+
+impl StmtKind {
+  fn name(self) -> &'static str {
+    match self {
+      StmtKind::Term => "term",
+      StmtKind::Def => "def",
+      StmtKind::Axiom => "axiom",
+      StmtKind::Thm => "theorem",
+    }
+  }
+
+  fn describe(self, local: bool) -> String {
+    if local { format!("local {}", self.name()) } else { self.name().into() }
+  }
+}
+
+impl Stmt {
+  fn describe(&self) -> String { self.kind.describe(self.local) }
+}
+
+/// The IDs in the output file of the sorts, terms and theorems of an input file.
+#[derive(Default)]
+struct Remap {
+  sorts: SortVec<SortId>,
+  terms: TermVec<TermId>,
+  thms: ThmVec<ThmId>,
+}
+
+impl Remap {
+  fn sort(&self, s: SortId) -> Result<SortId> {
+    self.sorts.0.get(usize::from(s.0)).copied()
+      .ok_or_else(|| LinkError::Msg(format!("reference to unknown sort {}", s.0)))
+  }
+
+  fn term(&self, t: TermId) -> Result<TermId> {
+    self.terms.0.get(u32_as_usize(t.0)).copied()
+      .ok_or_else(|| LinkError::Msg(format!("reference to unknown term {}", t.0)))
+  }
+
+  fn thm(&self, t: ThmId) -> Result<ThmId> {
+    self.thms.0.get(u32_as_usize(t.0)).copied()
+      .ok_or_else(|| LinkError::Msg(format!("reference to unknown theorem {}", t.0)))
+  }
+
+  fn arg(&self, mut arg: Arg) -> Result<Arg> {
+    arg.add_sort(self.sort(arg.sort())?);
+    Ok(arg)
+  }
+
+  /// Write a unify stream (without the final `END`) with renumbered IDs.
+  fn unify(&self, w: &mut impl Write, it: UnifyIter<'_>) -> Result<()> {
+    for cmd in it {
+      match cmd? {
+        UnifyCmd::Term { tid, save } => UnifyCmd::Term { tid: self.term(tid)?, save },
+        UnifyCmd::Dummy(s) => UnifyCmd::Dummy(self.sort(s)?),
+        cmd => cmd,
+      }.write_to(w)?
+    }
+    Ok(())
+  }
+
+  /// Write a proof stream (without the final `END`) with renumbered IDs.
+  fn proof(&self, w: &mut impl Write, it: ProofIter<'_>) -> Result<()> {
+    for cmd in it {
+      match cmd? {
+        ProofCmd::Term { tid, save } => ProofCmd::Term { tid: self.term(tid)?, save },
+        ProofCmd::Thm { tid, save } => ProofCmd::Thm { tid: self.thm(tid)?, save },
+        ProofCmd::Dummy(s) => ProofCmd::Dummy(self.sort(s)?),
+        cmd => cmd,
+      }.write_to(w)?
+    }
+    Ok(())
+  }
+}
+
+/// Get the names of `n` variables or hypotheses from the index, using `_` for missing names.
+fn names<'a>(n: usize, get: impl Fn(usize) -> Option<&'a str>) -> Vec<&'a str> {
+  (0..n).map(|i| get(i).unwrap_or("_")).collect()
+}
+
+/// The state of the linker: the output file, and the declarations added to it so far.
+struct Linker<W> {
+  out: Mm0Writer<W>,
+  /// The sorts of the output file, by name.
+  sort_names: HashMap<String, SortId>,
+  /// The terms and theorems of the output file, by name. (These share a namespace.)
+  decl_names: HashMap<String, DeclKey>,
+  sorts: SortVec<SortData>,
+  terms: TermVec<Stmt>,
+  thms: ThmVec<Stmt>,
+}
+
+impl<W: Reopen> Linker<W> {
+  fn new(out: Mm0Writer<W>) -> Self {
+    Self {
+      out,
+      sort_names: HashMap::new(),
+      decl_names: HashMap::new(),
+      sorts: Default::default(),
+      terms: Default::default(),
+      thms: Default::default(),
+    }
+  }
+
+  /// Check that a declaration is identical to the earlier declaration `old` with the same name.
+  fn check(name: &str, old: &Stmt, new: &Stmt) -> Result<()> {
+    let what = if (old.kind, old.local) != (new.kind, new.local) {
+      format!("{} {} was a {} in an earlier file", new.describe(), name, old.describe())
+    } else if old.args != new.args {
+      format!("the arguments of {} {} differ from an earlier file", new.describe(), name)
+    } else if old.unify != new.unify {
+      let part = if new.kind == StmtKind::Def { "value" } else { "statement" };
+      format!("the {} of {} {} differs from an earlier file", part, new.describe(), name)
+    } else {
+      return Ok(())
+    };
+    Err(LinkError::Msg(what))
+  }
+
+  /// Add the declarations of `file` which have not been seen yet to the output.
+  fn add_file(&mut self, file: &BasicMmbFile<'_>) -> Result<()> {
+    let mut remap = Remap::default();
+    for e in file.proof() {
+      let (stmt, pf) = e?;
+      let pos = pf.pos;
+      let bad = |what| LinkError::Msg(format!("bad {} at {:#x}", what, pos));
+      let name = file.stmt_index(stmt).and_then(|e| e.value()).ok_or_else(|| LinkError::Msg(
+        format!("declaration at {:#x} has no name in the index", pos)))?;
+      match stmt {
+        NumdStmtCmd::Sort { sort_id } => {
+          let sd = file.sort(sort_id).ok_or_else(|| bad("sort"))?;
+          let id = if let Some(&id) = self.sort_names.get(name) {
+            if self.sorts[id].0 != sd.0 {
+              return Err(LinkError::Msg(format!(
+                "the modifiers of sort {} differ from an earlier file", name)))
+            }
+            id
+          } else {
+            if self.sorts.len() >= 128 {
+              return Err(LinkError::Msg("too many sorts (max 128)".into()))
+            }
+            let id = self.out.add_sort(Some(name), sd)?;
+            self.sort_names.insert(name.into(), id);
+            self.sorts.push(sd);
+            id
+          };
+          remap.sorts.push(id);
+        }
+        NumdStmtCmd::TermDef { term_id, local } => {
+          let td = file.term(term_id).ok_or_else(|| bad("term"))?;
+          let (kind, local) = if td.def() { (StmtKind::Def, local) } else { (StmtKind::Term, false) };
+          let mut args = td.args().iter().map(|&a| remap.arg(a)).collect::<Result<Vec<_>>>()?;
+          let ret = remap.arg(td.ret())?;
+          let mut unify = vec![];
+          if td.def() { remap.unify(&mut unify, td.unify())? }
+          let id = match self.decl_names.get(name) {
+            Some(&DeclKey::Term(id)) => {
+              args.push(ret);
+              Self::check(name, &self.terms[id], &Stmt { kind, local, args, unify })?;
+              id
+            }
+            Some(&DeclKey::Thm(id)) => return Err(LinkError::Msg(format!(
+              "{} {} was a {} in an earlier file",
+              kind.describe(local), name, self.thms[id].describe()))),
+            None => {
+              let id = if td.def() {
+                let mut b = self.out.add_def(local, Some(name), &args, ret);
+                b.unify().write_all(&unify)?;
+                remap.proof(b.proof(), pf)?;
+                b.finish()?
+              } else {
+                self.out.add_term(Some(name), &args, ret)?
+              };
+              if let Some(vars) = file.term_vars_opt(term_id) {
+                self.out.set_term_vars(id, &names(vars.len(), |i| vars.get_opt(i)))
+              }
+              self.decl_names.insert(name.into(), DeclKey::Term(id));
+              args.push(ret);
+              self.terms.push(Stmt { kind, local, args, unify });
+              id
+            }
+          };
+          remap.terms.push(id);
+        }
+        NumdStmtCmd::Axiom { thm_id } | NumdStmtCmd::Thm { thm_id, .. } => {
+          let td = file.thm(thm_id).ok_or_else(|| bad("theorem"))?;
+          let (kind, local) = match stmt {
+            NumdStmtCmd::Thm { local, .. } => (StmtKind::Thm, local),
+            _ => (StmtKind::Axiom, false),
+          };
+          let args = td.args().iter().map(|&a| remap.arg(a)).collect::<Result<Vec<_>>>()?;
+          let mut unify = vec![];
+          remap.unify(&mut unify, td.unify())?;
+          let id = match self.decl_names.get(name) {
+            Some(&DeclKey::Thm(id)) => {
+              Self::check(name, &self.thms[id], &Stmt { kind, local, args, unify })?;
+              id
+            }
+            Some(&DeclKey::Term(id)) => return Err(LinkError::Msg(format!(
+              "{} {} was a {} in an earlier file",
+              kind.describe(local), name, self.terms[id].describe()))),
+            None => {
+              let mut b = match kind {
+                StmtKind::Thm => self.out.add_thm(local, Some(name), &args),
+                _ => self.out.add_axiom(Some(name), &args),
+              };
+              b.unify().write_all(&unify)?;
+              remap.proof(b.proof(), pf)?;
+              let id = b.finish()?;
+              if let (Some(vars), Some(hyps)) = (file.thm_vars_opt(thm_id), file.thm_hyps_opt(thm_id)) {
+                self.out.set_thm_vars(id,
+                  &names(vars.len(), |i| vars.get_opt(i)),
+                  &names(hyps.len(), |i| hyps.get_opt(i)))
+              }
+              self.decl_names.insert(name.into(), DeclKey::Thm(id));
+              self.thms.push(Stmt { kind, local, args, unify });
+              id
+            }
+          };
+          remap.thms.push(id);
+        }
+      }
+    }
+    Ok(())
+  }
+}
+
+/// Link several MMB files into one
+#[derive(clap::Args, Debug)]
+pub struct Args {
+  /// Sets the input files (.mmb)
+  #[clap(required = true)]
+  pub inputs: Vec<String>,
+  /// Sets the output file (.mmb)
+  #[clap(short, long)]
+  pub output: String,
+}
+
+impl Args {
+  /// Main entry point for `mm0-rs mmb-link` subcommand.
+  ///
+  /// See the [module documentation](self) for how the files are combined.
+  ///
+  /// # Arguments
+  ///
+  /// `mm0-rs mmb-link <in.mmb>... -o <out.mmb>`, where:
+  ///
+  /// - `in.mmb` are the files to link, in order. They must all have an index.
+  /// - `out.mmb` is the file to write.
+  pub fn main(self) -> io::Result<()> {
+    // The temporary file is removed on every exit path, once the linker has closed it.
+    struct RemoveOnDrop(PathBuf);
+    impl Drop for RemoveOnDrop {
+      fn drop(&mut self) { let _ = fs::remove_file(&self.0); }
+    }
+    let tmp = RemoveOnDrop(
+      std::env::temp_dir().join(format!("mm0-rs-link-{}.tmp", std::process::id())));
+    let proof = OpenOptions::new().read(true).write(true).create(true).truncate(true).open(&tmp.0)?;
+    let mut linker = Linker::new(Mm0Writer::new(proof));
+    for path in &self.inputs {
+      let res = FileContents::new_bin_from_file(path.as_ref()).map_err(LinkError::from)
+        .and_then(|mmb| linker.add_file(&BasicMmbFile::parse(&mmb)?));
+      if let Err(e) = res {
+        // `exit` does not run destructors
+        drop(linker);
+        drop(tmp);
+        eprintln!("error: {}: {}", path, e);
+        std::process::exit(1)
+      }
+    }
+    let mut w = BufWriter::new(File::create(&self.output)?);
+    linker.out.finish(&mut w)?;
+    w.flush()
+  }
+}
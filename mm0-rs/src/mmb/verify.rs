@@ -0,0 +1,876 @@
+//! Native MMB verifier, which checks an `.mmb` proof file against its `.mm0` specification.
+//!
+//! This is a port of the stack machine in [`mm0-c/verifier.c`], with the same semantics:
+//! expressions are compared by pointer equality during proof checking, and every
+//! non-local statement in the proof stream is matched in order against the elaborated
+//! `.mm0` file, in the same way that [`mm0-c/parser.c`] does.
+//!
+//! Once the statements are known, the proof of each theorem can be checked on its own, so
+//! [`verify_par`] checks the statements in order and then splits the theorem proofs between
+//! several threads.
+//!
+//! [`mm0-c/verifier.c`]: https://github.com/digama0/mm0/blob/master/mm0-c/verifier.c
+//! [`mm0-c/parser.c`]: https://github.com/digama0/mm0/blob/master/mm0-c/parser.c
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::{fs, io, thread};
+use mm0b_parser::{BasicMmbFile, NumdStmtCmd, ParseError, ProofCmd, ProofIter, UnifyCmd, UnifyIter,
+  Arg, TermRef, ThmRef, TYPE_BOUND_MASK, TYPE_DEPS_MASK, TYPE_UPPER_MASK};
+use crate::{AtomId, DeclKey, ExprNode, FileRef, FrozenEnv, Modifiers,
+  StmtTrace, TermId, TermKind, ThmKind, Type, u32_as_usize};
+use crate::compiler::FileContents;
+
+type Result<T> = std::result::Result<T, ParseError>;
+
+/// An error produced while verifying an MMB file.
+#[derive(Debug)]
+pub struct VerifyError {
+  /// The byte offset of the statement or command that failed
+  pub pos: usize,
+  /// The name of the statement being checked, taken from the index if present
+  /// and otherwise from the `.mm0` file.
+  pub name: Option<String>,
+  /// The source location of the statement being checked, if the index has it.
+  pub source: Option<String>,
+  /// The underlying error
+  pub err: ParseError,
+}
+
+impl std::fmt::Display for VerifyError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "at {:#x}", self.pos)?;
+    match (&self.name, &self.source) {
+      (Some(name), Some(src)) => write!(f, " ({}, {})", name, src)?,
+      (Some(s), None) | (None, Some(s)) => write!(f, " ({})", s)?,
+      (None, None) => {}
+    }
+    write!(f, ": {}", self.err)
+  }
+}
+
+/// An expression in the verifier store.
+#[derive(Debug)]
+enum StoreExpr {
+  /// A variable, with its type and its index in the heap.
+  Var { ty: u64, var: u16 },
+  /// A term constructor applied to arguments. The type contains the sort
+  /// and the set of bound variables that the expression depends on.
+  App { ty: u64, tid: TermId, args: Box<[u32]> },
+}
+
+impl StoreExpr {
+  fn ty(&self) -> u64 {
+    match *self { StoreExpr::Var { ty, .. } | StoreExpr::App { ty, .. } => ty }
+  }
+
+  #[allow(clippy::cast_possible_truncation)]
+  fn sort(&self) -> u8 { ((self.ty() >> 56) & 0x7F) as u8 }
+}
+
+/// An element of the main stack or heap.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum StackEl {
+  /// An expression `e`
+  Expr(u32),
+  /// A proof `|- e`
+  Proof(u32),
+  /// A convertibility proof `e1 = e2`
+  Conv(u32, u32),
+  /// A convertibility obligation `e1 =?= e2`
+  CoConv(u32, u32),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum UnifyMode {
+  /// Checking a definition, or processing an `Unfold` command
+  Def,
+  /// Applying a theorem in a `Thm` command
+  Thm,
+  /// Checking a theorem statement at the end of its proof
+  ThmEnd,
+  /// Matching a statement against the `.mm0` file, with the given number of arguments.
+  /// Expressions are compared structurally in this mode.
+  Spec(u16),
+}
+
+/// Returns true if a value with type `from` can be cast to a value of type `to`.
+/// This requires that the sorts be the same, and additionally if `to` is a
+/// name then so is `from`.
+fn sorts_compatible(from: u64, to: u64) -> bool {
+  let diff = from ^ to;
+  diff & TYPE_UPPER_MASK == 0 ||
+  (diff & !TYPE_BOUND_MASK & TYPE_UPPER_MASK == 0 && from & TYPE_BOUND_MASK != 0)
+}
+
+/// Encode the binders of a `.mm0` declaration in the MMB format.
+fn encode_args(args: &[(Option<AtomId>, Type)]) -> impl Iterator<Item=u64> + '_ {
+  let mut bv = 1;
+  args.iter().map(move |&(_, ty)| match ty {
+    Type::Bound(s) => (TYPE_BOUND_MASK | u64::from(s.0) << 56 | bv, bv *= 2).0,
+    Type::Reg(s, deps) => u64::from(s.0) << 56 | deps,
+  })
+}
+
+/// A theorem whose proof is checked after the statements, by [`verify_par`].
+#[derive(Debug)]
+struct ThmJob<'a> {
+  /// The position of the statement
+  pos: usize,
+  stmt: NumdStmtCmd,
+  thm: ThmRef<'a>,
+  proof: ProofIter<'a>,
+  /// The number of sorts, terms and theorems declared before this one
+  num_sorts: usize,
+  num_terms: u32,
+  num_thms: u32,
+}
+
+struct Verifier<'a> {
+  file: &'a BasicMmbFile<'a>,
+  env: &'a FrozenEnv,
+  /// The remaining statements in the `.mm0` file
+  spec: std::slice::Iter<'a, StmtTrace>,
+  /// The name of the `.mm0` statement being matched, for error reporting
+  spec_name: Option<AtomId>,
+  /// Maps term IDs in the `.mm0` file to term IDs in the `.mmb` file
+  term_map: Vec<TermId>,
+  /// The modifiers of the sorts declared so far
+  sorts: Vec<Modifiers>,
+  num_terms: u32,
+  num_thms: u32,
+  /// The statement being checked
+  stmt: Option<NumdStmtCmd>,
+  /// The position of the current statement or command
+  pos: usize,
+  /// The first statement that used `sorry`
+  sorry: Option<(usize, NumdStmtCmd)>,
+  /// If set, the proofs of theorems are not checked in [`verify`](Self::verify),
+  /// but added to this list to be checked later.
+  deferred: Option<Vec<ThmJob<'a>>>,
+  next_bv: u64,
+  store: Vec<StoreExpr>,
+  heap: Vec<StackEl>,
+  stack: Vec<StackEl>,
+  hstack: Vec<u32>,
+  ustack: Vec<u32>,
+  uheap: Vec<u32>,
+}
+
+macro_rules! ensure {
+  ($self:ident, $e:expr, $msg:expr) => { if !$e { return Err($self.err($msg)) } }
+}
+
+impl<'a> Verifier<'a> {
+  fn new(file: &'a BasicMmbFile<'a>, env: &'a FrozenEnv) -> Self {
+    Self {
+      file, env,
+      spec: env.stmts().iter(),
+      spec_name: None,
+      term_map: vec![],
+      sorts: vec![],
+      num_terms: 0,
+      num_thms: 0,
+      stmt: None,
+      pos: 0,
+      sorry: None,
+      deferred: None,
+      next_bv: 1,
+      store: vec![],
+      heap: vec![],
+      stack: vec![],
+      hstack: vec![],
+      ustack: vec![],
+      uheap: vec![],
+    }
+  }
+
+  fn err(&self, msg: &'static str) -> ParseError { ParseError::StrError(msg, self.pos) }
+
+  fn stmt_name(&self, stmt: NumdStmtCmd) -> Option<String> {
+    self.file.stmt_index(stmt).and_then(|e| e.value()).map(String::from)
+  }
+
+  fn verify_error(&self, err: ParseError) -> VerifyError {
+    let name = self.stmt.and_then(|stmt| self.stmt_name(stmt))
+      .or_else(|| self.spec_name.map(|a| self.env.data()[a].name().to_string()));
+    let source = self.stmt.and_then(|stmt| self.file.stmt_source(stmt)).map(|loc| loc.to_string());
+    VerifyError { pos: self.pos, name, source, err }
+  }
+
+  fn alloc(&mut self, e: StoreExpr) -> Result<u32> {
+    let n = self.store.len().try_into().map_err(|_| self.err("store overflow"))?;
+    self.store.push(e);
+    Ok(n)
+  }
+
+  fn expr(&self, e: u32) -> &StoreExpr { &self.store[u32_as_usize(e)] }
+
+  fn sort_mods(&self, s: u8) -> Modifiers { self.sorts[usize::from(s)] }
+
+  fn pop(&mut self) -> Result<StackEl> {
+    self.stack.pop().ok_or_else(|| self.err("stack underflow"))
+  }
+
+  fn pop_expr(&mut self) -> Result<u32> {
+    if let StackEl::Expr(e) = self.pop()? { Ok(e) } else { Err(self.err("bad stack slot")) }
+  }
+
+  fn pop_proof(&mut self) -> Result<u32> {
+    if let StackEl::Proof(e) = self.pop()? { Ok(e) } else { Err(self.err("bad stack slot")) }
+  }
+
+  fn pop_co_conv(&mut self) -> Result<(u32, u32)> {
+    if let StackEl::CoConv(e1, e2) = self.pop()? { Ok((e1, e2)) } else { Err(self.err("bad stack slot")) }
+  }
+
+  fn pop_ustack(&mut self) -> Result<u32> {
+    self.ustack.pop().ok_or_else(|| self.err("unify stack underflow"))
+  }
+
+  /// Given a list of binders, load the main heap and allocate all the variables.
+  /// Also perform binder validity checking.
+  fn load_args(&mut self, args: &[Arg]) -> Result<()> {
+    self.store.clear();
+    self.heap.clear();
+    self.stack.clear();
+    self.hstack.clear();
+    self.next_bv = 1;
+    for (i, &ty) in args.iter().enumerate() {
+      let ty = ty.into_inner();
+      let deps = ty & TYPE_DEPS_MASK;
+      #[allow(clippy::cast_possible_truncation)]
+      let sort = ((ty >> 56) & 0x7F) as u8;
+      ensure!(self, usize::from(sort) < self.sorts.len(), "bad binder sort");
+      if ty & TYPE_BOUND_MASK == 0 {
+        ensure!(self, deps & !(self.next_bv - 1) == 0, "bad binder deps");
+      } else {
+        ensure!(self, !self.sort_mods(sort).contains(Modifiers::STRICT),
+          "bound variable in strict sort");
+        ensure!(self, deps == self.next_bv, "bad binder deps");
+        self.next_bv *= 2;
+      }
+      let var = i.try_into().map_err(|_| self.err("too many arguments"))?;
+      let e = self.alloc(StoreExpr::Var { ty, var })?;
+      self.heap.push(StackEl::Expr(e));
+    }
+    Ok(())
+  }
+
+  /// Set the unify heap to the variables at the start of the heap.
+  fn load_uheap(&mut self, nargs: usize) {
+    self.uheap.clear();
+    self.uheap.extend(self.heap[..nargs].iter().map(|&el| match el {
+      StackEl::Expr(e) => e,
+      _ => unreachable!(),
+    }));
+  }
+
+  /// Structural equality of expressions, used for matching against the `.mm0` file.
+  fn deep_eq(&self, e1: u32, e2: u32) -> bool {
+    e1 == e2 || match (self.expr(e1), self.expr(e2)) {
+      (StoreExpr::Var { var: v1, .. }, StoreExpr::Var { var: v2, .. }) => v1 == v2,
+      (StoreExpr::App { tid: t1, args: a1, .. }, StoreExpr::App { tid: t2, args: a2, .. }) =>
+        t1 == t2 && a1.iter().zip(&**a2).all(|(&a1, &a2)| self.deep_eq(a1, a2)),
+      _ => false,
+    }
+  }
+
+  /// Run a unify command stream, with `tgt` as the target expression.
+  fn run_unify(&mut self, mode: UnifyMode, mut it: UnifyIter<'_>, tgt: u32) -> Result<()> {
+    let old_pos = self.pos;
+    self.ustack.clear();
+    self.ustack.push(tgt);
+    loop {
+      self.pos = it.pos;
+      let cmd = match it.next() {
+        None => break,
+        Some(cmd) => cmd?,
+      };
+      match cmd {
+        UnifyCmd::Ref(i) => {
+          let e = *self.uheap.get(u32_as_usize(i)).ok_or_else(|| self.err("bad ref step"))?;
+          let p = self.pop_ustack()?;
+          if let UnifyMode::Spec(_) = mode {
+            ensure!(self, self.deep_eq(e, p), "expression mismatch")
+          } else {
+            ensure!(self, e == p, "unify failure at ref")
+          }
+        }
+        UnifyCmd::Term { tid, save } => {
+          let p = self.pop_ustack()?;
+          match &self.store[u32_as_usize(p)] {
+            StoreExpr::App { tid: t2, args, .. } => {
+              ensure!(self, *t2 == tid, "unify failure at term");
+              self.ustack.extend(args.iter().rev())
+            }
+            StoreExpr::Var { .. } => return Err(self.err("store type error")),
+          }
+          if save { self.uheap.push(p) }
+        }
+        UnifyCmd::Dummy(s) => {
+          let p = self.pop_ustack()?;
+          let (ty, var) = match *self.expr(p) {
+            StoreExpr::Var { ty, var } => (ty, var),
+            StoreExpr::App { .. } => return Err(self.err("store type error")),
+          };
+          match mode {
+            UnifyMode::Def => {
+              ensure!(self, ty >> 56 == u64::from(0x80 | s.0), "unify failure at dummy");
+              let deps = ty & TYPE_DEPS_MASK;
+              for &e in &self.uheap {
+                ensure!(self, self.expr(e).ty() & deps == 0, "dummy disjoint variable violation");
+              }
+            }
+            UnifyMode::Spec(nargs) => {
+              ensure!(self, var >= nargs, "expected a dummy");
+              ensure!(self, self.expr(p).sort() == s.0, "unify failure at dummy");
+            }
+            UnifyMode::Thm | UnifyMode::ThmEnd =>
+              return Err(self.err("Dummy command not allowed in theorem statements")),
+          }
+          self.uheap.push(p)
+        }
+        UnifyCmd::Hyp => match mode {
+          UnifyMode::Thm => {
+            let e = self.pop_proof()?;
+            self.ustack.push(e)
+          }
+          UnifyMode::ThmEnd | UnifyMode::Spec(_) => {
+            ensure!(self, self.ustack.is_empty(), "unfinished unify stack");
+            let e = self.hstack.pop().ok_or_else(|| self.err("hypothesis stack underflow"))?;
+            self.ustack.push(e)
+          }
+          UnifyMode::Def => return Err(self.err("Hyp command not allowed in definition statements")),
+        }
+      }
+    }
+    if matches!(mode, UnifyMode::ThmEnd | UnifyMode::Spec(_)) {
+      ensure!(self, self.hstack.is_empty(), "unfinished hypothesis stack");
+    }
+    ensure!(self, self.ustack.is_empty(), "unfinished unify stack");
+    self.pos = old_pos;
+    Ok(())
+  }
+
+  /// Pop the arguments to a term or theorem application off the stack, and check their types.
+  /// Returns the arguments and the dependencies of the bound arguments.
+  fn pop_args(&mut self, targs: &[Arg]) -> Result<(Vec<u32>, Vec<u64>)> {
+    ensure!(self, self.stack.len() >= targs.len(), "stack underflow");
+    let els = self.stack.split_off(self.stack.len() - targs.len());
+    let mut args = Vec::with_capacity(targs.len());
+    let mut bound = vec![];
+    for (el, &target) in els.into_iter().zip(targs) {
+      let arg = if let StackEl::Expr(e) = el { e } else { return Err(self.err("bad stack slot")) };
+      let ty = self.expr(arg).ty();
+      let target = target.into_inner();
+      ensure!(self, sorts_compatible(ty, target), "type mismatch");
+      if target & TYPE_BOUND_MASK != 0 {
+        bound.push(ty & TYPE_DEPS_MASK)
+      }
+      args.push(arg);
+    }
+    Ok((args, bound))
+  }
+
+  /// Run a proof command stream. If `def` is true, this is the value of a definition,
+  /// otherwise it is the proof of an axiom or theorem.
+  fn run_proof(&mut self, def: bool, mut it: ProofIter<'_>) -> Result<()> {
+    loop {
+      self.pos = it.pos;
+      let cmd = match it.next() {
+        None => return Ok(()),
+        Some(cmd) => cmd?,
+      };
+      match cmd {
+        ProofCmd::Ref(i) => {
+          let s = *self.heap.get(u32_as_usize(i)).ok_or_else(|| self.err("bad ref step"))?;
+          if let StackEl::Conv(e1, e2) = s {
+            let (c1, c2) = self.pop_co_conv()?;
+            ensure!(self, c1 == e1 && c2 == e2, "ConvRef unify error");
+          } else {
+            self.stack.push(s)
+          }
+        }
+        ProofCmd::Dummy(s) => {
+          ensure!(self, usize::from(s.0) < self.sorts.len(), "bad dummy sort");
+          ensure!(self, !self.sort_mods(s.0).intersects(Modifiers::STRICT | Modifiers::FREE),
+            "dummy variable in strict or free sort");
+          ensure!(self, self.next_bv >> 56 == 0, "too many bound variables");
+          let ty = TYPE_BOUND_MASK | u64::from(s.0) << 56 | self.next_bv;
+          self.next_bv *= 2;
+          let var = self.heap.len().try_into().map_err(|_| self.err("heap overflow"))?;
+          let e = StackEl::Expr(self.alloc(StoreExpr::Var { ty, var })?);
+          self.stack.push(e);
+          self.heap.push(e);
+        }
+        ProofCmd::Term { tid, save } => {
+          ensure!(self, tid.0 < self.num_terms, "term out of range");
+          let t = self.file.term(tid).ok_or_else(|| self.err("term out of range"))?;
+          let (args, bound) = self.pop_args(t.args())?;
+          let mut accum = u64::from(t.sort().0) << 56;
+          for (&arg, &target) in args.iter().zip(t.args()) {
+            let target = target.into_inner();
+            if target & TYPE_BOUND_MASK == 0 {
+              let mut deps = self.expr(arg).ty() & TYPE_DEPS_MASK;
+              if def {
+                for (j, &d) in bound.iter().enumerate() {
+                  if target & (1 << j) != 0 { deps &= !d }
+                }
+              }
+              accum |= deps;
+            }
+          }
+          if def {
+            let target = t.ret().into_inner() & TYPE_DEPS_MASK;
+            for (j, &d) in bound.iter().enumerate() {
+              if target & (1 << j) != 0 { accum |= d }
+            }
+          }
+          let e = StackEl::Expr(self.alloc(StoreExpr::App { ty: accum, tid, args: args.into() })?);
+          self.stack.push(e);
+          if save { self.heap.push(e) }
+        }
+        ProofCmd::Thm { tid, save } => {
+          ensure!(self, !def, "invalid opcode in def");
+          ensure!(self, tid.0 < self.num_thms, "theorem out of range");
+          let t = self.file.thm(tid).ok_or_else(|| self.err("theorem out of range"))?;
+          let e = self.pop_expr()?;
+          let (args, _) = self.pop_args(t.args())?;
+          let mut bound = vec![];
+          for (i, (&arg, &target)) in args.iter().zip(t.args()).enumerate() {
+            let target = target.into_inner();
+            let deps = self.expr(arg).ty() & TYPE_DEPS_MASK;
+            if target & TYPE_BOUND_MASK == 0 {
+              for (j, &d) in bound.iter().enumerate() {
+                ensure!(self, target & (1 << j) != 0 || d & deps == 0,
+                  "disjoint variable violation");
+              }
+            } else {
+              bound.push(deps);
+              for &a in &args[..i] {
+                ensure!(self, self.expr(a).ty() & deps == 0, "disjoint variable violation");
+              }
+            }
+          }
+          self.uheap = args;
+          self.run_unify(UnifyMode::Thm, t.unify(), e)?;
+          self.stack.push(StackEl::Proof(e));
+          if save { self.heap.push(StackEl::Proof(e)) }
+        }
+        ProofCmd::Hyp => {
+          ensure!(self, !def, "invalid opcode in def");
+          let e = self.pop_expr()?;
+          ensure!(self, self.sort_mods(self.expr(e).sort()).contains(Modifiers::PROVABLE),
+            "hypothesis should have provable sort");
+          self.hstack.push(e);
+          self.heap.push(StackEl::Proof(e));
+        }
+        ProofCmd::Conv => {
+          let e2 = self.pop_proof()?;
+          let e1 = self.pop_expr()?;
+          self.stack.push(StackEl::Proof(e1));
+          self.stack.push(StackEl::CoConv(e1, e2));
+        }
+        ProofCmd::Refl => {
+          let (e1, e2) = self.pop_co_conv()?;
+          ensure!(self, e1 == e2, "Refl unify failure");
+        }
+        ProofCmd::Sym => {
+          let (e1, e2) = self.pop_co_conv()?;
+          self.stack.push(StackEl::CoConv(e2, e1));
+        }
+        ProofCmd::Cong => {
+          let (e1, e2) = self.pop_co_conv()?;
+          match (&self.store[u32_as_usize(e1)], &self.store[u32_as_usize(e2)]) {
+            (StoreExpr::App { tid: t1, args: a1, .. }, StoreExpr::App { tid: t2, args: a2, .. }) => {
+              ensure!(self, t1 == t2, "Cong unify error");
+              self.stack.extend(a1.iter().zip(&**a2).rev().map(|(&a1, &a2)| StackEl::CoConv(a1, a2)))
+            }
+            _ => return Err(self.err("store type error")),
+          }
+        }
+        ProofCmd::Unfold => {
+          let e = self.pop_expr()?;
+          let (e1, e2) = self.pop_co_conv()?;
+          let (tid, args) = match self.expr(e1) {
+            StoreExpr::App { tid, args, .. } => (*tid, args.to_vec()),
+            StoreExpr::Var { .. } => return Err(self.err("store type error")),
+          };
+          let t = self.file.term(tid).ok_or_else(|| self.err("term out of range"))?;
+          ensure!(self, t.def(), "Unfold: not a definition");
+          self.uheap = args;
+          self.run_unify(UnifyMode::Def, t.unify(), e)?;
+          self.stack.push(StackEl::CoConv(e, e2));
+        }
+        ProofCmd::ConvCut => {
+          let (e1, e2) = self.pop_co_conv()?;
+          self.stack.push(StackEl::Conv(e1, e2));
+          self.stack.push(StackEl::CoConv(e1, e2));
+        }
+        ProofCmd::ConvSave => {
+          if let StackEl::Conv(e1, e2) = self.pop()? {
+            self.heap.push(StackEl::Conv(e1, e2))
+          } else {
+            return Err(self.err("bad stack slot"))
+          }
+        }
+        ProofCmd::Save => match *self.stack.last().ok_or_else(|| self.err("stack underflow"))? {
+          StackEl::CoConv(..) => return Err(self.err("Can't save proof obligation")),
+          s => self.heap.push(s),
+        }
+        ProofCmd::Sorry => {
+          ensure!(self, !def, "invalid opcode in def");
+          if let (None, Some(stmt)) = (self.sorry, self.stmt) {
+            self.sorry = Some((self.pos, stmt))
+          }
+          match self.pop()? {
+            StackEl::Expr(e) => self.stack.push(StackEl::Proof(e)),
+            StackEl::CoConv(..) => {}
+            _ => return Err(self.err("bad stack slot")),
+          }
+        }
+      }
+    }
+  }
+
+  /// Get the next sort or declaration in the `.mm0` file.
+  fn next_spec(&mut self) -> Result<Option<AtomId>> {
+    loop {
+      match self.spec.next() {
+        None => return Ok(None),
+        Some(&StmtTrace::Sort(a) | &StmtTrace::Decl(a)) => {
+          self.spec_name = Some(a);
+          return Ok(Some(a))
+        }
+        Some(StmtTrace::Global(_)) => {}
+        Some(StmtTrace::OutputString(_)) =>
+          return Err(self.err("output statements are not supported")),
+      }
+    }
+  }
+
+  /// Convert an expression in the `.mm0` file into the store.
+  fn spec_expr(&mut self,
+    heap: &[ExprNode], cache: &mut [Option<u32>], store: &[ExprNode],
+    dummies: &mut HashMap<AtomId, u32>, node: &ExprNode,
+  ) -> Result<u32> {
+    match *node {
+      ExprNode::Ref(i) => if let Some(e) = cache[i] { Ok(e) } else {
+        let e = self.spec_expr(heap, cache, store, dummies, &heap[i])?;
+        cache[i] = Some(e);
+        Ok(e)
+      },
+      ExprNode::Dummy(a, s) => if let Some(&e) = dummies.get(&a) { Ok(e) } else {
+        let var = (cache.len() + dummies.len()).try_into()
+          .map_err(|_| self.err("too many variables"))?;
+        let e = self.alloc(StoreExpr::Var { ty: TYPE_BOUND_MASK | u64::from(s.0) << 56, var })?;
+        dummies.insert(a, e);
+        Ok(e)
+      },
+      ExprNode::App(t, p) => {
+        let n = self.env.term(t).args.len();
+        let tid = *self.term_map.get(u32_as_usize(t.0)).ok_or_else(|| self.err("unknown term"))?;
+        let args = store[p..p + n].iter()
+          .map(|e| self.spec_expr(heap, cache, store, dummies, e))
+          .collect::<Result<Box<[_]>>>()?;
+        self.alloc(StoreExpr::App { ty: 0, tid, args })
+      }
+    }
+  }
+
+  /// Check that the binders of a statement match the `.mm0` file.
+  fn match_args(&self, args: &[(Option<AtomId>, Type)], targs: &[Arg]) -> Result<()> {
+    ensure!(self, args.len() == targs.len(), "incorrect number of arguments");
+    for (ty, &target) in encode_args(args).zip(targs) {
+      ensure!(self, ty == target.into_inner(), "variable type does not match");
+    }
+    Ok(())
+  }
+
+  /// Match a term or definition against the next statement in the `.mm0` file.
+  fn match_term(&mut self, t: TermRef<'a>) -> Result<()> {
+    let tid = match self.next_spec()?.and_then(|a| self.env.data()[a].decl()) {
+      Some(DeclKey::Term(tid)) => tid,
+      _ => return Err(self.err("expecting a term/def")),
+    };
+    let env = self.env;
+    let td = env.term(tid);
+    match &td.kind {
+      TermKind::Term => ensure!(self, !t.def(), "expecting a term"),
+      TermKind::Def(_) => ensure!(self, t.def(), "expecting a def"),
+    }
+    self.match_args(&td.args, t.args())?;
+    let ret = u64::from(td.ret.0 .0) << 56 | td.ret.1;
+    ensure!(self, ret == t.ret().into_inner(), "return type does not match");
+    self.term_map.push(t.tid);
+    if let TermKind::Def(Some(expr)) = &td.kind {
+      self.load_args(t.args())?;
+      let nargs = td.args.len();
+      let mut cache = vec![None; expr.heap.len()];
+      for (i, c) in cache.iter_mut().enumerate().take(nargs) {
+        *c = Some(i.try_into().expect("impossible"))
+      }
+      let e = self.spec_expr(&expr.heap, &mut cache, &expr.store, &mut HashMap::new(), expr.head())?;
+      self.load_uheap(nargs);
+      #[allow(clippy::cast_possible_truncation)]
+      self.run_unify(UnifyMode::Spec(nargs as u16), t.unify(), e)?;
+    }
+    Ok(())
+  }
+
+  /// Match an axiom or theorem against the next statement in the `.mm0` file.
+  fn match_thm(&mut self, t: ThmRef<'a>, axiom: bool) -> Result<()> {
+    let tid = match self.next_spec()?.and_then(|a| self.env.data()[a].decl()) {
+      Some(DeclKey::Thm(tid)) => tid,
+      _ => return Err(self.err(if axiom {"expecting an axiom"} else {"expecting a theorem"})),
+    };
+    let env = self.env;
+    let td = env.thm(tid);
+    match td.kind {
+      ThmKind::Axiom => ensure!(self, axiom, "expecting a theorem"),
+      ThmKind::Thm(_) => ensure!(self, !axiom, "expecting an axiom"),
+    }
+    self.match_args(&td.args, t.args())?;
+    self.load_args(t.args())?;
+    let nargs = td.args.len();
+    let mut cache = vec![None; td.heap.len()];
+    for (i, c) in cache.iter_mut().enumerate().take(nargs) {
+      *c = Some(i.try_into().expect("impossible"))
+    }
+    let mut dummies = HashMap::new();
+    for (_, h) in &*td.hyps {
+      let e = self.spec_expr(&td.heap, &mut cache, &td.store, &mut dummies, h)?;
+      self.hstack.push(e);
+    }
+    let e = self.spec_expr(&td.heap, &mut cache, &td.store, &mut dummies, &td.ret)?;
+    self.load_uheap(nargs);
+    #[allow(clippy::cast_possible_truncation)]
+    self.run_unify(UnifyMode::Spec(nargs as u16), t.unify(), e)
+  }
+
+  /// Check the proof of an axiom or theorem against its statement.
+  fn check_thm(&mut self, stmt_pos: usize, axiom: bool, t: ThmRef<'a>, pf: ProofIter<'_>) -> Result<()> {
+    self.pos = stmt_pos;
+    self.load_args(t.args())?;
+    self.run_proof(false, pf)?;
+    self.pos = stmt_pos;
+    ensure!(self, self.stack.len() == 1, "stack has != one element");
+    let val = if axiom { self.pop_expr()? } else { self.pop_proof()? };
+    ensure!(self, self.sort_mods(self.expr(val).sort()).contains(Modifiers::PROVABLE),
+      "conclusion should have provable sort");
+    self.load_uheap(t.args().len());
+    self.run_unify(UnifyMode::ThmEnd, t.unify(), val)
+  }
+
+  fn verify(&mut self) -> Result<()> {
+    let mut it = self.file.proof();
+    loop {
+      let stmt_pos = it.pos;
+      self.pos = stmt_pos;
+      self.stmt = None;
+      let (stmt, pf) = match it.next() {
+        None => break,
+        Some(r) => r?,
+      };
+      self.stmt = Some(stmt);
+      self.spec_name = None;
+      match stmt {
+        NumdStmtCmd::Sort { sort_id } => {
+          ensure!(self, pf.is_null(), "Next statement incorrect");
+          let sd = self.file.sort(sort_id).ok_or_else(|| self.err("Step sort overflow"))?;
+          let mods = Modifiers::try_from(sd).map_err(|_| self.err("bad sort modifiers"))?;
+          self.sorts.push(mods);
+          let sort = self.next_spec()?.and_then(|a| self.env.data()[a].sort());
+          let sort = sort.ok_or_else(|| self.err("expecting a sort"))?;
+          ensure!(self, self.env.sort(sort).mods == mods, "sort modifiers do not match");
+        }
+        NumdStmtCmd::TermDef { term_id, local } => {
+          let t = self.file.term(term_id).ok_or_else(|| self.err("Step term overflow"))?;
+          let sort = t.sort().0;
+          ensure!(self, usize::from(sort) < self.sorts.len(), "bad sort");
+          ensure!(self, !self.sort_mods(sort).contains(Modifiers::PURE), "term in pure sort");
+          self.load_args(t.args_and_ret())?;
+          let ret = t.ret().into_inner();
+          ensure!(self, ret >> 56 == u64::from(sort), "bad return type");
+          self.heap.pop();
+          if t.def() {
+            self.run_proof(true, pf)?;
+            self.pos = stmt_pos;
+            ensure!(self, self.stack.len() == 1, "stack has != one element");
+            let val = self.pop_expr()?;
+            let ty = self.expr(val).ty();
+            ensure!(self, sorts_compatible(ty, ret), "type mismatch");
+            ensure!(self, ty & TYPE_DEPS_MASK & !ret == 0, "type has unaccounted dependencies");
+            self.load_uheap(t.args().len());
+            self.run_unify(UnifyMode::Def, t.unify(), val)?;
+          } else {
+            ensure!(self, pf.is_null(), "Next statement incorrect");
+          }
+          if !local { self.match_term(t)? }
+          self.num_terms += 1;
+        }
+        NumdStmtCmd::Axiom { thm_id } | NumdStmtCmd::Thm { thm_id, .. } => {
+          let axiom = matches!(stmt, NumdStmtCmd::Axiom { .. });
+          let t = self.file.thm(thm_id).ok_or_else(|| self.err("Step theorem overflow"))?;
+          match &mut self.deferred {
+            Some(jobs) if !axiom => jobs.push(ThmJob {
+              pos: stmt_pos, stmt, thm: t, proof: pf,
+              num_sorts: self.sorts.len(), num_terms: self.num_terms, num_thms: self.num_thms,
+            }),
+            _ => self.check_thm(stmt_pos, axiom, t, pf)?,
+          }
+          if !stmt.is_local() { self.match_thm(t, axiom)? }
+          self.num_thms += 1;
+        }
+      }
+    }
+    self.stmt = None;
+    self.spec_name = None;
+    ensure!(self, self.sorts.len() == self.file.sorts.len(), "not all sorts proved");
+    ensure!(self, u32_as_usize(self.num_terms) == self.file.terms.len(), "not all terms proved");
+    ensure!(self, u32_as_usize(self.num_thms) == self.file.thms.len(), "not all theorems proved");
+    if self.next_spec()?.is_some() {
+      return Err(self.err("statement in .mm0 file is not matched by the proof"))
+    }
+    Ok(())
+  }
+
+  /// Report the first statement that used `sorry`, once everything else has been checked.
+  fn check_sorry(&mut self) -> Result<()> {
+    if let Some((pos, stmt)) = self.sorry {
+      self.pos = pos;
+      self.stmt = Some(stmt);
+      return Err(ParseError::SorryError)
+    }
+    Ok(())
+  }
+}
+
+/// Verify an MMB file against the environment produced by elaborating its `.mm0` specification.
+pub fn verify(file: &BasicMmbFile<'_>, env: &FrozenEnv) -> std::result::Result<(), VerifyError> {
+  let mut v = Verifier::new(file, env);
+  match v.verify().and_then(|()| v.check_sorry()) {
+    Ok(()) => Ok(()),
+    Err(err) => Err(v.verify_error(err)),
+  }
+}
+
+/// Verify an MMB file like [`verify`], but check the proofs of theorems on `jobs` threads.
+///
+/// The statements are checked first, in order, and then the theorem proofs are split
+/// between the threads. Unlike [`verify`], this does not stop at the first error, since
+/// the proofs are checked out of order; all errors are returned, in declaration order.
+pub fn verify_par(
+  file: &BasicMmbFile<'_>, env: &FrozenEnv, jobs: usize,
+) -> std::result::Result<(), Vec<VerifyError>> {
+  let mut v = Verifier::new(file, env);
+  v.deferred = Some(vec![]);
+  // If checking the statements fails, the theorems before the error are still checked.
+  let stmt_err = v.verify().err().map(|err| v.verify_error(err));
+  let thms = v.deferred.take().expect("deferred");
+  let next = AtomicUsize::new(0);
+  let results = thread::scope(|scope| {
+    let workers = (0..jobs.max(1)).map(|_| scope.spawn(|| {
+      let mut w = Verifier::new(file, env);
+      let mut errors = vec![];
+      loop {
+        let i = next.fetch_add(1, Ordering::Relaxed);
+        let job = match thms.get(i) { Some(job) => job, None => break };
+        w.sorts.clear();
+        w.sorts.extend_from_slice(&v.sorts[..job.num_sorts]);
+        w.num_terms = job.num_terms;
+        w.num_thms = job.num_thms;
+        w.stmt = Some(job.stmt);
+        if let Err(err) = w.check_thm(job.pos, false, job.thm, job.proof.clone()) {
+          errors.push((i, w.verify_error(err)))
+        }
+      }
+      (errors, w.sorry)
+    })).collect::<Vec<_>>();
+    workers.into_iter().map(|w| w.join().expect("verifier thread panicked")).collect::<Vec<_>>()
+  });
+  let mut errors = vec![];
+  for (errs, sorry) in results {
+    errors.extend(errs);
+    if let Some((pos, stmt)) = sorry {
+      if v.sorry.is_none_or(|(pos2, _)| pos < pos2) { v.sorry = Some((pos, stmt)) }
+    }
+  }
+  errors.sort_by_key(|&(i, _)| i);
+  let mut errors = errors.into_iter().map(|(_, e)| e).collect::<Vec<_>>();
+  errors.extend(stmt_err);
+  if errors.is_empty() {
+    if let Err(err) = v.check_sorry() { errors.push(v.verify_error(err)) }
+  }
+  if errors.is_empty() { Ok(()) } else { Err(errors) }
+}
+
+/// Verify an MMB file against its MM0 specification
+#[derive(clap::Args, Debug)]
+pub struct Args {
+  /// Sets the specification file (.mm0)
+  pub spec: String,
+  /// Sets the proof file (.mmb or .mmu)
+  pub proof: String,
+  /// Sets the number of threads for checking theorem proofs in an .mmb file
+  /// (0 to use all available cores)
+  #[clap(short, long, default_value_t = 1)]
+  pub jobs: usize,
+}
+
+impl Args {
+  /// Main entry point for `mm0-rs verify` subcommand.
+  ///
+  /// # Arguments
+  ///
+  /// `mm0-rs verify <in.mm0> <in.mmb|in.mmu>`, where:
+  ///
+  /// - `in.mm0` is the specification file, which is elaborated to find the statements
+  ///   that the proof file should contain.
+  /// - `in.mmb` or `in.mmu` is the proof file to check. An `.mmb` file is checked
+  ///   by the native verifier in this module, while an `.mmu` file is imported
+  ///   (which checks the proofs) and then matched against the specification by
+  ///   [`mmu::verify`](crate::mmu::verify).
+  /// - `-j N` or `--jobs N` checks the theorem proofs of an `.mmb` file on `N` threads
+  ///   using [`verify_par`] (or on all cores for `-j 0`). All errors are then reported,
+  ///   in declaration order.
+  pub fn main(self) -> io::Result<()> {
+    let path: FileRef = fs::canonicalize(self.spec)?.into();
+    let (_, env) = crate::compiler::elab_for_result(path)?;
+    let env = env.unwrap_or_else(|| std::process::exit(1));
+    if crate::compiler::has_errors() { std::process::exit(1) }
+    let proof: FileRef = fs::canonicalize(self.proof)?.into();
+    if proof.has_extension("mmu") {
+      let (text, penv) = crate::compiler::elab_for_result(proof.clone())?;
+      let penv = penv.unwrap_or_else(|| std::process::exit(1));
+      if crate::compiler::has_errors() { std::process::exit(1) }
+      if let Err(e) = crate::mmu::verify::verify(&env, &penv) {
+        crate::compiler::report(&proof, &text, &e);
+        std::process::exit(1)
+      }
+      println!("verified {} sorts, {} terms, {} theorems",
+        penv.sorts().len(), penv.terms().len(), penv.thms().len());
+      return Ok(())
+    }
+    let mmb = FileContents::new_bin_from_file(proof.path())?;
+    let file = BasicMmbFile::parse(&mmb).unwrap_or_else(|e| {
+      eprintln!("error: {}", e);
+      std::process::exit(1)
+    });
+    let jobs = match self.jobs {
+      0 => thread::available_parallelism().map_or(1, std::num::NonZeroUsize::get),
+      n => n,
+    };
+    let res = if jobs == 1 { verify(&file, &env).map_err(|e| vec![e]) } else {
+      verify_par(&file, &env, jobs)
+    };
+    if let Err(errors) = res {
+      for e in errors { eprintln!("error {}", e) }
+      std::process::exit(1)
+    }
+    println!("verified {} sorts, {} terms, {} theorems",
+      file.sorts.len(), file.terms.len(), file.thms.len());
+    Ok(())
+  }
+}
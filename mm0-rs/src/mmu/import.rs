@@ -220,9 +220,20 @@ impl Dedup<ExprHash> {
 }
 
 impl<'a> Importer<'a> {
-  fn run(&mut self) -> Result<()> {
-    self.ws();
-    while let Some(start) = self.open() {
+  /// Construct a new importer for the `.mmu` file `file` with contents `source`.
+  #[must_use] pub fn new(file: &'a FileRef, source: &'a [u8]) -> Self {
+    let mut p = Importer { file, source, idx: 0, env: Environment::new() };
+    p.ws();
+    p
+  }
+
+  /// The environment containing the statements imported so far.
+  pub fn env_mut(&mut self) -> &mut Environment { &mut self.env }
+
+  /// Import the next statement into the environment.
+  /// Returns `false` if the end of the file has been reached.
+  pub fn next_stmt(&mut self) -> Result<bool> {
+    if let Some(start) = self.open() {
       match self.ident_str() {
         Some(b"sort") => {
           let x = self.ident_err()?;
@@ -248,10 +259,16 @@ impl<'a> Importer<'a> {
         }
         _ => return Err(self.err("expecting command keyword".into()))
       }
+      return Ok(true)
     }
     if self.idx != self.source.len() {
       return Err(self.err("expected '(' or EOF".into()))
     }
+    Ok(false)
+  }
+
+  fn run(&mut self) -> Result<()> {
+    while self.next_stmt()? {}
     Ok(())
   }
 
@@ -492,6 +509,6 @@ impl<'a> Importer<'a> {
 
 /// Construct an [`Environment`] from an `mmu` file.
 pub fn elab(file: &FileRef, source: &[u8]) -> (Result<()>, Environment) {
-  let mut p = Importer { file, source, idx: 0, env: Environment::new() };
+  let mut p = Importer::new(file, source);
   (p.run(), p.env)
 }
@@ -0,0 +1,198 @@
+//! MMU verifier, which checks an `.mmu` proof file against its `.mm0` specification.
+//!
+//! This mirrors `mm0-hs verify MM0-file MMU-file`. The proofs themselves are checked
+//! when the `.mmu` file is imported (see [`mmu::import`](super::import)); this module
+//! checks that every statement in the `.mm0` file is matched, in order, by a statement
+//! in the `.mmu` file with the same name and the same content. Local definitions and
+//! theorems in the `.mmu` file do not appear in the specification and are skipped.
+
+use std::collections::HashMap;
+use crate::{AtomId, DeclKey, ExprNode, FrozenEnv, Modifiers, SortId, SortVec, Span,
+  StmtTrace, TermId, TermVec, TermKind, ThmKind, Type};
+use crate::elab::{ElabError, Result};
+
+/// An expression in one of the two environments, represented as a heap, a store,
+/// and a node.
+type ExprRef<'a> = (&'a [ExprNode], &'a [ExprNode], &'a ExprNode);
+
+struct Matcher<'a> {
+  spec: &'a FrozenEnv,
+  env: &'a FrozenEnv,
+  /// Maps sort IDs in the `.mm0` file to sort IDs in the `.mmu` file
+  sorts: SortVec<SortId>,
+  /// Maps term IDs in the `.mm0` file to term IDs in the `.mmu` file
+  terms: TermVec<TermId>,
+}
+
+impl Matcher<'_> {
+  fn mismatch(&self, pos: Span, a: AtomId, msg: &str) -> ElabError {
+    let name = self.env.data()[a].name();
+    let info = match self.spec.get_atom(name).and_then(|a| self.spec.data()[a].decl()) {
+      Some(DeclKey::Term(t)) => vec![(self.spec.term(t).span.clone(), "declared here".into())],
+      Some(DeclKey::Thm(t)) => vec![(self.spec.thm(t).span.clone(), "declared here".into())],
+      None => vec![],
+    };
+    ElabError::with_info(pos, format!("{}: {}", name, msg).into(), info)
+  }
+
+  fn args_eq(&self, args1: &[(Option<AtomId>, Type)], args2: &[(Option<AtomId>, Type)]) -> bool {
+    args1.len() == args2.len() && args1.iter().zip(args2).all(|(&(_, ty1), &(_, ty2))|
+      match (ty1, ty2) {
+        (Type::Bound(s1), Type::Bound(s2)) => self.sorts[s1] == s2,
+        (Type::Reg(s1, d1), Type::Reg(s2, d2)) => self.sorts[s1] == s2 && d1 == d2,
+        _ => false,
+      })
+  }
+
+  /// Structural equality of expressions in the two environments, up to renaming of dummies.
+  fn expr_eq(&self, nargs: usize,
+    dummies: &mut (HashMap<AtomId, AtomId>, HashMap<AtomId, AtomId>),
+    (heap1, store1, e1): ExprRef<'_>, (heap2, store2, e2): ExprRef<'_>,
+  ) -> bool {
+    match (e1, e2) {
+      (&ExprNode::Ref(i), &ExprNode::Ref(j)) if i < nargs && j < nargs => i == j,
+      (&ExprNode::Ref(i), _) if i >= nargs =>
+        self.expr_eq(nargs, dummies, (heap1, store1, &heap1[i]), (heap2, store2, e2)),
+      (_, &ExprNode::Ref(j)) if j >= nargs =>
+        self.expr_eq(nargs, dummies, (heap1, store1, e1), (heap2, store2, &heap2[j])),
+      (&ExprNode::Dummy(a1, s1), &ExprNode::Dummy(a2, s2)) =>
+        self.sorts[s1] == s2 &&
+        *dummies.0.entry(a1).or_insert(a2) == a2 &&
+        *dummies.1.entry(a2).or_insert(a1) == a1,
+      (&ExprNode::App(t1, p1), &ExprNode::App(t2, p2)) => {
+        let n = self.spec.term(t1).args.len();
+        self.terms.get(t1) == Some(&t2) &&
+        store1[p1..p1 + n].iter().zip(&store2[p2..p2 + n]).all(|(a1, a2)|
+          self.expr_eq(nargs, dummies, (heap1, store1, a1), (heap2, store2, a2)))
+      }
+      _ => false,
+    }
+  }
+
+  /// Match a statement in the `.mmu` file against a statement in the `.mm0` file.
+  fn match_stmt(&mut self, (spec, spec_sort): (AtomId, bool), (a, sort): (AtomId, bool)) -> Result<()> {
+    let (sd, ad) = (&self.spec.data()[spec], &self.env.data()[a]);
+    let (s1, s2, d1, d2) = match (spec_sort, sort) {
+      (true, true) => (sd.sort(), ad.sort(), None, None),
+      (false, false) => (None, None, sd.decl(), ad.decl()),
+      _ => (None, None, None, None),
+    };
+    match (s1, s2, d1, d2) {
+      (Some(s1), Some(s2), _, _) => {
+        let sort = self.env.sort(s2);
+        if sd.name() != ad.name() {
+          return Err(ElabError::new_e(sort.full,
+            format!("expected sort '{}', found '{}'", sd.name(), ad.name())))
+        }
+        if self.spec.sort(s1).mods != sort.mods {
+          return Err(self.mismatch(sort.full, a, "sort modifiers do not match"))
+        }
+        self.sorts.push(s2);
+      }
+      (_, _, Some(DeclKey::Term(t1)), Some(DeclKey::Term(t2))) => {
+        let (td1, td2) = (self.spec.term(t1), self.env.term(t2));
+        if sd.name() != ad.name() {
+          return Err(ElabError::new_e(td2.full,
+            format!("expected term '{}', found '{}'", sd.name(), ad.name())))
+        }
+        let same_kind = matches!((&td1.kind, &td2.kind),
+          (TermKind::Term, TermKind::Term) | (TermKind::Def(_), TermKind::Def(_)));
+        if !same_kind {
+          return Err(self.mismatch(td2.full, a, "term/def mismatch"))
+        }
+        if !self.args_eq(&td1.args, &td2.args) ||
+          self.sorts[td1.ret.0] != td2.ret.0 || td1.ret.1 != td2.ret.1 {
+          return Err(self.mismatch(td2.full, a, "type does not match the specification"))
+        }
+        if let (TermKind::Def(Some(e1)), TermKind::Def(Some(e2))) = (&td1.kind, &td2.kind) {
+          if !self.expr_eq(td1.args.len(), &mut Default::default(),
+            (&e1.heap, &e1.store, e1.head()), (&e2.heap, &e2.store, e2.head())) {
+            return Err(self.mismatch(td2.full, a, "definition does not match the specification"))
+          }
+        }
+        self.terms.push(t2);
+      }
+      (_, _, Some(DeclKey::Thm(t1)), Some(DeclKey::Thm(t2))) => {
+        let (td1, td2) = (self.spec.thm(t1), self.env.thm(t2));
+        if sd.name() != ad.name() {
+          return Err(ElabError::new_e(td2.full,
+            format!("expected theorem '{}', found '{}'", sd.name(), ad.name())))
+        }
+        match (&td1.kind, &td2.kind) {
+          (ThmKind::Axiom, ThmKind::Axiom) | (ThmKind::Thm(_), ThmKind::Thm(_)) => {}
+          (ThmKind::Axiom, _) => return Err(self.mismatch(td2.full, a, "expected an axiom")),
+          (ThmKind::Thm(_), _) => return Err(self.mismatch(td2.full, a, "expected a theorem")),
+        }
+        let nargs = td1.args.len();
+        let mut dummies = Default::default();
+        let mut eq = |e1, e2| self.expr_eq(nargs, &mut dummies,
+          (&td1.heap, &td1.store, e1), (&td2.heap, &td2.store, e2));
+        if !(self.args_eq(&td1.args, &td2.args) &&
+          td1.hyps.len() == td2.hyps.len() &&
+          td1.hyps.iter().zip(&*td2.hyps).all(|((_, h1), (_, h2))| eq(h1, h2)) &&
+          eq(&td1.ret, &td2.ret)) {
+          return Err(self.mismatch(td2.full, a, "statement does not match the specification"))
+        }
+      }
+      _ => return Err(ElabError::new_e(self.full(a, sort),
+        format!("expected '{}', found '{}'", sd.name(), ad.name())))
+    }
+    Ok(())
+  }
+
+  /// The span of the declaration of `a` in the `.mmu` file.
+  fn full(&self, a: AtomId, sort: bool) -> Span {
+    let ad = &self.env.data()[a];
+    match (ad.sort(), ad.decl()) {
+      (Some(s), _) if sort => self.env.sort(s).full,
+      (_, Some(DeclKey::Term(t))) => self.env.term(t).full,
+      (_, Some(DeclKey::Thm(t))) => self.env.thm(t).full,
+      _ => Span::default(),
+    }
+  }
+
+  /// Returns true if this declaration does not appear in the specification.
+  fn is_local(&self, a: AtomId) -> bool {
+    match self.env.data()[a].decl() {
+      Some(DeclKey::Term(t)) => self.env.term(t).vis.contains(Modifiers::LOCAL),
+      Some(DeclKey::Thm(t)) => {
+        let td = self.env.thm(t);
+        matches!(td.kind, ThmKind::Thm(_)) && !td.vis.contains(Modifiers::PUB)
+      }
+      None => false,
+    }
+  }
+}
+
+/// Get the next sort or declaration statement, along with a flag which is true for sorts.
+/// (An atom can be both a sort and a term, so the flag is needed to tell them apart.)
+fn next_stmt(it: &mut std::slice::Iter<'_, StmtTrace>) -> Option<(AtomId, bool)> {
+  it.find_map(|s| match *s {
+    StmtTrace::Sort(a) => Some((a, true)),
+    StmtTrace::Decl(a) => Some((a, false)),
+    StmtTrace::Global(_) | StmtTrace::OutputString(_) => None,
+  })
+}
+
+/// Check that the environment `env` produced by importing an `.mmu` file matches
+/// the environment `spec` produced by elaborating its `.mm0` specification.
+/// The returned error is located in the `.mmu` file.
+pub fn verify(spec: &FrozenEnv, env: &FrozenEnv) -> Result<()> {
+  let mut m = Matcher { spec, env, sorts: SortVec::default(), terms: TermVec::default() };
+  let (mut spec_it, mut it) = (spec.stmts().iter(), env.stmts().iter());
+  let mut end = Span::default();
+  while let Some((a, sort)) = next_stmt(&mut it) {
+    end = m.full(a, sort);
+    if !sort && m.is_local(a) { continue }
+    match next_stmt(&mut spec_it) {
+      Some(spec_stmt) => m.match_stmt(spec_stmt, (a, sort))?,
+      None => return Err(ElabError::new_e(end,
+        format!("'{}' is not in the specification", env.data()[a].name()))),
+    }
+  }
+  if let Some((sa, _)) = next_stmt(&mut spec_it) {
+    return Err(ElabError::new_e(Span::from(end.end..end.end),
+      format!("'{}' is missing from the proof file", spec.data()[sa].name())))
+  }
+  Ok(())
+}
@@ -0,0 +1,992 @@
+//! [OpenTheory] exporter, which translates an MM0 formalization of HOL into an article.
+//!
+//! This implements `mm0-rs export-ot`. The environment has to use the HOL signature of
+//! `examples/hol.mm1`:
+//!
+//! - The sorts `wff`, `type` and `term`, where a `wff` is built from `G |- P` (`im`) and
+//!   `P; Q` (`an`), the typing judgement `x: A` (`ty`), and the coercion `thm` of a term of
+//!   type `bool`. The terms are built from `f @ x` (`app`) and `\ x: A. t` (`lam`).
+//! - The axioms `id`, `syl`, `ian`, `anl` and `anr` of the sequent calculus, and the axioms
+//!   `refl`, `aeq`, `leq`, `beta`, `eqmp`, `ded`, `cbvv` and `inst` of the HOL kernel, which
+//!   are translated to the inference rules of articles.
+//!
+//! Everything else is described by a mapping file, which has one declaration per line (and
+//! comments starting with `--`):
+//!
+//! - `type bool bool` says that the MM0 term `bool` of sort `type` is the HOL type operator
+//!   `bool`. The arguments of the MM0 term are the arguments of the type operator.
+//! - `const eq A = : A -> A -> bool` says that the MM0 term `eq` of sort `term` is the HOL
+//!   constant `=`, with the given type. The arguments of the MM0 term can be renamed by
+//!   listing them before the HOL name (`A` here, because `eq` has an unnamed argument). The
+//!   arguments of sort `type` can be used in the type, and the arguments of sort `term` are
+//!   applied to the constant.
+//!
+//! A statement `G |- P` is read as the HOL sequents whose hypotheses are the `thm`
+//! judgements in `G` and whose conclusions are the `thm` judgements in `P`. Typing
+//! judgements are dropped, because HOL terms are typed, and so are `wff` variables. Each
+//! proof is expanded all the way down to the inference rules: theorems are unfolded where
+//! they are used, and the other axioms are assumptions of the article, stated with generic
+//! variables and instantiated with `subst`. Definitions are unfolded, unless they are in
+//! the mapping, in which case they are constants, and unfolding one uses the assumption
+//! `⊢ c = body`. Every step is checked by a small HOL kernel.
+//!
+//! Each `pub` theorem whose hypotheses are all typing judgements is a theorem of the
+//! article. The types of its variables are inferred from the statement, and a variable
+//! whose type is not determined by the statement gets the type `bool`.
+//!
+//! [OpenTheory]: https://www.gilith.com/opentheory/article.html
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::rc::Rc;
+use crate::{AtomId, DeclKey, ErrorLevel, ExprNode, FileRef, FrozenAtomData, FrozenEnv, Modifiers,
+  ProofNode, SortId, StmtTrace, TermId, TermKind, ThmId, ThmKind};
+use super::kernel::{self, Article, Kernel, Name, Result, Th, TmId, TyId};
+
+/// The axioms of `examples/hol.mm1` which are translated to inference rules.
+#[derive(Clone, Copy, Debug)]
+enum Rule { Id, Syl, Ian, Anl, Anr, Refl, Aeq, Leq, Beta, Eqmp, Ded, Cbvv, Inst }
+
+/// The names of the [`Rule`] axioms, and their number of arguments.
+const RULES: [(&str, Rule, usize); 13] = [
+  ("id", Rule::Id, 1), ("syl", Rule::Syl, 3), ("ian", Rule::Ian, 3),
+  ("anl", Rule::Anl, 2), ("anr", Rule::Anr, 2), ("refl", Rule::Refl, 3),
+  ("aeq", Rule::Aeq, 7), ("leq", Rule::Leq, 6), ("beta", Rule::Beta, 5),
+  ("eqmp", Rule::Eqmp, 3), ("ded", Rule::Ded, 3), ("cbvv", Rule::Cbvv, 6),
+  ("inst", Rule::Inst, 5),
+];
+
+/// The sorts and terms of the HOL signature, and the axioms which are inference rules.
+#[derive(Debug)]
+struct Core {
+  ty: SortId,
+  tm: SortId,
+  im: TermId,
+  an: TermId,
+  typing: TermId,
+  thm: TermId,
+  app: TermId,
+  lam: TermId,
+  rules: HashMap<ThmId, Rule>,
+}
+
+impl Core {
+  fn new(env: &FrozenEnv) -> Result<Self> {
+    let missing = |s: &str| format!(
+      "expected a HOL signature like `examples/hol.mm1`, but `{}` is missing", s);
+    let get = |s: &str| env.get_atom(s.as_bytes()).map(|a| &env.data()[a]);
+    let sort = |s| get(s).and_then(FrozenAtomData::sort).ok_or_else(|| missing(s));
+    let term = |s| match get(s).and_then(FrozenAtomData::decl) {
+      Some(DeclKey::Term(t)) => Ok(t),
+      _ => Err(missing(s)),
+    };
+    let mut rules = HashMap::new();
+    for (s, rule, nargs) in RULES {
+      match get(s).and_then(FrozenAtomData::decl) {
+        Some(DeclKey::Thm(t)) if env.thm(t).args.len() == nargs => { rules.insert(t, rule); }
+        Some(DeclKey::Thm(_)) => return Err(format!("axiom `{}` has the wrong arguments", s)),
+        _ => return Err(missing(s)),
+      }
+    }
+    sort("wff")?;
+    Ok(Self {
+      ty: sort("type")?, tm: sort("term")?,
+      im: term("im")?, an: term("an")?, typing: term("ty")?, thm: term("thm")?,
+      app: term("app")?, lam: term("lam")?, rules,
+    })
+  }
+}
+
+/// A HOL type in the mapping file, over the arguments of an MM0 term.
+#[derive(Debug)]
+enum Scheme {
+  /// The argument with this index, which has sort `type`
+  Arg(usize),
+  /// A type operator applied to a list of types
+  Op(Name, Box<[Scheme]>),
+}
+
+impl Scheme {
+  fn parse<'b>(toks: &mut std::iter::Peekable<impl Iterator<Item = &'b str>>, args: &[&str]
+  ) -> Result<Self> {
+    let atom = |toks: &mut std::iter::Peekable<_>| match toks.next() {
+      Some("(") => {
+        let ty = Self::parse(toks, args)?;
+        if toks.next() == Some(")") { Ok((ty, false)) } else { Err("expected `)`".into()) }
+      }
+      Some(s) if s != ")" && s != "->" => Ok(match args.iter().position(|&x| x == s) {
+        Some(i) => (Self::Arg(i), false),
+        None => (Self::Op(s.into(), Box::new([])), true),
+      }),
+      _ => Err("expected a type".to_owned()),
+    };
+    let (mut ty, op) = atom(toks)?;
+    let mut tys = vec![];
+    while !matches!(toks.peek(), None | Some(&(")" | "->"))) { tys.push(atom(toks)?.0) }
+    if !tys.is_empty() {
+      match ty {
+        Self::Op(n, _) if op => ty = Self::Op(n, tys.into()),
+        _ => return Err("expected a type operator".into()),
+      }
+    }
+    if toks.peek() == Some(&"->") {
+      toks.next();
+      let ret = Self::parse(toks, args)?;
+      ty = Self::Op("->".into(), Box::new([ty, ret]))
+    }
+    Ok(ty)
+  }
+
+  /// The type for the given arguments.
+  fn inst(&self, k: &mut Kernel, args: &[Val]) -> Result<TyId> {
+    Ok(match self {
+      Self::Arg(i) => args[*i].ty()?,
+      Self::Op(n, tys) => {
+        let tys = tys.iter().map(|ty| ty.inst(k, args)).collect::<Result<_>>()?;
+        k.ty(kernel::Type::Op(n.clone(), tys))
+      }
+    })
+  }
+
+  /// The type for the given arguments, during type inference.
+  fn infer(&self, args: &[Option<ITy>]) -> Result<ITy> {
+    Ok(match self {
+      Self::Arg(i) => args[*i].clone().ok_or("expected a type")?,
+      Self::Op(n, tys) =>
+        ITy::Op(n.clone(), tys.iter().map(|ty| ty.infer(args)).collect::<Result<_>>()?),
+    })
+  }
+}
+
+/// The mapping from MM0 terms to HOL type operators and constants.
+#[derive(Debug, Default)]
+struct Mapping {
+  /// The HOL type operator of an MM0 term of sort `type`
+  types: HashMap<TermId, Name>,
+  /// The HOL constant of an MM0 term of sort `term`, and its type
+  consts: HashMap<TermId, (Name, Scheme)>,
+}
+
+impl Mapping {
+  fn parse(env: &FrozenEnv, core: &Core, src: &str) -> Result<Self> {
+    let mut map = Self::default();
+    for (n, line) in src.lines().enumerate() {
+      let line = line.find("--").map_or(line, |i| &line[..i]).replace('(', " ( ");
+      let line = line.replace(')', " ) ");
+      let mut toks = line.split_whitespace().peekable();
+      let mut decl = || -> Result<()> {
+        let kw = if let Some(kw) = toks.next() { kw } else { return Ok(()) };
+        let x = toks.next().ok_or("expected a term")?;
+        let t = match env.get_atom(x.as_bytes()).and_then(|a| env.data()[a].decl()) {
+          Some(DeclKey::Term(t)) => t,
+          _ => return Err(format!("unknown term `{}`", x)),
+        };
+        let td = env.term(t);
+        let arg_sorts = || td.args.iter().map(|(_, ty)| (ty.sort(), ty.bound()));
+        match kw {
+          "type" => {
+            if td.ret.0 != core.ty || arg_sorts().any(|s| s != (core.ty, false)) {
+              return Err(format!("`{}` is not a type operator", x))
+            }
+            map.types.insert(t, toks.next().ok_or("expected a name")?.into());
+          }
+          "const" => {
+            if td.ret.0 != core.tm ||
+              arg_sorts().any(|s| s.1 || (s.0 != core.ty && s.0 != core.tm)) {
+              return Err(format!("`{}` is not a constant", x))
+            }
+            let mut names = vec![];
+            loop {
+              match toks.next() {
+                Some(":") => break,
+                Some(s) => names.push(s),
+                None => return Err("expected `:`".into()),
+              }
+            }
+            let name = names.pop().ok_or("expected a name")?;
+            if names.len() > td.args.len() {
+              return Err(format!("`{}` has only {} arguments", x, td.args.len()))
+            }
+            let args = td.args.iter().enumerate().map(|(i, (a, _))| match names.get(i) {
+              Some(&s) => s,
+              None => a.map_or("_", |a| env.data()[a].name().as_str()),
+            }).collect::<Vec<_>>();
+            let ty = Scheme::parse(&mut toks, &args)?;
+            map.consts.insert(t, (name.into(), ty));
+          }
+          _ => return Err(format!("unknown declaration `{}`", kw)),
+        }
+        if toks.next().is_some() { return Err("expected end of line".into()) }
+        Ok(())
+      };
+      decl().map_err(|e| format!("line {}: {}", n + 1, e))?;
+    }
+    Ok(map)
+  }
+}
+
+/// An equation `lhs = rhs` between terms, and its proof, or `None` if the sides are the same.
+type Eqn = (TmId, TmId, Option<Th>);
+
+/// The judgements of the context and of the conclusion of a statement `G |- P`.
+type Stmt = (Rc<[Eqn]>, Rc<[Eqn]>);
+
+/// The translation of an expression, or of a conversion between two expressions. For an
+/// expression, the two sides of each equation are the same.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum Val {
+  /// An expression of sort `type`. There are no conversions between types.
+  Type(TyId),
+  /// An expression of sort `term`
+  Term(Eqn),
+  /// An expression of sort `wff`, given by the terms of its `thm` judgements
+  Wff(Rc<[Eqn]>),
+  /// A statement `G |- P`
+  Stmt(Rc<[Eqn]>, Rc<[Eqn]>),
+}
+
+impl Val {
+  fn expr(tm: TmId) -> Self { Self::Term((tm, tm, None)) }
+
+  fn ty(&self) -> Result<TyId> {
+    if let Self::Type(ty) = *self { Ok(ty) } else { Err("expected a type".into()) }
+  }
+
+  fn eqn(&self) -> Result<Eqn> {
+    if let Self::Term(e) = *self { Ok(e) } else { Err("expected a term".into()) }
+  }
+
+  fn tm(&self) -> Result<TmId> { Ok(self.eqn()?.0) }
+
+  fn wff(&self) -> Result<Rc<[Eqn]>> {
+    if let Self::Wff(es) = self { Ok(es.clone()) } else { Err("expected a wff".into()) }
+  }
+
+  fn stmt(&self) -> Result<Stmt> {
+    match self {
+      Self::Wff(es) => Ok((Rc::new([]), es.clone())),
+      Self::Stmt(ctx, es) => Ok((ctx.clone(), es.clone())),
+      _ => Err("expected a statement".into()),
+    }
+  }
+}
+
+/// The expression node of an [`ExprNode`] or [`ProofNode`].
+enum View<'a, N> {
+  /// The argument with this index
+  Var(usize),
+  /// The heap element with this index
+  Heap(usize),
+  /// A dummy variable
+  Dummy(AtomId),
+  /// A term application
+  App(TermId, &'a [N]),
+}
+
+trait Node: Sized {
+  fn view<'a>(&'a self, env: &FrozenEnv, nargs: usize, store: &'a [Self]) -> View<'a, Self>;
+}
+
+impl Node for ExprNode {
+  fn view<'a>(&'a self, env: &FrozenEnv, nargs: usize, store: &'a [Self]) -> View<'a, Self> {
+    match *self {
+      ExprNode::Ref(i) if i < nargs => View::Var(i),
+      ExprNode::Ref(i) => View::Heap(i),
+      ExprNode::Dummy(a, _) => View::Dummy(a),
+      ExprNode::App(t, p) => View::App(t, env.term(t).unpack_app(&store[p..])),
+    }
+  }
+}
+
+impl Node for ProofNode {
+  fn view<'a>(&'a self, env: &FrozenEnv, nargs: usize, store: &'a [Self]) -> View<'a, Self> {
+    match *self {
+      ProofNode::Ref(i) if i < nargs => View::Var(i),
+      ProofNode::Ref(i) => View::Heap(i),
+      ProofNode::Dummy(a, _) => View::Dummy(a),
+      ProofNode::Term(t, p) => View::App(t, env.term(t).unpack_term(&store[p..])),
+      _ => panic!("expected an expression"),
+    }
+  }
+}
+
+/// A type during type inference, which can contain metavariables.
+#[derive(Clone, Debug)]
+enum ITy {
+  /// A metavariable
+  Meta(usize),
+  /// A known type
+  Ty(TyId),
+  /// A type operator applied to a list of types
+  Op(Name, Box<[ITy]>),
+}
+
+/// The assignment of the metavariables during type inference.
+#[derive(Debug, Default)]
+struct Infer(Vec<Option<ITy>>);
+
+impl Infer {
+  fn meta(&mut self) -> ITy {
+    self.0.push(None);
+    ITy::Meta(self.0.len() - 1)
+  }
+
+  fn whnf(&self, mut ty: ITy) -> ITy {
+    while let ITy::Meta(m) = ty {
+      match &self.0[m] { Some(t) => ty = t.clone(), None => break }
+    }
+    ty
+  }
+
+  fn occurs(&self, m: usize, ty: &ITy) -> bool {
+    match self.whnf(ty.clone()) {
+      ITy::Meta(n) => m == n,
+      ITy::Ty(_) => false,
+      ITy::Op(_, tys) => tys.iter().any(|ty| self.occurs(m, ty)),
+    }
+  }
+
+  fn unify(&mut self, k: &Kernel, lhs: &ITy, rhs: &ITy) -> Result<()> {
+    let ok = match (self.whnf(lhs.clone()), self.whnf(rhs.clone())) {
+      (ITy::Meta(m1), ITy::Meta(m2)) if m1 == m2 => true,
+      (ITy::Meta(m), ty) | (ty, ITy::Meta(m)) => {
+        if self.occurs(m, &ty) { false } else { self.0[m] = Some(ty); true }
+      }
+      (ITy::Ty(ty1), ITy::Ty(ty2)) => ty1 == ty2,
+      (ITy::Ty(ty), ITy::Op(op, tys)) | (ITy::Op(op, tys), ITy::Ty(ty)) => match k.get_ty(ty) {
+        kernel::Type::Op(op2, args) if op == *op2 && tys.len() == args.len() => {
+          let args = args.clone();
+          for (ty, &arg) in tys.iter().zip(&*args) { self.unify(k, &ITy::Ty(arg), ty)? }
+          true
+        }
+        _ => false,
+      },
+      (ITy::Op(op1, tys1), ITy::Op(op2, tys2)) => {
+        if op1 != op2 || tys1.len() != tys2.len() { false } else {
+          for (ty1, ty2) in tys1.iter().zip(&*tys2) { self.unify(k, ty1, ty2)? }
+          true
+        }
+      }
+    };
+    if ok { Ok(()) } else { Err("type mismatch".into()) }
+  }
+
+  /// The inferred type, where undetermined types are `bool`.
+  fn resolve(&self, k: &mut Kernel, ty: &ITy) -> TyId {
+    match self.whnf(ty.clone()) {
+      ITy::Meta(_) => k.bool(),
+      ITy::Ty(ty) => ty,
+      ITy::Op(n, tys) => {
+        let tys = tys.iter().map(|ty| self.resolve(k, ty)).collect();
+        k.ty(kernel::Type::Op(n, tys))
+      }
+    }
+  }
+}
+
+/// The local context for type inference in a definition, statement or proof.
+struct IFrame<'a, N> {
+  nargs: usize,
+  heap: &'a [N],
+  store: &'a [N],
+  /// The value of each argument of sort `type`, and the type of each argument of sort `term`
+  args: Vec<Option<ITy>>,
+  /// The type of each dummy variable
+  dummies: HashMap<AtomId, ITy>,
+  /// The inferred nodes
+  memo: HashMap<*const N, Option<ITy>>,
+}
+
+impl<'a, N> IFrame<'a, N> {
+  fn new(nargs: usize, heap: &'a [N], store: &'a [N], args: Vec<Option<ITy>>) -> Self {
+    Self { nargs, heap, store, args, dummies: HashMap::new(), memo: HashMap::new() }
+  }
+}
+
+/// The local context for the translation of a definition, statement or proof.
+struct Frame<'a, N> {
+  nargs: usize,
+  heap: &'a [N],
+  store: &'a [N],
+  /// The values of the arguments
+  args: Vec<Val>,
+  /// The variables for the dummy variables
+  dummies: HashMap<AtomId, TmId>,
+  /// The proofs of the hypotheses, with one theorem per `thm` judgement
+  hyps: Vec<Rc<[Th]>>,
+  /// The translated expressions and conversions on the heap
+  vals: HashMap<usize, Val>,
+  /// The translated proofs on the heap
+  proofs: HashMap<usize, Rc<[Th]>>,
+}
+
+impl<'a, N> Frame<'a, N> {
+  fn new(nargs: usize, heap: &'a [N], store: &'a [N], args: Vec<Val>) -> Self {
+    Self { nargs, heap, store, args, dummies: HashMap::new(), hyps: vec![],
+      vals: HashMap::new(), proofs: HashMap::new() }
+  }
+}
+
+/// A list of theorems over generic variables, which are instantiated with `subst`.
+#[derive(Debug)]
+struct Generic {
+  /// The type variables and variables for the arguments
+  args: Vec<Val>,
+  thms: Vec<Th>,
+}
+
+/// An application of a theorem, to arguments and to the proofs of the hypotheses.
+type ThmApp = (ThmId, Vec<Val>, Vec<Rc<[Th]>>);
+
+/// The state of the translation.
+struct Exporter<'a> {
+  env: &'a FrozenEnv,
+  core: Core,
+  map: Mapping,
+  k: Kernel,
+  /// The assumptions for the axioms which are not inference rules
+  axioms: HashMap<ThmId, Rc<Generic>>,
+  /// The assumptions `⊢ c args = body` for the definitions in the mapping
+  defs: HashMap<TermId, Rc<Generic>>,
+  /// The translations of theorem applications, by arguments and hypotheses
+  memo: HashMap<ThmApp, Rc<[Th]>>,
+  /// A counter for the names of dummy variables
+  fresh: usize,
+}
+
+impl<'a> Exporter<'a> {
+  fn name(&self, a: AtomId) -> &'a str { self.env.data()[a].name().as_str() }
+
+  fn fresh_var(&mut self, a: AtomId, ty: TyId) -> TmId {
+    self.fresh += 1;
+    let name = format!("{}'{}", self.name(a), self.fresh);
+    self.k.var(name.into(), ty)
+  }
+
+  /// Infer the type of a `term` expression, or the value of a `type` expression.
+  fn infer<N: Node>(&mut self, inf: &mut Infer, f: &mut IFrame<'_, N>, e: &N
+  ) -> Result<Option<ITy>> {
+    let key: *const N = e;
+    if let Some(ty) = f.memo.get(&key) { return Ok(ty.clone()) }
+    let res = match e.view(self.env, f.nargs, f.store) {
+      View::Var(i) => f.args[i].clone(),
+      View::Heap(i) => { let heap = f.heap; self.infer(inf, f, &heap[i])? }
+      View::Dummy(a) => Some(f.dummies.entry(a).or_insert_with(|| inf.meta()).clone()),
+      View::App(t, args) => {
+        let mut tys = vec![];
+        for e in args { tys.push(self.infer(inf, f, e)?) }
+        self.infer_app(inf, t, tys)?
+      }
+    };
+    f.memo.insert(key, res.clone());
+    Ok(res)
+  }
+
+  fn infer_app(&mut self, inf: &mut Infer, t: TermId, tys: Vec<Option<ITy>>
+  ) -> Result<Option<ITy>> {
+    let ty = |i: usize| tys[i].clone().ok_or_else(|| "expected a term or type".to_owned());
+    let (env, core) = (self.env, &self.core);
+    Ok(if t == core.im || t == core.an { None } else if t == core.typing {
+      inf.unify(&self.k, &ty(0)?, &ty(1)?)?;
+      None
+    } else if t == core.thm {
+      let b = self.k.bool();
+      inf.unify(&self.k, &ty(0)?, &ITy::Ty(b))?;
+      None
+    } else if t == core.app {
+      let ret = inf.meta();
+      inf.unify(&self.k, &ty(0)?, &ITy::Op("->".into(), Box::new([ty(1)?, ret.clone()])))?;
+      Some(ret)
+    } else if t == core.lam {
+      inf.unify(&self.k, &ty(0)?, &ty(1)?)?;
+      Some(ITy::Op("->".into(), Box::new([ty(0)?, ty(2)?])))
+    } else if let Some(n) = self.map.types.get(&t) {
+      Some(ITy::Op(n.clone(), (0..tys.len()).map(ty).collect::<Result<_>>()?))
+    } else if let Some((_, sch)) = self.map.consts.get(&t) {
+      let mut res = sch.infer(&tys)?;
+      for (i, (_, arg)) in env.term(t).args.iter().enumerate() {
+        if arg.sort() == core.tm {
+          let ret = inf.meta();
+          inf.unify(&self.k, &res, &ITy::Op("->".into(), Box::new([ty(i)?, ret.clone()])))?;
+          res = ret
+        }
+      }
+      Some(res)
+    } else if let TermKind::Def(Some(e)) = &env.term(t).kind {
+      let mut f = IFrame::new(tys.len(), &e.heap, &e.store, tys);
+      self.infer(inf, &mut f, e.head())?
+    } else {
+      return Err(format!("`{}` is not in the mapping", self.name(self.env.term(t).atom)))
+    })
+  }
+
+  /// The values of the arguments of a theorem or definition, as type variables and
+  /// variables, where the types of the variables are inferred from the expressions `es`,
+  /// and the variables for the dummy variables in `es`.
+  fn generic_args<'b>(&mut self, args: &[(Option<AtomId>, crate::Type)],
+    heap: &'b [ExprNode], store: &'b [ExprNode], es: impl IntoIterator<Item = &'b ExprNode>
+  ) -> Result<(Vec<Val>, HashMap<AtomId, TmId>)> {
+    let mut inf = Infer::default();
+    let env = self.env;
+    let name = |a: Option<AtomId>| a.map_or("_", |a| env.data()[a].name().as_str());
+    let tys = args.iter().map(|&(a, ty)| match ty.sort() {
+      s if s == self.core.ty => Some(ITy::Ty(self.k.ty(kernel::Type::Var(name(a).into())))),
+      s if s == self.core.tm => Some(inf.meta()),
+      _ => None,
+    }).collect();
+    let mut f = IFrame::new(args.len(), heap, store, tys);
+    for e in es { self.infer(&mut inf, &mut f, e)?; }
+    let vals = args.iter().zip(f.args).map(|(&(a, _), ty)| match ty {
+      Some(ITy::Ty(ty)) if matches!(self.k.get_ty(ty), kernel::Type::Var(_)) => Val::Type(ty),
+      Some(ty) => {
+        let ty = inf.resolve(&mut self.k, &ty);
+        Val::expr(self.k.var(name(a).into(), ty))
+      }
+      None => Val::Wff(Rc::new([])),
+    }).collect();
+    let dummies = f.dummies.into_iter().map(|(a, ty)| {
+      let ty = inf.resolve(&mut self.k, &ty);
+      (a, self.fresh_var(a, ty))
+    }).collect();
+    Ok((vals, dummies))
+  }
+
+  /// The variables for the dummy variables of a proof, with inferred types.
+  fn dummies(&mut self, nargs: usize, heap: &[ProofNode], store: &[ProofNode], args: &[Val]
+  ) -> Result<HashMap<AtomId, TmId>> {
+    let nodes = || heap[nargs..].iter().chain(store);
+    if !nodes().any(|e| matches!(e, ProofNode::Dummy(..))) { return Ok(HashMap::new()) }
+    let mut inf = Infer::default();
+    let tys = args.iter().map(|v| match *v {
+      Val::Type(ty) => Some(ITy::Ty(ty)),
+      Val::Term((tm, ..)) => Some(ITy::Ty(self.k.type_of(tm))),
+      _ => None,
+    }).collect();
+    let mut f = IFrame::new(nargs, heap, store, tys);
+    for e in nodes() {
+      if let ProofNode::Term(..) = e { self.infer(&mut inf, &mut f, e)?; }
+    }
+    Ok(f.dummies.into_iter().map(|(a, ty)| {
+      let ty = inf.resolve(&mut self.k, &ty);
+      (a, self.fresh_var(a, ty))
+    }).collect())
+  }
+
+  /// Translate an expression.
+  fn eval<N: Node>(&mut self, f: &mut Frame<'_, N>, e: &N) -> Result<Val> {
+    match e.view(self.env, f.nargs, f.store) {
+      View::Var(i) => Ok(f.args[i].clone()),
+      View::Heap(i) => {
+        if let Some(v) = f.vals.get(&i) { return Ok(v.clone()) }
+        let heap = f.heap;
+        let v = self.eval(f, &heap[i])?;
+        f.vals.insert(i, v.clone());
+        Ok(v)
+      }
+      View::Dummy(a) => match f.dummies.get(&a) {
+        Some(&v) => Ok(Val::expr(v)),
+        None => Err(format!("can't infer the type of the dummy variable `{}`", self.name(a))),
+      },
+      View::App(t, args) => {
+        if t == self.core.lam {
+          // a dummy variable of a definition gets its type from the binder
+          let mut bv = args[0].view(self.env, f.nargs, f.store);
+          while let View::Heap(i) = bv { bv = f.heap[i].view(self.env, f.nargs, f.store) }
+          if let View::Dummy(a) = bv {
+            if !f.dummies.contains_key(&a) {
+              let ty = self.eval(f, &args[1])?.ty()?;
+              let var = self.fresh_var(a, ty);
+              f.dummies.insert(a, var);
+            }
+          }
+        }
+        let vals = args.iter().map(|e| self.eval(f, e)).collect::<Result<_>>()?;
+        self.cong(t, vals)
+      }
+    }
+  }
+
+  /// `f x = g y` from `f = g` and `x = y`.
+  fn cong_app(&mut self, (f1, f2, fth): Eqn, (x1, x2, xth): Eqn) -> Result<Eqn> {
+    let lhs = self.k.app(f1, x1)?;
+    if fth.is_none() && xth.is_none() { return Ok((lhs, lhs, None)) }
+    let rhs = self.k.app(f2, x2)?;
+    let fth = fth.unwrap_or_else(|| self.k.refl(f1));
+    let xth = xth.unwrap_or_else(|| self.k.refl(x1));
+    Ok((lhs, rhs, Some(self.k.app_thm(fth, xth)?)))
+  }
+
+  /// Translate the application of `t` to the arguments `vals`, which can be conversions.
+  fn cong(&mut self, t: TermId, vals: Vec<Val>) -> Result<Val> {
+    let (env, core) = (self.env, &self.core);
+    Ok(if t == core.im { Val::Stmt(vals[0].wff()?, vals[1].wff()?) } else if t == core.an {
+      Val::Wff(vals[0].wff()?.iter().chain(&*vals[1].wff()?).copied().collect())
+    } else if t == core.typing { Val::Wff(Rc::new([])) } else if t == core.thm {
+      Val::Wff(Rc::new([vals[0].eqn()?]))
+    } else if t == core.app {
+      Val::Term(self.cong_app(vals[0].eqn()?, vals[1].eqn()?)?)
+    } else if t == core.lam {
+      let ((x, ..), (l, r, th)) = (vals[0].eqn()?, vals[2].eqn()?);
+      if !matches!(*self.k.term(x), kernel::Term::Var(_, ty) if ty == vals[1].ty()?) {
+        return Err("the bound variable of a lambda has the wrong type".into())
+      }
+      let th = match th { Some(th) => Some(self.k.abs_thm(x, th)?), None => None };
+      Val::Term((self.k.abs(x, l)?, self.k.abs(x, r)?, th))
+    } else if let Some(n) = self.map.types.get(&t) {
+      let tys = vals.iter().map(Val::ty).collect::<Result<_>>()?;
+      Val::Type(self.k.ty(kernel::Type::Op(n.clone(), tys)))
+    } else if let Some((n, sch)) = self.map.consts.get(&t) {
+      let ty = sch.inst(&mut self.k, &vals)?;
+      let c = self.k.konst(n.clone(), ty);
+      let (tm, mut e) = (core.tm, (c, c, None));
+      for (val, (_, arg)) in vals.iter().zip(&*env.term(t).args) {
+        if arg.sort() == tm { e = self.cong_app(e, val.eqn()?)? }
+      }
+      Val::Term(e)
+    } else if let TermKind::Def(Some(e)) = &env.term(t).kind {
+      let mut f = Frame::new(vals.len(), &e.heap, &e.store, vals);
+      self.eval(&mut f, e.head())?
+    } else {
+      return Err(format!("`{}` is not in the mapping", self.name(self.env.term(t).atom)))
+    })
+  }
+
+  fn sym(&mut self, v: Val) -> Result<Val> {
+    let mut sym = |(l, r, th): Eqn| -> Result<Eqn> {
+      Ok((r, l, match th { Some(th) => Some(self.k.sym(th)?), None => None }))
+    };
+    Ok(match v {
+      Val::Type(_) => v,
+      Val::Term(e) => Val::Term(sym(e)?),
+      Val::Wff(es) => Val::Wff(es.iter().map(|&e| sym(e)).collect::<Result<_>>()?),
+      Val::Stmt(ctx, es) => Val::Stmt(ctx.iter().map(|&e| sym(e)).collect::<Result<_>>()?,
+        es.iter().map(|&e| sym(e)).collect::<Result<_>>()?),
+    })
+  }
+
+  /// Translate a conversion.
+  fn conv(&mut self, f: &mut Frame<'_, ProofNode>, c: &ProofNode) -> Result<Val> {
+    let (env, store) = (self.env, f.store);
+    match *c {
+      ProofNode::Ref(i) if i >= f.nargs => {
+        if let Some(v) = f.vals.get(&i) { return Ok(v.clone()) }
+        let heap = f.heap;
+        let v = self.conv(f, &heap[i])?;
+        f.vals.insert(i, v.clone());
+        Ok(v)
+      }
+      ProofNode::Refl(p) => self.eval(f, &store[p]),
+      ProofNode::Sym(p) => { let v = self.conv(f, &store[p])?; self.sym(v) }
+      ProofNode::Cong(t, p) => {
+        let vals = env.term(t).unpack_term(&store[p..]).iter()
+          .map(|c| self.conv(f, c)).collect::<Result<_>>()?;
+        self.cong(t, vals)
+      }
+      ProofNode::Unfold(t, p) => {
+        let (_, c, args) = env.term(t).unpack_unfold(&store[p..]);
+        let val = self.conv(f, c)?;
+        if !self.map.consts.contains_key(&t) { return Ok(val) }
+        let (body, rhs, th) = val.eqn()?;
+        let vals = args.iter().map(|e| self.eval(f, e)).collect::<Result<Vec<_>>>()?;
+        let lhs = self.cong(t, vals.clone())?.tm()?;
+        let gen = self.def(t)?;
+        let def = self.inst(&gen, &vals)?[0];
+        let th = match th {
+          Some(th) => self.k.trans(def, th)?,
+          None if self.k.dest_eq(self.k.concl(def))
+            .map_or(false, |(_, e)| self.k.alpha_eq(e, body)) => def,
+          None => return Err("the unfolding doesn't match the definition".into()),
+        };
+        Ok(Val::Term((lhs, rhs, Some(th))))
+      }
+      _ => self.eval(f, c),
+    }
+  }
+
+  /// The assumption `⊢ c args = body` for a definition in the mapping.
+  fn def(&mut self, t: TermId) -> Result<Rc<Generic>> {
+    if let Some(g) = self.defs.get(&t) { return Ok(g.clone()) }
+    let env = self.env;
+    let td = env.term(t);
+    let e = if let TermKind::Def(Some(e)) = &td.kind { e } else {
+      return Err(format!("`{}` is not a definition", self.name(td.atom)))
+    };
+    let (args, dummies) = self.generic_args(&td.args, &e.heap, &e.store, Some(e.head()))?;
+    let lhs = self.cong(t, args.clone())?.tm()?;
+    let mut f = Frame::new(args.len(), &e.heap, &e.store, args.clone());
+    f.dummies = dummies;
+    let rhs = self.eval(&mut f, e.head())?.tm()?;
+    let concl = self.k.eq(lhs, rhs)?;
+    let g = Rc::new(Generic { args, thms: vec![self.k.axiom(&[], concl)?] });
+    self.defs.insert(t, g.clone());
+    Ok(g)
+  }
+
+  /// The assumptions for an axiom which is not an inference rule, one for each `thm`
+  /// judgement in the conclusion. The hypotheses are the `thm` judgements in the context of
+  /// the conclusion and in the hypotheses of the axiom.
+  fn axiom(&mut self, t: ThmId) -> Result<Rc<Generic>> {
+    if let Some(g) = self.axioms.get(&t) { return Ok(g.clone()) }
+    let env = self.env;
+    let td = env.thm(t);
+    let es = td.hyps.iter().map(|(_, h)| h).chain(Some(&td.ret));
+    let (args, dummies) = self.generic_args(&td.args, &td.heap, &td.store, es)?;
+    let mut f = Frame::new(args.len(), &td.heap, &td.store, args.clone());
+    f.dummies = dummies;
+    let (ctx, concl) = self.eval(&mut f, &td.ret)?.stmt()?;
+    let mut hyps = ctx.iter().map(|e| e.0).collect::<Vec<_>>();
+    for (_, h) in &*td.hyps {
+      let (ctx2, concl2) = self.eval(&mut f, h)?.stmt()?;
+      if concl2.is_empty() { continue }
+      if !ctx2.iter().all(|e| self.k.contains(&ctx.iter().map(|e| e.0).collect::<Vec<_>>(), e.0)) {
+        return Err(format!("can't translate axiom `{}`, because a hypothesis has a \
+          judgement in its context that the conclusion doesn't have", self.name(td.atom)))
+      }
+      hyps.extend(concl2.iter().map(|e| e.0))
+    }
+    let thms = concl.iter().map(|e| self.k.axiom(&hyps, e.0)).collect::<Result<_>>()?;
+    let g = Rc::new(Generic { args, thms });
+    self.axioms.insert(t, g.clone());
+    Ok(g)
+  }
+
+  /// Instantiate the generic theorems with the arguments `vals`.
+  fn inst(&mut self, g: &Generic, vals: &[Val]) -> Result<Vec<Th>> {
+    let mut tys = vec![];
+    for (a, v) in g.args.iter().zip(vals) {
+      if let (&Val::Type(a), &Val::Type(v)) = (a, v) {
+        if let kernel::Type::Var(n) = self.k.get_ty(a) {
+          if a != v { tys.push((n.clone(), v)) }
+        }
+      }
+    }
+    let mut tms = vec![];
+    for (a, v) in g.args.iter().zip(vals) {
+      if let (&Val::Term((a, ..)), &Val::Term((v, ..))) = (a, v) {
+        let a = self.k.inst(a, &tys);
+        if a != v { tms.push((a, v)) }
+      }
+    }
+    if tys.is_empty() && tms.is_empty() { return Ok(g.thms.clone()) }
+    let (tys, tms): (Box<[_]>, Box<[_]>) = (tys.into(), tms.into());
+    g.thms.iter().map(|&th| self.k.subst_thm(tys.clone(), tms.clone(), th)).collect()
+  }
+
+  /// Check that the theorems `ths` prove the judgements `concl` from the judgements `ctx`.
+  fn check(&self, ctx: &[Eqn], concl: &[Eqn], ths: &[Th]) -> Result<()> {
+    let ctx = ctx.iter().map(|e| e.0).collect::<Vec<_>>();
+    if concl.len() == ths.len() && ths.iter().zip(concl).all(|(&th, e)|
+      self.k.alpha_eq(self.k.concl(th), e.0) &&
+      self.k.hyps(th).iter().all(|&h| self.k.contains(&ctx, h))
+    ) { Ok(()) } else { Err("the translation doesn't match the statement".into()) }
+  }
+
+  /// Translate a proof, giving a theorem for each `thm` judgement in the conclusion.
+  fn proof(&mut self, f: &mut Frame<'_, ProofNode>, p: &ProofNode) -> Result<Rc<[Th]>> {
+    let (env, store) = (self.env, f.store);
+    match *p {
+      ProofNode::Ref(i) => {
+        if let Some(ths) = f.proofs.get(&i) { return Ok(ths.clone()) }
+        let heap = f.heap;
+        let ths = self.proof(f, &heap[i])?;
+        f.proofs.insert(i, ths.clone());
+        Ok(ths)
+      }
+      ProofNode::Hyp(i, _) => Ok(f.hyps[i].clone()),
+      ProofNode::Thm(t, p) => {
+        let (res, args, hyps) = env.thm(t).unpack_thm(&store[p..]);
+        let (ctx, concl) = self.eval(f, res)?.stmt()?;
+        if concl.is_empty() { return Ok(Rc::new([])) }
+        let vals = args.iter().map(|e| self.eval(f, e)).collect::<Result<Vec<_>>>()?;
+        let hyps = hyps.iter().map(|p| self.proof(f, p)).collect::<Result<Vec<_>>>()?;
+        let ths = self.apply(t, &vals, &hyps)?;
+        self.check(&ctx, &concl, &ths)
+          .map_err(|e| format!("{}, in `{}`", e, self.name(env.thm(t).atom)))?;
+        Ok(ths)
+      }
+      ProofNode::Conv(p) => {
+        let (_, c, p) = ProofNode::unpack_conv(&store[p..]);
+        let ths = self.proof(f, p)?;
+        if ths.is_empty() { return Ok(ths) }
+        let (ctx, concl) = self.conv(f, c)?.stmt()?;
+        if concl.len() != ths.len() { return Err("the conversion doesn't match".into()) }
+        ths.iter().zip(&*concl).map(|(&th, &(_, _, e))| {
+          let mut th = match e {
+            Some(e) => { let e = self.k.sym(e)?; self.k.eq_mp(e, th)? }
+            None => th,
+          };
+          for &(l, r, e) in &*ctx {
+            if let Some(e) = e {
+              if self.k.contains(self.k.hyps(th), r) {
+                let h = self.k.assume(l)?;
+                let h = self.k.eq_mp(e, h)?;
+                th = self.k.prove_hyp(h, th)
+              }
+            }
+          }
+          Ok(th)
+        }).collect()
+      }
+      _ => Err("unexpected proof node".into()),
+    }
+  }
+
+  /// Translate an application of an inference rule.
+  fn rule(&mut self, rule: Rule, vals: &[Val], hyps: &[Rc<[Th]>]) -> Result<Rc<[Th]>> {
+    let hyp = |i: usize| hyps[i].first().copied().ok_or_else(|| "missing hypothesis".to_owned());
+    let k = &mut self.k;
+    let assume = |k: &mut Kernel, v: &Val| -> Result<Rc<[Th]>> {
+      v.wff()?.iter().map(|e| k.assume(e.0)).collect()
+    };
+    Ok(match rule {
+      Rule::Id | Rule::Anl => assume(k, &vals[0])?,
+      Rule::Anr => assume(k, &vals[1])?,
+      Rule::Syl => {
+        let qs = vals[1].wff()?;
+        if qs.len() != hyps[0].len() { return Err("missing hypothesis".into()) }
+        hyps[1].iter().map(|&th| qs.iter().zip(&*hyps[0]).fold(th, |th, (q, &h)| {
+          if k.contains(k.hyps(th), q.0) { k.prove_hyp(h, th) } else { th }
+        })).collect()
+      }
+      Rule::Ian => hyps[0].iter().chain(&*hyps[1]).copied().collect(),
+      Rule::Refl => Rc::new([k.refl(vals[2].tm()?)]),
+      Rule::Aeq => Rc::new([k.app_thm(hyp(0)?, hyp(1)?)?]),
+      Rule::Leq => Rc::new([k.abs_thm(vals[3].tm()?, hyp(0)?)?]),
+      Rule::Beta => {
+        let x = vals[2].tm()?;
+        let e = k.abs(x, vals[4].tm()?)?;
+        let e = k.app(e, x)?;
+        Rc::new([k.beta_conv(e)?])
+      }
+      Rule::Eqmp => Rc::new([k.eq_mp(hyp(0)?, hyp(1)?)?]),
+      Rule::Ded => Rc::new([k.deduct_antisym(hyp(1)?, hyp(0)?)]),
+      Rule::Cbvv => {
+        let e = k.abs(vals[3].tm()?, vals[5].tm()?)?;
+        Rc::new([k.refl(e)])
+      }
+      Rule::Inst => {
+        let (x, t) = (vals[2].tm()?, vals[3].tm()?);
+        let th = k.subst_thm(Box::new([]), Box::new([(x, t)]), hyp(2)?)?;
+        let refl = k.refl(t);
+        Rc::new([k.prove_hyp(refl, th)])
+      }
+    })
+  }
+
+  /// Translate an application of a theorem or axiom.
+  fn apply(&mut self, t: ThmId, vals: &[Val], hyps: &[Rc<[Th]>]) -> Result<Rc<[Th]>> {
+    if let Some(&rule) = self.core.rules.get(&t) { return self.rule(rule, vals, hyps) }
+    let key = (t, vals.to_vec(), hyps.to_vec());
+    if let Some(ths) = self.memo.get(&key) { return Ok(ths.clone()) }
+    let env = self.env;
+    let td = env.thm(t);
+    let ths: Rc<[Th]> = match &td.kind {
+      ThmKind::Axiom => {
+        let g = self.axiom(t)?;
+        let mut ths = self.inst(&g, vals)?;
+        for &h in hyps.iter().flat_map(|ths| &**ths) {
+          for th in &mut ths {
+            if self.k.contains(self.k.hyps(*th), self.k.concl(h)) { *th = self.k.prove_hyp(h, *th) }
+          }
+        }
+        ths.into()
+      }
+      ThmKind::Thm(Some(pf)) => {
+        let mut f = Frame::new(td.args.len(), &pf.heap, &pf.store, vals.to_vec());
+        f.dummies = self.dummies(td.args.len(), &pf.heap, &pf.store, vals)?;
+        f.hyps = hyps.to_vec();
+        self.proof(&mut f, pf.head())?
+      }
+      ThmKind::Thm(None) => return Err(format!("`{}` has no proof", self.name(td.atom))),
+    };
+    self.memo.insert(key, ths.clone());
+    Ok(ths)
+  }
+
+  /// Translate a theorem, if it is a theorem of the article, giving the theorem and the
+  /// expected conclusion for each `thm` judgement in the conclusion.
+  fn thm(&mut self, t: ThmId) -> Result<Vec<(Th, TmId)>> {
+    let env = self.env;
+    let td = env.thm(t);
+    if !matches!(td.kind, ThmKind::Thm(Some(_))) { return Ok(vec![]) }
+    let es = td.hyps.iter().map(|(_, h)| h).chain(Some(&td.ret));
+    let (args, dummies) = self.generic_args(&td.args, &td.heap, &td.store, es)?;
+    let mut f = Frame::new(args.len(), &td.heap, &td.store, args.clone());
+    f.dummies = dummies;
+    for (_, h) in &*td.hyps {
+      if !self.eval(&mut f, h)?.stmt()?.1.is_empty() { return Ok(vec![]) }
+    }
+    let (ctx, concl) = self.eval(&mut f, &td.ret)?.stmt()?;
+    if concl.is_empty() { return Ok(vec![]) }
+    let ths = self.apply(t, &args, &vec![Rc::new([]) as Rc<[Th]>; td.hyps.len()])?;
+    self.check(&ctx, &concl, &ths)?;
+    Ok(ths.iter().zip(&*concl).map(|(&th, e)| (th, e.0)).collect())
+  }
+}
+
+impl FrozenEnv {
+  /// Write this environment as an [OpenTheory] article, using the `mapping` (the contents of
+  /// a mapping file) for the HOL type operators and constants. See the
+  /// [module documentation](crate::opentheory::export) for the translation.
+  ///
+  /// [OpenTheory]: https://www.gilith.com/opentheory/article.html
+  pub fn export_opentheory(&self, mapping: &str, w: impl Write,
+    report: &mut dyn FnMut(ErrorLevel, &str)
+  ) -> io::Result<()> {
+    let err = |e| io::Error::new(io::ErrorKind::InvalidData, e);
+    let core = Core::new(self).map_err(err)?;
+    let map = Mapping::parse(self, &core, mapping).map_err(err)?;
+    let mut ex = Exporter { env: self, core, map, k: Kernel::default(),
+      axioms: HashMap::new(), defs: HashMap::new(), memo: HashMap::new(), fresh: 0 };
+    let mut out = vec![];
+    for s in self.stmts() {
+      if let StmtTrace::Decl(a) = *s {
+        if let Some(DeclKey::Thm(t)) = self.data()[a].decl() {
+          if !self.thm(t).vis.contains(Modifiers::PUB) { continue }
+          match ex.thm(t) {
+            Ok(ths) => out.extend(ths.into_iter().map(|(th, e)| (a, th, e))),
+            Err(e) => report(ErrorLevel::Warning,
+              &format!("can't translate `{}`: {}", ex.name(a), e)),
+          }
+        }
+      }
+    }
+    let mut art = Article::new(w)?;
+    for (a, th, e) in out { art.export(&ex.k, th, e, ex.name(a))? }
+    art.finish()
+  }
+}
+
+/// Export an MM1/MM0 file as a HOL article (.art)
+#[derive(clap::Args, Debug)]
+pub struct Args {
+  /// Sets the input file (.mm1 or .mm0)
+  pub input: String,
+  /// Sets the mapping file, which gives the HOL type operators and constants
+  pub mapping: String,
+  /// Sets the output file (.art)
+  pub output: String,
+}
+
+impl Args {
+  /// Main entry point for `mm0-rs export-ot` subcommand.
+  ///
+  /// See the [module documentation](self) for how the environment is translated.
+  ///
+  /// # Arguments
+  ///
+  /// `mm0-rs export-ot <in.mm1> <map> <out.art>`, where:
+  ///
+  /// - `in.mm1` is the initial file to elaborate.
+  /// - `map` is the mapping file.
+  /// - `out.art` is the article to write.
+  pub fn main(self) -> io::Result<()> {
+    let mapping = fs::read_to_string(&self.mapping)?;
+    let path: FileRef = fs::canonicalize(&self.input)?.into();
+    let (_, env) = crate::compiler::elab_for_result(path)?;
+    let env = env.unwrap_or_else(|| std::process::exit(1));
+    let w = BufWriter::new(File::create(&self.output)?);
+    let mut report = |lvl: ErrorLevel, err: &str| eprintln!("{}: {}", lvl, err);
+    env.export_opentheory(&mapping, w, &mut report)
+  }
+}
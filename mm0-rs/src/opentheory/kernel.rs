@@ -0,0 +1,644 @@
+//! A small HOL kernel, which checks the steps of the [OpenTheory] translation and writes the
+//! resulting theorems as article commands.
+//!
+//! Types and terms are hash-consed, so that syntactic equality is equality of indices, and
+//! every theorem records the article inference rule that derived it. [`Article`] replays
+//! these derivations as commands, using the article dictionary so that every type, term and
+//! theorem is written once.
+//!
+//! [OpenTheory]: https://www.gilith.com/opentheory/article.html
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+/// The result of a kernel operation, with an error message if the step is invalid.
+pub(crate) type Result<T> = std::result::Result<T, String>;
+
+/// The name of a type operator, constant or variable.
+pub(crate) type Name = Rc<str>;
+
+/// An index into the type table of a [`Kernel`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct TyId(u32);
+
+/// An index into the term table of a [`Kernel`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct TmId(u32);
+
+/// An index into the theorem table of a [`Kernel`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct Th(u32);
+
+/// A HOL type.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub(crate) enum Type {
+  /// A type variable
+  Var(Name),
+  /// A type operator applied to a list of types
+  Op(Name, Box<[TyId]>),
+}
+
+/// A HOL term. A variable is identified by its name together with its type.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub(crate) enum Term {
+  /// A variable
+  Var(Name, TyId),
+  /// A constant, at an instance of its type
+  Const(Name, TyId),
+  /// An application `f x`
+  App(TmId, TmId),
+  /// A lambda abstraction `λ v. t`, where `v` is a variable
+  Abs(TmId, TmId),
+}
+
+/// A substitution of types for type variables and of terms for variables.
+type Subst = (Box<[(Name, TyId)]>, Box<[(TmId, TmId)]>);
+
+/// The inference rule that derived a theorem. These are the article commands
+/// which push a theorem.
+#[derive(Debug)]
+enum Rule {
+  /// `axiom`: an assumption of the article
+  Axiom,
+  /// `refl t`: `⊢ t = t`
+  Refl(TmId),
+  /// `assume φ`: `{φ} ⊢ φ`
+  Assume(TmId),
+  /// `betaConv ((λ v. t) u)`: `⊢ (λ v. t) u = t[u/v]`
+  BetaConv(TmId),
+  /// `appThm (Γ ⊢ f = g) (Δ ⊢ x = y)`: `Γ ∪ Δ ⊢ f x = g y`
+  AppThm(Th, Th),
+  /// `absThm v (Γ ⊢ t = u)`: `Γ ⊢ (λ v. t) = (λ v. u)`
+  AbsThm(TmId, Th),
+  /// `eqMp (Γ ⊢ φ = ψ) (Δ ⊢ φ)`: `Γ ∪ Δ ⊢ ψ`
+  EqMp(Th, Th),
+  /// `deductAntisym (Γ ⊢ φ) (Δ ⊢ ψ)`: `(Γ - {ψ}) ∪ (Δ - {φ}) ⊢ φ = ψ`
+  DeductAntisym(Th, Th),
+  /// `proveHyp (Γ ⊢ φ) (Δ ⊢ ψ)`: `Γ ∪ (Δ - {φ}) ⊢ ψ`
+  ProveHyp(Th, Th),
+  /// `sym (Γ ⊢ t = u)`: `Γ ⊢ u = t`
+  Sym(Th),
+  /// `trans (Γ ⊢ t = u) (Δ ⊢ u = v)`: `Γ ∪ Δ ⊢ t = v`
+  Trans(Th, Th),
+  /// `subst σ (Γ ⊢ φ)`: `Γσ ⊢ φσ`, instantiating the type variables and then the variables
+  Subst(Box<Subst>, Th),
+}
+
+/// A theorem `hyps ⊢ concl`.
+#[derive(Debug)]
+struct ThmData {
+  /// The hypotheses, with no two alpha-equivalent
+  hyps: Box<[TmId]>,
+  /// The conclusion
+  concl: TmId,
+  /// How the theorem was derived
+  rule: Rule,
+}
+
+/// The tables of types, terms and theorems.
+#[derive(Debug, Default)]
+pub(crate) struct Kernel {
+  types: Vec<Type>,
+  type_ids: HashMap<Type, TyId>,
+  /// Each term, together with its type
+  terms: Vec<(Term, TyId)>,
+  term_ids: HashMap<Term, TmId>,
+  thms: Vec<ThmData>,
+}
+
+impl Kernel {
+  /// Intern a type.
+  pub(crate) fn ty(&mut self, ty: Type) -> TyId {
+    if let Some(&i) = self.type_ids.get(&ty) { return i }
+    let i = TyId(self.types.len().try_into().expect("too many types"));
+    self.types.push(ty.clone());
+    self.type_ids.insert(ty, i);
+    i
+  }
+
+  /// Get a type by index.
+  pub(crate) fn get_ty(&self, ty: TyId) -> &Type { &self.types[ty.0 as usize] }
+
+  /// The type `bool`.
+  pub(crate) fn bool(&mut self) -> TyId { self.ty(Type::Op("bool".into(), Box::new([]))) }
+
+  /// The function type `a -> b`.
+  pub(crate) fn fun(&mut self, a: TyId, b: TyId) -> TyId {
+    self.ty(Type::Op("->".into(), Box::new([a, b])))
+  }
+
+  fn dest_fun(&self, ty: TyId) -> Option<(TyId, TyId)> {
+    match self.get_ty(ty) {
+      Type::Op(n, args) if &**n == "->" && args.len() == 2 => Some((args[0], args[1])),
+      _ => None,
+    }
+  }
+
+  /// Print a type, for error messages.
+  pub(crate) fn show_ty(&self, ty: TyId) -> String {
+    match self.get_ty(ty) {
+      Type::Var(n) => n.to_string(),
+      Type::Op(n, args) if args.is_empty() => n.to_string(),
+      _ if self.dest_fun(ty).is_some() => {
+        let (a, b) = self.dest_fun(ty).expect("checked");
+        format!("({} -> {})", self.show_ty(a), self.show_ty(b))
+      }
+      Type::Op(n, args) => {
+        let args = args.iter().map(|&a| self.show_ty(a)).collect::<Vec<_>>();
+        format!("({} {})", n, args.join(" "))
+      }
+    }
+  }
+
+  fn mk(&mut self, tm: Term, ty: TyId) -> TmId {
+    if let Some(&i) = self.term_ids.get(&tm) { return i }
+    let i = TmId(self.terms.len().try_into().expect("too many terms"));
+    self.terms.push((tm.clone(), ty));
+    self.term_ids.insert(tm, i);
+    i
+  }
+
+  /// Get a term by index.
+  pub(crate) fn term(&self, tm: TmId) -> &Term { &self.terms[tm.0 as usize].0 }
+
+  /// The type of a term.
+  pub(crate) fn type_of(&self, tm: TmId) -> TyId { self.terms[tm.0 as usize].1 }
+
+  /// A variable `name: ty`.
+  pub(crate) fn var(&mut self, name: Name, ty: TyId) -> TmId { self.mk(Term::Var(name, ty), ty) }
+
+  /// A constant `name: ty`.
+  pub(crate) fn konst(&mut self, name: Name, ty: TyId) -> TmId {
+    self.mk(Term::Const(name, ty), ty)
+  }
+
+  /// The application `f x`.
+  pub(crate) fn app(&mut self, f: TmId, x: TmId) -> Result<TmId> {
+    match self.dest_fun(self.type_of(f)) {
+      Some((a, b)) if a == self.type_of(x) => Ok(self.mk(Term::App(f, x), b)),
+      _ => Err(format!("can't apply a term of type {} to a term of type {}",
+        self.show_ty(self.type_of(f)), self.show_ty(self.type_of(x)))),
+    }
+  }
+
+  /// The lambda abstraction `λ v. t`.
+  pub(crate) fn abs(&mut self, v: TmId, t: TmId) -> Result<TmId> {
+    let a = if let Term::Var(_, a) = *self.term(v) { a } else {
+      return Err("expected a variable".into())
+    };
+    let ty = self.fun(a, self.type_of(t));
+    Ok(self.mk(Term::Abs(v, t), ty))
+  }
+
+  /// The equation `l = r`.
+  pub(crate) fn eq(&mut self, l: TmId, r: TmId) -> Result<TmId> {
+    let ty = self.type_of(l);
+    let bool = self.bool();
+    let ret = self.fun(ty, bool);
+    let ty = self.fun(ty, ret);
+    let eq = self.konst("=".into(), ty);
+    let eq = self.app(eq, l)?;
+    self.app(eq, r)
+  }
+
+  /// The sides of an equation `l = r`.
+  pub(crate) fn dest_eq(&self, tm: TmId) -> Option<(TmId, TmId)> {
+    if let Term::App(f, r) = *self.term(tm) {
+      if let Term::App(e, l) = *self.term(f) {
+        if matches!(self.term(e), Term::Const(n, _) if &**n == "=") { return Some((l, r)) }
+      }
+    }
+    None
+  }
+
+  fn dest_eq_or_err(&self, th: Th) -> Result<(TmId, TmId)> {
+    self.dest_eq(self.concl(th)).ok_or_else(|| "expected an equation".into())
+  }
+
+  /// Check if two terms are equal up to renaming of bound variables.
+  pub(crate) fn alpha_eq(&self, a: TmId, b: TmId) -> bool {
+    a == b || self.alpha(a, b, &mut vec![])
+  }
+
+  fn alpha(&self, a: TmId, b: TmId, env: &mut Vec<(TmId, TmId)>) -> bool {
+    if a == b && env.is_empty() { return true }
+    match (self.term(a), self.term(b)) {
+      (Term::Var(..), Term::Var(..)) => match env.iter().rev().find(|e| e.0 == a || e.1 == b) {
+        Some(e) => e.0 == a && e.1 == b,
+        None => a == b,
+      },
+      (Term::Const(..), Term::Const(..)) => a == b,
+      (&Term::App(f1, x1), &Term::App(f2, x2)) =>
+        self.alpha(f1, f2, env) && self.alpha(x1, x2, env),
+      (&Term::Abs(v1, t1), &Term::Abs(v2, t2)) => {
+        if self.type_of(v1) != self.type_of(v2) { return false }
+        env.push((v1, v2));
+        let res = self.alpha(t1, t2, env);
+        env.pop();
+        res
+      }
+      _ => false,
+    }
+  }
+
+  /// Check if the variable `v` is free in `tm`.
+  fn free_in(&self, v: TmId, tm: TmId) -> bool {
+    match *self.term(tm) {
+      Term::Var(..) => v == tm,
+      Term::Const(..) => false,
+      Term::App(f, x) => self.free_in(v, f) || self.free_in(v, x),
+      Term::Abs(w, t) => v != w && self.free_in(v, t),
+    }
+  }
+
+  /// A variant of the variable `v` which is not free in any of the terms `avoid`.
+  fn variant(&mut self, v: TmId, avoid: &[TmId]) -> TmId {
+    let (mut name, ty) = match self.term(v) {
+      Term::Var(name, ty) => (name.to_string(), *ty),
+      _ => unreachable!("expected a variable"),
+    };
+    loop {
+      name.push('\'');
+      let v = self.var(name.as_str().into(), ty);
+      if !avoid.iter().any(|&t| self.free_in(v, t)) { return v }
+    }
+  }
+
+  /// Substitute terms for variables in `tm`, renaming bound variables to avoid capture.
+  pub(crate) fn subst(&mut self, tm: TmId, sub: &[(TmId, TmId)]) -> TmId {
+    if sub.is_empty() { return tm }
+    match *self.term(tm) {
+      Term::Var(..) => sub.iter().find(|p| p.0 == tm).map_or(tm, |p| p.1),
+      Term::Const(..) => tm,
+      Term::App(f, x) => {
+        let (f2, x2) = (self.subst(f, sub), self.subst(x, sub));
+        if (f, x) == (f2, x2) { return tm }
+        let ty = self.type_of(tm);
+        self.mk(Term::App(f2, x2), ty)
+      }
+      Term::Abs(v, t) => {
+        let sub2 = sub.iter().filter(|p| p.0 != v && self.free_in(p.0, t))
+          .copied().collect::<Vec<_>>();
+        if sub2.is_empty() { return tm }
+        let (v, t) = if sub2.iter().any(|p| self.free_in(v, p.1)) {
+          let avoid = sub2.iter().map(|p| p.1).chain(Some(t)).collect::<Vec<_>>();
+          let v2 = self.variant(v, &avoid);
+          (v2, self.subst(t, &[(v, v2)]))
+        } else { (v, t) };
+        let t = self.subst(t, &sub2);
+        let ty = self.type_of(tm);
+        self.mk(Term::Abs(v, t), ty)
+      }
+    }
+  }
+
+  /// Substitute types for type variables in a type.
+  fn inst_ty(&mut self, ty: TyId, sub: &[(Name, TyId)]) -> TyId {
+    match self.get_ty(ty) {
+      Type::Var(n) => sub.iter().find(|p| p.0 == *n).map_or(ty, |p| p.1),
+      Type::Op(n, args) => {
+        let (n, args) = (n.clone(), args.clone());
+        let args2 = args.iter().map(|&a| self.inst_ty(a, sub)).collect::<Box<[_]>>();
+        if args == args2 { ty } else { self.ty(Type::Op(n, args2)) }
+      }
+    }
+  }
+
+  /// Substitute types for type variables in a term.
+  pub(crate) fn inst(&mut self, tm: TmId, sub: &[(Name, TyId)]) -> TmId {
+    if sub.is_empty() { return tm }
+    let ty = self.type_of(tm);
+    let ty = self.inst_ty(ty, sub);
+    let tm = match self.term(tm).clone() {
+      Term::Var(n, _) => Term::Var(n, ty),
+      Term::Const(n, _) => Term::Const(n, ty),
+      Term::App(f, x) => Term::App(self.inst(f, sub), self.inst(x, sub)),
+      Term::Abs(v, t) => Term::Abs(self.inst(v, sub), self.inst(t, sub)),
+    };
+    self.mk(tm, ty)
+  }
+
+  /// The hypotheses of a theorem.
+  pub(crate) fn hyps(&self, th: Th) -> &[TmId] { &self.thms[th.0 as usize].hyps }
+
+  /// The conclusion of a theorem.
+  pub(crate) fn concl(&self, th: Th) -> TmId { self.thms[th.0 as usize].concl }
+
+  /// Check if a list of hypotheses contains `tm`, up to alpha-equivalence.
+  pub(crate) fn contains(&self, hyps: &[TmId], tm: TmId) -> bool {
+    hyps.iter().any(|&h| self.alpha_eq(h, tm))
+  }
+
+  fn add(&mut self, hyps: Box<[TmId]>, concl: TmId, rule: Rule) -> Th {
+    let th = Th(self.thms.len().try_into().expect("too many theorems"));
+    self.thms.push(ThmData { hyps, concl, rule });
+    th
+  }
+
+  fn union(&self, a: impl IntoIterator<Item = TmId>, b: impl IntoIterator<Item = TmId>
+  ) -> Box<[TmId]> {
+    let mut out = vec![];
+    for h in a.into_iter().chain(b) {
+      if !self.contains(&out, h) { out.push(h) }
+    }
+    out.into()
+  }
+
+  fn without(&self, th: Th, tm: TmId) -> impl Iterator<Item = TmId> + '_ {
+    self.hyps(th).iter().copied().filter(move |&h| !self.alpha_eq(h, tm))
+  }
+
+  fn check_bool(&mut self, tm: TmId) -> Result<()> {
+    if self.type_of(tm) == self.bool() { Ok(()) } else {
+      Err(format!("expected a term of type bool, got {}", self.show_ty(self.type_of(tm))))
+    }
+  }
+
+  /// `Γ ⊢ φ`, an assumption of the article.
+  pub(crate) fn axiom(&mut self, hyps: &[TmId], concl: TmId) -> Result<Th> {
+    for &h in hyps.iter().chain(Some(&concl)) { self.check_bool(h)? }
+    let hyps = self.union(hyps.iter().copied(), None);
+    Ok(self.add(hyps, concl, Rule::Axiom))
+  }
+
+  /// `⊢ t = t`
+  pub(crate) fn refl(&mut self, t: TmId) -> Th {
+    let concl = self.eq(t, t).expect("well typed");
+    self.add(Box::new([]), concl, Rule::Refl(t))
+  }
+
+  /// `{φ} ⊢ φ`
+  pub(crate) fn assume(&mut self, phi: TmId) -> Result<Th> {
+    self.check_bool(phi)?;
+    Ok(self.add(Box::new([phi]), phi, Rule::Assume(phi)))
+  }
+
+  /// `⊢ (λ v. t) u = t[u/v]`
+  pub(crate) fn beta_conv(&mut self, tm: TmId) -> Result<Th> {
+    let (v, t, u) = match *self.term(tm) {
+      Term::App(f, u) => match *self.term(f) {
+        Term::Abs(v, t) => (v, t, u),
+        _ => return Err("expected a beta redex".into()),
+      },
+      _ => return Err("expected a beta redex".into()),
+    };
+    let rhs = self.subst(t, &[(v, u)]);
+    let concl = self.eq(tm, rhs)?;
+    Ok(self.add(Box::new([]), concl, Rule::BetaConv(tm)))
+  }
+
+  /// `Γ ⊢ f = g` and `Δ ⊢ x = y` give `Γ ∪ Δ ⊢ f x = g y`
+  pub(crate) fn app_thm(&mut self, fth: Th, xth: Th) -> Result<Th> {
+    let ((f, g), (x, y)) = (self.dest_eq_or_err(fth)?, self.dest_eq_or_err(xth)?);
+    let (lhs, rhs) = (self.app(f, x)?, self.app(g, y)?);
+    let concl = self.eq(lhs, rhs)?;
+    let hyps = self.union(self.hyps(fth).iter().copied(), self.hyps(xth).iter().copied());
+    Ok(self.add(hyps, concl, Rule::AppThm(fth, xth)))
+  }
+
+  /// `Γ ⊢ t = u` gives `Γ ⊢ (λ v. t) = (λ v. u)`, if `v` is not free in `Γ`
+  pub(crate) fn abs_thm(&mut self, v: TmId, th: Th) -> Result<Th> {
+    let (t, u) = self.dest_eq_or_err(th)?;
+    if self.hyps(th).iter().any(|&h| self.free_in(v, h)) {
+      return Err("the bound variable is free in a hypothesis".into())
+    }
+    let (lhs, rhs) = (self.abs(v, t)?, self.abs(v, u)?);
+    let concl = self.eq(lhs, rhs)?;
+    let hyps = self.hyps(th).into();
+    Ok(self.add(hyps, concl, Rule::AbsThm(v, th)))
+  }
+
+  /// `Γ ⊢ φ = ψ` and `Δ ⊢ φ` give `Γ ∪ Δ ⊢ ψ`
+  pub(crate) fn eq_mp(&mut self, a: Th, b: Th) -> Result<Th> {
+    let (phi, psi) = self.dest_eq_or_err(a)?;
+    if !self.alpha_eq(phi, self.concl(b)) { return Err("eqMp: the terms don't match".into()) }
+    let hyps = self.union(self.hyps(a).iter().copied(), self.hyps(b).iter().copied());
+    Ok(self.add(hyps, psi, Rule::EqMp(a, b)))
+  }
+
+  /// `Γ ⊢ φ` and `Δ ⊢ ψ` give `(Γ - {ψ}) ∪ (Δ - {φ}) ⊢ φ = ψ`
+  pub(crate) fn deduct_antisym(&mut self, a: Th, b: Th) -> Th {
+    let (phi, psi) = (self.concl(a), self.concl(b));
+    let concl = self.eq(phi, psi).expect("well typed");
+    let hyps = self.union(self.without(a, psi), self.without(b, phi));
+    self.add(hyps, concl, Rule::DeductAntisym(a, b))
+  }
+
+  /// `Γ ⊢ φ` and `Δ ⊢ ψ` give `Γ ∪ (Δ - {φ}) ⊢ ψ`
+  pub(crate) fn prove_hyp(&mut self, a: Th, b: Th) -> Th {
+    let hyps = self.union(self.hyps(a).iter().copied(), self.without(b, self.concl(a)));
+    let concl = self.concl(b);
+    self.add(hyps, concl, Rule::ProveHyp(a, b))
+  }
+
+  /// `Γ ⊢ t = u` gives `Γ ⊢ u = t`
+  pub(crate) fn sym(&mut self, th: Th) -> Result<Th> {
+    let (t, u) = self.dest_eq_or_err(th)?;
+    let concl = self.eq(u, t)?;
+    let hyps = self.hyps(th).into();
+    Ok(self.add(hyps, concl, Rule::Sym(th)))
+  }
+
+  /// `Γ ⊢ t = u` and `Δ ⊢ u = v` give `Γ ∪ Δ ⊢ t = v`
+  pub(crate) fn trans(&mut self, a: Th, b: Th) -> Result<Th> {
+    let ((t, u1), (u2, v)) = (self.dest_eq_or_err(a)?, self.dest_eq_or_err(b)?);
+    if !self.alpha_eq(u1, u2) { return Err("trans: the terms don't match".into()) }
+    let concl = self.eq(t, v)?;
+    let hyps = self.union(self.hyps(a).iter().copied(), self.hyps(b).iter().copied());
+    Ok(self.add(hyps, concl, Rule::Trans(a, b)))
+  }
+
+  /// Instantiate the type variables `tys` and then the variables `tms` in a theorem.
+  pub(crate) fn subst_thm(&mut self,
+    tys: Box<[(Name, TyId)]>, tms: Box<[(TmId, TmId)]>, th: Th
+  ) -> Result<Th> {
+    for &(v, t) in &*tms {
+      if !matches!(self.term(v), Term::Var(..)) || self.type_of(v) != self.type_of(t) {
+        return Err("subst: ill-typed substitution".into())
+      }
+    }
+    let go = |k: &mut Self, tm| { let tm = k.inst(tm, &tys); k.subst(tm, &tms) };
+    let hyps = self.hyps(th).to_vec().into_iter().map(|h| go(self, h)).collect::<Vec<_>>();
+    let concl = go(self, self.concl(th));
+    let hyps = self.union(hyps, None);
+    Ok(self.add(hyps, concl, Rule::Subst(Box::new((tys, tms)), th)))
+  }
+}
+
+/// Writes theorems of a [`Kernel`] as an article.
+#[derive(Debug)]
+pub(crate) struct Article<W> {
+  w: W,
+  /// The next free dictionary key
+  next: usize,
+  types: HashMap<TyId, usize>,
+  /// The dictionary entries for variable objects (as opposed to variable terms)
+  vars: HashMap<TmId, usize>,
+  terms: HashMap<TmId, usize>,
+  thms: HashMap<Th, usize>,
+}
+
+impl<W: Write> Article<W> {
+  /// Start an article.
+  pub(crate) fn new(mut w: W) -> io::Result<Self> {
+    writeln!(w, "# Exported from MM0 by mm0-rs\n6\nversion")?;
+    Ok(Self { w, next: 0, types: HashMap::new(), vars: HashMap::new(),
+      terms: HashMap::new(), thms: HashMap::new() })
+  }
+
+  /// Push a name. The dots in the name separate its namespaces.
+  fn name(&mut self, s: &str) -> io::Result<()> {
+    writeln!(self.w, "\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+  }
+
+  /// Push the dictionary entry `n`, if there is one.
+  fn cached(&mut self, n: Option<usize>) -> io::Result<bool> {
+    if let Some(n) = n { writeln!(self.w, "{}\nref", n)? }
+    Ok(n.is_some())
+  }
+
+  /// Save the top of the stack in the dictionary.
+  fn def(&mut self) -> io::Result<usize> {
+    let n = self.next;
+    self.next += 1;
+    writeln!(self.w, "{}\ndef", n)?;
+    Ok(n)
+  }
+
+  fn list<T: Copy>(&mut self, k: &Kernel, items: &[T],
+    mut f: impl FnMut(&mut Self, &Kernel, T) -> io::Result<()>
+  ) -> io::Result<()> {
+    for &x in items { f(self, k, x)? }
+    writeln!(self.w, "nil")?;
+    for _ in items { writeln!(self.w, "cons")? }
+    Ok(())
+  }
+
+  fn ty(&mut self, k: &Kernel, ty: TyId) -> io::Result<()> {
+    if self.cached(self.types.get(&ty).copied())? { return Ok(()) }
+    match k.get_ty(ty) {
+      Type::Var(n) => { self.name(n)?; writeln!(self.w, "varType")? }
+      Type::Op(n, args) => {
+        self.name(n)?;
+        writeln!(self.w, "typeOp")?;
+        self.list(k, args, Self::ty)?;
+        writeln!(self.w, "opType")?
+      }
+    }
+    let n = self.def()?;
+    self.types.insert(ty, n);
+    Ok(())
+  }
+
+  fn var(&mut self, k: &Kernel, v: TmId) -> io::Result<()> {
+    if self.cached(self.vars.get(&v).copied())? { return Ok(()) }
+    let ty = match k.term(v) {
+      Term::Var(name, ty) => { self.name(name)?; *ty }
+      _ => unreachable!("expected a variable"),
+    };
+    self.ty(k, ty)?;
+    writeln!(self.w, "var")?;
+    let n = self.def()?;
+    self.vars.insert(v, n);
+    Ok(())
+  }
+
+  fn term(&mut self, k: &Kernel, tm: TmId) -> io::Result<()> {
+    if self.cached(self.terms.get(&tm).copied())? { return Ok(()) }
+    match *k.term(tm) {
+      Term::Var(..) => { self.var(k, tm)?; writeln!(self.w, "varTerm")? }
+      Term::Const(ref n, ty) => {
+        self.name(n)?;
+        writeln!(self.w, "const")?;
+        self.ty(k, ty)?;
+        writeln!(self.w, "constTerm")?
+      }
+      Term::App(f, x) => { self.term(k, f)?; self.term(k, x)?; writeln!(self.w, "appTerm")? }
+      Term::Abs(v, t) => { self.var(k, v)?; self.term(k, t)?; writeln!(self.w, "absTerm")? }
+    }
+    let n = self.def()?;
+    self.terms.insert(tm, n);
+    Ok(())
+  }
+
+  fn thm(&mut self, k: &Kernel, th: Th) -> io::Result<()> {
+    if self.cached(self.thms.get(&th).copied())? { return Ok(()) }
+    let data = &k.thms[th.0 as usize];
+    let cmd = match data.rule {
+      Rule::Axiom => {
+        self.list(k, &data.hyps, Self::term)?;
+        self.term(k, data.concl)?;
+        "axiom"
+      }
+      Rule::Refl(t) => { self.term(k, t)?; "refl" }
+      Rule::Assume(t) => { self.term(k, t)?; "assume" }
+      Rule::BetaConv(t) => { self.term(k, t)?; "betaConv" }
+      Rule::AppThm(a, b) => { self.thm(k, a)?; self.thm(k, b)?; "appThm" }
+      Rule::AbsThm(v, a) => { self.var(k, v)?; self.thm(k, a)?; "absThm" }
+      Rule::EqMp(a, b) => { self.thm(k, a)?; self.thm(k, b)?; "eqMp" }
+      Rule::DeductAntisym(a, b) => { self.thm(k, a)?; self.thm(k, b)?; "deductAntisym" }
+      Rule::ProveHyp(a, b) => { self.thm(k, a)?; self.thm(k, b)?; "proveHyp" }
+      Rule::Sym(a) => { self.thm(k, a)?; "sym" }
+      Rule::Trans(a, b) => { self.thm(k, a)?; self.thm(k, b)?; "trans" }
+      Rule::Subst(ref s, a) => {
+        let (tys, tms) = &**s;
+        self.list(k, &tys.iter().collect::<Vec<_>>(), |this, k, (n, ty)| {
+          this.name(n)?;
+          this.ty(k, *ty)?;
+          writeln!(this.w, "nil\ncons\ncons")
+        })?;
+        self.list(k, tms, |this, k, (v, t)| {
+          this.var(k, v)?;
+          this.term(k, t)?;
+          writeln!(this.w, "nil\ncons\ncons")
+        })?;
+        writeln!(self.w, "nil\ncons\ncons")?;
+        self.thm(k, a)?;
+        "subst"
+      }
+    };
+    writeln!(self.w, "{}", cmd)?;
+    let n = self.def()?;
+    self.thms.insert(th, n);
+    Ok(())
+  }
+
+  /// Export the theorem `th` with the conclusion `concl`, which must be alpha-equivalent to
+  /// the conclusion of `th`. The `comment` is written before the theorem.
+  pub(crate) fn export(&mut self, k: &Kernel, th: Th, concl: TmId, comment: &str
+  ) -> io::Result<()> {
+    writeln!(self.w, "# {}", comment)?;
+    self.thm(k, th)?;
+    self.list(k, k.hyps(th), Self::term)?;
+    self.term(k, concl)?;
+    writeln!(self.w, "thm")
+  }
+
+  /// Finish the article.
+  pub(crate) fn finish(mut self) -> io::Result<()> { self.w.flush() }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod test {
+  use super::Kernel;
+
+  #[test]
+  fn reject_bad_inferences() {
+    let mut k = Kernel::default();
+    let bool = k.bool();
+    let (p, q) = (k.var("p".into(), bool), k.var("q".into(), bool));
+    let (refl_p, refl_q) = (k.refl(p), k.refl(q));
+    assert_eq!(k.trans(refl_p, refl_q).unwrap_err(), "trans: the terms don't match");
+    let hq = k.assume(q).unwrap();
+    assert_eq!(k.eq_mp(refl_p, hq).unwrap_err(), "eqMp: the terms don't match");
+    // `q = q ⊢ q = q` does not give `q = q ⊢ (λ q. q) = (λ q. q)`
+    let qq = k.eq(q, q).unwrap();
+    let hqq = k.assume(qq).unwrap();
+    assert_eq!(k.abs_thm(q, hqq).unwrap_err(), "the bound variable is free in a hypothesis");
+    assert!(k.app(p, q).is_err());
+    // but `⊢ p = p` gives `⊢ (λ q. p) = (λ q. p)`
+    let th = k.abs_thm(q, refl_p).unwrap();
+    assert!(k.hyps(th).is_empty());
+  }
+}
@@ -0,0 +1,47 @@
+-- Exported from MM0 by mm0-rs
+
+namespace MM0
+
+/-- The provability predicate of a sort. `⊦ e` means that `e` is provable. -/
+class Provable (α : Type) where
+  proof : α → Prop
+
+@[inherit_doc] prefix:26 "⊦ " => Provable.proof
+
+noncomputable section
+
+axiom wff : Type
+axiom wff.nonempty : Nonempty wff
+axiom wff.proof : wff → Prop
+instance : Provable wff := ⟨wff.proof⟩
+
+axiom term : Type
+axiom term.nonempty : Nonempty term
+
+axiom im : wff → wff → wff
+
+axiom all : (term → wff) → wff
+
+axiom lam : (term → term) → term
+
+axiom ap : term → term → term
+
+/-- The K combinator. -/
+def K : term :=
+  lam (fun x => lam (fun y => x))
+
+axiom ax_1 {a b : wff} : ⊦ im a (im b a)
+
+axiom ax_mp {a b : wff} (h : ⊦ im a b) (h' : ⊦ a) : ⊦ b
+
+axiom ax_gen {p : term → wff} (h : ∀ (x : term), ⊦ p x) : ⊦ all (fun x => p x)
+
+theorem a1i {a b : wff} (h : ⊦ b) : ⊦ im a b :=
+  @ax_mp b (im a b) (@ax_1 b a) h
+
+theorem gen_a1i {a b : term → wff} (h : ∀ (x : term), ⊦ b x) : ⊦ all (fun x => im (a x) (b x)) :=
+  @ax_gen (fun x => im (a x) (b x)) (fun x => @a1i (a x) (b x) (h x))
+
+end
+
+end MM0
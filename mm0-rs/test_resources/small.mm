@@ -0,0 +1,44 @@
+$( A small Metamath database for testing `mm0-rs from-mm`. $)
+
+$c ( ) -> A. wff setvar |- $.
+$v ph ps ch x $.
+wph $f wff ph $.
+wps $f wff ps $.
+wch $f wff ch $.
+vx $f setvar x $.
+
+wi $a wff ( ph -> ps ) $.
+wal $a wff A. x ph $.
+
+${
+  min $e |- ph $.
+  maj $e |- ( ph -> ps ) $.
+  ax-mp $a |- ps $.
+$}
+
+ax-1 $a |- ( ph -> ( ps -> ph ) ) $.
+ax-2 $a |- ( ( ph -> ( ps -> ch ) ) -> ( ( ph -> ps ) -> ( ph -> ch ) ) ) $.
+
+${
+  ax-gen.1 $e |- ph $.
+  ax-gen $a |- A. x ph $.
+$}
+
+${
+  $d x ph $.
+  ax-5 $a |- ( ph -> A. x ph ) $.
+$}
+
+${
+  a1i.1 $e |- ph $.
+  a1i $p |- ( ps -> ph ) $= ( wi ax-1 ax-mp ) ABADCABEF $.
+$}
+
+id $p |- ( ph -> ph ) $= ( wi ax-1 ax-2 ax-mp ) AAABZBFAACAFABBAFBFBAFCAFADEE $.
+
+genid $p |- A. x ( ph -> ph ) $= ( wi id ax-gen ) AACBADE $.
+
+${
+  $d x ph $.
+  a5i $p |- ( ph -> A. x ph ) $= ( ax-5 ) ABC $.
+$}
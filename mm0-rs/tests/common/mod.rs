@@ -0,0 +1,43 @@
+//! Helpers for running the `mm0-rs` binary on the files in `examples/`.
+#![allow(dead_code)]
+
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+/// The `examples/` directory of the repository.
+pub fn examples() -> PathBuf { Path::new(env!("CARGO_MANIFEST_DIR")).join("../examples") }
+
+/// A fresh temporary directory for the test `name`.
+pub fn tmp_dir(name: &str) -> PathBuf {
+  let dir = std::env::temp_dir().join(format!("mm0-rs-test-{}-{}", std::process::id(), name));
+  drop(std::fs::remove_dir_all(&dir));
+  std::fs::create_dir_all(&dir).unwrap();
+  dir
+}
+
+/// Run `mm0-rs` with the given arguments, in the `examples/` directory.
+pub fn run<I: AsRef<std::ffi::OsStr>>(args: impl IntoIterator<Item=I>) -> Output {
+  Command::new(env!("CARGO_BIN_EXE_mm0-rs")).current_dir(examples()).args(args).output().unwrap()
+}
+
+/// Run `mm0-rs` and check that it succeeds, returning its standard output.
+pub fn run_ok<I: AsRef<std::ffi::OsStr>>(args: impl IntoIterator<Item=I>) -> String {
+  let out = run(args);
+  assert!(out.status.success(), "mm0-rs failed:\n{}{}",
+    String::from_utf8_lossy(&out.stdout), String::from_utf8_lossy(&out.stderr));
+  String::from_utf8(out.stdout).unwrap()
+}
+
+/// Run `mm0-rs` and check that it fails, returning its standard output and error.
+pub fn run_err<I: AsRef<std::ffi::OsStr>>(args: impl IntoIterator<Item=I>) -> String {
+  let out = run(args);
+  assert!(!out.status.success(), "mm0-rs unexpectedly succeeded");
+  String::from_utf8_lossy(&out.stdout).into_owned() + &String::from_utf8_lossy(&out.stderr)
+}
+
+/// Compile an example file into `dir`, returning the path of the output file.
+pub fn compile(dir: &Path, file: &str, ext: &str) -> PathBuf {
+  let out = dir.join(Path::new(file).with_extension(ext).file_name().unwrap());
+  run_ok(["compile".as_ref(), file.as_ref(), out.as_os_str()]);
+  out
+}
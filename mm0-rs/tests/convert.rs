@@ -0,0 +1,32 @@
+mod common;
+
+use std::path::Path;
+
+fn convert(input: &Path, output: &Path) {
+  common::run_ok(["convert".as_ref(), input.as_os_str(), output.as_os_str()]);
+}
+
+fn verify(proof: &Path) {
+  let out = common::run_ok(["verify".as_ref(), "peano.mm0".as_ref(), proof.as_os_str()]);
+  assert!(out.lines().any(|l| l.starts_with("verified ")), "{}", out);
+}
+
+#[test]
+fn convert_round_trip() {
+  let dir = common::tmp_dir("convert_round_trip");
+  let mmb = common::compile(&dir, "peano.mm1", "mmb");
+  let (mmu1, mmb1) = (dir.join("peano1.mmu"), dir.join("peano1.mmb"));
+  convert(&mmb, &mmu1);
+  verify(&mmu1);
+  convert(&mmu1, &mmb1);
+  verify(&mmb1);
+  // the statements are unchanged, although the proofs may be encoded differently
+  let out = common::run(["mmb-diff".as_ref(), mmb.as_os_str(), mmb1.as_os_str()]);
+  let diff = String::from_utf8(out.stdout).unwrap();
+  assert!(diff.lines().all(|l| l.ends_with(": proof changed")), "{}", diff);
+  // the output has no variable names, so the second round trip is exact
+  let (mmu2, mmb2) = (dir.join("peano2.mmu"), dir.join("peano2.mmb"));
+  convert(&mmb1, &mmu2);
+  convert(&mmu2, &mmb2);
+  assert!(std::fs::read(&mmb1).unwrap() == std::fs::read(&mmb2).unwrap());
+}
@@ -0,0 +1,21 @@
+mod common;
+
+/// The `verified ...` line of the output of `verify`.
+fn verified(out: &str) -> &str {
+  out.lines().find(|l| l.starts_with("verified ")).unwrap_or_else(|| panic!("{}", out))
+}
+
+#[test]
+fn decompile_round_trip() {
+  let dir = common::tmp_dir("decompile_round_trip");
+  let mmb = common::compile(&dir, "peano.mm1", "mmb");
+  let (mm0, mm1) = (dir.join("out.mm0"), dir.join("out.mm1"));
+  common::run_ok(["decompile".as_ref(), mmb.as_os_str(), mm0.as_os_str(), mm1.as_os_str()]);
+  let out = common::compile(&dir, mm1.to_str().unwrap(), "mmb");
+  let orig = common::run_ok(["verify".as_ref(), "peano.mm0".as_ref(), mmb.as_os_str()]);
+  // the recompiled file checks against both the original and the decompiled specification
+  for spec in ["peano.mm0".as_ref(), mm0.as_os_str()] {
+    let res = common::run_ok(["verify".as_ref(), spec, out.as_os_str()]);
+    assert_eq!(verified(&res), verified(&orig));
+  }
+}
@@ -0,0 +1,18 @@
+mod common;
+
+#[test]
+fn deps_json_stdout() {
+  let out = common::run_ok(["deps", "--format", "json", "--ancestors-of", "a1i", "peano.mm1"]);
+  let json: serde_json::Value = serde_json::from_str(&out).unwrap();
+  let nodes = json["nodes"].as_array().unwrap();
+  let a1i = nodes.iter().find(|n| n["name"] == "a1i").unwrap();
+  assert_eq!(a1i["kind"], "theorem");
+  assert!(a1i["deps"].as_array().unwrap().iter().any(|d| d == "ax_1"));
+  assert!(nodes.iter().all(|n| n["name"] != "a1d"));
+}
+
+#[test]
+fn deps_dot_stdout() {
+  let out = common::run_ok(["deps", "--ancestors-of", "a1i", "peano.mm1"]);
+  assert!(out.starts_with("digraph deps {"), "{}", out);
+}
@@ -0,0 +1,50 @@
+mod common;
+
+use std::path::{Path, PathBuf};
+
+/// Compile the old and new versions of the test library into a fresh directory.
+fn compile_pair(name: &str) -> (PathBuf, PathBuf) {
+  let dir = common::tmp_dir(name);
+  let res = Path::new(env!("CARGO_MANIFEST_DIR")).join("test_resources");
+  let compile = |file: &str| common::compile(&dir, res.join(file).to_str().unwrap(), "mmb");
+  (compile("diff_old.mm1"), compile("diff_new.mm1"))
+}
+
+/// Run `mmb-diff` on the two files in the given format, checking that it reports differences.
+fn diff(old: &Path, new: &Path, format: &str) -> String {
+  let out = common::run(["mmb-diff".as_ref(), "--format".as_ref(), format.as_ref(),
+    old.as_os_str(), new.as_os_str()]);
+  assert_eq!(out.status.code(), Some(1), "{}", String::from_utf8_lossy(&out.stderr));
+  String::from_utf8(out.stdout).unwrap()
+}
+
+#[test]
+fn diff_text() {
+  let (old, new) = compile_pair("diff_text");
+  assert_eq!(diff(&old, &new, "text").lines().collect::<Vec<_>>(), [
+    "- term an: removed",
+    "+ term or: added",
+    "~ theorem a1ii: proof changed",
+    "~ theorem a1s: changed (arguments changed, statement changed)",
+  ]);
+}
+
+#[test]
+fn diff_json() {
+  let (old, new) = compile_pair("diff_json");
+  let out: serde_json::Value = serde_json::from_str(&diff(&old, &new, "json")).unwrap();
+  assert_eq!(out, serde_json::json!({ "changes": [
+    { "name": "an", "kind": "term", "local": false, "change": "removed", "details": [] },
+    { "name": "or", "kind": "term", "local": false, "change": "added", "details": [] },
+    { "name": "a1ii", "kind": "theorem", "local": false, "change": "proof changed", "details": [] },
+    { "name": "a1s", "kind": "theorem", "local": false, "change": "changed",
+      "details": ["arguments changed", "statement changed"] },
+  ]}));
+}
+
+#[test]
+fn diff_same() {
+  let (old, _) = compile_pair("diff_same");
+  let out = common::run_ok(["mmb-diff".as_ref(), old.as_os_str(), old.as_os_str()]);
+  assert_eq!(out, "");
+}
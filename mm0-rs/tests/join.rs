@@ -0,0 +1,19 @@
+mod common;
+
+#[test]
+fn join_only_closure() {
+  let dir = common::tmp_dir("join_only_closure");
+  let out = dir.join("a1i.mm1");
+  common::run_ok(["join".as_ref(), "--only".as_ref(), "a1i".as_ref(),
+    "peano.mm1".as_ref(), out.as_os_str()]);
+  let src = std::fs::read_to_string(&out).unwrap();
+  let decls = src.lines().filter_map(|l| {
+    let mut it = l.split_whitespace().skip_while(|w| matches!(*w, "pub" | "local"));
+    match it.next()? {
+      "term" | "def" | "axiom" | "theorem" => it.next(),
+      _ => None,
+    }
+  }).map(|x| x.trim_end_matches(':')).collect::<Vec<_>>();
+  assert_eq!(decls, ["im", "ax_1", "ax_mp", "a1i"], "{}", src);
+  common::run_ok(["compile".as_ref(), "-W".as_ref(), out.as_os_str()]);
+}
@@ -0,0 +1,13 @@
+mod common;
+
+use std::path::Path;
+
+#[test]
+fn export_lean_snapshot() {
+  let res = Path::new(env!("CARGO_MANIFEST_DIR")).join("test_resources");
+  let out = common::tmp_dir("export_lean_snapshot").join("lean_export.lean");
+  common::run_ok(["export-lean".as_ref(), res.join("lean_export.mm1").as_os_str(),
+    out.as_os_str()]);
+  let expected = std::fs::read_to_string(res.join("lean_export.lean")).unwrap();
+  assert_eq!(std::fs::read_to_string(out).unwrap(), expected);
+}
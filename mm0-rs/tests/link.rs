@@ -0,0 +1,44 @@
+mod common;
+
+use std::path::{Path, PathBuf};
+
+fn resource(file: &str) -> String {
+  Path::new(env!("CARGO_MANIFEST_DIR")).join("test_resources").join(file)
+    .to_str().unwrap().to_owned()
+}
+
+/// Link `inputs` into `out.mmb` in `dir`, returning the path of the output and the result.
+fn link(dir: &Path, inputs: &[PathBuf]) -> (PathBuf, std::process::Output) {
+  let out = dir.join("out.mmb");
+  let mut args = vec!["mmb-link".as_ref(), "-o".as_ref(), out.as_os_str()];
+  args.extend(inputs.iter().map(|p| p.as_os_str()));
+  let res = common::run(args);
+  (out, res)
+}
+
+#[test]
+fn link_shared_prefix() {
+  let dir = common::tmp_dir("link_shared_prefix");
+  let a = common::compile(&dir, &resource("link_a.mm1"), "mmb");
+  let b = common::compile(&dir, &resource("link_b.mm1"), "mmb");
+  let (out, res) = link(&dir, &[a, b]);
+  assert!(res.status.success(), "{}", String::from_utf8_lossy(&res.stderr));
+  let verified = common::run_ok(["verify".as_ref(), resource("link.mm0").as_ref(), out.as_os_str()]);
+  assert!(verified.lines().any(|l| l.starts_with("verified ")), "{}", verified);
+}
+
+#[test]
+fn link_mismatch() {
+  let dir = common::tmp_dir("link_mismatch");
+  let a = common::compile(&dir, &resource("link_a.mm1"), "mmb");
+  let src = std::fs::read_to_string(resource("link_b.mm1")).unwrap();
+  let b = dir.join("link_b.mm1");
+  std::fs::write(&b, src.replace("provable sort wff;", "strict provable sort wff;")).unwrap();
+  let b = common::compile(&dir, b.to_str().unwrap(), "mmb");
+  let (out, res) = link(&dir, &[a, b.clone()]);
+  assert!(!res.status.success());
+  let err = String::from_utf8_lossy(&res.stderr);
+  let expected = format!("error: {}: the modifiers of sort wff differ", b.display());
+  assert!(err.contains(&expected), "{}", err);
+  assert!(!out.exists());
+}
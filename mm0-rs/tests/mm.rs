@@ -0,0 +1,16 @@
+mod common;
+
+#[test]
+fn import_mm() {
+  let mm = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("test_resources/small.mm");
+  let dir = common::tmp_dir("import_mm");
+  let (mm0, mmb) = (dir.join("small.mm0"), dir.join("small.mmb"));
+  common::run_ok(["from-mm".as_ref(), mm.as_os_str(), mm0.as_os_str(), mmb.as_os_str()]);
+  let spec = std::fs::read_to_string(&mm0).unwrap();
+  assert!(spec.contains("pure sort setvar;"), "{}", spec);
+  // `ph` depends on `x` unless there is a `$d x ph` condition
+  assert!(spec.contains("axiom ax_gen {x: setvar} (ph: wff x) (ax_gen_1: $ ph $):"), "{}", spec);
+  assert!(spec.contains("theorem a5i {x: setvar} (ph: wff):"), "{}", spec);
+  let out = common::run_ok(["verify".as_ref(), mm0.as_os_str(), mmb.as_os_str()]);
+  assert!(out.lines().any(|l| l == "verified 2 sorts, 2 terms, 9 theorems"), "{}", out);
+}
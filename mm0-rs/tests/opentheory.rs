@@ -0,0 +1,14 @@
+mod common;
+
+#[test]
+fn export_ot_hol() {
+  let art = common::tmp_dir("export_ot_hol").join("hol.art");
+  common::run_ok(["export-ot".as_ref(), "hol.mm1".as_ref(), "hol.otmap".as_ref(),
+    art.as_os_str()]);
+  let art = std::fs::read_to_string(art).unwrap();
+  let names = art.lines().skip(1).filter_map(|l| l.strip_prefix("# ")).collect::<Vec<_>>();
+  assert_eq!(names, ["T_DEF", "TRUTH", "AND_DEF", "IMP_DEF", "IMP_ID", "FORALL_DEF",
+    "EXISTS_DEF", "OR_DEF", "F_DEF", "NOT_DEF", "NOT_FALSE", "EXISTS_UNIQUE_DEF", "SELECT_AX",
+    "em", "ONE_ONE", "ONTO"]);
+  assert_eq!(art.lines().filter(|&l| l == "thm").count(), names.len());
+}
@@ -0,0 +1,42 @@
+mod common;
+
+use std::path::Path;
+use serde_json::Value;
+
+/// Run `compile --timings` with JSON messages and the given arguments, returning the
+/// timing report.
+fn timings(args: &[&std::ffi::OsStr]) -> Value {
+  let out = common::run(["compile".as_ref(), "--timings".as_ref(),
+    "--message-format".as_ref(), "json".as_ref()].iter().chain(args));
+  let out = String::from_utf8(out.stdout).unwrap();
+  out.lines().map(|l| serde_json::from_str::<Value>(l).unwrap())
+    .find(|v| v["type"] == "timings").unwrap_or_else(|| panic!("{}", out))
+}
+
+fn resource(file: &str) -> std::path::PathBuf {
+  Path::new(env!("CARGO_MANIFEST_DIR")).join("test_resources").join(file)
+}
+
+#[test]
+fn timings_failing_tactic() {
+  let res = timings(&[resource("timings.mm1").as_os_str()]);
+  let names = |key: &str| res[key].as_array().unwrap().iter()
+    .map(|t| t["name"].as_str().unwrap().to_owned()).collect::<Vec<_>>();
+  assert!(names("stmts").iter().any(|n| n == "theorem a1i"), "{}", res);
+  let mut procs = names("procs");
+  procs.sort();
+  assert_eq!(procs, ["count", "fail"], "{}", res);
+}
+
+#[test]
+fn timings_cached() {
+  let cache = common::tmp_dir("timings_cached");
+  let file = resource("link_a.mm1");
+  let args = ["--cache-dir".as_ref(), cache.as_os_str(), file.as_os_str()];
+  let res = timings(&args);
+  assert_eq!(res["cached"], serde_json::json!([]), "{}", res);
+  let res = timings(&args);
+  assert_eq!(res["stmts"], serde_json::json!([]), "{}", res);
+  let cached = res["cached"].as_array().unwrap();
+  assert!(cached.len() == 1 && cached[0].as_str().unwrap().ends_with("link_a.mm1"), "{}", res);
+}
@@ -0,0 +1,125 @@
+mod common;
+
+use std::path::PathBuf;
+
+/// Compile `peano.mm1` into a fresh directory for the test `name`, returning the directory,
+/// the path of the `.mmb` file and its `dump` listing.
+fn peano_mmb(name: &str) -> (PathBuf, PathBuf, String) {
+  let dir = common::tmp_dir(name);
+  let mmb = common::compile(&dir, "peano.mm1", "mmb");
+  let dump = common::run_ok(["dump".as_ref(), mmb.as_os_str()]);
+  (dir, mmb, dump)
+}
+
+fn hex(s: &str) -> usize {
+  usize::from_str_radix(s.trim_start_matches("0x"), 16).unwrap()
+}
+
+/// The `Thm` proof commands with one byte of data in a `dump` listing, as the name of the
+/// theorem whose proof they are in and their offset.
+fn thm_refs(dump: &str) -> Vec<(&str, usize)> {
+  let mut stmt = None;
+  let mut out = vec![];
+  for line in dump.lines() {
+    let mut words = line.split_whitespace();
+    if line.starts_with("  0x") {
+      stmt = words.find(|w| w.ends_with(':') && w[..w.len() - 1].parse::<u32>().is_ok())
+        .and_then(|_| words.next()?.strip_suffix(','))
+        .filter(|_| line.contains("Thm "));
+    } else if let (Some(name), true) = (stmt, line.starts_with("    0x")) {
+      let off = words.next().unwrap();
+      if words.next() == Some("54") { out.push((name, hex(off))) }
+    }
+  }
+  out
+}
+
+#[test]
+fn verify_mmb() {
+  let (_, mmb, _) = peano_mmb("verify_mmb");
+  let out = common::run_ok(["verify".as_ref(), "peano.mm0".as_ref(), mmb.as_os_str()]);
+  assert!(out.lines().any(|l| l.starts_with("verified ")), "{}", out);
+}
+
+#[test]
+fn verify_mmb_bad_proof() {
+  let (_, mmb, dump) = peano_mmb("verify_mmb_bad_proof");
+  let (name, off) = thm_refs(&dump)[0];
+  let mut buf = std::fs::read(&mmb).unwrap();
+  // refer to a theorem which has not been declared yet
+  buf[off + 1] = 0xff;
+  std::fs::write(&mmb, buf).unwrap();
+  let err = common::run_err(["verify".as_ref(), "peano.mm0".as_ref(), mmb.as_os_str()]);
+  let expected = format!("error at {:#x} ({}, ", off, name);
+  assert!(err.contains(&expected) && err.contains("theorem out of range"), "{}", err);
+}
+
+/// Write a copy of `peano.mm0` to `dir` in which the statement of `ifppos` is changed.
+fn bad_spec(dir: &std::path::Path) -> PathBuf {
+  let spec = std::fs::read_to_string(common::examples().join("peano.mm0")).unwrap();
+  let old = "theorem ifppos (p a b: wff): $ p -> (ifp p a b <-> a) $;";
+  assert!(spec.contains(old));
+  let bad = dir.join("peano.mm0");
+  std::fs::write(&bad, spec.replace(old, &old.replace("<-> a", "<-> b"))).unwrap();
+  bad
+}
+
+#[test]
+fn verify_mmb_bad_statement() {
+  let (dir, mmb, dump) = peano_mmb("verify_mmb_bad_statement");
+  let bad = bad_spec(&dir);
+  // the last `URef` in the unify stream of `ifppos`, which is the `a` on the right
+  let unify = dump.lines().skip_while(|l| !(l.contains(": ifppos, ") && l.contains(" args @")));
+  let off = unify.skip_while(|l| !l.trim_start().starts_with("unify @"))
+    .take_while(|l| !l.trim_end().ends_with("End"))
+    .filter(|l| l.contains("URef")).last().unwrap();
+  let off = hex(off.split_whitespace().next().unwrap());
+  let err = common::run_err(["verify".as_ref(), bad.as_os_str(), mmb.as_os_str()]);
+  let expected = format!("error at {:#x} (ifppos, ", off);
+  assert!(err.contains(&expected) && err.contains("expression mismatch"), "{}", err);
+}
+
+#[test]
+fn verify_mmb_par() {
+  let (_, mmb, dump) = peano_mmb("verify_mmb_par");
+  let mut refs = thm_refs(&dump);
+  refs.truncate(100);
+  refs.dedup_by_key(|r| r.0);
+  let refs = refs.into_iter().step_by(5).take(4).collect::<Vec<_>>();
+  assert_eq!(refs.len(), 4);
+  let mut buf = std::fs::read(&mmb).unwrap();
+  for &(_, off) in &refs { buf[off + 1] = 0xff }
+  std::fs::write(&mmb, buf).unwrap();
+  let errors = |jobs: &str| {
+    common::run_err(["verify".as_ref(), "-j".as_ref(), jobs.as_ref(),
+      "peano.mm0".as_ref(), mmb.as_os_str()])
+      .lines().filter(|l| l.starts_with("error")).map(str::to_owned).collect::<Vec<_>>()
+  };
+  let expected = refs.iter().map(|&(name, off)| format!("error at {:#x} ({}, ", off, name))
+    .collect::<Vec<_>>();
+  let seq = errors("1");
+  assert_eq!(seq.len(), 1);
+  assert!(seq[0].starts_with(&expected[0]), "{:?}", seq);
+  let par = errors("4");
+  assert_eq!(par.len(), refs.len(), "{:?}", par);
+  assert_eq!(par[0], seq[0]);
+  for (e, exp) in par.iter().zip(&expected) {
+    assert!(e.starts_with(exp) && e.ends_with("theorem out of range"), "{:?}", par)
+  }
+}
+
+#[test]
+fn verify_mmu() {
+  let out = common::run_ok(["verify", "peano.mm0", "peano.mmu"]);
+  assert!(out.lines().any(|l| l.starts_with("verified ")), "{}", out);
+}
+
+#[test]
+fn verify_mmu_bad_statement() {
+  let bad = bad_spec(&common::tmp_dir("verify_mmu_bad_statement"));
+  let err = common::run_err(["verify".as_ref(), bad.as_os_str(), "peano.mmu".as_ref()]);
+  let mmu = std::fs::read_to_string(common::examples().join("peano.mmu")).unwrap();
+  let line = mmu.lines().position(|l| l.trim_start().starts_with("(theorem ifppos ")).unwrap();
+  assert!(err.contains("ifppos: statement does not match the specification"), "{}", err);
+  assert!(err.contains(&format!("peano.mmu:{}:", line + 1)), "{}", err);
+}